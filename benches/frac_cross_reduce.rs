@@ -0,0 +1,66 @@
+// Frac `+` and `*`, which cancel common factors before multiplying, against
+// multiplying out in full and reducing the result: `cargo bench`
+use rust_calculator::{BigNum, Frac};
+
+use std::time::Instant;
+
+fn naive_add(a: Frac, b: Frac) -> Frac {
+    Frac::new(
+        a.numerator().clone() * b.denominator().clone()
+            + a.denominator().clone() * b.numerator().clone(),
+        a.denominator().clone() * b.denominator().clone(),
+    )
+}
+
+fn naive_mul(a: Frac, b: Frac) -> Frac {
+    Frac::new(
+        a.numerator().clone() * b.numerator().clone(),
+        a.denominator().clone() * b.denominator().clone(),
+    )
+}
+
+fn frac(numerator: u64, denominator: u64) -> Frac {
+    Frac::new(BigNum::from(numerator), BigNum::from(denominator))
+}
+
+// Harmonic sum 1/1 + ... + 1/n
+fn harmonic(n: u64, add: Op) -> Frac {
+    (1..=n).map(|k| frac(1, k)).fold(frac(0, 1), add)
+}
+
+// Product of (k+1)/k telescoping down to n+1
+fn telescoping(n: u64, mul: Op) -> Frac {
+    (1..=n).map(|k| frac(k + 1, k)).fold(frac(1, 1), mul)
+}
+
+// Product of binomial ratios (n-k)/(k+1), building C(n, k) one factor at a
+// time
+fn binomials(n: u64, mul: Op) -> Frac {
+    (0..n / 2).map(|k| frac(n - k, k + 1)).fold(frac(1, 1), mul)
+}
+
+type Op = fn(Frac, Frac) -> Frac;
+type Bench = (&'static str, fn(u64, Op) -> Frac, u64);
+
+fn main() {
+    let benches: [(Bench, Op, Op); 3] = [
+        (("harmonic", harmonic, 300), |a, b| a + b, naive_add),
+        (("telescoping", telescoping, 2000), |a, b| a * b, naive_mul),
+        (("binomials", binomials, 600), |a, b| a * b, naive_mul),
+    ];
+    for ((name, bench, n), cross, naive) in benches {
+        for (method, op) in [("cross-reduced", cross), ("naive", naive)] {
+            let start = Instant::now();
+            let result = bench(n, op);
+            let elapsed = start.elapsed();
+            println!(
+                "{:<12} n={:<5} {:<14} {:>10.2?} ({} chars)",
+                name,
+                n,
+                method,
+                elapsed,
+                result.to_string().len()
+            );
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Chained Frac arithmetic under different GCD thresholds: `cargo bench`
+use rust_calculator::{BigNum, Frac};
+
+use std::time::Instant;
+
+// Harmonic sum 1/1 + ... + 1/n, whose denominators grow with every term
+fn harmonic(n: u64) -> Frac {
+    (1..=n)
+        .map(|k| Frac::new(BigNum::one(), BigNum::from(k)))
+        .sum()
+}
+
+// Product of (k+1)/k telescoping down to n+1, lots of cancellation
+fn telescoping(n: u64) -> Frac {
+    (1..=n)
+        .map(|k| Frac::new(BigNum::from(k + 1), BigNum::from(k)))
+        .product()
+}
+
+type Bench = (&'static str, fn(u64) -> Frac, u64);
+
+fn main() {
+    let benches: [Bench; 2] = [
+        ("harmonic", harmonic, 120),
+        ("telescoping", telescoping, 400),
+    ];
+    for (name, bench, n) in benches {
+        for threshold in [0, 10, 40, 160] {
+            Frac::set_gcd_threshold(threshold);
+            let start = Instant::now();
+            let result = bench(n);
+            let elapsed = start.elapsed();
+            println!(
+                "{:<12} n={:<4} threshold={:<4} {:>10.2?} ({} chars)",
+                name,
+                n,
+                threshold,
+                elapsed,
+                result.to_string().len()
+            );
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::common::Value;
+use crate::parser::CalcError;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// One evaluation, as reported to the audit sink
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct AuditEvent<'a> {
+    /// The statement evaluated
+    pub input: &'a str,
+    /// Its value, or the error message
+    pub result: Result<&'a Value, String>,
+    /// Time the evaluation took
+    pub duration: Duration,
+    /// The evaluation stopped at one of its `EvalLimits`
+    pub limit_hit: bool,
+}
+
+type Sink = Rc<dyn Fn(&AuditEvent)>;
+
+thread_local! {
+    static SINK: RefCell<Option<Sink>> = const { RefCell::new(None) };
+}
+
+/// Call `sink` after every evaluation on this thread, in the REPL, scripts,
+/// `eval_to_string` and `eval_all` alike, to log usage or count toward a
+/// quota. Replaces any previous sink. A session with a sink of its own,
+/// see `Session::set_audit_sink`, reports to that one instead.
+#[deprecated(note = "use Session::set_audit_sink, which reports one session only")]
+pub fn set_audit_sink(sink: impl Fn(&AuditEvent) + 'static) {
+    SINK.with(|current| *current.borrow_mut() = Some(Rc::new(sink)));
+}
+
+#[deprecated(note = "use Session::clear_audit_sink")]
+pub fn clear_audit_sink() {
+    SINK.with(|current| *current.borrow_mut() = None);
+}
+
+// Sink of a `Session`, shared with its clones
+#[derive(Clone)]
+pub(crate) struct SessionSink(Arc<dyn Fn(&AuditEvent) + Send + Sync>);
+
+impl SessionSink {
+    pub(crate) fn new(sink: impl Fn(&AuditEvent) + Send + Sync + 'static) -> Self {
+        SessionSink(Arc::new(sink))
+    }
+}
+
+impl fmt::Debug for SessionSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SessionSink")
+    }
+}
+
+// Run `evaluate` reporting to `sink` in place of the thread's sink, or to
+// the thread's sink when there is none
+pub(crate) fn with_sink<T>(sink: Option<&SessionSink>, evaluate: impl FnOnce() -> T) -> T {
+    let Some(SessionSink(sink)) = sink else {
+        return evaluate();
+    };
+    let sink = Arc::clone(sink);
+    let sink: Sink = Rc::new(move |event: &AuditEvent| sink(event));
+    let outer = SINK.with(|current| current.replace(Some(sink)));
+    let result = evaluate();
+    SINK.with(|current| *current.borrow_mut() = outer);
+    result
+}
+
+// Run one evaluation of `input`, reporting it when a sink is set
+pub(crate) fn audited(
+    input: &str,
+    evaluate: impl FnOnce() -> Result<Value, CalcError>,
+) -> Result<Value, CalcError> {
+    // Cloned out so the sink may itself evaluate or replace the sink
+    let Some(sink) = SINK.with(|current| current.borrow().clone()) else {
+        return evaluate();
+    };
+    let start = Instant::now();
+    let result = evaluate();
+    let duration = start.elapsed();
+    let (result_ref, limit_hit) = match &result {
+        Ok(value) => (Ok(value), false),
+        Err(e) => (Err(e.to_string()), e.is_limit_exceeded()),
+    };
+    sink(&AuditEvent {
+        input,
+        result: result_ref,
+        duration,
+        limit_hit,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::EvalLimits;
+    use crate::parser::{eval_all, eval_value};
+    use crate::session::Session;
+
+    use std::sync::Mutex;
+
+    mod test_audit {
+        use super::*;
+
+        // Inputs and results seen by the sink, `!` marking a limit hit
+        #[allow(deprecated)]
+        fn record(run: impl FnOnce()) -> Vec<String> {
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let seen = Rc::clone(&events);
+            set_audit_sink(move |event| {
+                let result = match &event.result {
+                    Ok(value) => value.to_string(),
+                    Err(e) => e.clone(),
+                };
+                let mark = if event.limit_hit { "!" } else { "" };
+                seen.borrow_mut()
+                    .push(format!("{} -> {}{}", event.input, result, mark));
+            });
+            run();
+            clear_audit_sink();
+            let events = events.borrow().clone();
+            events
+        }
+
+        #[test]
+        fn test_events() {
+            let events = record(|| {
+                let _ = eval_value("1/2 + 1/3");
+                let _ = eval_value("1 +");
+                let _: Vec<_> = eval_all("x = 2; x * 3").collect();
+            });
+            assert_eq!(events.len(), 4);
+            assert_eq!(events[0], "1/2 + 1/3 -> 5/6");
+            assert!(events[1].starts_with("1 + -> "));
+            assert_eq!(events[2..], ["x = 2 -> 2", "x * 3 -> 6"]);
+            // Nothing is reported once the sink is cleared
+            assert!(eval_value("1").is_ok());
+        }
+
+        #[test]
+        fn test_session_sink() {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let seen = Arc::clone(&events);
+            let mut session = Session::new();
+            session.set_audit_sink(move |event| {
+                seen.lock()
+                    .unwrap()
+                    .push((event.input.to_string(), event.limit_hit));
+            });
+            session.set("max-digits", "5").unwrap();
+            // Reported to the session's sink rather than the thread's
+            let on_thread = record(|| {
+                let _ = session.eval("10^2");
+                let _ = session.eval("10^9");
+                let _ = session.eval("1 / 0");
+                session.set_limits(EvalLimits {
+                    max_steps: Some(3),
+                    ..EvalLimits::default()
+                });
+                let _ = session.eval("1 + 2 + 3");
+            });
+            assert!(on_thread.is_empty());
+            assert_eq!(
+                *events.lock().unwrap(),
+                [
+                    ("10^2".to_string(), false),
+                    ("10^9".to_string(), true),
+                    ("1 / 0".to_string(), false),
+                    ("1 + 2 + 3".to_string(), true),
+                ]
+            );
+            session.clear_audit_sink();
+            assert_eq!(record(|| drop(session.eval("1"))), ["1 -> 1"]);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::common::Value;
+use crate::format::{format_value, FormatOptions};
+use crate::parser::{eval_all, eval_value, is_blank, syntax_errors};
+
+// Scripts print exact results, only the REPL shortens huge numbers
+fn script_options() -> FormatOptions {
+    FormatOptions {
+        full_output: true,
+        ..FormatOptions::default()
+    }
+}
+
+/// Run a script non-interactively, printing the result of every statement.
+/// Stops at the first error or `exit(n)` and returns the process exit status.
+/// A script with syntax errors does not run, all of them being reported.
+pub fn run(source: &str) -> i32 {
+    let errors = syntax_errors(source);
+    if let Some(first) = errors.first() {
+        for e in &errors {
+            eprintln!("Error: {}", e);
+        }
+        return first.exit_code();
+    }
+    let options = script_options();
+    for result in eval_all(source) {
+        match result {
+            Ok(value) => println!("{}", format_value(&value, &options)),
+            Err(e) if e.is_exit() => return e.exit_code(),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return e.exit_code();
+            }
+        }
+    }
+    0
+}
+
+/// Verify a worksheet of `expression = claimed answer` lines, printing ✓ or
+/// ✗ with the correct value for each. Blank and comment-only lines are
+/// skipped. Returns 0 when
+/// every claim holds and 1 otherwise.
+pub fn check(source: &str) -> i32 {
+    let options = script_options();
+    let (mut total, mut correct) = (0, 0);
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if is_blank(line) {
+            continue;
+        }
+        total += 1;
+        match check_line(line) {
+            Ok(None) => {
+                correct += 1;
+                println!("✓ {}", line);
+            }
+            Ok(Some(value)) => println!("✗ {}, correct: {}", line, format_value(&value, &options)),
+            Err(e) => println!("✗ {}, line {}: {}", line, number + 1, e),
+        }
+    }
+    println!("{}/{} correct", correct, total);
+    if correct == total {
+        0
+    } else {
+        1
+    }
+}
+
+// None when the claimed answer equals the expression, otherwise the correct
+// value. Answers are compared by value, so 2/4 is accepted for 1/2.
+fn check_line(line: &str) -> Result<Option<Value>, String> {
+    let (expression, claim) = line
+        .rsplit_once('=')
+        .ok_or("Expected expression = answer")?;
+    let value = eval_value(expression).map_err(|e| e.to_string())?;
+    let claimed = eval_value(claim).map_err(|e| e.to_string())?;
+    let equal = value.equals(&claimed).unwrap_or(false);
+    Ok((!equal).then_some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod test_run {
+        use super::*;
+
+        #[test]
+        fn test_exit_status() {
+            assert_eq!(run("x = 2\nx * 3"), 0);
+            assert_eq!(run("1 / 0"), 1);
+            assert_eq!(run("exit(3)"), 3);
+            // Nothing runs, so the exit is never reached
+            assert_eq!(run("exit(3)\n1 +\n2 * )"), 1);
+        }
+    }
+
+    mod test_check {
+        use super::*;
+
+        #[test]
+        fn test_check_line() {
+            assert_eq!(check_line("1/2 + 1/3 = 5/6"), Ok(None));
+            assert_eq!(check_line("1/4 + 1/4 = 2/4"), Ok(None));
+            assert_eq!(check_line(" 3 * 4 =12 "), Ok(None));
+            assert_eq!(
+                check_line("1/2 + 1/3 = 2/5"),
+                Ok(Some("5/6".parse().unwrap()))
+            );
+            assert_eq!(check_line("90deg = 90"), Ok(Some("90deg".parse().unwrap())));
+            assert!(check_line("1 + 1").is_err());
+            assert!(check_line("1 + = 2").is_err());
+            assert!(check_line("2 = 1 +").is_err());
+        }
+
+        #[test]
+        fn test_exit_status() {
+            assert_eq!(check("# sums\n1 + 1 = 2\n\n2 * 3 = 6 # easy\n"), 0);
+            assert_eq!(check("1 + 1 = 2\n2 * 3 = 5"), 1);
+            assert_eq!(check("1 / 0 = 0"), 1);
+        }
+    }
+}
@@ -1,36 +1,88 @@
 use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::iter::{Product, Sum};
 use std::ops::{
-    Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
+    Add, AddAssign, BitAnd, BitOr, BitXor, Div, DivAssign, Mul, MulAssign, Neg, Not, Rem,
+    RemAssign, Sub, SubAssign,
 };
 use std::str::FromStr;
 use std::string::ToString;
+use std::sync::{Arc, LazyLock};
 
+use crate::error::ArithError;
 use crate::frac::{Frac, IntoFrac};
+use crate::limits;
+use crate::pool;
+use crate::stats::{self, Op};
+// Smallest and largest of the interned constants
+const INTERNED_MIN: i8 = -1;
+const INTERNED_MAX: i8 = 10;
+
+// Shared instances of the small constants the evaluator and Frac
+// normalization create all the time, indexed from INTERNED_MIN
+static INTERNED: LazyLock<Vec<BigNum>> = LazyLock::new(|| {
+    (INTERNED_MIN..=INTERNED_MAX)
+        .map(|n| {
+            let digits = n.unsigned_abs().to_string();
+            BigNum {
+                sign: n >= 0,
+                num: Arc::new(digits.bytes().map(|b| b - b'0').collect()),
+            }
+        })
+        .collect()
+});
+
+// Start of the error of any operation stopped by the digit limit
+pub(crate) const TOO_LARGE: &str = "Result too large";
+// Largest factorial computed, in digits, as powers are limited: the product
+// takes seconds past this, well before memory runs short
+const FACTORIAL_MAX_DIGITS: usize = 50_000;
+// Error of `gcd(0, 0)`, also given by the `gcd` function for any number of
+// zeroes
+pub(crate) const GCD_OF_ZEROES: &str = "GCD of zeroes is undefined";
+
 #[derive(Clone, Debug)]
 pub struct BigNum {
-    sign: bool,   // true = positive, false = negative
-    num: Vec<u8>, // Stores digits in most significant to least significant order
+    sign: bool, // true = positive, false = negative
+    // Digits, most significant first. Shared, so interned constants and
+    // clones do not copy them
+    num: Arc<Vec<u8>>,
 }
 
 impl BigNum {
-    fn new() -> BigNum {
-        BigNum {
-            sign: true,
-            num: vec![0],
-        }
-    }
-
-    pub fn from(num: Vec<u8>, sign: bool) -> BigNum {
+    pub fn from_digits(num: Vec<u8>, sign: bool) -> BigNum {
         if BigNum::is_num_zero(&num) {
             return BigNum::zero();
         }
-        BigNum {
-            sign,
-            num: BigNum::remove_leading_zeros(num),
+        let num = BigNum::remove_leading_zeros(num);
+        let small = match (num.as_slice(), sign) {
+            (&[d], true) => Some(d as i8),
+            (&[1], false) => Some(-1),
+            (&[1, 0], true) => Some(10),
+            _ => None,
+        };
+        match small {
+            Some(n) => BigNum::interned(n),
+            None => BigNum {
+                sign,
+                num: Arc::new(num),
+            },
         }
     }
 
+    // Shared copy of a constant between INTERNED_MIN and INTERNED_MAX
+    fn interned(n: i8) -> BigNum {
+        INTERNED[(n - INTERNED_MIN) as usize].clone()
+    }
+
+    // Whether both numbers use the same digit storage
+    #[cfg(test)]
+    pub(crate) fn shares_digits(&self, other: &BigNum) -> bool {
+        Arc::ptr_eq(&self.num, &other.num)
+    }
+
     pub fn abs(&self) -> BigNum {
         BigNum {
             sign: true,
@@ -42,15 +94,32 @@ impl BigNum {
         self.sign = sign;
     }
 
-    fn remove_leading_zeros(num: Vec<u8>) -> Vec<u8> {
-        num.into_iter().skip_while(|n| *n == 0).collect()
+    fn remove_leading_zeros(mut num: Vec<u8>) -> Vec<u8> {
+        let zeros = num.iter().take_while(|&&n| n == 0).count();
+        num.drain(..zeros);
+        num
+    }
+
+    // Digits for in-place changes, copied first only if another BigNum
+    // (a clone or an interned constant) shares them
+    fn digits_mut(&mut self) -> &mut Vec<u8> {
+        Arc::make_mut(&mut self.num)
+    }
+
+    // Magnitude times ten plus `digit`, in place
+    fn push_digit(&mut self, digit: u8) {
+        if self.is_zero() {
+            *self = BigNum::from(digit);
+        } else {
+            self.digits_mut().push(digit);
+        }
     }
 
     pub fn zero() -> BigNum {
-        BigNum::new()
+        BigNum::interned(0)
     }
 
-    fn is_num_zero(num: &Vec<u8>) -> bool {
+    fn is_num_zero(num: &[u8]) -> bool {
         num.iter().all(|&n| n == 0)
     }
     pub fn is_zero(&self) -> bool {
@@ -58,20 +127,165 @@ impl BigNum {
     }
 
     pub fn is_negative(&self) -> bool {
-        self.sign == false
+        !self.sign
+    }
+
+    // Error when a result of at least `digits` digits would go over the
+    // `max_result_digits` of the limits in force, see `EvalLimits::run`
+    pub(crate) fn check_digits(digits: usize) -> Result<(), ArithError> {
+        match limits::max_result_digits() {
+            Some(limit) if digits > limit => Err(ArithError::Overflow(format!(
+                "{}: at least {} digits, the limit is {}",
+                TOO_LARGE, digits, limit
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// `self * other`, or an error if the product exceeds the digit limit,
+    /// refused up front when it is sure to
+    pub fn checked_mul(&self, other: &BigNum) -> Result<BigNum, ArithError> {
+        if !self.is_zero() && !other.is_zero() {
+            BigNum::check_digits(self.num.len() + other.num.len() - 1)?;
+        }
+        let product = self.clone() * other.clone();
+        BigNum::check_digits(product.num_digits())?;
+        Ok(product)
+    }
+
+    /// `self^exponent`, or an error if the result exceeds the digit limit,
+    /// refused up front when it is sure to
+    pub fn checked_pow(&self, exponent: u64) -> Result<BigNum, ArithError> {
+        if self.num.len() > 1 || self.num[0] > 1 {
+            let digits = (self.log10_floor_bound() * exponent as f64) as usize;
+            BigNum::check_digits(digits.saturating_add(1))?;
+        }
+        let power = self.pow(exponent);
+        BigNum::check_digits(power.num_digits())?;
+        Ok(power)
+    }
+
+    // Lower bound on log10 of the magnitude, from its leading 15 digits,
+    // shaded down past any rounding of the float arithmetic
+    pub(crate) fn log10_floor_bound(&self) -> f64 {
+        let lead = self.num.len().min(15);
+        let mantissa = self.num[..lead]
+            .iter()
+            .fold(0.0, |mantissa, &d| mantissa * 10.0 + d as f64);
+        (mantissa.log10() + (self.num.len() - lead) as f64) * (1.0 - 1e-12)
+    }
+
+    /// Decimal form with `separator` between groups of three digits, e.g.
+    /// `-1,234,567` for ","
+    pub fn to_grouped_string(&self, separator: &str) -> String {
+        let digits = self.grouped_digits(separator);
+        if self.is_negative() && !self.is_zero() {
+            format!("-{}", digits)
+        } else {
+            digits
+        }
+    }
+
+    // Magnitude digits without a sign
+    fn digit_string(&self) -> String {
+        self.num.iter().map(|&d| (b'0' + d) as char).collect()
+    }
+
+    fn grouped_digits(&self, separator: &str) -> String {
+        let mut grouped = String::with_capacity(self.num.len() * (1 + separator.len()));
+        for (i, &d) in self.num.iter().enumerate() {
+            if i > 0 && (self.num.len() - i).is_multiple_of(3) {
+                grouped.push_str(separator);
+            }
+            grouped.push((b'0' + d) as char);
+        }
+        grouped
+    }
+
+    /// Number of decimal digits of the magnitude, 1 for zero
+    pub fn num_digits(&self) -> usize {
+        self.num.len()
+    }
+
+    pub fn digit_sum(&self) -> BigNum {
+        BigNum::from(self.num.iter().map(|&d| d as u64).sum::<u64>())
+    }
+
+    /// Digit sum repeated down to a single digit, 0 only for zero
+    pub fn digital_root(&self) -> u8 {
+        let sum: u64 = self.num.iter().map(|&d| d as u64).sum();
+        match sum {
+            0 => 0,
+            sum => (1 + (sum - 1) % 9) as u8,
+        }
+    }
+
+    /// Digits in reverse order keeping the sign, trailing zeros are dropped
+    pub fn reverse_digits(&self) -> BigNum {
+        BigNum::from_digits(self.num.iter().rev().copied().collect(), self.sign)
+    }
+
+    /// Floor of log10, an error for zero and negative numbers
+    pub fn ilog10(&self) -> Result<usize, String> {
+        self.check_log_argument()?;
+        Ok(self.num.len() - 1)
+    }
+
+    /// Floor of log2, an error for zero and negative numbers
+    pub fn ilog2(&self) -> Result<usize, String> {
+        self.check_log_argument()?;
+        // Bits of the leading byte plus eight for every byte after it
+        let (_, bytes) = self.to_bytes_be();
+        Ok((bytes.len() - 1) * 8 + bytes[0].ilog2() as usize)
+    }
+
+    fn check_log_argument(&self) -> Result<(), String> {
+        if self.is_zero() || self.is_negative() {
+            return Err(format!("Logarithm of non-positive number {}", self));
+        }
+        Ok(())
+    }
+
+    /// -1, 0 or 1 following the sign
+    pub fn signum(&self) -> BigNum {
+        if self.is_zero() {
+            BigNum::zero()
+        } else if self.is_negative() {
+            -BigNum::one()
+        } else {
+            BigNum::one()
+        }
+    }
+
+    /// |self - other|
+    pub fn abs_diff(&self, other: &BigNum) -> BigNum {
+        if self >= other {
+            self.clone() - other.clone()
+        } else {
+            other.clone() - self.clone()
+        }
+    }
+
+    /// Parity only needs the last decimal digit
+    pub fn is_even(&self) -> bool {
+        self.num.last().is_none_or(|d| d % 2 == 0)
+    }
+
+    pub fn is_odd(&self) -> bool {
+        !self.is_even()
     }
 
     pub fn negate(&self) -> BigNum {
+        // Zero stays positive so that -0 == 0
         BigNum {
-            sign: !self.sign,
+            sign: !self.sign || self.is_zero(),
             num: self.num.clone(),
         }
     }
 
     pub fn gcd(&self, other: &BigNum) -> Result<BigNum, String> {
-        // GCD of 2 zeroes is undefined, so return an error
         if self.is_zero() && other.is_zero() {
-            return Err("GCD of 2 zeroes is undefined".to_string());
+            return Err(GCD_OF_ZEROES.to_string());
         }
         // GCD of a number and 0 is the number itself
         if self.is_zero() {
@@ -92,60 +306,674 @@ impl BigNum {
         Ok(a)
     }
 
-    fn one() -> BigNum {
-        BigNum::from(vec![1], true)
+    /// Least common multiple, never negative, 0 when either side is 0
+    pub fn lcm(&self, other: &BigNum) -> BigNum {
+        if self.is_zero() || other.is_zero() {
+            return BigNum::zero();
+        }
+        // Neither is zero, so the gcd exists
+        let gcd = self.gcd(other).unwrap();
+        (self.abs() / gcd) * other.abs()
     }
-}
 
-// Implementing Display for BigNum
-impl fmt::Display for BigNum {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if !self.sign && !self.is_zero() {
-            write!(f, "-")?;
+    /// Euclidean quotient and remainder, `self = q * divisor + r` with
+    /// `0 <= r < |divisor|`: -7 and 2 give (-4, 1), unlike `/` and `%`
+    pub fn div_rem(&self, divisor: &BigNum) -> Result<(BigNum, BigNum), ArithError> {
+        if divisor.is_zero() {
+            return Err(ArithError::DivisionByZero);
         }
-        for &n in &self.num {
-            write!(f, "{}", n)?;
+        let quotient = self.clone() / divisor.clone();
+        let remainder = self.clone() - quotient.clone() * divisor.clone();
+        if !remainder.is_negative() || remainder.is_zero() {
+            return Ok((quotient, remainder));
+        }
+        // One step further from zero brings the remainder up to positive
+        if divisor.is_negative() {
+            Ok((quotient + BigNum::one(), remainder - divisor.clone()))
+        } else {
+            Ok((quotient - BigNum::one(), remainder + divisor.clone()))
         }
-        Ok(())
     }
-}
 
-impl FromStr for BigNum {
-    type Err = String;
+    /// Largest integer whose square is at most `self`
+    pub fn isqrt(&self) -> Result<BigNum, String> {
+        if self.is_negative() {
+            return Err(format!("Square root of negative number {}", self));
+        }
+        if self.is_zero() {
+            return Ok(BigNum::zero());
+        }
+        // Newton's method from 10^ceil(digits / 2), which is above the root,
+        // decreases until it reaches it
+        let two = BigNum::from(2);
+        let mut x = BigNum::one().shift_left(self.num_digits().div_ceil(2));
+        loop {
+            let next = (x.clone() + self.clone() / x.clone()) / two.clone();
+            if next >= x {
+                return Ok(x);
+            }
+            x = next;
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut chars = s.chars().peekable();
-        let mut sign = true;
-        if let Some(&c) = chars.peek() {
-            if c == '-' {
-                sign = false;
-                chars.next();
-            } else if c == '+' {
-                chars.next();
+    /// Read a number from a stream in fixed-size chunks, so pasted or piped
+    /// numbers with millions of digits never need one huge string. Whitespace
+    /// between digits, such as line breaks in wrapped input, is skipped.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<BigNum, String> {
+        let mut buf = [0u8; 64 * 1024];
+        let mut sign = None;
+        let mut num = Vec::new();
+        loop {
+            let read = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            for part in buf[..read].split(u8::is_ascii_whitespace) {
+                let part = match (sign, part) {
+                    (_, []) => continue,
+                    (None, [b'-', rest @ ..]) => {
+                        sign = Some(false);
+                        rest
+                    }
+                    (None, [b'+', rest @ ..]) => {
+                        sign = Some(true);
+                        rest
+                    }
+                    (None, rest) => {
+                        sign = Some(true);
+                        rest
+                    }
+                    (Some(_), rest) => rest,
+                };
+                push_digits(part, &mut num)?;
             }
         }
+        if num.is_empty() {
+            return Err("Invalid number format".to_string());
+        }
+        Ok(BigNum::from_digits(num, sign.unwrap_or(true)))
+    }
+
+    /// Parse an optionally signed run of ASCII digits straight from bytes, so
+    /// input read as bytes needs no UTF-8 check or String first
+    pub fn from_ascii(bytes: &[u8]) -> Result<BigNum, String> {
+        let (sign, digits) = match bytes {
+            [b'-', rest @ ..] => (false, rest),
+            [b'+', rest @ ..] => (true, rest),
+            rest => (true, rest),
+        };
+        if digits.is_empty() {
+            return Err("Invalid number format".to_string());
+        }
+        let mut num = Vec::with_capacity(digits.len());
+        push_digits(digits, &mut num)?;
+        Ok(BigNum::from_digits(num, sign))
+    }
+
+    pub fn is_power_of_ten(&self) -> bool {
+        self.sign && self.ten_exponent().is_some()
+    }
+
+    pub fn is_power_of_two(&self) -> bool {
+        if self.is_negative() || self.is_zero() {
+            return false;
+        }
+        // Odd numbers past 1 are not, without computing anything
+        if self.is_odd() {
+            return *self == BigNum::one();
+        }
+        let power = BigNum::from(2).pow(self.log2_estimate().round() as u64);
+        power == *self
+    }
 
-        let digits: Vec<u8> = chars
+    /// Smallest power of two greater than or equal to self, 1 for anything
+    /// below 1
+    pub fn next_power_of_two(&self) -> BigNum {
+        if *self <= BigNum::one() {
+            return BigNum::one();
+        }
+        // From just below, the estimate being off by a fraction of a bit
+        let bits = (self.log2_estimate() - 1.0).max(0.0) as u64;
+        let mut power = BigNum::from(2).pow(bits);
+        while power < *self {
+            power = power.clone() + power;
+        }
+        power
+    }
+
+    // log2 of a positive number from its leading digits, within a small
+    // fraction of the exact value
+    fn log2_estimate(&self) -> f64 {
+        self.log10_floor_bound() / std::f64::consts::LOG10_2
+    }
+
+    // k when the magnitude is exactly 10^k
+    fn ten_exponent(&self) -> Option<usize> {
+        match self.num.split_first() {
+            Some((1, rest)) if BigNum::is_num_zero(rest) => Some(rest.len()),
+            _ => None,
+        }
+    }
+
+    // Magnitude times 10^places, just appending zeros
+    pub(crate) fn shift_left(&self, places: usize) -> BigNum {
+        let mut num = Vec::with_capacity(self.num.len() + places);
+        num.extend_from_slice(&self.num);
+        num.resize(self.num.len() + places, 0);
+        BigNum::from_digits(num, self.sign)
+    }
+
+    // Magnitude divided by 10^places, dropping the lowest digits
+    pub(crate) fn shift_right(&self, places: usize) -> BigNum {
+        let keep = self.num.len().saturating_sub(places);
+        BigNum::from_digits(self.num[..keep].to_vec(), self.sign)
+    }
+
+    // Magnitude split into the digits above and the `places` lowest digits
+    fn split_digits(&self, places: usize) -> (BigNum, BigNum) {
+        let at = self.num.len().saturating_sub(places);
+        (
+            BigNum::from_digits(self.num[..at].to_vec(), true),
+            BigNum::from_digits(self.num[at..].to_vec(), true),
+        )
+    }
+
+    // Magnitude divided by a small divisor in one pass over the digits
+    fn div_rem_small(&self, divisor: u32) -> (BigNum, u32) {
+        let mut remainder = 0;
+        let num = self
+            .num
+            .iter()
+            .map(|&d| {
+                let current = remainder * 10 + d as u32;
+                remainder = current % divisor;
+                (current / divisor) as u8
+            })
+            .collect();
+        (BigNum::from_digits(num, true), remainder)
+    }
+
+    /// Sign and big-endian bytes of the magnitude, zero is a single 0 byte
+    pub fn to_bytes_be(&self) -> (bool, Vec<u8>) {
+        let mut bytes = Vec::new();
+        let mut num = self.abs();
+        while !num.is_zero() {
+            let (quotient, remainder) = num.div_rem_small(256);
+            bytes.push(remainder as u8);
+            num = quotient;
+        }
+        if bytes.is_empty() {
+            bytes.push(0);
+        }
+        bytes.reverse();
+        (self.sign, bytes)
+    }
+
+    /// Inverse of `to_bytes_be`, an empty slice gives zero
+    pub fn from_bytes_be(sign: bool, bytes: &[u8]) -> BigNum {
+        BigNum::from_radix_digits(sign, bytes.iter().map(|&byte| byte as u32), 256)
+    }
+
+    /// Parse `s` written in `radix` (2 to 36, letters in either case) with
+    /// an optional leading `-`, as in `from_str_radix("ff", 16)` for 255
+    pub fn from_str_radix(s: &str, radix: u32) -> Result<BigNum, String> {
+        if !(2..=36).contains(&radix) {
+            return Err(format!("Radix must be between 2 and 36, got {}", radix));
+        }
+        let (sign, digits) = match s.strip_prefix('-') {
+            Some(rest) => (false, rest),
+            None => (true, s),
+        };
+        if digits.is_empty() {
+            return Err(format!("Empty base {} number", radix));
+        }
+        let values = digits
+            .chars()
             .map(|c| {
-                c.to_digit(10)
-                    .ok_or_else(|| format!("Invalid character: {}", c))
+                c.to_digit(radix)
+                    .ok_or_else(|| format!("Invalid base {} digit: {}", radix, c))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        // Past leading zeroes, each digit in the radix adds at least
+        // log10(radix) decimal digits
+        let significant = values.iter().skip_while(|&&value| value == 0).count();
+        let log10 = (radix as f64).log10() * (1.0 - 1e-12);
+        BigNum::check_digits((significant.saturating_sub(1) as f64 * log10) as usize + 1)?;
+        let num = BigNum::from_radix_digits(sign, values.into_iter(), radix);
+        BigNum::check_digits(num.num_digits())?;
+        Ok(num)
+    }
+
+    // Most significant digit first, each below `radix`
+    fn from_radix_digits(sign: bool, values: impl Iterator<Item = u32>, radix: u32) -> BigNum {
+        // Least significant digit first while accumulating
+        let mut digits: Vec<u8> = Vec::new();
+        for value in values {
+            let mut carry = value;
+            for digit in digits.iter_mut() {
+                let current = *digit as u32 * radix + carry;
+                *digit = (current % 10) as u8;
+                carry = current / 10;
+            }
+            while carry > 0 {
+                digits.push((carry % 10) as u8);
+                carry /= 10;
+            }
+        }
+        digits.reverse();
+        BigNum::from_digits(digits, sign)
+    }
+
+    /// Balanced ternary with digits `1`, `0` and `T` for -1, e.g. `1T0` for 6.
+    /// Negative numbers need no sign, only their digits flipped.
+    pub fn to_balanced_ternary(&self) -> String {
+        if self.is_zero() {
+            return "0".to_string();
+        }
+        let mut trits = Vec::new();
+        let mut num = self.abs();
+        while !num.is_zero() {
+            let (quotient, remainder) = num.div_rem_small(3);
+            num = quotient;
+            // 2 is written as 3 - 1, carrying into the next trit
+            trits.push(match remainder {
+                0 => 0,
+                1 => 1,
+                _ => {
+                    num += BigNum::one();
+                    -1
+                }
+            });
+        }
+        trits
+            .iter()
+            .rev()
+            .map(|&trit| match if self.sign { trit } else { -trit } {
+                1 => '1',
+                0 => '0',
+                _ => 'T',
             })
-            .collect::<Result<Vec<u32>, _>>()?
+            .collect()
+    }
+
+    /// Inverse of `to_balanced_ternary`
+    pub fn from_balanced_ternary(s: &str) -> Result<BigNum, String> {
+        if s.is_empty() {
+            return Err("Empty balanced ternary number".to_string());
+        }
+        let three = BigNum::from(3);
+        let mut num = BigNum::zero();
+        for c in s.chars() {
+            let trit = match c {
+                '1' => BigNum::one(),
+                '0' => BigNum::zero(),
+                'T' => -BigNum::one(),
+                _ => return Err(format!("Invalid balanced ternary digit: {}", c)),
+            };
+            num = num * three.clone() + trit;
+        }
+        Ok(num)
+    }
+
+    /// Binary coded decimal, four bits per digit, e.g. `-0001 0010` for -12
+    pub fn to_bcd(&self) -> String {
+        let nibbles: Vec<String> = self.num.iter().map(|d| format!("{:04b}", d)).collect();
+        let sign = if self.sign { "" } else { "-" };
+        format!("{}{}", sign, nibbles.join(" "))
+    }
+
+    /// Inverse of `to_bcd`. Spaces between the groups of four bits are
+    /// optional.
+    pub fn from_bcd(s: &str) -> Result<BigNum, String> {
+        let (sign, bits) = match s.strip_prefix('-') {
+            Some(rest) => (false, rest),
+            None => (true, s),
+        };
+        let bits: Vec<u8> = bits.bytes().filter(|b| *b != b' ').collect();
+        if bits.is_empty() || !bits.len().is_multiple_of(4) {
+            return Err(format!("BCD needs groups of four bits, got {}", s));
+        }
+        let mut digits = Vec::with_capacity(bits.len() / 4);
+        for nibble in bits.chunks(4) {
+            let mut digit = 0;
+            for &bit in nibble {
+                match bit {
+                    b'0' | b'1' => digit = digit * 2 + (bit - b'0'),
+                    _ => return Err(format!("Invalid bit: {}", bit as char)),
+                }
+            }
+            if digit > 9 {
+                return Err(format!("{:04b} is not a decimal digit", digit));
+            }
+            digits.push(digit);
+        }
+        Ok(BigNum::from_digits(digits, sign))
+    }
+
+    /// Reflected binary Gray code `n ^ (n >> 1)`, so consecutive numbers
+    /// differ in a single bit
+    pub fn to_gray(&self) -> Result<BigNum, String> {
+        let (_, bytes) = self.non_negative_bytes("Gray code")?;
+        let mut shifted_in = 0;
+        let gray: Vec<u8> = bytes
+            .iter()
+            .map(|&byte| {
+                let gray = byte ^ (byte >> 1 | shifted_in);
+                shifted_in = byte << 7;
+                gray
+            })
+            .collect();
+        Ok(BigNum::from_bytes_be(true, &gray))
+    }
+
+    /// Inverse of `to_gray`, each bit the XOR of all the Gray code bits from
+    /// the top down to it
+    pub fn from_gray(&self) -> Result<BigNum, String> {
+        let (_, bytes) = self.non_negative_bytes("Gray code")?;
+        let mut parity = 0;
+        let binary: Vec<u8> = bytes
+            .iter()
+            .map(|&byte| {
+                let mut binary = 0;
+                for bit in (0..8).rev() {
+                    parity ^= (byte >> bit) & 1;
+                    binary |= parity << bit;
+                }
+                binary
+            })
+            .collect();
+        Ok(BigNum::from_bytes_be(true, &binary))
+    }
+
+    // Two's complement of `self` in `len` bytes, least significant first.
+    // `len` must leave room for the sign bit.
+    fn twos_complement(&self, len: usize) -> Vec<u8> {
+        let (_, bytes) = self.to_bytes_be();
+        let mut bytes: Vec<u8> = bytes.into_iter().rev().collect();
+        bytes.resize(len, 0);
+        if self.is_negative() {
+            negate_bytes(&mut bytes);
+        }
+        bytes
+    }
+
+    // Inverse of `twos_complement`, the top bit giving the sign
+    fn from_twos_complement(mut bytes: Vec<u8>) -> BigNum {
+        let negative = bytes.last().is_some_and(|byte| byte & 0x80 != 0);
+        if negative {
+            negate_bytes(&mut bytes);
+        }
+        bytes.reverse();
+        BigNum::from_bytes_be(!negative, &bytes)
+    }
+
+    // Apply `op` to each pair of bytes of the two's complements, which
+    // behave as if sign extended forever like Python's integers
+    fn bitwise(&self, other: &BigNum, op: impl Fn(u8, u8) -> u8) -> BigNum {
+        let len = self.to_bytes_be().1.len().max(other.to_bytes_be().1.len()) + 1;
+        let bytes = self
+            .twos_complement(len)
             .into_iter()
-            .map(|d| d as u8)
+            .zip(other.twos_complement(len))
+            .map(|(a, b)| op(a, b))
             .collect();
+        BigNum::from_twos_complement(bytes)
+    }
 
-        if digits.is_empty() {
-            return Err("Invalid number format".to_string());
+    // Bytes of a number `what` is only defined on when non-negative
+    fn non_negative_bytes(&self, what: &str) -> Result<(bool, Vec<u8>), String> {
+        if self.is_negative() {
+            return Err(format!(
+                "{} is only defined for non-negative integers, got {}",
+                what, self
+            ));
+        }
+        Ok(self.to_bytes_be())
+    }
+
+    /// self * self, computing each cross product of digits only once, or
+    /// with Karatsuba for large numbers
+    pub fn square(&self) -> BigNum {
+        stats::record(Op::Mul, self.num.len());
+        if self.num.len() >= KARATSUBA_THRESHOLD {
+            let magnitude = self.abs();
+            return karatsuba(&magnitude, &magnitude, PARALLEL_THRESHOLD);
+        }
+        // Least significant digit first
+        let digits: Vec<u64> = self.num.iter().rev().map(|&d| d as u64).collect();
+        let mut columns = vec![0u64; 2 * digits.len()];
+        for (i, &a) in digits.iter().enumerate() {
+            columns[2 * i] += a * a;
+            for (j, &b) in digits.iter().enumerate().skip(i + 1) {
+                columns[i + j] += 2 * a * b;
+            }
+        }
+        let mut result = Vec::with_capacity(columns.len() + 1);
+        let mut carry = 0;
+        for column in columns {
+            let total = column + carry;
+            result.push((total % 10) as u8);
+            carry = total / 10;
+        }
+        while carry > 0 {
+            result.push((carry % 10) as u8);
+            carry /= 10;
+        }
+        result.reverse();
+        BigNum::from_digits(result, true)
+    }
+
+    /// self raised to `exponent` by repeated squaring, 0^0 is 1
+    pub fn pow(&self, exponent: u64) -> BigNum {
+        let mut result = BigNum::one();
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result *= base.clone();
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.square();
+            }
+        }
+        result
+    }
+
+    /// `self!`, refused up front when the result is sure to exceed the digit
+    /// limit or 50000 digits
+    pub fn factorial(&self) -> Result<BigNum, ArithError> {
+        if self.is_negative() && !self.is_zero() {
+            return Err(format!("Factorial of negative number {}", self).into());
+        }
+        // Past u64 it is past any limit too
+        let n = u64::try_from(self).unwrap_or(u64::MAX);
+        let digits = factorial_digits(n);
+        if digits > FACTORIAL_MAX_DIGITS {
+            return Err(ArithError::Overflow(format!(
+                "{}: at least {} digits, factorials are limited to {}",
+                TOO_LARGE, digits, FACTORIAL_MAX_DIGITS
+            )));
+        }
+        BigNum::check_digits(digits)?;
+        let product: BigNum = (2..=n).map(BigNum::from).product();
+        BigNum::check_digits(product.num_digits())?;
+        Ok(product)
+    }
+
+    /// Nearest f64, infinite when the number is out of range
+    pub fn to_f64(&self) -> f64 {
+        // Float parsing of an exact decimal is correctly rounded
+        self.to_string().parse().unwrap()
+    }
+
+    pub fn one() -> BigNum {
+        BigNum::from(1u8)
+    }
+
+    fn from_u128(mut magnitude: u128, sign: bool) -> BigNum {
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push((magnitude % 10) as u8);
+            magnitude /= 10;
+        }
+        digits.reverse();
+        BigNum::from_digits(digits, sign)
+    }
+
+    // Magnitude as a u128, or None if it does not fit
+    fn to_u128_magnitude(&self) -> Option<u128> {
+        self.num
+            .iter()
+            .try_fold(0u128, |acc, &d| acc.checked_mul(10)?.checked_add(d as u128))
+    }
+}
+
+macro_rules! impl_from_unsigned {
+    ($($t:ty),*) => {$(
+        impl From<$t> for BigNum {
+            fn from(n: $t) -> BigNum {
+                BigNum::from_u128(n as u128, true)
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_from_signed {
+    ($($t:ty),*) => {$(
+        impl From<$t> for BigNum {
+            fn from(n: $t) -> BigNum {
+                BigNum::from_u128(n.unsigned_abs() as u128, n >= 0)
+            }
+        }
+    )*};
+}
+
+impl_from_unsigned!(u8, u16, u32, u64, u128, usize);
+impl_from_signed!(i8, i16, i32, i64, i128, isize);
+
+impl TryFrom<&BigNum> for u128 {
+    type Error = String;
+
+    fn try_from(num: &BigNum) -> Result<Self, Self::Error> {
+        if num.is_negative() && !num.is_zero() {
+            return Err("Negative number cannot be converted to an unsigned integer".to_string());
         }
+        num.to_u128_magnitude()
+            .ok_or_else(|| "Number too large for u128".to_string())
+    }
+}
+
+impl TryFrom<&BigNum> for i128 {
+    type Error = String;
+
+    fn try_from(num: &BigNum) -> Result<Self, Self::Error> {
+        let overflow = || "Number out of range for i128".to_string();
+        let magnitude = num.to_u128_magnitude().ok_or_else(overflow)?;
+        if !num.is_negative() {
+            i128::try_from(magnitude).map_err(|_| overflow())
+        } else if magnitude == i128::MIN.unsigned_abs() {
+            Ok(i128::MIN)
+        } else {
+            i128::try_from(magnitude)
+                .map(|n| -n)
+                .map_err(|_| overflow())
+        }
+    }
+}
+
+// Lower bound on the digits of n!, from Stirling's formula, which is low by
+// less than one part in 12n, so one digit short at most
+fn factorial_digits(n: u64) -> usize {
+    if n < 2 {
+        return 1;
+    }
+    let n = n as f64;
+    let log =
+        n * (n / std::f64::consts::E).log10() + (2.0 * std::f64::consts::PI * n).log10() / 2.0;
+    // Saturating past usize
+    (log as usize).saturating_add(1)
+}
+
+impl TryFrom<&BigNum> for u64 {
+    type Error = String;
+
+    fn try_from(num: &BigNum) -> Result<Self, Self::Error> {
+        u64::try_from(u128::try_from(num)?).map_err(|_| "Number too large for u64".to_string())
+    }
+}
+
+impl TryFrom<&BigNum> for i64 {
+    type Error = String;
+
+    fn try_from(num: &BigNum) -> Result<Self, Self::Error> {
+        let out_of_range = || "Number out of range for i64".to_string();
+        let n = i128::try_from(num).map_err(|_| out_of_range())?;
+        i64::try_from(n).map_err(|_| out_of_range())
+    }
+}
 
-        Ok(BigNum { sign, num: digits })
+// Implementing Display for BigNum
+// Honors width, fill, alignment, `+` and `0`; `{:#}` groups thousands with
+// commas
+impl fmt::Display for BigNum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = if f.alternate() {
+            self.grouped_digits(",")
+        } else {
+            self.digit_string()
+        };
+        f.pad_integral(self.sign || self.is_zero(), "", &digits)
+    }
+}
+
+impl FromStr for BigNum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BigNum::from_ascii(s.as_bytes())
+    }
+}
+
+const ASCII_ZEROS: u64 = 0x3030_3030_3030_3030;
+const HIGH_NIBBLES: u64 = 0xF0F0_F0F0_F0F0_F0F0;
+
+// Append the values of ASCII digits to `out`, checking and converting eight
+// bytes at a time so huge literals are not handled char by char
+fn push_digits(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let word = u64::from_be_bytes(chunk.try_into().unwrap());
+        // Every byte must be 0x30..=0x39: high nibble 3, and still 3 after adding 6
+        let all_digits = word & HIGH_NIBBLES == ASCII_ZEROS
+            && word.wrapping_add(0x0606_0606_0606_0606) & HIGH_NIBBLES == ASCII_ZEROS;
+        if !all_digits {
+            return push_digits_slow(chunk, out);
+        }
+        out.extend_from_slice(&(word - ASCII_ZEROS).to_be_bytes());
+    }
+    push_digits_slow(chunks.remainder(), out)
+}
+
+fn push_digits_slow(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+    for &b in bytes {
+        if !b.is_ascii_digit() {
+            return Err(if b.is_ascii() {
+                format!("Invalid character: {}", b as char)
+            } else {
+                format!("Invalid byte: {:#04x}", b)
+            });
+        }
+        out.push(b - b'0');
     }
+    Ok(())
 }
 
 impl IntoFrac for BigNum {
     fn to_frac(self) -> Frac {
-        Frac::new(self, BigNum::one())
+        Frac::from_bignum(self)
     }
 }
 
@@ -161,17 +989,14 @@ impl Add for BigNum {
     type Output = BigNum;
 
     fn add(self: BigNum, other: BigNum) -> BigNum {
-        let mut num1 = self.num.clone();
-        let mut num2 = other.num.clone();
-
-        let max_len = num1.len().max(num2.len());
-        while num1.len() < max_len {
-            num1.insert(0, 0);
-        }
-        while num2.len() < max_len {
-            num2.insert(0, 0);
-        }
-        if self.sign == other.sign {
+        stats::record(Op::Add, self.num.len().max(other.num.len()));
+        // Both operands padded with leading zeros to the same length
+        let max_len = self.num.len().max(other.num.len());
+        let mut num1 = pool::take(max_len);
+        let mut num2 = pool::take(max_len);
+        num1[max_len - self.num.len()..].copy_from_slice(&self.num);
+        num2[max_len - other.num.len()..].copy_from_slice(&other.num);
+        let result = if self.sign == other.sign {
             let mut result = vec![0; max_len + 1];
             let mut carry = 0;
 
@@ -182,19 +1007,12 @@ impl Add for BigNum {
             }
             result[0] = carry;
 
-            while result.len() > 1 && result[0] == 0 {
-                result.remove(0);
-            }
-
-            return BigNum {
-                sign: self.sign,
-                num: result,
-            };
+            BigNum::from_digits(result, self.sign)
         } else {
             let (larger, smaller, result_sign) = if num1 > num2 {
-                (num1, num2, self.sign)
+                (&num1, &num2, self.sign)
             } else {
-                (num2, num1, other.sign)
+                (&num2, &num1, other.sign)
             };
 
             let mut result = vec![0; max_len];
@@ -211,15 +1029,11 @@ impl Add for BigNum {
                 result[i] = diff as u8;
             }
 
-            while result.len() > 1 && result[0] == 0 {
-                result.remove(0);
-            }
-
-            return BigNum {
-                sign: result_sign,
-                num: result,
-            };
-        }
+            BigNum::from_digits(result, result_sign)
+        };
+        pool::give(num1);
+        pool::give(num2);
+        result
     }
 }
 
@@ -237,51 +1051,91 @@ impl AddAssign for BigNum {
     }
 }
 
+impl Sum for BigNum {
+    fn sum<I: Iterator<Item = BigNum>>(iter: I) -> BigNum {
+        iter.fold(BigNum::zero(), |acc, n| acc + n)
+    }
+}
+
+impl<'a> Sum<&'a BigNum> for BigNum {
+    fn sum<I: Iterator<Item = &'a BigNum>>(iter: I) -> BigNum {
+        iter.cloned().sum()
+    }
+}
+
 impl PartialEq for BigNum {
     fn eq(&self, other: &BigNum) -> bool {
         self.sign == other.sign && self.num == other.num
     }
 }
 
+impl Eq for BigNum {}
+
 impl PartialOrd for BigNum {
     fn partial_cmp(&self, other: &BigNum) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq<Frac> for BigNum {
+    fn eq(&self, other: &Frac) -> bool {
+        other == self
+    }
+}
+
+impl PartialOrd<Frac> for BigNum {
+    fn partial_cmp(&self, other: &Frac) -> Option<Ordering> {
+        other.partial_cmp(self).map(Ordering::reverse)
+    }
+}
+
+// Zero is always stored positive, so equal numbers hash the same
+impl Hash for BigNum {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sign.hash(state);
+        self.num.hash(state);
+    }
+}
+
+impl Ord for BigNum {
+    fn cmp(&self, other: &BigNum) -> Ordering {
         if self.sign && !other.sign {
-            return Some(Ordering::Greater);
+            return Ordering::Greater;
         }
         if !self.sign && other.sign {
-            return Some(Ordering::Less);
+            return Ordering::Less;
         }
         if self.num.len() > other.num.len() {
-            return Some(if self.sign {
+            return if self.sign {
                 Ordering::Greater
             } else {
                 Ordering::Less
-            });
+            };
         }
         if self.num.len() < other.num.len() {
-            return Some(if self.sign {
+            return if self.sign {
                 Ordering::Less
             } else {
                 Ordering::Greater
-            });
+            };
         }
         for (&n1, &n2) in self.num.iter().zip(other.num.iter()) {
             if n1 > n2 {
-                return Some(if self.sign {
+                return if self.sign {
                     Ordering::Greater
                 } else {
                     Ordering::Less
-                });
+                };
             }
             if n1 < n2 {
-                return Some(if self.sign {
+                return if self.sign {
                     Ordering::Less
                 } else {
                     Ordering::Greater
-                });
+                };
             }
         }
-        Some(Ordering::Equal)
+        Ordering::Equal
     }
 }
 
@@ -311,20 +1165,22 @@ impl Mul for BigNum {
     type Output = BigNum;
 
     fn mul(self: BigNum, other: BigNum) -> BigNum {
-        let mut result = BigNum::zero();
-        for (i, &n) in other.num.iter().rev().enumerate() {
-            let mut temp = vec![0; i];
-            let mut carry = 0;
-            for &m in self.num.iter().rev() {
-                let product = n * m + carry;
-                temp.insert(0, product % 10);
-                carry = product / 10;
-            }
-            if carry > 0 {
-                temp.insert(0, carry);
-            }
-            result += BigNum::from(temp, true);
+        stats::record(Op::Mul, self.num.len().max(other.num.len()));
+        // Multiplying by a power of ten only shifts the digits
+        if let Some(places) = other.ten_exponent() {
+            let result = self.shift_left(places);
+            return if other.sign { result } else { result.negate() };
+        }
+        if let Some(places) = self.ten_exponent() {
+            let result = other.shift_left(places);
+            return if self.sign { result } else { result.negate() };
         }
+        let (x, y) = (self.abs(), other.abs());
+        let result = if x.num.len().min(y.num.len()) >= KARATSUBA_THRESHOLD {
+            karatsuba(&x, &y, PARALLEL_THRESHOLD)
+        } else {
+            schoolbook(&x, &y)
+        };
         if self.sign != other.sign {
             result.negate()
         } else {
@@ -333,6 +1189,113 @@ impl Mul for BigNum {
     }
 }
 
+// Operands with at least this many digits on both sides use Karatsuba
+const KARATSUBA_THRESHOLD: usize = 48;
+// Karatsuba levels this large run their sub-products on the rayon pool
+const PARALLEL_THRESHOLD: usize = 100_000;
+
+// Schoolbook rows summed into one pooled accumulator, least significant digit
+// first
+fn schoolbook(x: &BigNum, y: &BigNum) -> BigNum {
+    let mut acc = pool::take(x.num.len() + y.num.len());
+    for (i, &n) in y.num.iter().rev().enumerate() {
+        let mut carry = 0;
+        for (j, &m) in x.num.iter().rev().enumerate() {
+            let current = acc[i + j] + n * m + carry;
+            acc[i + j] = current % 10;
+            carry = current / 10;
+        }
+        acc[i + x.num.len()] += carry;
+    }
+    let result = BigNum::from_digits(acc.iter().rev().copied().collect(), true);
+    pool::give(acc);
+    result
+}
+
+// x * y for non-negative operands from three half-size products:
+// (x1 h + x0)(y1 h + y0) = z2 h^2 + (z1 - z2 - z0) h + z0
+fn karatsuba(x: &BigNum, y: &BigNum, parallel_above: usize) -> BigNum {
+    let half = x.num.len().max(y.num.len()) / 2;
+    let (x1, x0) = x.split_digits(half);
+    let (y1, y0) = y.split_digits(half);
+    let xs = x1.clone() + x0.clone();
+    let ys = y1.clone() + y0.clone();
+    let product = |a: &BigNum, b: &BigNum| {
+        if a.num.len().min(b.num.len()) >= KARATSUBA_THRESHOLD {
+            karatsuba(a, b, parallel_above)
+        } else {
+            schoolbook(a, b)
+        }
+    };
+    let (z2, (z0, z1)) = join(
+        half * 2 >= parallel_above,
+        || product(&x1, &y1),
+        || product(&x0, &y0),
+        || product(&xs, &ys),
+    );
+    let middle = z1 - z2.clone() - z0.clone();
+    z2.shift_left(2 * half) + middle.shift_left(half) + z0
+}
+
+// The three run on the rayon pool when `parallel`, counted and limited as
+// on the calling thread, their operation counts added to its own
+#[cfg(feature = "rayon")]
+fn join<A: Send, B: Send, C: Send>(
+    parallel: bool,
+    a: impl FnOnce() -> A + Send,
+    b: impl FnOnce() -> B + Send,
+    c: impl FnOnce() -> C + Send,
+) -> (A, (B, C)) {
+    if !parallel {
+        return (a(), (b(), c()));
+    }
+    let (tracing, budget) = (stats::is_tracing(), limits::current());
+    let ((a, a_counts), ((b, b_counts), (c, c_counts))) = rayon::join(
+        || as_caller(tracing, budget, a),
+        || {
+            rayon::join(
+                || as_caller(tracing, budget, b),
+                || as_caller(tracing, budget, c),
+            )
+        },
+    );
+    for counts in [a_counts, b_counts, c_counts] {
+        stats::add_counts(counts);
+    }
+    (a, (b, c))
+}
+
+#[cfg(feature = "rayon")]
+fn as_caller<T>(
+    tracing: bool,
+    budget: Option<limits::Budget>,
+    work: impl FnOnce() -> T,
+) -> (T, stats::OpCounts) {
+    limits::within(budget, || stats::counted(tracing, work))
+}
+
+#[cfg(not(feature = "rayon"))]
+fn join<A, B, C>(
+    _parallel: bool,
+    a: impl FnOnce() -> A,
+    b: impl FnOnce() -> B,
+    c: impl FnOnce() -> C,
+) -> (A, (B, C)) {
+    (a(), (b(), c()))
+}
+
+impl Product for BigNum {
+    fn product<I: Iterator<Item = BigNum>>(iter: I) -> BigNum {
+        iter.fold(BigNum::one(), |acc, n| acc * n)
+    }
+}
+
+impl<'a> Product<&'a BigNum> for BigNum {
+    fn product<I: Iterator<Item = &'a BigNum>>(iter: I) -> BigNum {
+        iter.cloned().product()
+    }
+}
+
 impl Mul<Frac> for BigNum {
     type Output = Frac;
 
@@ -353,31 +1316,35 @@ impl Div for BigNum {
     // The quotient has the same sign as the dividend multiplied by the divisor
     #[inline]
     fn div(self, other: Self) -> Self::Output {
+        stats::record(Op::Div, self.num.len().max(other.num.len()));
         if other.is_zero() {
             panic!("Division by zero");
         }
         if self.is_zero() {
             return BigNum::zero();
         }
+        // Dividing by a power of ten only drops digits
+        if let Some(places) = other.ten_exponent() {
+            let result = self.shift_right(places);
+            return if other.sign { result } else { result.negate() };
+        }
         let self_sign: bool = self.sign;
         let other_sign = other.sign;
         let other = other.abs();
-        let self_abs = self.abs();
-        let mut result = BigNum::zero();
+        let mut quotient = Vec::with_capacity(self.num.len());
         let mut remainder = BigNum::zero();
-        for &n in &self_abs.num {
-            remainder = remainder * BigNum::from(vec![10], true) + BigNum::from(vec![n], true);
-            let mut count = BigNum::zero();
-            while &remainder >= &other {
+        for &n in self.num.iter() {
+            remainder.push_digit(n);
+            let mut count = 0;
+            while remainder >= other {
                 remainder -= other.clone();
-                count += BigNum::from(vec![1], true);
+                count += 1;
             }
-            result = result * BigNum::from(vec![10], true) + count;
+            quotient.push(count);
         }
+        let mut result = BigNum::from_digits(quotient, true);
         if self_sign != other_sign && !result.is_zero() {
             result.set_sign(false);
-        } else {
-            result.set_sign(true);
         }
         result
     }
@@ -404,14 +1371,58 @@ impl Rem for BigNum {
     fn rem(self: BigNum, other: BigNum) -> BigNum {
         let self_sign: bool = self.sign;
         let mut reminder = self.clone() - (self / other.clone()) * other;
-        reminder.set_sign(self_sign);
+        reminder.set_sign(self_sign || reminder.is_zero());
         reminder
     }
 }
 
-impl RemAssign for BigNum {
-    fn rem_assign(&mut self, other: BigNum) {
-        *self = self.clone() % other;
+impl RemAssign for BigNum {
+    fn rem_assign(&mut self, other: BigNum) {
+        *self = self.clone() % other;
+    }
+}
+
+// Bitwise operators act on the infinite two's complement, so `-1 & n` is
+// `n` and `!n` is `-n - 1`
+impl BitAnd for BigNum {
+    type Output = BigNum;
+
+    fn bitand(self, other: BigNum) -> BigNum {
+        self.bitwise(&other, |a, b| a & b)
+    }
+}
+
+impl BitOr for BigNum {
+    type Output = BigNum;
+
+    fn bitor(self, other: BigNum) -> BigNum {
+        self.bitwise(&other, |a, b| a | b)
+    }
+}
+
+impl BitXor for BigNum {
+    type Output = BigNum;
+
+    fn bitxor(self, other: BigNum) -> BigNum {
+        self.bitwise(&other, |a, b| a ^ b)
+    }
+}
+
+impl Not for BigNum {
+    type Output = BigNum;
+
+    fn not(self) -> BigNum {
+        -self - BigNum::one()
+    }
+}
+
+// Two's complement negation in place: invert and add one
+fn negate_bytes(bytes: &mut [u8]) {
+    let mut carry = true;
+    for byte in bytes.iter_mut() {
+        let (value, overflow) = (!*byte).overflowing_add(carry as u8);
+        *byte = value;
+        carry = overflow;
     }
 }
 
@@ -424,19 +1435,26 @@ mod tests {
 
         #[test]
         fn test_negate_positive() {
-            let num = BigNum::from(vec![1, 2, 3], true);
-            let expected = BigNum::from(vec![1, 2, 3], false);
+            let num = BigNum::from_digits(vec![1, 2, 3], true);
+            let expected = BigNum::from_digits(vec![1, 2, 3], false);
             assert_eq!(num.negate(), expected);
             assert_eq!(-num, expected);
         }
 
         #[test]
         fn test_negate_negative() {
-            let num = BigNum::from(vec![1, 2, 3], false);
-            let expected = BigNum::from(vec![1, 2, 3], true);
+            let num = BigNum::from_digits(vec![1, 2, 3], false);
+            let expected = BigNum::from_digits(vec![1, 2, 3], true);
             assert_eq!(num.negate(), expected);
             assert_eq!(-num, expected);
         }
+
+        #[test]
+        fn test_negate_zero() {
+            assert_eq!(-BigNum::zero(), BigNum::zero());
+            let num = BigNum::from_digits(vec![5], true);
+            assert_eq!(num.clone() - num, BigNum::zero());
+        }
     }
 
     mod test_add {
@@ -444,43 +1462,43 @@ mod tests {
 
         #[test]
         fn test_add_positive_positive() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![5, 7, 9], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![5, 7, 9], true);
             assert_eq!(num1 + num2, expected);
         }
 
         #[test]
         fn test_add_positive_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![3, 3, 3], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![3, 3, 3], false);
             assert_eq!(num1 + num2, expected);
 
-            let num3 = BigNum::from(vec![6, 7, 8], true);
-            let num4 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![2, 2, 2], true);
+            let num3 = BigNum::from_digits(vec![6, 7, 8], true);
+            let num4 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![2, 2, 2], true);
             assert_eq!(num3 + num4, expected);
         }
 
         #[test]
         fn test_add_negative_positive() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![3, 3, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![3, 3, 3], true);
             assert_eq!(num1 + num2, expected);
 
-            let num3 = BigNum::from(vec![6, 7, 8], false);
-            let num4 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![2, 2, 2], false);
+            let num3 = BigNum::from_digits(vec![6, 7, 8], false);
+            let num4 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![2, 2, 2], false);
             assert_eq!(num3 + num4, expected);
         }
 
         #[test]
         fn test_add_negative_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![5, 7, 9], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![5, 7, 9], false);
             assert_eq!(num1 + num2, expected);
         }
     }
@@ -490,48 +1508,48 @@ mod tests {
 
         #[test]
         fn test_add_assign_positive_positive() {
-            let mut num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![5, 7, 9], true);
+            let mut num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![5, 7, 9], true);
             num1 += num2;
             assert_eq!(num1, expected);
         }
 
         #[test]
         fn test_add_assign_positive_negative() {
-            let mut num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![3, 3, 3], false);
+            let mut num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![3, 3, 3], false);
             num1 += num2;
             assert_eq!(num1, expected);
 
-            let mut num3 = BigNum::from(vec![6, 7, 8], true);
-            let num4 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![2, 2, 2], true);
+            let mut num3 = BigNum::from_digits(vec![6, 7, 8], true);
+            let num4 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![2, 2, 2], true);
             num3 += num4;
             assert_eq!(num3, expected);
         }
 
         #[test]
         fn test_add_assign_negative_positive() {
-            let mut num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![3, 3, 3], true);
+            let mut num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![3, 3, 3], true);
             num1 += num2;
             assert_eq!(num1, expected);
 
-            let mut num3 = BigNum::from(vec![6, 7, 8], false);
-            let num4 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![2, 2, 2], false);
+            let mut num3 = BigNum::from_digits(vec![6, 7, 8], false);
+            let num4 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![2, 2, 2], false);
             num3 += num4;
             assert_eq!(num3, expected);
         }
 
         #[test]
         fn test_add_assign_negative_negative() {
-            let mut num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![5, 7, 9], false);
+            let mut num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![5, 7, 9], false);
             num1 += num2;
             assert_eq!(num1, expected);
         }
@@ -542,103 +1560,288 @@ mod tests {
 
         #[test]
         fn test_mul_positive_positive() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![5, 6, 0, 8, 8], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![5, 6, 0, 8, 8], true);
             assert_eq!(num1 * num2, expected);
         }
 
         #[test]
         fn test_mul_positive_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![5, 6, 0, 8, 8], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![5, 6, 0, 8, 8], false);
             assert_eq!(num1 * num2, expected);
 
-            let num3 = BigNum::from(vec![6, 7, 8], true);
-            let num4 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![3, 0, 9, 1, 6, 8], false);
+            let num3 = BigNum::from_digits(vec![6, 7, 8], true);
+            let num4 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![3, 0, 9, 1, 6, 8], false);
             assert_eq!(num3 * num4, expected);
         }
 
         #[test]
         fn test_mul_negative_positive() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![5, 6, 0, 8, 8], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![5, 6, 0, 8, 8], false);
             assert_eq!(num1 * num2, expected);
 
-            let num3 = BigNum::from(vec![6, 7, 8], false);
-            let num4 = BigNum::from(vec![4, 5, 6], true);
-            let expected = BigNum::from(vec![3, 0, 9, 1, 6, 8], false);
+            let num3 = BigNum::from_digits(vec![6, 7, 8], false);
+            let num4 = BigNum::from_digits(vec![4, 5, 6], true);
+            let expected = BigNum::from_digits(vec![3, 0, 9, 1, 6, 8], false);
             assert_eq!(num3 * num4, expected);
         }
 
         #[test]
         fn test_mul_negative_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5, 6], false);
-            let expected = BigNum::from(vec![5, 6, 0, 8, 8], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5, 6], false);
+            let expected = BigNum::from_digits(vec![5, 6, 0, 8, 8], true);
             assert_eq!(num1 * num2, expected);
         }
 
         #[test]
         fn test_mul_zero() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
             let num2 = BigNum::zero();
             let expected = BigNum::zero();
             assert_eq!(num1 * num2, expected);
         }
     }
 
+    mod test_karatsuba {
+        use super::*;
+        use crate::gen::Rng;
+
+        fn random(rng: &mut Rng, len: usize) -> BigNum {
+            let digits = (0..len).map(|_| rng.below(10) as u8).collect();
+            BigNum::from_digits(digits, true)
+        }
+
+        #[test]
+        fn test_matches_schoolbook() {
+            let mut rng = Rng::new(31);
+            for (lx, ly) in [(48, 48), (100, 61), (333, 200), (500, 2000), (257, 256)] {
+                let x = random(&mut rng, lx);
+                let y = random(&mut rng, ly);
+                assert_eq!(karatsuba(&x, &y, usize::MAX), schoolbook(&x, &y));
+                assert_eq!(x.clone() * y.clone(), schoolbook(&x, &y));
+            }
+        }
+
+        #[test]
+        fn test_parallel_split() {
+            // A low threshold takes the parallel path when rayon is enabled
+            let mut rng = Rng::new(37);
+            let x = random(&mut rng, 700);
+            let y = random(&mut rng, 650);
+            assert_eq!(karatsuba(&x, &y, 64), schoolbook(&x, &y));
+        }
+
+        #[test]
+        fn test_parallel_counts() {
+            // Operations on the rayon pool count toward the calling thread
+            let mut rng = Rng::new(41);
+            let x = random(&mut rng, 700);
+            let y = random(&mut rng, 650);
+            let counted = |parallel_above| {
+                stats::set_tracing(true);
+                stats::reset_op_counts();
+                karatsuba(&x, &y, parallel_above);
+                let ops = stats::stats().ops;
+                stats::set_tracing(false);
+                ops
+            };
+            let serial = counted(usize::MAX);
+            assert!(serial.adds > 0);
+            assert_eq!(counted(64), serial);
+        }
+
+        #[test]
+        fn test_square() {
+            let mut rng = Rng::new(43);
+            for len in [1, 47, 48, 300, 1001] {
+                let x = random(&mut rng, len);
+                assert_eq!(x.square(), schoolbook(&x, &x), "{}", len);
+                assert_eq!((-x.clone()).square(), schoolbook(&x, &x), "{}", len);
+            }
+            assert_eq!(
+                BigNum::from(7).pow(400),
+                schoolbook(&BigNum::from(7).pow(200), &BigNum::from(7).pow(200))
+            );
+        }
+
+        #[test]
+        fn test_signs_and_zeros() {
+            let x: BigNum = format!("-{}", "9".repeat(120)).parse().unwrap();
+            let y: BigNum = ("1".to_string() + &"0".repeat(59) + "1").parse().unwrap();
+            assert_eq!(
+                (x.clone() * y.clone()).to_string(),
+                format!("-{}", schoolbook(&x.abs(), &y))
+            );
+            assert_eq!(x.clone() * x.clone(), schoolbook(&x.abs(), &x.abs()));
+            assert!((x * BigNum::zero()).is_zero());
+        }
+    }
+
+    mod test_powers {
+        use super::*;
+
+        #[test]
+        fn test_is_power_of_ten() {
+            assert!(BigNum::from(1).is_power_of_ten());
+            assert!(BigNum::from(1000).is_power_of_ten());
+            assert!(!BigNum::from(1001).is_power_of_ten());
+            assert!(!BigNum::from(-100).is_power_of_ten());
+            assert!(!BigNum::zero().is_power_of_ten());
+        }
+
+        #[test]
+        fn test_is_power_of_two() {
+            assert!(BigNum::from(1).is_power_of_two());
+            assert!(BigNum::from(1024).is_power_of_two());
+            assert!(BigNum::from(2).pow(200).is_power_of_two());
+            assert!(!BigNum::from(96).is_power_of_two());
+            assert!(!BigNum::from(-8).is_power_of_two());
+            assert!(!BigNum::zero().is_power_of_two());
+        }
+
+        #[test]
+        fn test_next_power_of_two() {
+            assert_eq!(BigNum::from(0).next_power_of_two(), BigNum::from(1));
+            assert_eq!(BigNum::from(-5).next_power_of_two(), BigNum::from(1));
+            assert_eq!(BigNum::from(64).next_power_of_two(), BigNum::from(64));
+            assert_eq!(BigNum::from(65).next_power_of_two(), BigNum::from(128));
+            assert_eq!(BigNum::from(2).next_power_of_two(), BigNum::from(2));
+            assert_eq!(BigNum::from(3).next_power_of_two(), BigNum::from(4));
+            let power = BigNum::from(2).pow(5000);
+            assert_eq!(power.next_power_of_two(), power);
+            let above = power.clone() + BigNum::one();
+            assert_eq!(above.next_power_of_two(), power.clone() + power.clone());
+            let below = power.clone() - BigNum::one();
+            assert_eq!(below.next_power_of_two(), power);
+        }
+
+        #[test]
+        fn test_large_powers_of_two() {
+            // One power computed, rather than a halving for each bit
+            let power = BigNum::from(2).pow(20_000);
+            assert!(power.is_power_of_two());
+            assert!(!(power.clone() + BigNum::from(2)).is_power_of_two());
+            assert!(!(power.clone() - BigNum::from(2)).is_power_of_two());
+            assert!(!(power * BigNum::from(3)).is_power_of_two());
+        }
+
+        #[test]
+        fn test_power_of_ten_fast_paths() {
+            let num = BigNum::from(-1234);
+            assert_eq!(num.clone() * BigNum::from(100), BigNum::from(-123400));
+            assert_eq!(BigNum::from(-100) * num.clone(), BigNum::from(123400));
+            assert_eq!(num.clone() / BigNum::from(100), BigNum::from(-12));
+            assert_eq!(num.clone() / BigNum::from(-10000), BigNum::zero());
+            assert_eq!(num % BigNum::from(100), BigNum::from(-34));
+        }
+    }
+
+    mod test_square_pow {
+        use super::*;
+
+        #[test]
+        fn test_square_matches_mul() {
+            for s in ["0", "7", "-12", "99999", "123456789123456789", "-1000000"] {
+                let num = BigNum::from_str(s).unwrap();
+                assert_eq!(num.square(), num.clone() * num);
+            }
+        }
+
+        #[test]
+        fn test_pow() {
+            assert_eq!(
+                BigNum::from(2).pow(100).to_string(),
+                "1267650600228229401496703205376"
+            );
+            assert_eq!(BigNum::from(-3).pow(3), BigNum::from(-27));
+            assert_eq!(BigNum::from(-3).pow(4), BigNum::from(81));
+            assert_eq!(BigNum::zero().pow(0), BigNum::one());
+            assert_eq!(BigNum::zero().pow(5), BigNum::zero());
+        }
+    }
+
+    mod test_sum_product {
+        use super::*;
+
+        #[test]
+        fn test_sum() {
+            let nums: Vec<BigNum> = (1..=100).map(BigNum::from).collect();
+            assert_eq!(nums.iter().sum::<BigNum>(), BigNum::from(5050));
+            assert_eq!(nums.into_iter().sum::<BigNum>(), BigNum::from(5050));
+            assert_eq!(
+                Vec::<BigNum>::new().into_iter().sum::<BigNum>(),
+                BigNum::zero()
+            );
+        }
+
+        #[test]
+        fn test_product() {
+            let nums: Vec<BigNum> = (1..=20).map(BigNum::from).collect();
+            let expected = BigNum::from(2432902008176640000u64);
+            assert_eq!(nums.iter().product::<BigNum>(), expected);
+            assert_eq!(nums.into_iter().product::<BigNum>(), expected);
+            assert_eq!(
+                Vec::<BigNum>::new().iter().product::<BigNum>(),
+                BigNum::one()
+            );
+        }
+    }
+
     mod test_eq_ord {
         use super::*;
 
         #[test]
         fn test_eq() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![1, 2, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![1, 2, 3], true);
             assert_eq!(num1, num2);
         }
 
         #[test]
         fn test_ne() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![1, 2, 4], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![1, 2, 4], true);
             assert_ne!(num1, num2);
         }
 
         #[test]
         fn test_lt() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![1, 2, 4], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![1, 2, 4], true);
             assert!(num1 < num2);
         }
 
         #[test]
         fn test_le() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![1, 2, 4], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![1, 2, 4], true);
             assert!(num1 <= num2);
-            let num3 = BigNum::from(vec![1, 2, 3], true);
-            let num4 = BigNum::from(vec![1, 2, 3], true);
+            let num3 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num4 = BigNum::from_digits(vec![1, 2, 3], true);
             assert!(num3 <= num4);
         }
 
         #[test]
         fn test_gt() {
-            let num1 = BigNum::from(vec![1, 2, 4], true);
-            let num2 = BigNum::from(vec![1, 2, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 4], true);
+            let num2 = BigNum::from_digits(vec![1, 2, 3], true);
             assert!(num1 > num2);
         }
 
         #[test]
         fn test_ge() {
-            let num1 = BigNum::from(vec![1, 2, 4], true);
-            let num2 = BigNum::from(vec![1, 2, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 4], true);
+            let num2 = BigNum::from_digits(vec![1, 2, 3], true);
             assert!(num1 >= num2);
-            let num3 = BigNum::from(vec![1, 2, 3], true);
-            let num4 = BigNum::from(vec![1, 2, 3], true);
+            let num3 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num4 = BigNum::from_digits(vec![1, 2, 3], true);
             assert!(num3 >= num4);
         }
     }
@@ -647,40 +1850,40 @@ mod tests {
         use super::*;
         #[test]
         fn test_div_positive_positive() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5], true);
-            let expected = BigNum::from(vec![2], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5], true);
+            let expected = BigNum::from_digits(vec![2], true);
             assert_eq!(num1 / num2, expected);
         }
 
         #[test]
         fn test_div_negative_positive() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5], true);
-            let expected = BigNum::from(vec![2], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5], true);
+            let expected = BigNum::from_digits(vec![2], false);
             assert_eq!(num1 / num2, expected);
         }
 
         #[test]
         fn test_div_positive_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5], false);
-            let expected = BigNum::from(vec![2], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5], false);
+            let expected = BigNum::from_digits(vec![2], false);
             assert_eq!(num1 / num2, expected);
         }
 
         #[test]
         fn test_div_negative_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5], false);
-            let expected = BigNum::from(vec![2], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5], false);
+            let expected = BigNum::from_digits(vec![2], true);
             assert_eq!(num1 / num2, expected);
         }
 
         #[test]
         #[should_panic]
         fn test_div_by_zero() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
             let num2 = BigNum::zero();
 
             let _ = num1 / num2;
@@ -691,84 +1894,680 @@ mod tests {
         use super::*;
         #[test]
         fn test_rem_positive_positive() {
-            let num1 = BigNum::from(vec![5], true);
-            let num2 = BigNum::from(vec![3], true);
-            let expected = BigNum::from(vec![2], true);
+            let num1 = BigNum::from_digits(vec![5], true);
+            let num2 = BigNum::from_digits(vec![3], true);
+            let expected = BigNum::from_digits(vec![2], true);
             assert_eq!(num1 % num2, expected);
         }
 
         #[test]
         fn test_rem_negative_positive() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5], true);
-            let expected = BigNum::from(vec![3, 3], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5], true);
+            let expected = BigNum::from_digits(vec![3, 3], false);
             assert_eq!(num1 % num2, expected);
         }
 
         #[test]
         fn test_rem_positive_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![4, 5], false);
-            let expected = BigNum::from(vec![3, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![4, 5], false);
+            let expected = BigNum::from_digits(vec![3, 3], true);
             assert_eq!(num1 % num2, expected);
         }
 
         #[test]
         fn test_rem_negative_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![4, 5], false);
-            let expected = BigNum::from(vec![3, 3], false);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![4, 5], false);
+            let expected = BigNum::from_digits(vec![3, 3], false);
             assert_eq!(num1 % num2, expected);
         }
 
         #[test]
         #[should_panic]
         fn test_rem_by_zero() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
             let num2 = BigNum::zero();
 
             let _ = num1 % num2;
         }
+
+        #[test]
+        fn test_div_rem() {
+            let div_rem = |a: i64, b: i64| {
+                let (q, r) = BigNum::from(a).div_rem(&BigNum::from(b)).unwrap();
+                (i64::try_from(&q).unwrap(), i64::try_from(&r).unwrap())
+            };
+            assert_eq!(div_rem(7, 2), (3, 1));
+            assert_eq!(div_rem(-7, 2), (-4, 1));
+            assert_eq!(div_rem(7, -2), (-3, 1));
+            assert_eq!(div_rem(-7, -2), (4, 1));
+            assert_eq!(div_rem(-6, 3), (-2, 0));
+            assert_eq!(div_rem(0, 5), (0, 0));
+            assert!(BigNum::from(1).div_rem(&BigNum::zero()).is_err());
+        }
+    }
+
+    mod test_from_str {
+        use super::*;
+
+        #[test]
+        fn test_parse_chunks() {
+            // Lengths around the eight-digit chunk size
+            for len in [1, 7, 8, 9, 16, 17] {
+                let digits: String = (0..len).map(|i| char::from(b'1' + (i % 9) as u8)).collect();
+                assert_eq!(digits.parse::<BigNum>().unwrap().to_string(), digits);
+            }
+            assert_eq!(
+                "-000000000123".parse::<BigNum>().unwrap(),
+                BigNum::from(-123)
+            );
+            assert_eq!("+42".parse::<BigNum>().unwrap(), BigNum::from(42));
+        }
+
+        #[test]
+        fn test_parse_invalid() {
+            for input in [
+                "",
+                "-",
+                "12345678:",
+                "1234567/9",
+                "12345a78",
+                &("9".repeat(20) + "x"),
+            ] {
+                assert!(input.parse::<BigNum>().is_err(), "accepted {}", input);
+            }
+        }
+
+        #[test]
+        fn test_parse_huge() {
+            let digits = "1234567890".repeat(100_000);
+            let num: BigNum = digits.parse().unwrap();
+            assert_eq!(num.to_string(), digits);
+        }
+
+        #[test]
+        fn test_from_reader() {
+            let input = "-1234\n5678 9\n";
+            assert_eq!(
+                BigNum::from_reader(input.as_bytes()).unwrap(),
+                BigNum::from(-123456789)
+            );
+            let digits = "9".repeat(200_000);
+            assert_eq!(
+                BigNum::from_reader(digits.as_bytes()).unwrap().to_string(),
+                digits
+            );
+            assert!(BigNum::from_reader(" \n".as_bytes()).is_err());
+            assert!(BigNum::from_reader("12 -3".as_bytes()).is_err());
+        }
+    }
+
+    mod test_digits {
+        use super::*;
+
+        #[test]
+        fn test_num_digits() {
+            assert_eq!(BigNum::zero().num_digits(), 1);
+            assert_eq!(BigNum::from(-12345).num_digits(), 5);
+        }
+
+        #[test]
+        fn test_digit_sum_root() {
+            assert_eq!(BigNum::from(-9875).digit_sum(), BigNum::from(29));
+            assert_eq!(BigNum::from(9875).digital_root(), 2);
+            assert_eq!(BigNum::from(999).digital_root(), 9);
+            assert_eq!(BigNum::zero().digital_root(), 0);
+        }
+
+        #[test]
+        fn test_reverse_digits() {
+            assert_eq!(BigNum::from(-1230).reverse_digits(), BigNum::from(-321));
+            assert_eq!(BigNum::from(7).reverse_digits(), BigNum::from(7));
+            assert_eq!(BigNum::zero().reverse_digits(), BigNum::zero());
+        }
+    }
+
+    mod test_factorial {
+        use super::*;
+
+        #[test]
+        fn test_factorial() {
+            assert_eq!(BigNum::zero().factorial(), Ok(BigNum::one()));
+            assert_eq!(BigNum::from(5).factorial(), Ok(BigNum::from(120)));
+            assert_eq!(
+                BigNum::from(25).factorial().unwrap().to_string(),
+                "15511210043330985984000000"
+            );
+            assert!(BigNum::from(-3).factorial().is_err());
+        }
+
+        #[test]
+        fn test_too_large() {
+            for n in [0, 1, 2, 10, 25, 100, 1000] {
+                let digits = BigNum::from(n).factorial().unwrap().num_digits();
+                let estimate = factorial_digits(n);
+                assert!((digits - 1..=digits).contains(&estimate), "{}", n);
+            }
+            for n in [BigNum::from(1_000_000), BigNum::from(10).pow(30)] {
+                let err = n.factorial().unwrap_err();
+                let too_large = matches!(&err, ArithError::Overflow(message)
+                    if message.starts_with("Result too large") && message.ends_with("limited to 50000"));
+                assert!(too_large, "{}", err);
+            }
+            let limits = crate::limits::EvalLimits {
+                max_result_digits: Some(100),
+                ..Default::default()
+            };
+            assert!(limits.run(|| BigNum::from(100).factorial()).is_err());
+            assert!(limits.run(|| BigNum::from(60).factorial()).is_ok());
+            // 7! = 5040, and 8! has a digit more
+            let limits = crate::limits::EvalLimits {
+                max_result_digits: Some(4),
+                ..Default::default()
+            };
+            assert!(limits.run(|| BigNum::from(7).factorial()).is_ok());
+            assert!(limits.run(|| BigNum::from(8).factorial()).is_err());
+        }
+    }
+
+    mod test_interned {
+        use super::*;
+
+        #[test]
+        fn test_constants_shared() {
+            assert!(BigNum::zero().shares_digits(&BigNum::zero()));
+            assert!(BigNum::one().shares_digits(&BigNum::from(1u64)));
+            assert!(BigNum::from(-1).shares_digits(&"-1".parse().unwrap()));
+            assert!(BigNum::from(10).shares_digits(&BigNum::from_digits(vec![0, 1, 0], true)));
+            assert!((BigNum::from(7) - BigNum::from(5)).shares_digits(&BigNum::from(2)));
+            assert!(!BigNum::from(11).shares_digits(&BigNum::from(11)));
+            assert!(!BigNum::from(-2).shares_digits(&BigNum::from(-2)));
+        }
+
+        #[test]
+        fn test_constants_values() {
+            for n in -1..=10 {
+                assert_eq!(BigNum::from(n).to_string(), n.to_string());
+            }
+        }
+    }
+
+    mod test_copy_on_write {
+        use super::*;
+
+        #[test]
+        fn test_clone_shares_digits() {
+            let num: BigNum = "123456789012345678901234567890".parse().unwrap();
+            let copy = num.clone();
+            assert!(copy.shares_digits(&num));
+            assert!(num.negate().shares_digits(&num));
+            assert!(num.abs().shares_digits(&num));
+        }
+
+        #[test]
+        fn test_mutation_copies_shared_digits() {
+            let num = BigNum::from(12345);
+            let mut copy = num.clone();
+            copy.push_digit(6);
+            assert_eq!(copy, BigNum::from(123456));
+            assert_eq!(num, BigNum::from(12345));
+            // Interned constants are never changed in place
+            let mut one = BigNum::one();
+            one.push_digit(7);
+            assert_eq!(one, BigNum::from(17));
+            assert_eq!(BigNum::one(), BigNum::from(1));
+            let mut zero = BigNum::zero();
+            zero.push_digit(0);
+            assert_eq!(zero, BigNum::zero());
+        }
+    }
+
+    mod test_display {
+        use super::*;
+
+        #[test]
+        fn test_flags() {
+            let n = BigNum::from(-1234);
+            assert_eq!(format!("{}", n), "-1234");
+            assert_eq!(format!("{:>8}", n), "   -1234");
+            assert_eq!(format!("{:*<8}", n), "-1234***");
+            assert_eq!(format!("{:^9}", BigNum::from(5)), "    5    ");
+            assert_eq!(format!("{:+}", BigNum::from(5)), "+5");
+            assert_eq!(format!("{:+}", BigNum::zero()), "+0");
+            assert_eq!(format!("{:08}", n), "-0001234");
+        }
+
+        #[test]
+        fn test_grouping() {
+            assert_eq!(format!("{:#}", BigNum::from(-1234567)), "-1,234,567");
+            assert_eq!(format!("{:#}", BigNum::from(123)), "123");
+            assert_eq!(format!("{:>+#12}", BigNum::from(123456)), "    +123,456");
+            assert_eq!(BigNum::from(1000).to_grouped_string(" "), "1 000");
+            assert_eq!(BigNum::from(-100000).to_grouped_string("_"), "-100_000");
+            assert_eq!(BigNum::zero().to_grouped_string(","), "0");
+        }
+    }
+
+    mod test_max_digits {
+        use super::*;
+        use crate::limits::EvalLimits;
+
+        #[test]
+        fn test_check_digits() {
+            let million = 1_000_000;
+            let limits = EvalLimits {
+                max_result_digits: Some(million),
+                ..EvalLimits::default()
+            };
+            limits.run(|| test_under_limit(million));
+            // No limit outside of them
+            assert!(BigNum::check_digits(2 * million).is_ok());
+        }
+
+        fn test_under_limit(million: usize) {
+            assert_eq!(BigNum::from(10).checked_pow(5), Ok(BigNum::from(100_000)));
+            assert!(BigNum::from(10).checked_pow(2 * million as u64).is_err());
+            assert!(BigNum::from(-1).checked_pow(1 << 40).is_ok());
+            let big = BigNum::one().shift_left(600_000);
+            assert!(big.checked_mul(&BigNum::from(2)).is_ok());
+            assert!(big.checked_mul(&big).is_err());
+            // Evaluation checks each operation against the same limit
+            let value = crate::common::Value::Number(big);
+            assert!((value.clone() + value.clone()).is_ok());
+            assert_eq!(
+                value.clone() * value,
+                Err(ArithError::Overflow(
+                    "Result too large: at least 1200001 digits, the limit is 1000000".to_string()
+                ))
+            );
+        }
+
+        #[test]
+        fn test_results_that_fit() {
+            let limits = EvalLimits {
+                max_result_digits: Some(5),
+                ..EvalLimits::default()
+            };
+            limits.run(|| {
+                let hundred = BigNum::from(100);
+                assert_eq!(hundred.checked_mul(&hundred), Ok(BigNum::from(10_000)));
+                assert!(hundred.checked_mul(&BigNum::from(1000)).is_err());
+                assert_eq!(BigNum::from(2).checked_pow(16), Ok(BigNum::from(65_536)));
+                assert!(BigNum::from(2).checked_pow(17).is_err());
+                assert_eq!(BigNum::from(10).checked_pow(4), Ok(BigNum::from(10_000)));
+                assert!(BigNum::from(10).checked_pow(5).is_err());
+                assert_eq!(BigNum::from_str_radix("ffff", 16), Ok(BigNum::from(65_535)));
+                assert_eq!(BigNum::from_str_radix("000ff", 16), Ok(BigNum::from(255)));
+                assert!(BigNum::from_str_radix("fffff", 16).is_err());
+            });
+        }
+    }
+
+    mod test_ilog {
+        use super::*;
+
+        #[test]
+        fn test_ilog10() {
+            assert_eq!(BigNum::from(1).ilog10(), Ok(0));
+            assert_eq!(BigNum::from(999).ilog10(), Ok(2));
+            assert_eq!(BigNum::from(1000).ilog10(), Ok(3));
+            assert!(BigNum::zero().ilog10().is_err());
+            assert!(BigNum::from(-10).ilog10().is_err());
+        }
+
+        #[test]
+        fn test_ilog2() {
+            assert_eq!(BigNum::from(1).ilog2(), Ok(0));
+            assert_eq!(BigNum::from(255).ilog2(), Ok(7));
+            assert_eq!(BigNum::from(256).ilog2(), Ok(8));
+            assert_eq!(BigNum::from(2).pow(300).ilog2(), Ok(300));
+            assert_eq!((BigNum::from(2).pow(300) - BigNum::one()).ilog2(), Ok(299));
+            assert!(BigNum::zero().ilog2().is_err());
+            assert!(BigNum::from(-4).ilog2().is_err());
+        }
+    }
+
+    mod test_sign_parity {
+        use super::*;
+
+        #[test]
+        fn test_signum() {
+            assert_eq!(BigNum::from(-42).signum(), BigNum::from(-1));
+            assert_eq!(BigNum::zero().signum(), BigNum::zero());
+            assert_eq!(BigNum::from(7).signum(), BigNum::one());
+        }
+
+        #[test]
+        fn test_abs_diff() {
+            assert_eq!(BigNum::from(3).abs_diff(&BigNum::from(10)), BigNum::from(7));
+            assert_eq!(BigNum::from(10).abs_diff(&BigNum::from(3)), BigNum::from(7));
+            assert_eq!(
+                BigNum::from(-5).abs_diff(&BigNum::from(5)),
+                BigNum::from(10)
+            );
+            assert_eq!(BigNum::from(-5).abs_diff(&BigNum::from(-5)), BigNum::zero());
+        }
+
+        #[test]
+        fn test_parity() {
+            assert!(BigNum::zero().is_even());
+            assert!(BigNum::from(-12).is_even());
+            assert!(BigNum::from(-7).is_odd());
+            assert!("12345678901234567890123"
+                .parse::<BigNum>()
+                .unwrap()
+                .is_odd());
+        }
+    }
+
+    mod test_from_ascii {
+        use super::*;
+
+        #[test]
+        fn test_from_ascii() {
+            assert_eq!(BigNum::from_ascii(b"-00123"), Ok(BigNum::from(-123)));
+            assert_eq!(BigNum::from_ascii(b"+7"), Ok(BigNum::from(7)));
+            let digits = b"123456789012345678901234567890";
+            assert_eq!(
+                BigNum::from_ascii(digits).unwrap().to_string().as_bytes(),
+                digits
+            );
+        }
+
+        #[test]
+        fn test_from_ascii_invalid() {
+            assert!(BigNum::from_ascii(b"").is_err());
+            assert!(BigNum::from_ascii(b"-").is_err());
+            assert_eq!(
+                BigNum::from_ascii(b"12345678x"),
+                Err("Invalid character: x".to_string())
+            );
+            assert_eq!(
+                BigNum::from_ascii(b"1234\xff5678"),
+                Err("Invalid byte: 0xff".to_string())
+            );
+        }
+    }
+
+    mod test_bytes {
+        use super::*;
+
+        #[test]
+        fn test_to_bytes_be() {
+            assert_eq!(BigNum::zero().to_bytes_be(), (true, vec![0]));
+            assert_eq!(BigNum::from(255).to_bytes_be(), (true, vec![255]));
+            assert_eq!(BigNum::from(-256).to_bytes_be(), (false, vec![1, 0]));
+            let num = BigNum::from(0x0102_0304_0506_0708_090au128);
+            assert_eq!(num.to_bytes_be().1, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        }
+
+        #[test]
+        fn test_from_bytes_be() {
+            assert_eq!(BigNum::from_bytes_be(true, &[]), BigNum::zero());
+            assert_eq!(BigNum::from_bytes_be(false, &[0, 0]), BigNum::zero());
+            assert!(!BigNum::from_bytes_be(false, &[0]).is_negative());
+            assert_eq!(BigNum::from_bytes_be(false, &[1, 0]), BigNum::from(-256));
+            assert_eq!(
+                BigNum::from_bytes_be(true, &u128::MAX.to_be_bytes()),
+                BigNum::from(u128::MAX)
+            );
+        }
+
+        #[test]
+        fn test_bytes_round_trip() {
+            let num: BigNum = "-98765432109876543210987654321098765432109876543210"
+                .parse()
+                .unwrap();
+            let (sign, bytes) = num.to_bytes_be();
+            assert_eq!(BigNum::from_bytes_be(sign, &bytes), num);
+        }
+    }
+
+    mod test_bitwise {
+        use super::*;
+
+        #[test]
+        fn test_matches_i64() {
+            let values: [i64; 9] = [0, 1, -1, 5, -5, 12, -128, 255, 1 << 40];
+            for a in values {
+                for b in values {
+                    let (x, y) = (BigNum::from(a), BigNum::from(b));
+                    assert_eq!(x.clone() & y.clone(), BigNum::from(a & b), "{} & {}", a, b);
+                    assert_eq!(x.clone() | y.clone(), BigNum::from(a | b), "{} | {}", a, b);
+                    assert_eq!(x ^ y, BigNum::from(a ^ b), "{} ^ {}", a, b);
+                }
+                assert_eq!(!BigNum::from(a), BigNum::from(!a), "!{}", a);
+            }
+        }
+
+        #[test]
+        fn test_large() {
+            let all_ones: BigNum = "340282366920938463463374607431768211455".parse().unwrap();
+            let high = all_ones.clone() ^ BigNum::from(u64::MAX);
+            assert_eq!(high.clone() & BigNum::from(u64::MAX), BigNum::zero());
+            assert_eq!(high | BigNum::from(u64::MAX), all_ones.clone());
+            assert_eq!(BigNum::from(-1) & all_ones.clone(), all_ones);
+        }
+    }
+
+    mod test_numeral_systems {
+        use super::*;
+
+        #[test]
+        fn test_from_str_radix() {
+            let cases = [
+                ("ff", 16, "255"),
+                ("FF", 16, "255"),
+                ("-17", 8, "-15"),
+                ("1010", 2, "10"),
+                ("0", 2, "0"),
+                ("zz", 36, "1295"),
+                (
+                    "ffffffffffffffffffffffffffffffff",
+                    16,
+                    "340282366920938463463374607431768211455",
+                ),
+            ];
+            for (s, radix, expected) in cases {
+                assert_eq!(
+                    BigNum::from_str_radix(s, radix).unwrap().to_string(),
+                    expected,
+                    "{} in base {}",
+                    s,
+                    radix
+                );
+            }
+            assert!(BigNum::from_str_radix("12", 2).is_err());
+            assert!(BigNum::from_str_radix("", 16).is_err());
+            assert!(BigNum::from_str_radix("-", 16).is_err());
+            assert!(BigNum::from_str_radix("1", 37).is_err());
+        }
+
+        #[test]
+        fn test_balanced_ternary() {
+            let cases = [
+                (0, "0"),
+                (1, "1"),
+                (2, "1T"),
+                (6, "1T0"),
+                (-6, "T10"),
+                (-8, "T01"),
+            ];
+            for (n, ternary) in cases {
+                assert_eq!(BigNum::from(n).to_balanced_ternary(), ternary);
+                assert_eq!(BigNum::from_balanced_ternary(ternary), Ok(BigNum::from(n)));
+            }
+            let big = BigNum::from_str("-123456789012345678901234567890").unwrap();
+            let round_trip = BigNum::from_balanced_ternary(&big.to_balanced_ternary());
+            assert_eq!(round_trip, Ok(big));
+            assert!(BigNum::from_balanced_ternary("").is_err());
+            assert!(BigNum::from_balanced_ternary("12").is_err());
+        }
+
+        #[test]
+        fn test_bcd() {
+            assert_eq!(BigNum::from(0).to_bcd(), "0000");
+            assert_eq!(BigNum::from(-12).to_bcd(), "-0001 0010");
+            assert_eq!(BigNum::from(905).to_bcd(), "1001 0000 0101");
+            assert_eq!(BigNum::from_bcd("-0001 0010"), Ok(BigNum::from(-12)));
+            assert_eq!(BigNum::from_bcd("100100000101"), Ok(BigNum::from(905)));
+            assert!(BigNum::from_bcd("1010").is_err());
+            assert!(BigNum::from_bcd("001").is_err());
+            assert!(BigNum::from_bcd("0012").is_err());
+        }
+
+        #[test]
+        fn test_gray() {
+            let cases = [
+                (0, 0),
+                (1, 1),
+                (2, 3),
+                (3, 2),
+                (4, 6),
+                (255, 128),
+                (256, 384),
+            ];
+            for (n, gray) in cases {
+                assert_eq!(BigNum::from(n).to_gray(), Ok(BigNum::from(gray)));
+                assert_eq!(BigNum::from(gray).from_gray(), Ok(BigNum::from(n)));
+            }
+            let big = BigNum::from_str("98765432109876543210987654321").unwrap();
+            assert_eq!(big.to_gray().unwrap().from_gray(), Ok(big));
+            assert!(BigNum::from(-1).to_gray().is_err());
+        }
+    }
+
+    mod test_conversions {
+        use super::*;
+
+        #[test]
+        fn test_from_primitive() {
+            assert_eq!(BigNum::from(0u8), BigNum::zero());
+            assert_eq!(
+                BigNum::from(123u32),
+                BigNum::from_digits(vec![1, 2, 3], true)
+            );
+            assert_eq!(
+                BigNum::from(-123i64),
+                BigNum::from_digits(vec![1, 2, 3], false)
+            );
+            assert_eq!(
+                BigNum::from(i128::MIN).to_string(),
+                "-170141183460469231731687303715884105728"
+            );
+            assert_eq!(
+                BigNum::from(u128::MAX).to_string(),
+                "340282366920938463463374607431768211455"
+            );
+        }
+
+        #[test]
+        fn test_try_into_primitive() {
+            let num = BigNum::from(-9876543210i64);
+            assert_eq!(i64::try_from(&num), Ok(-9876543210));
+            assert_eq!(i128::try_from(&num), Ok(-9876543210));
+            assert!(u64::try_from(&num).is_err());
+            assert!(u128::try_from(&num).is_err());
+            assert_eq!(u64::try_from(&BigNum::from(u64::MAX)), Ok(u64::MAX));
+            assert_eq!(i128::try_from(&BigNum::from(i128::MIN)), Ok(i128::MIN));
+        }
+
+        #[test]
+        fn test_try_into_overflow() {
+            assert!(i64::try_from(&BigNum::from(i64::MAX as i128 + 1)).is_err());
+            assert!(i64::try_from(&BigNum::from(i64::MIN as i128 - 1)).is_err());
+            assert!(u128::try_from(&(BigNum::from(u128::MAX) + BigNum::one())).is_err());
+            assert!(i128::try_from(&BigNum::from(u128::MAX)).is_err());
+        }
+    }
+
+    mod test_to_f64 {
+        use super::*;
+
+        #[test]
+        fn test_to_f64() {
+            assert_eq!(BigNum::from(123).to_f64(), 123.0);
+            assert_eq!(BigNum::from(-123).to_f64(), -123.0);
+            assert_eq!(BigNum::zero().to_f64(), 0.0);
+            // 2^53 + 1 rounds to the even neighbour
+            assert_eq!(
+                BigNum::from(9007199254740993u64).to_f64(),
+                9007199254740992.0
+            );
+        }
+
+        #[test]
+        fn test_to_f64_out_of_range() {
+            let huge = BigNum::from_str(&"9".repeat(400)).unwrap();
+            assert_eq!(huge.to_f64(), f64::INFINITY);
+            assert_eq!((-huge).to_f64(), f64::NEG_INFINITY);
+        }
     }
 
     mod test_gcd {
         use super::*;
 
+        #[test]
+        fn test_lcm() {
+            assert_eq!(BigNum::from(4).lcm(&BigNum::from(6)), BigNum::from(12));
+            assert_eq!(BigNum::from(-4).lcm(&BigNum::from(6)), BigNum::from(12));
+            assert_eq!(BigNum::from(7).lcm(&BigNum::from(5)), BigNum::from(35));
+            assert_eq!(BigNum::zero().lcm(&BigNum::from(5)), BigNum::zero());
+        }
+
+        #[test]
+        fn test_isqrt() {
+            for n in [
+                0u64, 1, 2, 3, 4, 15, 16, 17, 99, 100, 101, 999_999, 1_000_000,
+            ] {
+                let root = (n as f64).sqrt() as u64;
+                assert_eq!(BigNum::from(n).isqrt(), Ok(BigNum::from(root)), "{}", n);
+            }
+            let big: BigNum = "123456789012345678901234567890".parse().unwrap();
+            let root = (big.clone() * big.clone() + BigNum::from(5))
+                .isqrt()
+                .unwrap();
+            assert_eq!(root, big);
+            assert!(BigNum::from(-4).isqrt().is_err());
+        }
+
         #[test]
         fn test_gcd_normal() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
-            let num2 = BigNum::from(vec![6, 0], true);
-            let expected = BigNum::from(vec![3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], true);
+            let num2 = BigNum::from_digits(vec![6, 0], true);
+            let expected = BigNum::from_digits(vec![3], true);
             assert_eq!(num1.gcd(&num2).unwrap(), expected);
         }
 
         #[test]
         #[should_panic]
         fn test_gcd_zero() {
-            let num1 = BigNum::from(vec![1, 2, 3], true);
+            let num1 = BigNum::zero();
             let num2 = BigNum::zero();
 
-            let _ = num1.gcd(&num2);
+            let _ = num1.gcd(&num2).unwrap();
         }
 
         #[test]
         fn test_gcd_negative() {
-            let num1 = BigNum::from(vec![1, 2, 3], false);
-            let num2 = BigNum::from(vec![6, 0], true);
-            let expected = BigNum::from(vec![3], true);
+            let num1 = BigNum::from_digits(vec![1, 2, 3], false);
+            let num2 = BigNum::from_digits(vec![6, 0], true);
+            let expected = BigNum::from_digits(vec![3], true);
             assert_eq!(num1.gcd(&num2).unwrap(), expected);
         }
 
         #[test]
         fn test_gcd_coprime() {
-            let num1 = BigNum::from(vec![1, 0], true);
-            let num2 = BigNum::from(vec![3], true);
-            let expected = BigNum::from(vec![1], true);
+            let num1 = BigNum::from_digits(vec![1, 0], true);
+            let num2 = BigNum::from_digits(vec![3], true);
+            let expected = BigNum::from_digits(vec![1], true);
             assert_eq!(num1.gcd(&num2).unwrap(), expected);
 
-            let num3 = BigNum::from(vec![1, 0], false);
-            let num4 = BigNum::from(vec![3], true);
-            let expected = BigNum::from(vec![1], true);
+            let num3 = BigNum::from_digits(vec![1, 0], false);
+            let num4 = BigNum::from_digits(vec![3], true);
+            let expected = BigNum::from_digits(vec![1], true);
             assert_eq!(num3.gcd(&num4).unwrap(), expected);
         }
     }
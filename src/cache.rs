@@ -0,0 +1,31 @@
+use crate::parser::Expr;
+
+use std::collections::HashMap;
+
+// Statements kept at most, the cache starting over once it is full
+const CAPACITY: usize = 1024;
+
+// Trees of statements a Session evaluated, by source text, with their pure
+// constant parts already computed. Only valid while the syntax and limits
+// they were parsed with last, so the session clears it when they change.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ExprCache {
+    trees: HashMap<String, Expr>,
+}
+
+impl ExprCache {
+    pub(crate) fn get(&self, statement: &str) -> Option<Expr> {
+        self.trees.get(statement).cloned()
+    }
+
+    pub(crate) fn insert(&mut self, statement: &str, expr: Expr) {
+        if self.trees.len() >= CAPACITY {
+            self.trees.clear();
+        }
+        self.trees.insert(statement.to_string(), expr);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.trees.clear();
+    }
+}
@@ -0,0 +1,380 @@
+use crate::common::Value;
+use crate::gen::{render, Rng};
+use crate::parser::{Expr, Operator};
+
+/// Rewrite `expr` into a deterministic normal form: subtraction becomes
+/// addition of a negation, signs are pushed down into the literals and the
+/// operands of `+` and `*` are sorted. Equivalent shapes of the same sum or
+/// product end up printing identically.
+pub fn canonical(expr: &Expr) -> Expr {
+    normalize(expr, false)
+}
+
+// Canonical form of `expr`, negated when `negate` is set
+fn normalize(expr: &Expr, negate: bool) -> Expr {
+    match expr {
+        Expr::Literal(value) if negate => match -value.clone() {
+            Ok(value) => Expr::Literal(value),
+            // Negating true or false is an error to keep, not a sign to push down
+            Err(_) => negated(expr.clone(), negate),
+        },
+        Expr::Literal(value) => Expr::Literal(value.clone()),
+        Expr::Unary(Operator::Negative, operand, _) => normalize(operand, !negate),
+        Expr::Unary(Operator::Positive, operand, _) => normalize(operand, negate),
+        Expr::Unary(operator, operand, span) => negated(
+            Expr::Unary(*operator, Box::new(canonical(operand)), *span),
+            negate,
+        ),
+        Expr::Conditional(test, then, otherwise, span) => Expr::Conditional(
+            Box::new(canonical(test)),
+            Box::new(normalize(then, negate)),
+            Box::new(normalize(otherwise, negate)),
+            *span,
+        ),
+        Expr::Call(name, args, span) => {
+            let call = Expr::Call(name.clone(), args.iter().map(canonical).collect(), *span);
+            negated(call, negate)
+        }
+        Expr::List(items, span) => {
+            let list = Expr::List(items.iter().map(canonical).collect(), *span);
+            negated(list, negate)
+        }
+        Expr::Postfix(operator, operand, span) => negated(
+            Expr::Postfix(*operator, Box::new(canonical(operand)), *span),
+            negate,
+        ),
+        Expr::Var(..) | Expr::Raise(..) => negated(expr.clone(), negate),
+        // -(x = 3) stores 3, so the sign stays outside the assignment
+        Expr::Assign(name, value) => negated(
+            Expr::Assign(name.clone(), Box::new(canonical(value))),
+            negate,
+        ),
+        Expr::Binary(Operator::Add | Operator::Subtract, ..) => {
+            let mut terms = Vec::new();
+            collect_terms(expr, negate, &mut terms);
+            rebuild(Operator::Add, sorted(terms))
+        }
+        Expr::Binary(Operator::Multiply, ..) => {
+            let mut factors = Vec::new();
+            let mut negative = negate;
+            collect_factors(expr, &mut factors, &mut negative);
+            let mut factors = sorted(factors);
+            if negative {
+                factors[0] = normalize(&factors[0], true);
+            }
+            rebuild(Operator::Multiply, factors)
+        }
+        Expr::Binary(Operator::Divide, left, right, span) => {
+            // Division keeps its operand order, the sign moves to the numerator
+            let (negative, denominator) = split_sign(normalize(right, false));
+            let numerator = normalize(left, negate != negative);
+            Expr::Binary(
+                Operator::Divide,
+                Box::new(numerator),
+                Box::new(denominator),
+                *span,
+            )
+        }
+        // Operands stay in place, only normalized inside
+        Expr::Binary(operator, left, right, span) => negated(
+            Expr::Binary(
+                *operator,
+                Box::new(canonical(left)),
+                Box::new(canonical(right)),
+                *span,
+            ),
+            negate,
+        ),
+    }
+}
+
+// Atoms with no literal to carry the sign keep an explicit negation
+fn negated(expr: Expr, negate: bool) -> Expr {
+    if negate {
+        Expr::unary(Operator::Negative, expr)
+    } else {
+        expr
+    }
+}
+
+fn collect_terms(expr: &Expr, negate: bool, terms: &mut Vec<Expr>) {
+    match expr {
+        Expr::Binary(Operator::Add, left, right, _) => {
+            collect_terms(left, negate, terms);
+            collect_terms(right, negate, terms);
+        }
+        Expr::Binary(Operator::Subtract, left, right, _) => {
+            collect_terms(left, negate, terms);
+            collect_terms(right, !negate, terms);
+        }
+        Expr::Unary(Operator::Negative, operand, _) => collect_terms(operand, !negate, terms),
+        _ => terms.push(normalize(expr, negate)),
+    }
+}
+
+fn collect_factors(expr: &Expr, factors: &mut Vec<Expr>, negative: &mut bool) {
+    match expr {
+        Expr::Binary(Operator::Multiply, left, right, _) => {
+            collect_factors(left, factors, negative);
+            collect_factors(right, factors, negative);
+        }
+        Expr::Unary(Operator::Negative, operand, _) => {
+            *negative = !*negative;
+            collect_factors(operand, factors, negative);
+        }
+        _ => {
+            let (factor_negative, factor) = split_sign(normalize(expr, false));
+            *negative ^= factor_negative;
+            factors.push(factor);
+        }
+    }
+}
+
+// Pull the sign out of a canonical expression, which always sits in the
+// leftmost literal of a product or quotient and in the first term of a sum
+fn split_sign(mut expr: Expr) -> (bool, Expr) {
+    match &mut expr {
+        Expr::Literal(value) if value.is_negative() => (
+            true,
+            Expr::Literal((-value.clone()).expect("only numbers are negative")),
+        ),
+        Expr::Unary(Operator::Negative, operand, _) => (true, operand.take()),
+        Expr::Binary(Operator::Multiply | Operator::Divide, left, ..) => {
+            let (negative, unsigned) = split_sign(left.take());
+            **left = unsigned;
+            (negative, expr)
+        }
+        Expr::Binary(Operator::Add, ..) => {
+            let negative = split_sign(first_term(&expr).clone()).0;
+            if negative {
+                (true, normalize(&expr, true))
+            } else {
+                (false, expr)
+            }
+        }
+        _ => (false, expr),
+    }
+}
+
+fn first_term(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Binary(Operator::Add, left, ..) => first_term(left),
+        _ => expr,
+    }
+}
+
+// Operands are ordered by their unsigned form first, so negating every
+// operand of a sum keeps their order
+fn sorted(exprs: Vec<Expr>) -> Vec<Expr> {
+    let mut keyed: Vec<((String, bool), Expr)> = exprs
+        .into_iter()
+        .map(|e| {
+            let (negative, unsigned) = split_sign(e.clone());
+            ((render(&unsigned), negative), e)
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    keyed.into_iter().map(|(_, e)| e).collect()
+}
+
+fn rebuild(operator: Operator, exprs: Vec<Expr>) -> Expr {
+    let mut iter = exprs.into_iter();
+    let first = iter.next().unwrap();
+    iter.fold(first, |acc, e| Expr::binary(operator, acc, e))
+}
+
+/// Produce an expression with the same value but a different shape, by
+/// randomly swapping and regrouping operands of `+` and `*`, turning sums
+/// into differences and adding double negations
+pub fn randomize_equivalent(expr: &Expr, rng: &mut Rng) -> Expr {
+    let negate = |e: Expr| Expr::unary(Operator::Negative, e);
+    let binary = Expr::binary;
+    match expr {
+        Expr::Literal(value) if !matches!(value, Value::Bool(_)) && rng.below(8) == 0 => {
+            negate(negate(expr.clone()))
+        }
+        Expr::Literal(_) | Expr::Var(..) | Expr::Raise(..) => expr.clone(),
+        Expr::Assign(name, value) => {
+            Expr::Assign(name.clone(), Box::new(randomize_equivalent(value, rng)))
+        }
+        Expr::Call(name, args, _) => Expr::call(
+            name,
+            args.iter()
+                .map(|arg| randomize_equivalent(arg, rng))
+                .collect(),
+        ),
+        Expr::List(items, _) => Expr::list(
+            items
+                .iter()
+                .map(|item| randomize_equivalent(item, rng))
+                .collect(),
+        ),
+        Expr::Unary(operator, operand, _) => {
+            Expr::unary(*operator, randomize_equivalent(operand, rng))
+        }
+        Expr::Postfix(operator, operand, _) => {
+            Expr::postfix(*operator, randomize_equivalent(operand, rng))
+        }
+        Expr::Conditional(test, then, otherwise, _) => Expr::conditional(
+            randomize_equivalent(test, rng),
+            randomize_equivalent(then, rng),
+            randomize_equivalent(otherwise, rng),
+        ),
+        Expr::Binary(operator, left, right, _) => {
+            let mut left = randomize_equivalent(left, rng);
+            let right = randomize_equivalent(right, rng);
+            if let Expr::Binary(inner, a, b, _) = &mut left {
+                if matches!(operator, Operator::Add | Operator::Multiply)
+                    && inner == operator
+                    && rng.below(3) == 0
+                {
+                    // (a . b) . c -> a . (b . c)
+                    return binary(*operator, a.take(), binary(*operator, b.take(), right));
+                }
+            }
+            match (operator, left) {
+                (Operator::Add, left) => match rng.below(3) {
+                    0 => binary(Operator::Add, left, right),
+                    1 => binary(Operator::Add, right, left),
+                    _ => binary(Operator::Subtract, left, negate(right)),
+                },
+                (Operator::Subtract, left) => match rng.below(2) {
+                    0 => binary(Operator::Subtract, left, right),
+                    _ => binary(Operator::Add, negate(right), left),
+                },
+                (Operator::Multiply, left) if rng.below(2) == 0 => {
+                    binary(Operator::Multiply, right, left)
+                }
+                (operator, left) => binary(*operator, left, right),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::{gen_expr_with, ExprKind};
+    use crate::parser::parse;
+
+    fn canonical_str(input: &str) -> String {
+        render(&canonical(&parse(input).unwrap()))
+    }
+
+    mod test_canonical {
+        use super::*;
+
+        #[test]
+        fn test_commutative_operands() {
+            assert_eq!(canonical_str("1 + 2"), canonical_str("2 + 1"));
+            assert_eq!(canonical_str("2 * 3 * 4"), canonical_str("4 * (3 * 2)"));
+        }
+
+        #[test]
+        fn test_subtraction() {
+            assert_eq!(canonical_str("3 - 2"), canonical_str("-2 + 3"));
+            assert_eq!(canonical_str("5 - (1 + 2)"), canonical_str("-1 + 5 - 2"));
+        }
+
+        #[test]
+        fn test_signs() {
+            assert_eq!(canonical_str("--5"), "5");
+            assert_eq!(canonical_str("-(2 * 3)"), canonical_str("2 * -3"));
+            assert_eq!(canonical_str("-2 * -3"), canonical_str("2 * 3"));
+            assert_eq!(canonical_str("1 / -2"), canonical_str("-1 / 2"));
+        }
+
+        #[test]
+        fn test_calls() {
+            assert_eq!(
+                canonical_str("is_power_of_two(2 + 1) * 3"),
+                canonical_str("3 * is_power_of_two(1 + 2)")
+            );
+            assert_eq!(
+                canonical_str("-next_power_of_two(5) * 2"),
+                canonical_str("2 * -next_power_of_two(5)")
+            );
+        }
+
+        #[test]
+        fn test_postfix() {
+            assert_eq!(canonical_str("3! * 2"), canonical_str("2 * 3!"));
+            assert_eq!(canonical_str("-(1 + 2)!"), canonical_str("-(2 + 1)!"));
+            let mut expr = parse(&canonical_str("-(3 - 1)! + 50% * 2")).unwrap();
+            assert_eq!(expr.eval().unwrap().to_string(), "-1");
+        }
+
+        #[test]
+        fn test_variables() {
+            assert_eq!(canonical_str("x * 2 - y"), canonical_str("-y + 2 * x"));
+            assert_eq!(canonical_str("z = b + a"), "z = a + b");
+            let three = Expr::literal("3".parse().unwrap());
+            let expr = Expr::unary(Operator::Negative, Expr::assign("x", three));
+            assert_eq!(render(&canonical(&expr)), "-(x = 3)");
+        }
+
+        #[test]
+        fn test_comparisons() {
+            assert_eq!(
+                canonical_str("2 + 1 < 1 * 4"),
+                canonical_str("1 + 2 < 4 * 1")
+            );
+            assert_ne!(canonical_str("1 < 2"), canonical_str("2 < 1"));
+            assert_eq!(canonical_str("1 / 2 == x"), "(1/2) == x");
+        }
+
+        #[test]
+        fn test_logic() {
+            assert_eq!(canonical_str("!(2 + 1 > x) && y"), "(!((1 + 2) > x)) && y");
+            assert_eq!(canonical_str("-(c ? 1 : x)"), "c ? (-1) : (-x)");
+        }
+
+        #[test]
+        fn test_division_order_kept() {
+            assert_ne!(canonical_str("1 / 2"), canonical_str("2 / 1"));
+        }
+
+        #[test]
+        fn test_canonical_preserves_value() {
+            let mut rng = Rng::new(17);
+            for _ in 0..100 {
+                let mut expr = parse(&gen_expr_with(&mut rng, 4, &ExprKind::all())).unwrap();
+                assert_eq!(canonical(&expr).eval().unwrap(), expr.eval().unwrap());
+            }
+        }
+
+        #[test]
+        fn test_canonical_idempotent() {
+            let mut rng = Rng::new(19);
+            for _ in 0..100 {
+                let expr = parse(&gen_expr_with(&mut rng, 4, &ExprKind::all())).unwrap();
+                let once = canonical(&expr);
+                assert_eq!(render(&canonical(&once)), render(&once));
+            }
+        }
+    }
+
+    mod test_randomize_equivalent {
+        use super::*;
+
+        #[test]
+        fn test_randomize_preserves_value() {
+            let mut rng = Rng::new(23);
+            for _ in 0..100 {
+                let mut expr = parse(&gen_expr_with(&mut rng, 4, &ExprKind::all())).unwrap();
+                let mut variant = randomize_equivalent(&expr, &mut rng);
+                assert_eq!(variant.eval().unwrap(), expr.eval().unwrap());
+            }
+        }
+
+        #[test]
+        fn test_randomize_same_canonical_form() {
+            let mut rng = Rng::new(29);
+            for _ in 0..100 {
+                let expr = parse(&gen_expr_with(&mut rng, 4, &ExprKind::all())).unwrap();
+                let variant = randomize_equivalent(&expr, &mut rng);
+                assert_eq!(render(&canonical(&variant)), render(&canonical(&expr)));
+            }
+        }
+    }
+}
@@ -1,14 +1,80 @@
-use crate::big_num::BigNum;
-use crate::frac::{Frac, IntoFrac};
+use crate::big_num::{BigNum, TOO_LARGE};
+use crate::error::ArithError;
+use crate::frac::Frac;
 
+use std::cmp::Ordering;
 use std::fmt;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 use std::str::FromStr;
 
+// Powers sure to pass this many digits are refused even without a digit
+// limit, computing them would take minutes
+const POW_MAX_DIGITS: usize = 10_000_000;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub enum AngleUnit {
+    Degree,
+    Radian,
+    Gradian,
+}
+
+impl AngleUnit {
+    pub fn from_suffix(suffix: &str) -> Option<AngleUnit> {
+        match suffix {
+            "deg" => Some(AngleUnit::Degree),
+            "rad" => Some(AngleUnit::Radian),
+            "grad" => Some(AngleUnit::Gradian),
+            _ => None,
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            AngleUnit::Degree => "deg",
+            AngleUnit::Radian => "rad",
+            AngleUnit::Gradian => "grad",
+        }
+    }
+
+    // A full turn in this unit, radians have no exact one
+    fn full_turn(&self) -> Option<Value> {
+        match self {
+            AngleUnit::Degree => Some(Value::Number(BigNum::from(360))),
+            AngleUnit::Radian => None,
+            AngleUnit::Gradian => Some(Value::Number(BigNum::from(400))),
+        }
+    }
+
+    /// Convert a magnitude between units, only possible exactly when radians
+    /// are not involved
+    pub fn convert(value: Value, from: AngleUnit, to: AngleUnit) -> Result<Value, ArithError> {
+        if from == to {
+            return Ok(value);
+        }
+        match (from.full_turn(), to.full_turn()) {
+            (Some(from_turn), Some(to_turn)) => (value * to_turn)? / from_turn,
+            _ => Err(format!(
+                "Cannot convert exactly between {} and {}",
+                from.suffix(),
+                to.suffix()
+            )
+            .into()),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
 pub enum Value {
     Number(BigNum),
     Frac(Frac),
+    // Magnitude tagged with the unit of an angle literal such as `30deg`
+    Angle(Box<Value>, AngleUnit),
+    // Result of a comparison, which arithmetic refuses
+    Bool(bool),
+    // `[a, b, c]`, several values at once such as the result of `divmod`
+    List(Vec<Value>),
 }
 
 impl Value {
@@ -19,6 +85,9 @@ impl Value {
                 Ok(num) => Value::Number(num),
                 Err(_) => Value::Frac(frac),
             },
+            Value::Angle(value, unit) => Value::Angle(Box::new(value.simplify()), unit),
+            Value::Bool(b) => Value::Bool(b),
+            Value::List(items) => Value::List(items.into_iter().map(Value::simplify).collect()),
         }
     }
 
@@ -26,89 +95,557 @@ impl Value {
         match self {
             Value::Number(num) => num.is_zero(),
             Value::Frac(frac) => frac.is_zero(),
+            Value::Angle(value, _) => value.is_zero(),
+            Value::Bool(_) => false,
+            Value::List(items) => items.iter().all(Value::is_zero),
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Value::Number(num) => num.is_negative() && !num.is_zero(),
+            Value::Frac(frac) => frac.is_negative(),
+            Value::Angle(value, _) => value.is_negative(),
+            Value::Bool(_) | Value::List(_) => false,
+        }
+    }
+
+    /// `n!` of a non-negative integer
+    pub fn factorial(self) -> Result<Value, ArithError> {
+        match self.simplify() {
+            Value::Number(num) => num.factorial().map(Value::Number),
+            value => Err(format!("Factorial expects an integer, got {}", value).into()),
+        }
+    }
+
+    /// A plain number read as degrees, as written `90°`
+    pub fn in_degrees(self) -> Result<Value, ArithError> {
+        match self {
+            Value::Angle(..) => Err(format!("{} is already an angle", self).into()),
+            value => Ok(Value::Angle(Box::new(value), AngleUnit::Degree)),
+        }
+    }
+
+    /// Quotient of `//`, rounded toward zero like BigNum division:
+    /// `-7 // 2 = -3`, `(7/2) // (1/3) = 10`. Two angles give the whole
+    /// number of times one fits in the other.
+    pub fn int_div(self, other: Value) -> Result<Value, ArithError> {
+        if self.is_list() || other.is_list() {
+            return self.elementwise(other, Value::int_div);
+        }
+        match (self / other)? {
+            Value::Number(num) => Ok(Value::Number(num)),
+            Value::Frac(frac) => Ok(Value::Number(
+                frac.numerator().clone() / frac.denominator().clone(),
+            )),
+            _ => Err("// needs two numbers or two angles".to_string().into()),
+        }
+    }
+
+    /// Remainder of `%`, `self - other * (self // other)`, so it has the sign
+    /// of `self` like BigNum `%`: `-7 % 2 = -1`, `(7/2) % (1/3) = 1/6`,
+    /// `370deg % 360deg = 10deg`
+    pub fn modulo(self, other: Value) -> Result<Value, ArithError> {
+        let quotient = self.clone().int_div(other.clone())?;
+        self - (other * quotient)?
+    }
+
+    /// `self ^ exponent` for an integer exponent, exact even when negative:
+    /// `2 ^ -2 = 1/4`. A power too large to compute is an error up front.
+    pub fn pow(self, exponent: Value) -> Result<Value, ArithError> {
+        if self.is_list() || exponent.is_list() {
+            return self.elementwise(exponent, Value::pow);
+        }
+        let exponent = match exponent.simplify() {
+            Value::Number(num) => num,
+            other => return Err(format!("Exponent must be an integer, got {}", other).into()),
+        };
+        let base = match self.simplify() {
+            Value::Number(num) => Frac::from_bignum(num),
+            Value::Frac(frac) => frac,
+            angle => return Err(format!("Cannot raise the angle {} to a power", angle).into()),
+        };
+        let exponent = if base.is_zero() || base.abs() == BigNum::one() {
+            // 0, 1 and -1 only depend on the sign and parity of the exponent
+            let small = match (exponent.is_zero(), exponent.is_odd()) {
+                (true, _) => 0,
+                (false, true) => 1,
+                (false, false) => 2,
+            };
+            if exponent.is_negative() {
+                -small
+            } else {
+                small
+            }
+        } else {
+            let exponent = i64::try_from(&exponent).map_err(|_| {
+                ArithError::Overflow(format!("{}: exponent {}", TOO_LARGE, exponent))
+            })?;
+            let log10 = base
+                .numerator()
+                .log10_floor_bound()
+                .max(base.denominator().log10_floor_bound());
+            let digits = (log10 * exponent.unsigned_abs() as f64) as usize;
+            if digits > POW_MAX_DIGITS {
+                return Err(ArithError::Overflow(format!(
+                    "{}: at least {} digits, powers are limited to {}",
+                    TOO_LARGE, digits, POW_MAX_DIGITS
+                )));
+            }
+            exponent
+        };
+        Ok(Value::Frac(base.checked_pow(exponent)?).simplify())
+    }
+
+    /// Order of two numbers, or of two angles in any units: `90deg` equals
+    /// `100grad`. Angles do not compare with plain numbers, and true and
+    /// false only with each other, through `equals`.
+    pub fn compare(&self, other: &Value) -> Result<Ordering, ArithError> {
+        match (self, other) {
+            (Value::Angle(left, unit), right @ Value::Angle(..)) => {
+                left.compare(&right.clone().angle_in(*unit)?)
+            }
+            (Value::Number(left), Value::Number(right)) => Ok(left.cmp(right)),
+            (Value::Frac(left), Value::Frac(right)) => Ok(left.cmp(right)),
+            (Value::Number(left), Value::Frac(right)) => {
+                Ok(Frac::from_bignum(left.clone()).cmp(right))
+            }
+            (Value::Frac(left), Value::Number(right)) => {
+                Ok(left.cmp(&Frac::from_bignum(right.clone())))
+            }
+            _ => Err(format!("Cannot compare {} and {}", self, other).into()),
+        }
+    }
+
+    /// Equality by value as `==` sees it, so `2/4` equals `1/2`. Lists are
+    /// equal when all their items are, not item by item.
+    pub fn equals(&self, other: &Value) -> Result<bool, ArithError> {
+        match (self, other) {
+            (Value::Bool(left), Value::Bool(right)) => Ok(left == right),
+            (Value::List(left), Value::List(right)) => {
+                if left.len() != right.len() {
+                    return Ok(false);
+                }
+                for (left, right) in left.iter().zip(right) {
+                    if !left.equals(right)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            _ => self.compare(other).map(Ordering::is_eq),
+        }
+    }
+
+    /// Item of a list at `index`, counting from 0: `[5, 6, 7][1] = 6`
+    pub fn index(self, index: Value) -> Result<Value, ArithError> {
+        let Value::List(mut items) = self else {
+            return Err(format!("Cannot index {}, only lists", self).into());
+        };
+        let index = match index.simplify() {
+            Value::Number(index) => index,
+            index => return Err(format!("Index must be an integer, got {}", index).into()),
+        };
+        let position = u64::try_from(&index)
+            .ok()
+            .and_then(|position| usize::try_from(position).ok())
+            .filter(|&position| position < items.len());
+        match position {
+            Some(position) => Ok(items.swap_remove(position)),
+            None => Err(format!(
+                "Index {} out of range for a list of {} items",
+                index,
+                items.len()
+            )
+            .into()),
+        }
+    }
+
+    fn is_list(&self) -> bool {
+        matches!(self, Value::List(_))
+    }
+
+    // `operation` item by item, one side at least being a list: two lists of
+    // the same length pair up their items, and a list with anything else
+    // pairs each item with it, so `[1, 2] * 3 = [3, 6]`
+    fn elementwise(
+        self,
+        other: Value,
+        operation: impl Fn(Value, Value) -> Result<Value, ArithError>,
+    ) -> Result<Value, ArithError> {
+        let items: Result<Vec<Value>, ArithError> = match (self, other) {
+            (Value::List(left), Value::List(right)) => {
+                if left.len() != right.len() {
+                    return Err(format!(
+                        "Lists of {} and {} items do not match",
+                        left.len(),
+                        right.len()
+                    )
+                    .into());
+                }
+                left.into_iter()
+                    .zip(right)
+                    .map(|(left, right)| operation(left, right))
+                    .collect()
+            }
+            (Value::List(left), right) => left
+                .into_iter()
+                .map(|left| operation(left, right.clone()))
+                .collect(),
+            (left, Value::List(right)) => right
+                .into_iter()
+                .map(|right| operation(left.clone(), right))
+                .collect(),
+            _ => unreachable!("one side is a list"),
+        };
+        items.map(Value::List)
+    }
+
+    /// `&` of two integers, negative ones taken in two's complement
+    pub fn bit_and(self, other: Value) -> Result<Value, ArithError> {
+        let (left, right) = self.integer_operands(other, "&")?;
+        Ok(Value::Number(left & right))
+    }
+
+    pub fn bit_or(self, other: Value) -> Result<Value, ArithError> {
+        let (left, right) = self.integer_operands(other, "|")?;
+        Ok(Value::Number(left | right))
+    }
+
+    pub fn bit_xor(self, other: Value) -> Result<Value, ArithError> {
+        let (left, right) = self.integer_operands(other, "^^")?;
+        Ok(Value::Number(left ^ right))
+    }
+
+    /// `~n`, which is `-n - 1`
+    pub fn bit_not(self) -> Result<Value, ArithError> {
+        match self.simplify() {
+            Value::Number(num) => Ok(Value::Number(!num)),
+            value => Err(format!("~ expects an integer, got {}", value).into()),
+        }
+    }
+
+    /// `self << bits`, multiplying by `2^bits`. Shifts too large to compute
+    /// are refused like `^`.
+    pub fn shift_left(self, bits: Value) -> Result<Value, ArithError> {
+        let (num, bits) = self.integer_operands(bits, "<<")?;
+        Ok(Value::Number(num * power_of_two(bits)?))
+    }
+
+    /// `self >> bits`, rounding toward negative infinity as a two's
+    /// complement shift does: `-1 >> 1 = -1`
+    pub fn shift_right(self, bits: Value) -> Result<Value, ArithError> {
+        let (num, bits) = self.integer_operands(bits, ">>")?;
+        // 2^bits is past 10^digits, only the sign is left
+        if bits > BigNum::from(4 * num.num_digits()) {
+            return Ok(Value::Number(-BigNum::from(num.is_negative() as u8)));
+        }
+        let factor = power_of_two(bits)?;
+        Ok(Value::Number(Frac::new(num, factor).floor()))
+    }
+
+    // Both operands of a bitwise operator, which must be integers
+    fn integer_operands(
+        self,
+        other: Value,
+        operator: &str,
+    ) -> Result<(BigNum, BigNum), ArithError> {
+        match (self.simplify(), other.simplify()) {
+            (Value::Number(left), Value::Number(right)) => Ok((left, right)),
+            (left, right) => Err(format!(
+                "{} expects two integers, got {} and {}",
+                operator, left, right
+            )
+            .into()),
+        }
+    }
+
+    /// The integer, `None` for anything else including fractions and angles
+    pub fn as_integer(&self) -> Option<&BigNum> {
+        match self {
+            Value::Number(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    /// Exact rational value of an integer or fraction, integers over 1
+    pub fn to_frac(&self) -> Option<Frac> {
+        match self {
+            Value::Number(num) => Some(Frac::new(num.clone(), BigNum::one())),
+            Value::Frac(frac) => Some(frac.clone()),
+            _ => None,
+        }
+    }
+
+    /// Magnitude and unit of an angle
+    pub fn as_angle(&self) -> Option<(&Value, AngleUnit)> {
+        match self {
+            Value::Angle(value, unit) => Some((value, *unit)),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Short name of the variant, as listed by `:vars`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "int",
+            Value::Frac(_) => "frac",
+            Value::Angle(..) => "angle",
+            Value::Bool(_) => "bool",
+            Value::List(_) => "list",
+        }
+    }
+
+    /// Digits of the numerator and denominator, the denominator of an
+    /// integer counting as 1
+    pub fn digit_counts(&self) -> (usize, usize) {
+        match self {
+            Value::Number(num) => (num.num_digits(), 1),
+            Value::Frac(frac) => (
+                frac.numerator().num_digits(),
+                frac.denominator().num_digits(),
+            ),
+            Value::Angle(value, _) => value.digit_counts(),
+            Value::Bool(_) => (1, 1),
+            // The longest numerator and denominator of the items
+            Value::List(items) => items
+                .iter()
+                .map(Value::digit_counts)
+                .fold((1, 1), |(num, den), (n, d)| (num.max(n), den.max(d))),
+        }
+    }
+
+    // Bound on the digits any part of this value can contribute to a result
+    fn digit_bound(&self) -> usize {
+        match self {
+            Value::Number(num) => num.num_digits(),
+            Value::Frac(frac) => frac.total_digits(),
+            Value::Angle(value, _) => value.digit_bound(),
+            Value::Bool(_) => 1,
+            Value::List(items) => items.iter().map(Value::digit_bound).max().unwrap_or(1),
+        }
+    }
+
+    // Refuse an operation before computing it when its result is sure to go
+    // over the BigNum digit limit. Only the product of two integers is known
+    // to be that large, falling short of their digits together by one at most.
+    fn check_result_size(&self, other: &Value, additive: bool) -> Result<(), ArithError> {
+        match (self, other) {
+            (Value::Number(left), Value::Number(right))
+                if !additive && !left.is_zero() && !right.is_zero() =>
+            {
+                BigNum::check_digits(left.num_digits() + right.num_digits() - 1)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Refuse the result of an operation that went over the digit limit
+    fn checked_size(self) -> Result<Value, ArithError> {
+        BigNum::check_digits(self.digit_bound())?;
+        Ok(self)
+    }
+
+    // Magnitude of an angle in the given unit
+    fn angle_in(self, unit: AngleUnit) -> Result<Value, ArithError> {
+        match self {
+            Value::Angle(value, from) => AngleUnit::convert(*value, from, unit),
+            _ => Err("Expected an angle".to_string().into()),
         }
     }
 }
 
+// 2^bits for a shift
+fn power_of_two(bits: BigNum) -> Result<BigNum, ArithError> {
+    if bits.is_negative() {
+        return Err(format!("Cannot shift by a negative amount {}", bits).into());
+    }
+    match Value::Number(BigNum::from(2)).pow(Value::Number(bits))? {
+        Value::Number(num) => Ok(num),
+        value => Err(format!("Expected an integer power of two, got {}", value).into()),
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Number(num) => write!(f, "{}", num),
             Value::Frac(frac) => write!(f, "{}", frac),
+            Value::Angle(value, unit) => match **value {
+                Value::Frac(_) => write!(f, "({}){}", value, unit.suffix()),
+                _ => write!(f, "{}{}", value, unit.suffix()),
+            },
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
         }
     }
 }
 
+impl From<BigNum> for Value {
+    fn from(num: BigNum) -> Self {
+        Value::Number(num)
+    }
+}
+
+// Fractions with denominator 1 become integers, as results do
+impl From<Frac> for Value {
+    fn from(frac: Frac) -> Self {
+        Value::Frac(frac).simplify()
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
 impl Neg for Value {
-    type Output = Self;
+    type Output = Result<Value, ArithError>;
 
-    fn neg(self) -> Self {
-        match self {
+    fn neg(self) -> Self::Output {
+        Ok(match self {
             Value::Number(num) => Value::Number(-num),
             Value::Frac(frac) => Value::Frac(-frac),
-        }
+            Value::Angle(value, unit) => Value::Angle(Box::new((-*value)?), unit),
+            Value::Bool(b) => return Err(format!("Cannot negate {}", b).into()),
+            Value::List(items) => {
+                Value::List(items.into_iter().map(Neg::neg).collect::<Result<_, _>>()?)
+            }
+        })
     }
 }
 
 impl Add for Value {
-    type Output = Self;
+    type Output = Result<Value, ArithError>;
 
-    fn add(self, other: Self) -> Self {
+    fn add(self, other: Self) -> Self::Output {
+        self.check_result_size(&other, true)?;
         match (self, other) {
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => {
+                return Err("Cannot add true or false".to_string().into())
+            }
+            (left @ Value::List(_), right) | (left, right @ Value::List(_)) => {
+                return left.elementwise(right, |left, right| left + right)
+            }
+            (Value::Angle(left, unit), right @ Value::Angle(..)) => {
+                Value::Angle(Box::new((*left + right.angle_in(unit)?)?), unit)
+            }
+            (Value::Angle(..), _) | (_, Value::Angle(..)) => {
+                return Err("Cannot add an angle and a plain number".to_string().into())
+            }
             (Value::Number(left), Value::Number(right)) => Value::Number(left + right),
             (Value::Frac(left), Value::Frac(right)) => Value::Frac(left + right),
             (Value::Number(num), Value::Frac(frac)) => Value::Frac(frac + num),
             (Value::Frac(frac), Value::Number(num)) => Value::Frac(frac + num),
         }
         .simplify()
+        .checked_size()
     }
 }
 
 impl Sub for Value {
-    type Output = Self;
+    type Output = Result<Value, ArithError>;
 
-    fn sub(self, other: Self) -> Self {
+    fn sub(self, other: Self) -> Self::Output {
+        self.check_result_size(&other, true)?;
         match (self, other) {
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => {
+                return Err("Cannot subtract true or false".to_string().into())
+            }
+            (left @ Value::List(_), right) | (left, right @ Value::List(_)) => {
+                return left.elementwise(right, |left, right| left - right)
+            }
+            (Value::Angle(left, unit), right @ Value::Angle(..)) => {
+                Value::Angle(Box::new((*left - right.angle_in(unit)?)?), unit)
+            }
+            (Value::Angle(..), _) | (_, Value::Angle(..)) => {
+                return Err("Cannot subtract an angle and a plain number"
+                    .to_string()
+                    .into())
+            }
             (Value::Number(left), Value::Number(right)) => Value::Number(left - right),
             (Value::Frac(left), Value::Frac(right)) => Value::Frac(left - right),
-            (Value::Number(num), Value::Frac(frac)) => Value::Frac(frac - num),
+            (Value::Number(num), Value::Frac(frac)) => Value::Frac(num - frac),
             (Value::Frac(frac), Value::Number(num)) => Value::Frac(frac - num),
         }
         .simplify()
+        .checked_size()
     }
 }
 
 impl Mul for Value {
-    type Output = Self;
+    type Output = Result<Value, ArithError>;
 
-    fn mul(self, other: Self) -> Self {
+    fn mul(self, other: Self) -> Self::Output {
+        self.check_result_size(&other, false)?;
         match (self, other) {
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => {
+                return Err("Cannot multiply true or false".to_string().into())
+            }
+            (left @ Value::List(_), right) | (left, right @ Value::List(_)) => {
+                return left.elementwise(right, |left, right| left * right)
+            }
+            (Value::Angle(..), Value::Angle(..)) => {
+                return Err("Cannot multiply two angles".to_string().into())
+            }
+            (Value::Angle(value, unit), factor) | (factor, Value::Angle(value, unit)) => {
+                Value::Angle(Box::new((*value * factor)?), unit)
+            }
             (Value::Number(left), Value::Number(right)) => Value::Number(left * right),
             (Value::Frac(left), Value::Frac(right)) => Value::Frac(left * right),
             (Value::Number(num), Value::Frac(frac)) => Value::Frac(frac * num),
             (Value::Frac(frac), Value::Number(num)) => Value::Frac(frac * num),
         }
         .simplify()
+        .checked_size()
     }
 }
 
 impl Div for Value {
-    type Output = Self;
+    type Output = Result<Value, ArithError>;
 
-    fn div(self, other: Self) -> Self {
+    fn div(self, other: Self) -> Self::Output {
+        self.check_result_size(&other, false)?;
         match (self, other) {
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => {
+                return Err("Cannot divide true or false".to_string().into())
+            }
+            (left @ Value::List(_), right) | (left, right @ Value::List(_)) => {
+                return left.elementwise(right, |left, right| left / right)
+            }
+            // The ratio of two angles is a plain number
+            (Value::Angle(left, unit), right @ Value::Angle(..)) => {
+                return *left / right.angle_in(unit)?;
+            }
+            (Value::Angle(value, unit), divisor) => {
+                Value::Angle(Box::new((*value / divisor)?), unit)
+            }
+            (_, Value::Angle(..)) => return Err("Cannot divide by an angle".to_string().into()),
+            // Whole quotients come back as numbers from simplify below
             (Value::Number(left), Value::Number(right)) => {
-                if left.clone() % right.clone() == BigNum::zero() {
-                    Value::Number(left / right)
-                } else {
-                    Value::Frac(Frac::new(left, right))
-                }
+                Value::Frac(Frac::from_bignum(left).checked_div(&Frac::from_bignum(right))?)
+            }
+            (Value::Frac(left), Value::Frac(right)) => Value::Frac(left.checked_div(&right)?),
+            (Value::Number(num), Value::Frac(frac)) => {
+                Value::Frac(Frac::from_bignum(num).checked_div(&frac)?)
+            }
+            (Value::Frac(frac), Value::Number(num)) => {
+                Value::Frac(frac.checked_div(&Frac::from_bignum(num))?)
             }
-            (Value::Frac(left), Value::Frac(right)) => Value::Frac(left / right),
-            (Value::Number(num), Value::Frac(frac)) => Value::Frac(frac / num),
-            (Value::Frac(frac), Value::Number(num)) => Value::Frac(frac / num),
         }
         .simplify()
+        .checked_size()
     }
 }
 
@@ -116,12 +653,344 @@ impl FromStr for Value {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Ok(num) = BigNum::from_str(s) {
+        if let Ok(b) = s.parse::<bool>() {
+            Ok(Value::Bool(b))
+        } else if let Some(items) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            // Same shape as the Display output, `[1, 3/4, [2, 5]]`
+            split_items(items)
+                .into_iter()
+                .map(|item| item.trim().parse())
+                .collect::<Result<_, _>>()
+                .map(Value::List)
+        } else if let Ok(num) = BigNum::from_str(s) {
             Ok(Value::Number(num).simplify())
         } else if let Ok(frac) = Frac::from_str(s) {
             Ok(Value::Frac(frac).simplify())
+        } else if let Some((magnitude, unit)) = split_angle(s) {
+            // Same shape as the Display output, `30deg` or `(45/2)deg`
+            let magnitude = magnitude
+                .strip_prefix('(')
+                .and_then(|m| m.strip_suffix(')'))
+                .unwrap_or(magnitude);
+            Ok(Value::Angle(Box::new(magnitude.parse()?), unit))
         } else {
             Err(())
         }
     }
 }
+
+// The items of a list, split at the commas outside any nested list
+fn split_items(items: &str) -> Vec<&str> {
+    if items.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut depth = 0;
+    let mut start = 0;
+    let mut split = Vec::new();
+    for (i, c) in items.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(&items[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&items[start..]);
+    split
+}
+
+fn split_angle(s: &str) -> Option<(&str, AngleUnit)> {
+    let split = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    AngleUnit::from_suffix(&s[split.len()..]).map(|unit| (split, unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod test_mixed_operands {
+        use super::*;
+        use std::str::FromStr;
+
+        fn number(num: &str) -> Value {
+            Value::Number(BigNum::from_str(num).unwrap())
+        }
+
+        fn frac(numerator: &str, denominator: &str) -> Value {
+            Value::Frac(Frac::new(
+                BigNum::from_str(numerator).unwrap(),
+                BigNum::from_str(denominator).unwrap(),
+            ))
+        }
+
+        #[test]
+        fn test_number_minus_frac() {
+            assert_eq!((number("1") - frac("1", "3")).unwrap().to_string(), "2/3");
+            assert_eq!((frac("1", "3") - number("1")).unwrap().to_string(), "-2/3");
+        }
+
+        #[test]
+        fn test_number_over_frac() {
+            assert_eq!((number("1") / frac("1", "3")).unwrap().to_string(), "3");
+            assert_eq!((frac("1", "3") / number("2")).unwrap().to_string(), "1/6");
+        }
+    }
+
+    mod test_angle_unit {
+        use super::*;
+
+        #[test]
+        fn test_convert_exact() {
+            let value = Value::Number(BigNum::from(90));
+            let grad = AngleUnit::convert(value.clone(), AngleUnit::Degree, AngleUnit::Gradian);
+            assert_eq!(grad.unwrap(), Value::Number(BigNum::from(100)));
+            let deg = AngleUnit::convert(value.clone(), AngleUnit::Degree, AngleUnit::Degree);
+            assert_eq!(deg.unwrap(), value);
+        }
+
+        #[test]
+        fn test_from_str_round_trip() {
+            for input in ["30deg", "(45/2)deg", "-2rad", "(-1/3)grad"] {
+                assert_eq!(input.parse::<Value>().unwrap().to_string(), input);
+            }
+            assert!("30".parse::<Value>().is_ok());
+            assert!("30min".parse::<Value>().is_err());
+            assert!("deg".parse::<Value>().is_err());
+        }
+
+        #[test]
+        fn test_convert_radian() {
+            let value = Value::Number(BigNum::from(1));
+            assert!(AngleUnit::convert(value, AngleUnit::Radian, AngleUnit::Degree).is_err());
+        }
+    }
+    mod test_introspection {
+        use super::*;
+
+        #[test]
+        fn test_kind_and_digit_counts() {
+            let cases = [
+                ("-1234", "int", (4, 1)),
+                ("22/7", "frac", (2, 1)),
+                ("(1/300)rad", "angle", (1, 3)),
+                ("[22/7, -1234, []]", "list", (4, 1)),
+            ];
+            for (input, kind, counts) in cases {
+                let value: Value = input.parse().unwrap();
+                assert_eq!(value.kind(), kind);
+                assert_eq!(value.digit_counts(), counts);
+            }
+        }
+    }
+
+    mod test_accessors {
+        use super::*;
+
+        #[test]
+        fn test_accessors() {
+            let value: Value = "-12".parse().unwrap();
+            assert_eq!(value.as_integer(), Some(&BigNum::from(-12)));
+            assert_eq!(value.to_frac().unwrap().to_string(), "-12/1");
+            let value: Value = "3/4".parse().unwrap();
+            assert_eq!(value.as_integer(), None);
+            assert_eq!(
+                value.to_frac(),
+                Some(Frac::new(BigNum::from(3), BigNum::from(4)))
+            );
+            let value: Value = "30deg".parse().unwrap();
+            let (magnitude, unit) = value.as_angle().unwrap();
+            assert_eq!(
+                (magnitude.to_string(), unit),
+                ("30".to_string(), AngleUnit::Degree)
+            );
+            assert_eq!(value.to_frac(), None);
+            assert_eq!(Value::from(true).as_bool(), Some(true));
+            assert_eq!(value.as_bool(), None);
+        }
+
+        #[test]
+        fn test_from() {
+            assert_eq!(Value::from(BigNum::from(5)).to_string(), "5");
+            let frac = Frac::new(BigNum::from(6), BigNum::from(3));
+            assert_eq!(Value::from(frac), Value::Number(BigNum::from(2)));
+        }
+    }
+
+    mod test_neg {
+        use super::*;
+
+        #[test]
+        fn test_neg() {
+            let value: Value = "[1, -1/2, 90deg]".parse().unwrap();
+            assert_eq!(-value, Ok("[-1, 1/2, -90deg]".parse().unwrap()));
+        }
+
+        #[test]
+        fn test_bool_is_an_error() {
+            assert_eq!(
+                -Value::Bool(true),
+                Err("Cannot negate true".to_string().into())
+            );
+            let list = Value::List(vec![Value::Bool(false)]);
+            assert_eq!(-list, Err("Cannot negate false".to_string().into()));
+        }
+    }
+
+    mod test_div {
+        use super::*;
+
+        #[test]
+        fn test_division_by_zero_is_an_error() {
+            let zero: Value = "0".parse().unwrap();
+            for input in ["3", "1/2", "90deg"] {
+                let value: Value = input.parse().unwrap();
+                assert!((value / zero.clone()).is_err(), "{}", input);
+            }
+            let value: Value = "1/2".parse().unwrap();
+            assert!((value / "0/3".parse().unwrap()).is_err());
+            let value: Value = "6".parse().unwrap();
+            assert_eq!(value / "3".parse().unwrap(), Ok("2".parse().unwrap()));
+        }
+    }
+
+    mod test_int_div_modulo {
+        use super::*;
+
+        fn value(input: &str) -> Value {
+            input.parse().unwrap()
+        }
+
+        #[test]
+        fn test_numbers_and_fractions() {
+            let cases = [
+                ("7", "2", "3", "1"),
+                ("-7", "2", "-3", "-1"),
+                ("7", "-2", "-3", "1"),
+                ("6", "3", "2", "0"),
+                ("7/2", "1/3", "10", "1/6"),
+                ("-7/2", "1", "-3", "-1/2"),
+                ("5", "3/2", "3", "1/2"),
+            ];
+            for (a, b, quotient, remainder) in cases {
+                assert_eq!(
+                    value(a).int_div(value(b)),
+                    Ok(value(quotient)),
+                    "{} // {}",
+                    a,
+                    b
+                );
+                assert_eq!(
+                    value(a).modulo(value(b)),
+                    Ok(value(remainder)),
+                    "{} % {}",
+                    a,
+                    b
+                );
+            }
+        }
+
+        #[test]
+        fn test_angles() {
+            assert_eq!(value("370deg").modulo(value("360deg")), Ok(value("10deg")));
+            assert_eq!(value("370deg").int_div(value("360deg")), Ok(value("1")));
+            assert!(value("370deg").int_div(value("2")).is_err());
+            assert!(value("3").modulo(value("1deg")).is_err());
+            assert!(value("3").modulo(value("0")).is_err());
+        }
+    }
+
+    mod test_compare {
+        use super::*;
+
+        fn value(input: &str) -> Value {
+            input.parse().unwrap()
+        }
+
+        #[test]
+        fn test_compare() {
+            assert_eq!(value("1/2").compare(&value("1")), Ok(Ordering::Less));
+            assert_eq!(value("3").compare(&value("5/2")), Ok(Ordering::Greater));
+            assert_eq!(value("2/4").compare(&value("1/2")), Ok(Ordering::Equal));
+            assert_eq!(value("1rad").compare(&value("1rad")), Ok(Ordering::Equal));
+            assert_eq!(
+                value("180deg").compare(&value("100grad")),
+                Ok(Ordering::Greater)
+            );
+            assert!(value("1deg").compare(&value("1")).is_err());
+            assert!(value("true").compare(&value("false")).is_err());
+        }
+
+        #[test]
+        fn test_equals() {
+            assert_eq!(value("true").equals(&value("true")), Ok(true));
+            assert_eq!(value("true").equals(&value("false")), Ok(false));
+            assert_eq!(value("4/2").equals(&value("2")), Ok(true));
+            assert!(value("true").equals(&value("1")).is_err());
+        }
+
+        #[test]
+        fn test_bool_arithmetic_refused() {
+            assert!((value("true") + value("1")).is_err());
+            assert!((value("2") * value("false")).is_err());
+            assert!((value("1deg") * value("false")).is_err());
+            assert!((value("false") / value("2")).is_err());
+            assert_eq!(value("false").to_string(), "false");
+            assert_eq!(value("true").kind(), "bool");
+        }
+    }
+
+    mod test_pow {
+        use super::*;
+
+        fn value(input: &str) -> Value {
+            input.parse().unwrap()
+        }
+
+        #[test]
+        fn test_exact() {
+            let cases = [
+                ("2", "10", "1024"),
+                ("-3", "3", "-27"),
+                ("2", "-2", "1/4"),
+                ("2/3", "-2", "9/4"),
+                ("7", "0", "1"),
+                ("0", "5", "0"),
+            ];
+            for (base, exponent, result) in cases {
+                assert_eq!(
+                    value(base).pow(value(exponent)),
+                    Ok(value(result)),
+                    "{} ^ {}",
+                    base,
+                    exponent
+                );
+            }
+        }
+
+        #[test]
+        fn test_huge_exponents() {
+            // Only 0, 1 and -1 can be raised this far
+            let huge = value("123456789012345678901234567890");
+            assert_eq!(value("1").pow(huge.clone()), Ok(value("1")));
+            assert_eq!(value("-1").pow(huge.clone()), Ok(value("1")));
+            assert_eq!(value("-1").pow(value("-3")), Ok(value("-1")));
+            assert_eq!(value("0").pow(huge.clone()), Ok(value("0")));
+            let too_large = |err: &ArithError| matches!(err, ArithError::Overflow(message) if message.starts_with("Result too large"));
+            let err = value("2").pow(huge).unwrap_err();
+            assert!(too_large(&err), "{}", err);
+            let err = value("10").pow(value("100000000")).unwrap_err();
+            assert!(too_large(&err), "{}", err);
+        }
+
+        #[test]
+        fn test_errors() {
+            assert!(value("0").pow(value("-1")).is_err());
+            assert!(value("2").pow(value("1/2")).is_err());
+            assert!(value("2").pow(value("1deg")).is_err());
+            assert!(value("1deg").pow(value("2")).is_err());
+        }
+    }
+}
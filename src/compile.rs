@@ -0,0 +1,285 @@
+use crate::common::Value;
+use crate::env::Environment;
+use crate::limits;
+use crate::parser::{
+    apply_binary, apply_postfix, apply_unary, call, condition, logic, series, series_args,
+    CalcError, Expr, Operator, Span,
+};
+
+/// An expression simplified and flattened once, to evaluate against many
+/// sets of variables, such as the rows of a table, without parsing or
+/// walking the tree again. Assignments only give their value, the
+/// environment being read and never written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledExpr {
+    code: Vec<Instr>,
+}
+
+// One step of a compiled expression, working on a stack of values. Steps
+// that can fail keep the span of the node they came from for the error.
+#[derive(Debug, Clone, PartialEq)]
+enum Instr {
+    Push(Value),
+    Load(String, Span),
+    Raise(String, Span),
+    Unary(Operator, Span),
+    Postfix(Operator, Span),
+    Binary(Operator, Span),
+    // Name and argument count
+    Call(String, usize, Span),
+    // Item count
+    List(usize),
+    // `sum` or `prod` over the variable, its bounds on the stack
+    Series {
+        name: String,
+        var: String,
+        body: CompiledExpr,
+        span: Span,
+    },
+    // Left side of `&&` or `||`, jumping to `end` when it decides the result
+    ShortCircuit {
+        is_or: bool,
+        end: usize,
+        span: Span,
+    },
+    // Right side of `&&` or `||`
+    Logic(bool, Span),
+    // Condition of `?:`, jumping to the other branch when false
+    Branch(usize, Span),
+    Jump(usize),
+}
+
+impl CompiledExpr {
+    pub(crate) fn new(expr: &Expr) -> Self {
+        let mut code = Vec::new();
+        emit(&expr.simplify(), &mut code);
+        CompiledExpr { code }
+    }
+
+    /// Value with the variables of `env`
+    pub fn eval(&self, env: &Environment) -> Result<Value, CalcError> {
+        let mut values: Vec<Value> = Vec::new();
+        let mut pc = 0;
+        while let Some(instr) = self.code.get(pc) {
+            pc += 1;
+            limits::step().map_err(CalcError::from)?;
+            let value = match instr {
+                Instr::Push(value) => value.clone(),
+                Instr::Load(name, span) => env.get(name).cloned().ok_or_else(|| {
+                    CalcError::new_eval_error(format!("Unknown variable {}", name)).at(*span)
+                })?,
+                Instr::Raise(message, span) => {
+                    return Err(CalcError::new_user_error(message.clone()).at(*span))
+                }
+                Instr::Unary(operator, span) => {
+                    apply_unary(*operator, values.pop().unwrap()).map_err(|e| e.at(*span))?
+                }
+                Instr::Postfix(operator, span) => {
+                    apply_postfix(*operator, values.pop().unwrap()).map_err(|e| e.at(*span))?
+                }
+                Instr::Binary(operator, span) => {
+                    let right = values.pop().unwrap();
+                    let left = values.pop().unwrap();
+                    apply_binary(*operator, left, right).map_err(|e| e.at(*span))?
+                }
+                Instr::Call(name, count, span) => {
+                    let args = values.split_off(values.len() - count);
+                    call(name, args).map_err(|e| e.at(*span))?
+                }
+                Instr::List(count) => Value::List(values.split_off(values.len() - count)),
+                Instr::Series {
+                    name,
+                    var,
+                    body,
+                    span,
+                } => {
+                    let to = values.pop().unwrap();
+                    let from = values.pop().unwrap();
+                    // Bound for the body only, the environment is still only read
+                    let mut env = env.clone();
+                    series(name, var, from, to, &mut env, |env| body.eval(env))
+                        .map_err(|e| e.at(*span))?
+                }
+                Instr::ShortCircuit { is_or, end, span } => {
+                    match logic(*is_or, values.pop().unwrap()).map_err(|e| e.at(*span))? {
+                        Some(decided) => {
+                            pc = *end;
+                            decided
+                        }
+                        None => continue,
+                    }
+                }
+                Instr::Logic(is_or, span) => {
+                    let value = logic(*is_or, values.pop().unwrap()).map_err(|e| e.at(*span))?;
+                    value.unwrap_or(Value::Bool(!is_or))
+                }
+                Instr::Branch(otherwise, span) => {
+                    let test = values.pop().unwrap();
+                    if !condition(test, "?").map_err(|e| e.at(*span))? {
+                        pc = *otherwise;
+                    }
+                    continue;
+                }
+                Instr::Jump(target) => {
+                    pc = *target;
+                    continue;
+                }
+            };
+            values.push(value);
+        }
+        Ok(values.pop().unwrap())
+    }
+}
+
+// Append the code computing `expr` onto the stack
+fn emit(expr: &Expr, code: &mut Vec<Instr>) {
+    match expr {
+        Expr::Literal(value) => code.push(Instr::Push(value.clone())),
+        Expr::Var(name, span) => code.push(Instr::Load(name.clone(), *span)),
+        Expr::Raise(message, span) => code.push(Instr::Raise(message.clone(), *span)),
+        Expr::Assign(_, value) => emit(value, code),
+        Expr::Unary(operator, operand, span) => {
+            emit(operand, code);
+            code.push(Instr::Unary(*operator, *span));
+        }
+        Expr::Postfix(operator, operand, span) => {
+            emit(operand, code);
+            code.push(Instr::Postfix(*operator, *span));
+        }
+        Expr::Binary(operator @ (Operator::And | Operator::Or), left, right, span) => {
+            let (is_or, span) = (*operator == Operator::Or, *span);
+            emit(left, code);
+            let short_circuit = code.len();
+            code.push(Instr::ShortCircuit {
+                is_or,
+                end: 0,
+                span,
+            });
+            emit(right, code);
+            code.push(Instr::Logic(is_or, span));
+            code[short_circuit] = Instr::ShortCircuit {
+                is_or,
+                end: code.len(),
+                span,
+            };
+        }
+        Expr::Binary(operator, left, right, span) => {
+            emit(left, code);
+            emit(right, code);
+            code.push(Instr::Binary(*operator, *span));
+        }
+        Expr::Call(name, args, span) if series_args(name, args).is_some() => {
+            let (var, body) = series_args(name, args).unwrap();
+            emit(&args[1], code);
+            emit(&args[2], code);
+            let mut body_code = Vec::new();
+            emit(body, &mut body_code);
+            code.push(Instr::Series {
+                name: name.clone(),
+                var: var.to_string(),
+                body: CompiledExpr { code: body_code },
+                span: *span,
+            });
+        }
+        Expr::Call(name, args, span) => {
+            for arg in args {
+                emit(arg, code);
+            }
+            code.push(Instr::Call(name.clone(), args.len(), *span));
+        }
+        Expr::List(items, _) => {
+            for item in items {
+                emit(item, code);
+            }
+            code.push(Instr::List(items.len()));
+        }
+        Expr::Conditional(test, then, otherwise, span) => {
+            emit(test, code);
+            let branch = code.len();
+            code.push(Instr::Branch(0, *span));
+            emit(then, code);
+            let jump = code.len();
+            code.push(Instr::Jump(0));
+            code[branch] = Instr::Branch(code.len(), *span);
+            emit(otherwise, code);
+            code[jump] = Instr::Jump(code.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::big_num::BigNum;
+    use crate::parser::parse;
+
+    fn compile(input: &str) -> CompiledExpr {
+        parse(input).unwrap().compile()
+    }
+
+    fn row(x: i64, y: i64) -> Environment {
+        let mut env = Environment::new();
+        env.set("x", Value::Number(BigNum::from(x)));
+        env.set("y", Value::Number(BigNum::from(y)));
+        env
+    }
+
+    mod test_compile {
+        use super::*;
+
+        #[test]
+        fn test_rows() {
+            let compiled = compile("x * y + 1/2");
+            let results: Vec<String> = (1..=3)
+                .map(|x| compiled.eval(&row(x, 2)).unwrap().to_string())
+                .collect();
+            assert_eq!(results, ["5/2", "9/2", "13/2"]);
+        }
+
+        #[test]
+        fn test_same_as_tree() {
+            for input in [
+                "x < y ? x - y : (x + y)!",
+                "x > 0 && y / x > 1 || y == 0",
+                "gcd(x, y) ^ 2 - max(x, 3, y) % 4",
+                "z = -x * 2 + --y",
+                "sum(k, x, y, k * y) + prod(x, 1, 3, x)",
+                "[x, y, x * y][x % 3 + 2 * (x < 0 ? 1 : 0)] + [1, 2] * y",
+            ] {
+                let mut expr = parse(input).unwrap();
+                let compiled = expr.compile();
+                for (x, y) in [(1, 5), (4, 2), (0, 0), (-3, 7)] {
+                    let mut env = row(x, y);
+                    assert_eq!(
+                        compiled.eval(&env).map(|v| v.to_string()),
+                        expr.eval_in(&mut env).map(|v| v.to_string()),
+                        "{} with x = {}, y = {}",
+                        input,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_short_circuit() {
+            // The right sides would fail if they were evaluated
+            let compiled = compile("x == 0 || y / x > 1 ? 1 : 1 / (x - x)");
+            assert_eq!(compiled.eval(&row(0, 1)).unwrap().to_string(), "1");
+            assert!(compiled.eval(&row(2, 1)).is_err());
+        }
+
+        #[test]
+        fn test_errors() {
+            let err = compile("x + z").eval(&row(1, 1)).unwrap_err();
+            assert_eq!(err.to_string(), "Eval Error Unknown variable z");
+            let err = compile("x > 0 ? error(\"negative\") : 0").eval(&row(1, 1));
+            assert_eq!(err.unwrap_err().to_string(), "User Error negative");
+            // The environment is only read
+            let env = row(1, 1);
+            assert_eq!(compile("x = 5").eval(&env).unwrap().to_string(), "5");
+            assert_eq!(env.get("x"), Some(&Value::Number(BigNum::from(1))));
+        }
+    }
+}
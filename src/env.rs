@@ -0,0 +1,44 @@
+use crate::common::Value;
+
+use std::collections::BTreeMap;
+
+// Bound to the last successful result in the REPL and `eval_all`
+pub(crate) const ANS: &str = "ans";
+
+/// Variables bound with `name = expr`, shared by the statements of a session
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    vars: BTreeMap<String, Value>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.vars.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    /// Unbind `name`, returning its value if it had one
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.vars.remove(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    // Sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.vars.iter().map(|(name, value)| (name.as_str(), value))
+    }
+}
@@ -0,0 +1,44 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error of an operation on values: `Value` arithmetic, the checked BigNum
+/// and Frac operations and the builtin functions. The refusals the
+/// evaluator reports as their own [`CalcError`](crate::CalcError) variant
+/// keep their kind here, anything else is a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArithError {
+    DivisionByZero,
+    /// A result past the digit limit, or a power too large to compute
+    Overflow(String),
+    /// Past one of the `EvalLimits` of the evaluation
+    LimitExceeded(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArithError::DivisionByZero => write!(f, "Division by zero"),
+            ArithError::Overflow(message)
+            | ArithError::LimitExceeded(message)
+            | ArithError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ArithError {}
+
+impl From<String> for ArithError {
+    fn from(message: String) -> Self {
+        ArithError::Invalid(message)
+    }
+}
+
+// For the APIs that report plain messages, such as `FromStr` and the
+// functions registered on a session
+impl From<ArithError> for String {
+    fn from(error: ArithError) -> Self {
+        error.to_string()
+    }
+}
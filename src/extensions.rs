@@ -0,0 +1,173 @@
+use crate::common::Value;
+use crate::error::ArithError;
+use crate::functions;
+use crate::parser::{Assoc, Lexer};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+type Function = Arc<dyn Fn(&[Value]) -> Result<Value, ArithError> + Send + Sync>;
+type Operator = Arc<dyn Fn(&Value, &Value) -> Result<Value, ArithError> + Send + Sync>;
+
+// Precedence of the loosest and tightest binary operators, see INFIX
+const PRECEDENCES: std::ops::RangeInclusive<u8> = 2..=12;
+
+// Symbol, precedence and grouping of every operator registered in the
+// process. Symbols are leaked once each so `Operator::Custom` stays Copy,
+// and trees using them print the same whichever session reads them.
+static OPERATORS: Mutex<Vec<(&'static str, u8, Assoc)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // What the session evaluating on this thread adds to the language
+    static ACTIVE: RefCell<Option<Arc<Extensions>>> = const { RefCell::new(None) };
+}
+
+/// Functions and operators a `Session` adds to the language
+#[derive(Clone, Default)]
+pub(crate) struct Extensions {
+    // By name, with the argument count
+    functions: BTreeMap<String, (usize, Function)>,
+    operators: BTreeMap<&'static str, Operator>,
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .field("operators", &self.operators.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Extensions {
+    pub(crate) fn add_function(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, ArithError> + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_name {
+            return Err(format!("{} is not a function name", name));
+        }
+        if functions::is_builtin(name) || name == "error" || name == "true" || name == "false" {
+            return Err(format!("{} is already a function", name));
+        }
+        self.functions
+            .insert(name.to_string(), (arity, Arc::new(function)));
+        Ok(())
+    }
+
+    pub(crate) fn add_operator(
+        &mut self,
+        symbol: &str,
+        precedence: u8,
+        assoc: Assoc,
+        apply: impl Fn(&Value, &Value) -> Result<Value, ArithError> + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        let is_symbol = !symbol.is_empty()
+            && !symbol
+                .chars()
+                .any(|c| c.is_alphanumeric() || c.is_whitespace() || "()[],;#\"._".contains(c));
+        if !is_symbol {
+            return Err(format!("{} cannot be an operator symbol", symbol));
+        }
+        if let Ok([lexeme, _]) = <[_; 2]>::try_from(Lexer::new(symbol).collect::<Vec<_>>()) {
+            if lexeme.is_ok() {
+                return Err(format!("{} is already an operator", symbol));
+            }
+        }
+        if !PRECEDENCES.contains(&precedence) {
+            return Err(format!(
+                "Precedence must be from {} to {}, got {}",
+                PRECEDENCES.start(),
+                PRECEDENCES.end(),
+                precedence
+            ));
+        }
+        let symbol = intern(symbol, precedence, assoc);
+        self.operators.insert(symbol, Arc::new(apply));
+        Ok(())
+    }
+}
+
+// The symbol kept for good, with its precedence and grouping updated
+fn intern(symbol: &str, precedence: u8, assoc: Assoc) -> &'static str {
+    let mut operators = OPERATORS.lock().unwrap();
+    match operators.iter_mut().find(|(known, ..)| *known == symbol) {
+        Some(entry) => {
+            *entry = (entry.0, precedence, assoc);
+            entry.0
+        }
+        None => {
+            let symbol: &'static str = Box::leak(symbol.into());
+            operators.push((symbol, precedence, assoc));
+            symbol
+        }
+    }
+}
+
+// Run `evaluate` with the functions and operators of `extensions`, which
+// replace those of any evaluation it is nested in until it returns
+pub(crate) fn with_extensions<T>(extensions: &Arc<Extensions>, evaluate: impl FnOnce() -> T) -> T {
+    let outer = ACTIVE.with(|active| active.replace(Some(Arc::clone(extensions))));
+    let result = evaluate();
+    ACTIVE.with(|active| *active.borrow_mut() = outer);
+    result
+}
+
+fn active<T>(read: impl FnOnce(&Extensions) -> Option<T>) -> Option<T> {
+    ACTIVE.with(|active| active.borrow().as_deref().and_then(read))
+}
+
+// Length of the longest operator of the running session starting `code`
+pub(crate) fn operator_len(code: &str) -> Option<usize> {
+    active(|extensions| {
+        extensions
+            .operators
+            .keys()
+            .filter(|symbol| code.starts_with(**symbol))
+            .map(|symbol| symbol.len())
+            .max()
+    })
+}
+
+// The symbol kept for `symbol` with its precedence and grouping, for any
+// operator registered in the process
+pub(crate) fn syntax(symbol: &str) -> Option<(&'static str, u8, Assoc)> {
+    let operators = OPERATORS.lock().unwrap();
+    operators
+        .iter()
+        .copied()
+        .find(|(known, ..)| *known == symbol)
+}
+
+// `left symbol right` with the running session's operator, None when it has
+// no such operator
+pub(crate) fn apply(
+    symbol: &str,
+    left: &Value,
+    right: &Value,
+) -> Option<Result<Value, ArithError>> {
+    let apply = active(|extensions| extensions.operators.get(symbol).cloned())?;
+    Some(apply(left, right))
+}
+
+// `name(args)` with the running session's function, None when it has no
+// such function
+pub(crate) fn call(name: &str, args: &[Value]) -> Option<Result<Value, ArithError>> {
+    let (arity, function) = active(|extensions| extensions.functions.get(name).cloned())?;
+    if args.len() != arity {
+        return Some(Err(format!(
+            "{} expects {} arguments, got {}",
+            name,
+            arity,
+            args.len()
+        )
+        .into()));
+    }
+    Some(function(args))
+}
@@ -0,0 +1,315 @@
+use crate::common::Value;
+use crate::frac::Frac;
+
+// Digit runs longer than this are shortened unless full output is on
+const ELIDE_ABOVE_DIGITS: usize = 1000;
+// Digits kept at each end of a shortened run
+const ELIDED_EDGE_DIGITS: usize = 20;
+// Longest expansion, up to the end of the first period, shown in repeating
+// decimal form before falling back
+const REPEATING_MAX_DIGITS: usize = 100;
+// Integers up to this many digits are approximated by themselves
+const EXACT_APPROXIMATION_DIGITS: usize = 12;
+
+/// How results are printed. This only affects the text shown to the user,
+/// the exact values are kept for further computation.
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    // Fractions are shown as decimals rounded to this many digits
+    pub display_digits: Option<usize>,
+    // Fractions are shown as exact decimals like `0.1(6)` when the period is
+    // short enough, before trying display_digits
+    pub repeating: bool,
+    // Fractions otherwise shown as mixed numbers like `-2 1/3`
+    pub mixed: bool,
+    // Print every digit of huge numbers instead of `123…789 (5000 digits)`
+    pub full_output: bool,
+}
+
+pub fn format_value(value: &Value, options: &FormatOptions) -> String {
+    let text = format_exact(value, options);
+    if options.full_output {
+        text
+    } else {
+        elide_digits(&text)
+    }
+}
+
+fn format_exact(value: &Value, options: &FormatOptions) -> String {
+    match value {
+        Value::List(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| format_exact(item, options))
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Frac(frac) => {
+            let repeating = options
+                .repeating
+                .then(|| frac.to_repeating_decimal(REPEATING_MAX_DIGITS))
+                .flatten();
+            match (repeating, options.display_digits) {
+                (Some(decimal), _) => decimal,
+                (None, Some(digits)) => trim_zeros(frac.to_decimal_string(digits)),
+                (None, None) if options.mixed => frac.to_mixed_string(),
+                (None, None) => value.to_string(),
+            }
+        }
+        Value::Angle(value, unit)
+            if options.repeating || options.mixed || options.display_digits.is_some() =>
+        {
+            format!("{}{}", format_exact(value, options), unit.suffix())
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Short decimal approximation such as `0.333333` or `1.234568e40`, which
+/// stays readable however many digits the exact value has
+pub fn approximate(value: &Value) -> String {
+    let frac = match value {
+        Value::Angle(value, unit) => return format!("{}{}", approximate(value), unit.suffix()),
+        Value::Bool(_) => return value.to_string(),
+        Value::List(items) => {
+            let items: Vec<String> = items.iter().map(approximate).collect();
+            return format!("[{}]", items.join(", "));
+        }
+        Value::Number(num) if num.num_digits() <= EXACT_APPROXIMATION_DIGITS => {
+            return num.to_string()
+        }
+        Value::Number(num) => Frac::from_bignum(num.clone()),
+        Value::Frac(frac) => frac.clone(),
+    };
+    if frac.is_zero() {
+        return "0".to_string();
+    }
+    let float = frac.to_f64();
+    if (1e-4..1e6).contains(&float.abs()) {
+        return trim_zeros(format!("{:.6}", float));
+    }
+    // Scale by a power of ten first, the value itself may not fit in an f64
+    let (num, den) = (frac.numerator(), frac.denominator());
+    let mut exponent = num.num_digits() as i64 - den.num_digits() as i64;
+    let scaled = if exponent >= 0 {
+        Frac::new(num.clone(), den.shift_left(exponent as usize))
+    } else {
+        Frac::new(
+            num.shift_left(exponent.unsigned_abs() as usize),
+            den.clone(),
+        )
+    };
+    let mut mantissa = scaled.to_f64();
+    if mantissa.abs() < 1.0 {
+        mantissa *= 10.0;
+        exponent -= 1;
+    }
+    mantissa = (mantissa * 1e6).round() / 1e6;
+    if mantissa.abs() >= 10.0 {
+        mantissa /= 10.0;
+        exponent += 1;
+    }
+    format!("{}e{}", trim_zeros(format!("{:.6}", mantissa)), exponent)
+}
+
+// Digit count of an exact value, `n digits` or `n/m digits` for a fraction
+pub fn digit_size(value: &Value) -> String {
+    match value.digit_counts() {
+        (digits, 1) if !matches!(value, Value::Frac(_)) => format!("{} digits", digits),
+        (num, den) => format!("{}/{} digits", num, den),
+    }
+}
+
+// Shorten every run of more than ELIDE_ABOVE_DIGITS digits to its ends and
+// its length, so an accidental huge result stays a single readable line
+fn elide_digits(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut elided = String::with_capacity(text.len().min(4 * ELIDE_ABOVE_DIGITS));
+    let mut start = 0;
+    while start < bytes.len() {
+        let run = bytes[start..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if run > ELIDE_ABOVE_DIGITS {
+            let end = start + run;
+            elided.push_str(&text[start..start + ELIDED_EDGE_DIGITS]);
+            elided.push('…');
+            elided.push_str(&text[end - ELIDED_EDGE_DIGITS..end]);
+            elided.push_str(&format!(" ({} digits)", run));
+            start = end;
+        } else {
+            let end = start + run.max(1);
+            // Only ASCII separators sit between digit runs
+            elided.push_str(&text[start..end]);
+            start = end;
+        }
+    }
+    elided
+}
+
+fn trim_zeros(decimal: String) -> String {
+    if !decimal.contains('.') {
+        return decimal;
+    }
+    let trimmed = decimal.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod test_format_value {
+        use super::*;
+
+        fn digits(n: usize) -> FormatOptions {
+            FormatOptions {
+                display_digits: Some(n),
+                ..FormatOptions::default()
+            }
+        }
+
+        #[test]
+        fn test_exact_by_default() {
+            let value: Value = "1/3".parse().unwrap();
+            assert_eq!(format_value(&value, &FormatOptions::default()), "1/3");
+        }
+
+        #[test]
+        fn test_display_digits() {
+            let value: Value = "1/3".parse().unwrap();
+            assert_eq!(format_value(&value, &digits(10)), "0.3333333333");
+            let value: Value = "-1/2".parse().unwrap();
+            assert_eq!(format_value(&value, &digits(10)), "-0.5");
+            let value: Value = "123".parse().unwrap();
+            assert_eq!(format_value(&value, &digits(2)), "123");
+        }
+    }
+
+    mod test_repeating {
+        use super::*;
+
+        fn repeating() -> FormatOptions {
+            FormatOptions {
+                repeating: true,
+                ..FormatOptions::default()
+            }
+        }
+
+        #[test]
+        fn test_repeating() {
+            let value: Value = "-1/6".parse().unwrap();
+            assert_eq!(format_value(&value, &repeating()), "-0.1(6)");
+            let value: Value = "(1/3)deg".parse().unwrap();
+            assert_eq!(format_value(&value, &repeating()), "0.(3)deg");
+        }
+
+        #[test]
+        fn test_long_period_falls_back() {
+            // The period of 1/109 is 108 digits
+            let value: Value = "1/109".parse().unwrap();
+            assert_eq!(format_value(&value, &repeating()), "1/109");
+            let options = FormatOptions {
+                display_digits: Some(4),
+                ..repeating()
+            };
+            assert_eq!(format_value(&value, &options), "0.0092");
+        }
+    }
+
+    mod test_mixed {
+        use super::*;
+
+        #[test]
+        fn test_mixed() {
+            let options = FormatOptions {
+                mixed: true,
+                ..FormatOptions::default()
+            };
+            let value: Value = "-7/3".parse().unwrap();
+            assert_eq!(format_value(&value, &options), "-2 1/3");
+            let value: Value = "12".parse().unwrap();
+            assert_eq!(format_value(&value, &options), "12");
+            let options = FormatOptions {
+                display_digits: Some(2),
+                ..options
+            };
+            let value: Value = "7/3".parse().unwrap();
+            assert_eq!(format_value(&value, &options), "2.33");
+        }
+    }
+
+    mod test_elide {
+        use super::*;
+
+        #[test]
+        fn test_huge_results_elided() {
+            let digits = "1234567890".repeat(200);
+            let value: Value = format!("-{}/7", digits).parse().unwrap();
+            let expected = format!(
+                "-{}…{} (2000 digits)/7",
+                &digits[..ELIDED_EDGE_DIGITS],
+                &digits[digits.len() - ELIDED_EDGE_DIGITS..]
+            );
+            assert_eq!(format_value(&value, &FormatOptions::default()), expected);
+        }
+
+        #[test]
+        fn test_full_output() {
+            let digits = "9".repeat(ELIDE_ABOVE_DIGITS + 1);
+            let value: Value = digits.parse().unwrap();
+            let full = FormatOptions {
+                full_output: true,
+                ..FormatOptions::default()
+            };
+            assert_eq!(format_value(&value, &full), digits);
+            let shown = "9".repeat(ELIDE_ABOVE_DIGITS);
+            let value: Value = format!("({}/2)deg", shown).parse().unwrap();
+            assert_eq!(value.to_string(), format!("({}/2)deg", shown));
+            assert_eq!(
+                format_value(&value, &FormatOptions::default()),
+                value.to_string()
+            );
+        }
+    }
+
+    mod test_approximate {
+        use super::*;
+
+        fn approx(input: &str) -> String {
+            approximate(&input.parse().unwrap())
+        }
+
+        #[test]
+        fn test_small_values() {
+            assert_eq!(approx("-123456789012"), "-123456789012");
+            assert_eq!(approx("1/3"), "0.333333");
+            assert_eq!(approx("-5/2"), "-2.5");
+            assert_eq!(approx("0"), "0");
+            assert_eq!(approx("(1/8)deg"), "0.125deg");
+        }
+
+        #[test]
+        fn test_scientific() {
+            assert_eq!(approx("1234567890123456"), "1.234568e15");
+            assert_eq!(approx("1/30000"), "3.333333e-5");
+            assert_eq!(approx("-99999999999999999"), "-1e17");
+            let huge = format!("2{}/3", "0".repeat(400));
+            assert_eq!(approx(&huge), "6.666667e399");
+            let tiny = format!("1/7{}", "0".repeat(400));
+            assert_eq!(approx(&tiny), "1.428571e-401");
+        }
+
+        #[test]
+        fn test_digit_size() {
+            assert_eq!(digit_size(&"-1000".parse().unwrap()), "4 digits");
+            assert_eq!(digit_size(&"22/7".parse().unwrap()), "2/1 digits");
+            assert_eq!(digit_size(&"90deg".parse().unwrap()), "2 digits");
+        }
+    }
+}
@@ -1,8 +1,22 @@
 use std::fmt;
 
-use crate::big_num::BigNum;
+use crate::big_num::{BigNum, TOO_LARGE};
+use crate::error::ArithError;
+use crate::limits;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::iter::{Product, Sum};
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+// Decimals whose exponent takes them past this many digits are refused even
+// without a digit limit, before their zeroes are allocated
+const DECIMAL_MAX_DIGITS: usize = 10_000_000;
+
+// Digit count up to which arithmetic results are left unreduced, 0 reduces
+// every result
+static GCD_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug, Clone)]
 pub struct Frac {
@@ -32,51 +46,720 @@ impl Frac {
         })
     }
 
-    fn is_simplified(&self) -> bool {
-        self.numerator.gcd(&self.denominator).unwrap() == BigNum::from_str("1").unwrap()
+    /// Skip the GCD after additions and multiplications until the numerator
+    /// or denominator has more than `digits` digits. Chains of small
+    /// operations then pay for one reduction instead of one per step, at the
+    /// cost of working on larger operands in between. 0, the default,
+    /// reduces every result. An evaluation whose `EvalLimits` set
+    /// `gcd_threshold` uses that instead.
+    pub fn set_gcd_threshold(digits: usize) {
+        GCD_THRESHOLD.store(digits, AtomicOrdering::Relaxed);
+    }
+
+    /// Threshold of the running evaluation, the process-wide one outside of
+    /// evaluations that set their own
+    pub fn gcd_threshold() -> usize {
+        limits::gcd_threshold().unwrap_or_else(|| GCD_THRESHOLD.load(AtomicOrdering::Relaxed))
+    }
+
+    // Result of an arithmetic operation, reduced only once it outgrows the
+    // threshold. The denominator is kept positive either way.
+    fn from_op(numerator: BigNum, denominator: BigNum) -> Frac {
+        let threshold = Frac::gcd_threshold();
+        if threshold == 0 || numerator.num_digits().max(denominator.num_digits()) > threshold {
+            return Frac::new(numerator, denominator);
+        }
+        Frac::new_unreduced(numerator, denominator)
+    }
+
+    /// The same value in lowest terms, for fractions built with
+    /// `new_unreduced` or left unreduced by `set_gcd_threshold`
+    pub fn reduce(&self) -> Frac {
+        self.simplify().unwrap()
+    }
+
+    /// Whether the stored numerator and denominator have no common factor
+    pub fn is_reduced(&self) -> bool {
+        self.numerator.gcd(&self.denominator).unwrap() == BigNum::one()
     }
 
+    /// Panics on a zero denominator, see `try_new`
     pub fn new(numerator: BigNum, denominator: BigNum) -> Self {
+        Frac::try_new(numerator, denominator).unwrap()
+    }
+
+    /// `numerator/denominator` kept as given instead of in lowest terms, only
+    /// the sign moved to the numerator. Building many fractions this way and
+    /// calling `reduce` once when done saves a GCD per fraction, every value
+    /// compares and prints the same either way. Panics on a zero denominator.
+    pub fn new_unreduced(numerator: BigNum, denominator: BigNum) -> Self {
         if denominator.is_zero() {
             panic!("Denominator cannot be zero");
-        };
+        }
+        if denominator.is_negative() {
+            Frac {
+                numerator: -numerator,
+                denominator: -denominator,
+            }
+        } else {
+            Frac {
+                numerator,
+                denominator,
+            }
+        }
+    }
+
+    /// `numerator/denominator` in lowest terms, an error for a zero
+    /// denominator
+    pub fn try_new(numerator: BigNum, denominator: BigNum) -> Result<Frac, String> {
+        if denominator.is_zero() {
+            return Err("Denominator cannot be zero".to_string());
+        }
         Frac {
             numerator,
             denominator,
         }
         .simplify()
-        .unwrap()
     }
 
+    /// Panics on zero, see `checked_inverse`
     pub fn inverse(&self) -> Frac {
-        Frac::new(self.denominator.clone(), self.numerator.clone())
+        self.checked_inverse().unwrap()
+    }
+
+    pub fn checked_inverse(&self) -> Result<Frac, ArithError> {
+        if self.numerator.is_zero() {
+            return Err(ArithError::DivisionByZero);
+        }
+        Ok(Frac::from_op(
+            self.denominator.clone(),
+            self.numerator.clone(),
+        ))
+    }
+
+    /// `self / other`, an error instead of the panic of `/` when `other` is
+    /// zero
+    pub fn checked_div(&self, other: &Frac) -> Result<Frac, ArithError> {
+        Ok(self.clone() * other.checked_inverse()?)
+    }
+
+    /// `self^exponent`, a negative exponent raising the inverse. Powers of a
+    /// reduced fraction are already reduced, so no GCD is taken.
+    ///
+    /// Panics when raising zero to a negative power, see `checked_pow`.
+    pub fn pow(&self, exponent: i64) -> Frac {
+        self.checked_pow(exponent).unwrap()
+    }
+
+    /// `pow` returning an error for zero to a negative power or a result
+    /// over the BigNum digit limit
+    pub fn checked_pow(&self, exponent: i64) -> Result<Frac, ArithError> {
+        if self.numerator.is_zero() && exponent < 0 {
+            return Err("Cannot raise zero to a negative power".to_string().into());
+        }
+        let base = self.reduce();
+        let (numerator, denominator) = if exponent < 0 {
+            (base.denominator, base.numerator)
+        } else {
+            (base.numerator, base.denominator)
+        };
+        let exponent = exponent.unsigned_abs();
+        let numerator = numerator.checked_pow(exponent)?;
+        let denominator = denominator.checked_pow(exponent)?;
+        Ok(if denominator.is_negative() {
+            Frac {
+                numerator: -numerator,
+                denominator: -denominator,
+            }
+        } else {
+            Frac {
+                numerator,
+                denominator,
+            }
+        })
+    }
+
+    /// Numerator as stored, which is only in lowest terms once reduced (see
+    /// `set_gcd_threshold`)
+    pub fn numerator(&self) -> &BigNum {
+        &self.numerator
+    }
+
+    /// Denominator as stored, always positive
+    pub fn denominator(&self) -> &BigNum {
+        &self.denominator
+    }
+
+    /// Numerator and denominator in lowest terms, the denominator positive
+    pub fn into_parts(self) -> (BigNum, BigNum) {
+        let reduced = self.reduce();
+        (reduced.numerator, reduced.denominator)
     }
 
     pub fn from_bignum(num: BigNum) -> Frac {
-        Frac::new(num, BigNum::from_str("1").unwrap())
+        Frac {
+            numerator: num,
+            denominator: BigNum::one(),
+        }
     }
 
     pub fn is_bignum(&self) -> bool {
-        self.denominator == BigNum::from_str("1").unwrap() || (self.numerator.is_zero())
+        self.denominator == BigNum::one()
+            || self.numerator.is_zero()
+            // An unreduced fraction can still be a whole number
+            || (self.numerator.clone() % self.denominator.clone()).is_zero()
     }
 
     pub fn to_bignum(&self) -> Result<BigNum, String> {
         if self.is_bignum() {
-            Ok(self.numerator.clone())
+            Ok(self.numerator.clone() / self.denominator.clone())
         } else {
             Err("Fraction cannot be converted to BigNum".to_string())
         }
     }
 
+    // Digits of numerator and denominator together, which bounds the size
+    // of any cross product taken in arithmetic
+    pub(crate) fn total_digits(&self) -> usize {
+        self.numerator.num_digits() + self.denominator.num_digits()
+    }
+
     pub fn is_zero(&self) -> bool {
         self.numerator.is_zero()
     }
+
+    pub fn is_negative(&self) -> bool {
+        self.numerator.is_negative() && !self.numerator.is_zero()
+    }
+
+    pub fn is_positive(&self) -> bool {
+        !self.numerator.is_negative() && !self.numerator.is_zero()
+    }
+
+    /// -1, 0 or 1 as a fraction
+    pub fn signum(&self) -> Frac {
+        Frac::from_bignum(self.numerator.signum())
+    }
+
+    pub fn abs(&self) -> Frac {
+        Frac {
+            numerator: self.numerator.abs(),
+            denominator: self.denominator.clone(),
+        }
+    }
+
+    /// Decimal form with exactly `precision` digits after the point, rounded
+    /// half away from zero
+    pub fn to_decimal_string(&self, precision: usize) -> String {
+        let scaled = self.numerator.abs() * pow10(precision);
+        let mut digits = scaled.clone() / self.denominator.clone();
+        let remainder = scaled % self.denominator.clone();
+        if remainder * BigNum::from(2) >= self.denominator {
+            digits += BigNum::one();
+        }
+        let sign = if self.is_negative() && !digits.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        let digits = format!("{:0>width$}", digits.to_string(), width = precision + 1);
+        let (int_part, frac_part) = digits.split_at(digits.len() - precision);
+        if precision == 0 {
+            format!("{}{}", sign, int_part)
+        } else {
+            format!("{}{}.{}", sign, int_part, frac_part)
+        }
+    }
+
+    /// Nearest multiple of `1/denominator`, halves rounded away from zero
+    /// like `to_decimal_string`, e.g. 0.3 to 5/16 for the nearest sixteenth.
+    /// The result is in lowest terms, so 8/16 comes back as 1/2.
+    pub fn round_to_denominator(&self, denominator: &BigNum) -> Result<Frac, String> {
+        if *denominator < BigNum::one() {
+            return Err(format!(
+                "Can only round to a positive denominator, got {}",
+                denominator
+            ));
+        }
+        let scaled = self.numerator.abs() * denominator.clone();
+        let mut multiple = scaled.clone() / self.denominator.clone();
+        let remainder = scaled % self.denominator.clone();
+        if remainder * BigNum::from(2) >= self.denominator {
+            multiple += BigNum::one();
+        }
+        if self.is_negative() {
+            multiple = -multiple;
+        }
+        Ok(Frac::new(multiple, denominator.clone()))
+    }
+
+    /// Largest integer at most `self`
+    pub fn floor(&self) -> BigNum {
+        let quotient = self.numerator.clone() / self.denominator.clone();
+        // Division truncates, which is one too high for negative fractions
+        if self.is_negative() && !(self.numerator.clone() % self.denominator.clone()).is_zero() {
+            quotient - BigNum::one()
+        } else {
+            quotient
+        }
+    }
+
+    /// Smallest integer at least `self`
+    pub fn ceil(&self) -> BigNum {
+        -(-self.clone()).floor()
+    }
+
+    /// Nearest integer, halves rounded away from zero
+    pub fn round(&self) -> BigNum {
+        // A denominator of 1 is always valid
+        self.round_to_denominator(&BigNum::one())
+            .unwrap()
+            .into_parts()
+            .0
+    }
+
+    /// Exact square root, an error when it is irrational
+    pub fn sqrt(&self) -> Result<Frac, String> {
+        if self.is_negative() {
+            return Err(format!("Square root of negative number {}", self));
+        }
+        let (numerator, denominator) = self.clone().into_parts();
+        let (root_n, root_d) = (numerator.isqrt()?, denominator.isqrt()?);
+        if root_n.clone() * root_n.clone() != numerator
+            || root_d.clone() * root_d.clone() != denominator
+        {
+            return Err(format!("Square root of {} is irrational", self));
+        }
+        Ok(Frac::new(root_n, root_d))
+    }
+
+    /// Whole part and proper fraction, e.g. `-2 1/3` for -7/3. Whole numbers
+    /// and values between -1 and 1 have only one part.
+    pub fn to_mixed_string(&self) -> String {
+        let reduced = self.reduce();
+        let whole = reduced.numerator.abs() / reduced.denominator.clone();
+        let rest = reduced.numerator.abs() % reduced.denominator.clone();
+        let sign = if reduced.is_negative() { "-" } else { "" };
+        match (whole.is_zero(), rest.is_zero()) {
+            (_, true) => format!("{}{}", sign, whole),
+            (true, false) => format!("{}{}/{}", sign, rest, reduced.denominator),
+            (false, false) => format!("{}{} {}/{}", sign, whole, rest, reduced.denominator),
+        }
+    }
+
+    /// Exact decimal form with the repeating part in parentheses, e.g.
+    /// `0.(142857)` for 1/7 or `-0.1(6)` for -1/6. None when the digits up
+    /// to the end of the first period would exceed `max_digits`.
+    pub fn to_repeating_decimal(&self, max_digits: usize) -> Option<String> {
+        let Frac {
+            numerator,
+            denominator,
+        } = self.reduce();
+        // The period starts after as many digits as the larger power of 2 or
+        // 5 dividing the denominator
+        let mut rest = denominator.clone();
+        let mut twos = 0;
+        while rest.is_even() {
+            rest /= BigNum::from(2);
+            twos += 1;
+        }
+        let mut fives = 0;
+        while (rest.clone() % BigNum::from(5)).is_zero() {
+            rest /= BigNum::from(5);
+            fives += 1;
+        }
+        let prefix = twos.max(fives);
+        if prefix > max_digits {
+            return None;
+        }
+        let mut remainder = numerator.abs() % denominator.clone();
+        let mut digits = String::new();
+        for _ in 0..prefix {
+            remainder = remainder.shift_left(1);
+            digits.push_str(&(remainder.clone() / denominator.clone()).to_string());
+            remainder %= denominator.clone();
+        }
+        if !remainder.is_zero() {
+            let start = remainder.clone();
+            let mut period = String::new();
+            loop {
+                if prefix + period.len() >= max_digits {
+                    return None;
+                }
+                remainder = remainder.shift_left(1);
+                period.push_str(&(remainder.clone() / denominator.clone()).to_string());
+                remainder %= denominator.clone();
+                if remainder == start {
+                    break;
+                }
+            }
+            digits = format!("{}({})", digits, period);
+        }
+        let sign = if numerator.is_negative() { "-" } else { "" };
+        let int_part = numerator.abs() / denominator;
+        Some(if digits.is_empty() {
+            format!("{}{}", sign, int_part)
+        } else {
+            format!("{}{}.{}", sign, int_part, digits)
+        })
+    }
+
+    /// The exact value of a finite f64, a power of two as denominator
+    pub fn from_f64_exact(x: f64) -> Result<Frac, String> {
+        if !x.is_finite() {
+            return Err(format!("{} has no exact fraction", x));
+        }
+        let bits = x.to_bits();
+        let biased = ((bits >> 52) & 0x7FF) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        // x = mantissa * 2^exponent, subnormals lacking the implicit bit
+        let (mut mantissa, mut exponent) = match biased {
+            0 => (fraction, -1074),
+            _ => (fraction | 1 << 52, biased - 1075),
+        };
+        if mantissa == 0 {
+            return Ok(Frac::from_bignum(BigNum::zero()));
+        }
+        let zeros = mantissa.trailing_zeros();
+        mantissa >>= zeros;
+        exponent += zeros as i64;
+        let mantissa = BigNum::from(mantissa);
+        let numerator = if x < 0.0 { -mantissa } else { mantissa };
+        Ok(if exponent >= 0 {
+            Frac::from_bignum(numerator * pow2(exponent as u64))
+        } else {
+            // An odd numerator over a power of two is already reduced
+            Frac {
+                numerator,
+                denominator: pow2(exponent.unsigned_abs()),
+            }
+        })
+    }
+
+    /// Closest fraction to `x` with a denominator of at most
+    /// `max_denominator`, e.g. 355/113 for pi and 1000
+    pub fn approximate(x: f64, max_denominator: u64) -> Result<Frac, String> {
+        Frac::from_f64_exact(x)?.limit_denominator(&BigNum::from(max_denominator))
+    }
+
+    /// Closest fraction with a denominator of at most `max_denominator`,
+    /// found from the continued fraction convergents and the best
+    /// semiconvergent
+    pub fn limit_denominator(&self, max_denominator: &BigNum) -> Result<Frac, String> {
+        if *max_denominator < BigNum::one() {
+            return Err("The largest denominator must be at least 1".to_string());
+        }
+        let reduced = self.reduce();
+        if reduced.denominator <= *max_denominator {
+            return Ok(reduced);
+        }
+        let target = reduced.abs();
+        let (convergent, semiconvergent) = target.bracket(max_denominator);
+        let distance = |frac: &Frac| (frac.clone() - target.clone()).abs();
+        let best = if distance(&convergent) <= distance(&semiconvergent) {
+            convergent
+        } else {
+            semiconvergent
+        };
+        Ok(if reduced.is_negative() { -best } else { best })
+    }
+
+    // For a positive fraction in lowest terms with a denominator over `max`:
+    // the last convergent with a denominator up to `max`, and the largest
+    // semiconvergent after it. They lie on either side of the value with no
+    // fraction of denominator up to `max` between them, the Stern-Brocot
+    // path cut off at `max`.
+    fn bracket(&self, max: &BigNum) -> (Frac, Frac) {
+        let (mut p0, mut q0, mut p1, mut q1) =
+            (BigNum::zero(), BigNum::one(), BigNum::one(), BigNum::zero());
+        let (mut n, mut d) = (self.numerator.clone(), self.denominator.clone());
+        loop {
+            let a = n.clone() / d.clone();
+            let q2 = q0.clone() + a.clone() * q1.clone();
+            if q2 > *max {
+                break;
+            }
+            (p0, q0, p1, q1) = (p1.clone(), q1, p0 + a.clone() * p1, q2);
+            (n, d) = (d.clone(), n - a * d);
+        }
+        let k = (max.clone() - q0.clone()) / q1.clone();
+        let semiconvergent = Frac::new(p0 + k.clone() * p1.clone(), q0 + k * q1.clone());
+        (Frac::new(p1, q1), semiconvergent)
+    }
+
+    /// `(a + c)/(b + d)` for a/b and c/d in lowest terms, which lies between
+    /// them
+    pub fn mediant(&self, other: &Frac) -> Frac {
+        let (a, b) = self.clone().into_parts();
+        let (c, d) = other.clone().into_parts();
+        Frac::new(a + c, b + d)
+    }
+
+    /// Both fractions written over the least common denominator of their
+    /// lowest terms, unreduced (see `new_unreduced`): 3/6 and 2/6 for 1/2 and
+    /// 1/3, the first step of adding them by hand
+    pub fn with_common_denominator(&self, other: &Frac) -> (Frac, Frac) {
+        let (a, b) = self.clone().into_parts();
+        let (c, d) = other.clone().into_parts();
+        let common = b.lcm(&d);
+        let left = Frac::new_unreduced(a * (common.clone() / b), common.clone());
+        let right = Frac::new_unreduced(c * (common.clone() / d), common);
+        (left, right)
+    }
+
+    /// Closest fractions strictly below and strictly above this one with a
+    /// denominator of at most `max_denominator`, its neighbors in the Farey
+    /// sequence of that order. 2/5 and 3/5 for 1/2 and 5.
+    pub fn farey_neighbors(&self, max_denominator: &BigNum) -> Result<(Frac, Frac), String> {
+        if *max_denominator < BigNum::one() {
+            return Err("The largest denominator must be at least 1".to_string());
+        }
+        let reduced = self.reduce();
+        if reduced.denominator > *max_denominator {
+            let (convergent, semiconvergent) = reduced.abs().bracket(max_denominator);
+            let (below, above) = if convergent < semiconvergent {
+                (convergent, semiconvergent)
+            } else {
+                (semiconvergent, convergent)
+            };
+            return Ok(if reduced.is_negative() {
+                (-above, -below)
+            } else {
+                (below, above)
+            });
+        }
+        // The fraction is in the sequence itself. Its two Stern-Brocot
+        // parents p/q, the mediant of which it is, move toward it as
+        // (p + k n)/(q + k d) for as long as the denominator allows.
+        let (n, d) = (reduced.numerator.clone(), reduced.denominator.clone());
+        let parents = if d == BigNum::one() {
+            (
+                (n.clone() - BigNum::one(), BigNum::one()),
+                (n.clone() + BigNum::one(), BigNum::one()),
+            )
+        } else {
+            // The convergent before the last, and what it leaves over
+            let mut before_last = None;
+            for convergent in reduced.convergents() {
+                let convergent = convergent?;
+                if convergent == reduced {
+                    break;
+                }
+                before_last = Some(convergent);
+            }
+            let (p, q) = before_last.unwrap().into_parts();
+            ((n.clone() - p.clone(), d.clone() - q.clone()), (p, q))
+        };
+        let closest = |(p, q): (BigNum, BigNum)| {
+            let k = (max_denominator.clone() - q.clone()) / d.clone();
+            Frac::new(p + k.clone() * n.clone(), q + k * d.clone())
+        };
+        let (first, second) = (closest(parents.0), closest(parents.1));
+        Ok(if first < second {
+            (first, second)
+        } else {
+            (second, first)
+        })
+    }
+
+    /// Terms `[a0; a1, a2, ...]` of the finite continued fraction, with a0
+    /// the floor and every later term positive, e.g. `[3; 7, 16]` for 355/113
+    pub fn to_continued_fraction(&self) -> Vec<BigNum> {
+        let reduced = self.reduce();
+        let (mut n, mut d) = (reduced.numerator, reduced.denominator);
+        let mut terms = Vec::new();
+        while !d.is_zero() {
+            let mut a = n.clone() / d.clone();
+            let mut remainder = n - a.clone() * d.clone();
+            // Division truncates, step down to the floor for negative values
+            if remainder.is_negative() {
+                a -= BigNum::one();
+                remainder += d.clone();
+            }
+            terms.push(a);
+            (n, d) = (d, remainder);
+        }
+        terms
+    }
+
+    /// Value of the continued fraction `[a0; a1, a2, ...]`. Terms are not
+    /// required to be positive, but must not make a denominator zero.
+    pub fn from_continued_fraction(terms: &[BigNum]) -> Result<Frac, String> {
+        Convergents::new(terms.iter().cloned())
+            .last()
+            .ok_or_else(|| "A continued fraction needs at least one term".to_string())?
+    }
+
+    /// The convergents 3, 22/7, 333/106, 355/113, ... of the continued
+    /// fraction, ending with the value itself
+    pub fn convergents(&self) -> Convergents<std::vec::IntoIter<BigNum>> {
+        Convergents::new(self.to_continued_fraction().into_iter())
+    }
+
+    // a/b + c/d with the GCD taken on the smaller pieces instead of the full
+    // result (Henrici): only factors of gcd(b, d) can cancel. Operands in
+    // lowest terms give a sum in lowest terms.
+    fn add_reduced(self, other: Frac) -> Frac {
+        let g = self.denominator.gcd(&other.denominator).unwrap();
+        let b = self.denominator / g.clone();
+        let d = other.denominator / g.clone();
+        let t = self.numerator * d.clone() + other.numerator * b.clone();
+        if t.is_zero() {
+            return Frac::from_bignum(t);
+        }
+        let g2 = t.gcd(&g).unwrap();
+        Frac {
+            numerator: t / g2.clone(),
+            denominator: b * d * (g / g2),
+        }
+    }
+
+    // a/b * c/d cancelling gcd(a, d) and gcd(c, b) before multiplying, so the
+    // product never holds the common factors. Operands in lowest terms give a
+    // product in lowest terms.
+    fn mul_reduced(self, other: Frac) -> Frac {
+        if self.is_zero() || other.is_zero() {
+            return Frac::from_bignum(BigNum::zero());
+        }
+        let g1 = self.numerator.gcd(&other.denominator).unwrap();
+        let g2 = other.numerator.gcd(&self.denominator).unwrap();
+        Frac {
+            numerator: (self.numerator / g1.clone()) * (other.numerator / g2.clone()),
+            denominator: (self.denominator / g2) * (other.denominator / g1),
+        }
+    }
+
+    /// Nearest f64, rounding half to even. Values out of range become
+    /// infinite or zero.
+    pub fn to_f64(&self) -> f64 {
+        if self.is_zero() {
+            return 0.0;
+        }
+        let numerator = self.numerator.abs();
+        // Pick k so that q = floor(numerator * 2^k / denominator) has 55 bits:
+        // 53 for the mantissa and 2 for rounding, plus a sticky remainder bit
+        let log2 = approx_log2(&numerator) - approx_log2(&self.denominator);
+        let mut k = 54 - log2.floor() as i64;
+        let (q, sticky) = loop {
+            let (q, sticky) = scaled_quotient(&numerator, &self.denominator, k);
+            let q = u64::try_from(&q).unwrap_or(u64::MAX);
+            if q >= 1 << 55 {
+                k -= 1;
+            } else if q < 1 << 54 {
+                k += 1;
+            } else {
+                break (q, sticky);
+            }
+        };
+        // floor(log2(|self|))
+        let exponent = 54 - k;
+        let magnitude = if exponent >= -1022 {
+            let (mut mantissa, mut exponent) = (round_shift(q, 2, sticky), exponent);
+            if mantissa == 1 << 53 {
+                mantissa >>= 1;
+                exponent += 1;
+            }
+            if exponent > 1023 {
+                f64::INFINITY
+            } else {
+                let biased = (exponent + 1023) as u64;
+                f64::from_bits(biased << 52 | (mantissa & ((1 << 52) - 1)))
+            }
+        } else {
+            // Subnormal, counted in units of 2^-1074
+            let shift = k - 1074;
+            if shift >= 56 {
+                0.0
+            } else {
+                f64::from_bits(round_shift(q, shift as u32, sticky))
+            }
+        };
+        if self.is_negative() {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+// log2 from the leading digits, good to well within one bit
+fn approx_log2(num: &BigNum) -> f64 {
+    let digits = num.abs().to_string();
+    let lead = &digits[..digits.len().min(17)];
+    let rest = (digits.len() - lead.len()) as f64;
+    lead.parse::<f64>().unwrap().log2() + rest * std::f64::consts::LOG2_10
+}
+
+fn pow10(exponent: usize) -> BigNum {
+    BigNum::from_str(&format!("1{}", "0".repeat(exponent))).unwrap()
+}
+
+fn pow2(exponent: u64) -> BigNum {
+    BigNum::from(2).pow(exponent)
+}
+
+// floor(n * 2^k / d) and whether anything was left over
+fn scaled_quotient(n: &BigNum, d: &BigNum, k: i64) -> (BigNum, bool) {
+    let (n, d) = if k >= 0 {
+        (n.clone() * pow2(k as u64), d.clone())
+    } else {
+        (n.clone(), d.clone() * pow2(k.unsigned_abs()))
+    };
+    let q = n.clone() / d.clone();
+    (q, !(n % d).is_zero())
+}
+
+// q >> shift rounded half to even, `sticky` marks nonzero bits below q
+fn round_shift(q: u64, shift: u32, sticky: bool) -> u64 {
+    let kept = q >> shift;
+    let half = 1 << (shift - 1);
+    let dropped = q & ((1 << shift) - 1);
+    if dropped > half || (dropped == half && (sticky || kept & 1 == 1)) {
+        kept + 1
+    } else {
+        kept
+    }
 }
 
 pub trait IntoFrac {
     fn to_frac(self) -> Frac;
 }
 
+/// Successive convergents h/k of a sequence of continued fraction terms,
+/// from h(n) = a(n) h(n-1) + h(n-2) and the same for k
+#[derive(Debug, Clone)]
+pub struct Convergents<I> {
+    terms: I,
+    // h(n-1), h(n-2), k(n-1), k(n-2)
+    previous: (BigNum, BigNum, BigNum, BigNum),
+}
+
+impl<I: Iterator<Item = BigNum>> Convergents<I> {
+    pub fn new(terms: I) -> Self {
+        Convergents {
+            terms,
+            previous: (BigNum::one(), BigNum::zero(), BigNum::zero(), BigNum::one()),
+        }
+    }
+}
+
+impl<I: Iterator<Item = BigNum>> Iterator for Convergents<I> {
+    // An error when a denominator comes out zero
+    type Item = Result<Frac, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let a = self.terms.next()?;
+        let (h1, h0, k1, k0) = self.previous.clone();
+        let h = a.clone() * h1.clone() + h0;
+        let k = a * k1.clone() + k0;
+        self.previous = (h.clone(), h1, k.clone(), k1);
+        if k.is_zero() {
+            return Some(Err("Continued fraction has a zero denominator".to_string()));
+        }
+        Some(Ok(Frac::new(h, k)))
+    }
+}
+
 impl IntoFrac for Frac {
     fn to_frac(self) -> Frac {
         self
@@ -85,14 +768,79 @@ impl IntoFrac for Frac {
 
 impl fmt::Display for Frac {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}/{}", self.numerator, self.denominator)
+        let frac = self.reduce();
+        write!(f, "{}/{}", frac.numerator, frac.denominator)
     }
 }
 
+// Exact value of a decimal such as `-3.25`, `.5` or `2.5e-3`, an Overflow
+// past the digit limit
+pub(crate) fn parse_decimal(s: &str) -> Result<Frac, ArithError> {
+    let invalid = || format!("Invalid decimal {}", s);
+    let (mantissa, exponent) = match s.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().map_err(|_| invalid())?),
+        None => (s, 0),
+    };
+    let (negative, mantissa) = match mantissa.as_bytes().first() {
+        Some(b'-') => (true, &mantissa[1..]),
+        Some(b'+') => (false, &mantissa[1..]),
+        _ => (false, mantissa),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if int_part.len() + frac_part.len() == 0 || !all_digits(int_part) || !all_digits(frac_part) {
+        return Err(invalid().into());
+    }
+    let mut digits: BigNum = format!("0{}{}", int_part, frac_part).parse()?;
+    if negative {
+        digits = -digits;
+    }
+    // Value is digits * 10^scale
+    let scale = exponent
+        .checked_sub(frac_part.len() as i64)
+        .ok_or_else(invalid)?;
+    let places = usize::try_from(scale.unsigned_abs()).map_err(|_| invalid())?;
+    let digits_written = places.saturating_add(int_part.len() + frac_part.len());
+    if digits_written > DECIMAL_MAX_DIGITS {
+        return Err(ArithError::Overflow(format!(
+            "{}: up to {} digits, decimals are limited to {}",
+            TOO_LARGE, digits_written, DECIMAL_MAX_DIGITS
+        )));
+    }
+    BigNum::check_digits(digits_written)?;
+    Ok(if scale >= 0 {
+        Frac::from_bignum(digits.shift_left(places))
+    } else {
+        Frac::new(digits, BigNum::one().shift_left(places))
+    })
+}
+
+// Mixed number `2 1/3`, the sign of the whole part applying to the fraction
+// too. Not `2_1/3`, which expressions read as `21/3`.
+fn parse_mixed(whole: &str, fraction: &str) -> Result<Frac, String> {
+    // Checked on the text so that `-0 1/2` stays negative
+    let negative = whole.starts_with('-');
+    let whole: BigNum = whole.parse()?;
+    let fraction: Frac = fraction.parse()?;
+    if fraction.is_negative() {
+        return Err("The fraction of a mixed number cannot be negative".to_string());
+    }
+    let magnitude = Frac::from_bignum(whole.abs()) + fraction;
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
+// `a/b`, a mixed number like `2 1/3`, or a decimal like `3.25` or `2.5e-3`
+// read exactly
 impl FromStr for Frac {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains('/') && s.contains(['.', 'e', 'E']) {
+            return parse_decimal(s).map_err(String::from);
+        }
+        if let Some((whole, fraction)) = s.split_once(' ') {
+            return parse_mixed(whole, fraction);
+        }
         let parts: Vec<&str> = s.split('/').collect();
         if parts.len() != 2 {
             return Err(
@@ -102,21 +850,57 @@ impl FromStr for Frac {
 
         let numerator = parts[0].parse::<BigNum>()?;
         let denominator = parts[1].parse::<BigNum>()?;
-
-        if denominator.is_zero() {
-            return Err("Denominator cannot be zero".to_string());
-        }
-
-        Ok(Frac::new(numerator, denominator))
+        Frac::try_new(numerator, denominator)
     }
 }
 
+// Denominators are positive, so cross multiplication compares the values
+// whether or not either side is reduced
 impl PartialEq for Frac {
     fn eq(&self, other: &Self) -> bool {
         self.numerator.clone() * other.denominator.clone()
             == self.denominator.clone() * other.numerator.clone()
-            && self.is_simplified()
-            && other.is_simplified()
+    }
+}
+
+impl Eq for Frac {}
+
+impl PartialOrd for Frac {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Cross multiplication again, the positive denominators keep the order
+impl Ord for Frac {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.numerator.clone() * other.denominator.clone())
+            .cmp(&(self.denominator.clone() * other.numerator.clone()))
+    }
+}
+
+// Compared by value whether or not the fraction is whole, 4/2 == 2
+impl PartialEq<BigNum> for Frac {
+    fn eq(&self, other: &BigNum) -> bool {
+        self.numerator == self.denominator.clone() * other.clone()
+    }
+}
+
+impl PartialOrd<BigNum> for Frac {
+    fn partial_cmp(&self, other: &BigNum) -> Option<Ordering> {
+        Some(
+            self.numerator
+                .cmp(&(self.denominator.clone() * other.clone())),
+        )
+    }
+}
+
+// Hashes the reduced form, so fractions equal by value hash the same
+impl Hash for Frac {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let reduced = self.reduce();
+        reduced.numerator.hash(state);
+        reduced.denominator.hash(state);
     }
 }
 
@@ -124,7 +908,10 @@ impl Neg for Frac {
     type Output = Frac;
 
     fn neg(self) -> Self::Output {
-        Frac::new(-self.numerator, self.denominator)
+        Frac {
+            numerator: -self.numerator,
+            denominator: self.denominator,
+        }
     }
 }
 
@@ -132,10 +919,13 @@ impl Add for Frac {
     type Output = Frac;
 
     fn add(self, other: Self) -> Self::Output {
+        if Frac::gcd_threshold() == 0 {
+            return self.add_reduced(other);
+        }
         let numerator = self.numerator.clone() * other.denominator.clone()
             + self.denominator.clone() * other.numerator.clone();
         let denominator = self.denominator.clone() * other.denominator.clone();
-        Frac::new(numerator, denominator)
+        Frac::from_op(numerator, denominator)
     }
 }
 
@@ -153,6 +943,18 @@ impl AddAssign for Frac {
     }
 }
 
+impl Sum for Frac {
+    fn sum<I: Iterator<Item = Frac>>(iter: I) -> Frac {
+        iter.fold(BigNum::zero().to_frac(), |acc, f| acc + f)
+    }
+}
+
+impl<'a> Sum<&'a Frac> for Frac {
+    fn sum<I: Iterator<Item = &'a Frac>>(iter: I) -> Frac {
+        iter.cloned().sum()
+    }
+}
+
 impl Sub for Frac {
     type Output = Frac;
 
@@ -179,9 +981,12 @@ impl Mul for Frac {
     type Output = Frac;
 
     fn mul(self, other: Self) -> Self::Output {
+        if Frac::gcd_threshold() == 0 {
+            return self.mul_reduced(other);
+        }
         let numerator = self.numerator.clone() * other.numerator.clone();
         let denominator = self.denominator.clone() * other.denominator.clone();
-        Frac::new(numerator, denominator)
+        Frac::from_op(numerator, denominator)
     }
 }
 
@@ -199,9 +1004,22 @@ impl MulAssign for Frac {
     }
 }
 
+impl Product for Frac {
+    fn product<I: Iterator<Item = Frac>>(iter: I) -> Frac {
+        iter.fold(BigNum::one().to_frac(), |acc, f| acc * f)
+    }
+}
+
+impl<'a> Product<&'a Frac> for Frac {
+    fn product<I: Iterator<Item = &'a Frac>>(iter: I) -> Frac {
+        iter.cloned().product()
+    }
+}
+
 impl Div for Frac {
     type Output = Frac;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, other: Self) -> Self::Output {
         self * other.inverse()
     }
@@ -278,6 +1096,142 @@ mod tests {
             assert_eq!(inverse.numerator, BigNum::from_str("2").unwrap());
             assert_eq!(inverse.denominator, BigNum::from_str("1").unwrap());
         }
+
+        #[test]
+        fn test_checked() {
+            let zero = Frac::from_str("0/5").unwrap();
+            let half = Frac::from_str("-1/2").unwrap();
+            assert_eq!(zero.checked_inverse(), Err(ArithError::DivisionByZero));
+            assert_eq!(half.checked_inverse(), Ok(Frac::from_str("-2/1").unwrap()));
+            assert_eq!(half.checked_div(&half), Ok(Frac::from_str("1/1").unwrap()));
+            assert!(half.checked_div(&zero).is_err());
+            assert_eq!(
+                Frac::try_new(BigNum::from(6), BigNum::from(-4)),
+                Frac::from_str("-3/2")
+            );
+            assert!(Frac::try_new(BigNum::one(), BigNum::zero()).is_err());
+        }
+
+        #[test]
+        #[should_panic]
+        fn test_inverse_of_zero() {
+            Frac::from_str("0/1").unwrap().inverse();
+        }
+    }
+
+    mod test_parts {
+        use super::*;
+
+        #[test]
+        fn test_into_parts() {
+            let frac = Frac::new(BigNum::from(6), BigNum::from(-4));
+            assert_eq!(frac.numerator(), &BigNum::from(-3));
+            assert_eq!(frac.denominator(), &BigNum::from(2));
+            assert_eq!(frac.into_parts(), (BigNum::from(-3), BigNum::from(2)));
+            let unreduced = Frac {
+                numerator: BigNum::from(10),
+                denominator: BigNum::from(4),
+            };
+            assert_eq!(unreduced.into_parts(), (BigNum::from(5), BigNum::from(2)));
+        }
+
+        #[test]
+        fn test_sign() {
+            let cases = [("-3/4", -1, "3/4"), ("0/1", 0, "0/1"), ("5/2", 1, "5/2")];
+            for (input, sign, abs) in cases {
+                let frac = Frac::from_str(input).unwrap();
+                assert_eq!(frac.signum(), Frac::from_bignum(BigNum::from(sign)));
+                assert_eq!(frac.abs(), Frac::from_str(abs).unwrap());
+                assert_eq!(frac.is_negative(), sign < 0);
+                assert_eq!(frac.is_positive(), sign > 0);
+            }
+        }
+    }
+
+    mod test_unreduced {
+        use super::*;
+
+        #[test]
+        fn test_new_unreduced() {
+            let frac = Frac::new_unreduced(BigNum::from(6), BigNum::from(-4));
+            assert_eq!(frac.numerator(), &BigNum::from(-6));
+            assert_eq!(frac.denominator(), &BigNum::from(4));
+            assert!(!frac.is_reduced());
+            assert_eq!(frac, Frac::from_str("-3/2").unwrap());
+            assert_eq!(frac.to_string(), "-3/2");
+            let reduced = frac.reduce();
+            assert!(reduced.is_reduced());
+            assert_eq!(reduced.numerator(), &BigNum::from(-3));
+            assert_eq!(reduced.denominator(), &BigNum::from(2));
+        }
+
+        #[test]
+        #[should_panic]
+        fn test_zero_denominator() {
+            Frac::new_unreduced(BigNum::one(), BigNum::zero());
+        }
+
+        #[test]
+        fn test_with_common_denominator() {
+            let half = Frac::from_str("1/2").unwrap();
+            let (left, right) = half.with_common_denominator(&Frac::from_str("-2/6").unwrap());
+            assert_eq!(left.numerator(), &BigNum::from(3));
+            assert_eq!(left.denominator(), &BigNum::from(6));
+            assert_eq!(right.numerator(), &BigNum::from(-2));
+            assert_eq!(right.denominator(), &BigNum::from(6));
+            let (left, _) = half.with_common_denominator(&Frac::from_bignum(BigNum::from(3)));
+            assert_eq!(left.denominator(), &BigNum::from(2));
+        }
+    }
+
+    mod test_round_to_denominator {
+        use super::*;
+
+        #[test]
+        fn test_round_to_denominator() {
+            let cases = [
+                ("3/10", 16, "5/16"),
+                ("-3/10", 16, "-5/16"),
+                ("1/32", 16, "1/16"),
+                ("-1/32", 16, "-1/16"),
+                ("1/33", 16, "0/1"),
+                ("15/32", 16, "1/2"),
+                ("22/7", 1, "3/1"),
+                ("7/2", 1, "4/1"),
+            ];
+            for (frac, n, expected) in cases {
+                let rounded = Frac::from_str(frac)
+                    .unwrap()
+                    .round_to_denominator(&BigNum::from(n));
+                assert_eq!(rounded, Frac::from_str(expected), "{} to 1/{}", frac, n);
+            }
+            let frac = Frac::from_str("1/3").unwrap();
+            assert!(frac.round_to_denominator(&BigNum::zero()).is_err());
+            assert!(frac.round_to_denominator(&BigNum::from(-4)).is_err());
+        }
+    }
+
+    mod test_floor_ceil_round {
+        use super::*;
+
+        #[test]
+        fn test_floor_ceil_round() {
+            let cases = [
+                ("7/2", 3, 4, 4),
+                ("-7/2", -4, -3, -4),
+                ("10/3", 3, 4, 3),
+                ("-10/3", -4, -3, -3),
+                ("6/3", 2, 2, 2),
+                ("-6/3", -2, -2, -2),
+                ("0/5", 0, 0, 0),
+            ];
+            for (frac, floor, ceil, round) in cases {
+                let frac = Frac::from_str(frac).unwrap();
+                assert_eq!(frac.floor(), BigNum::from(floor), "floor {}", frac);
+                assert_eq!(frac.ceil(), BigNum::from(ceil), "ceil {}", frac);
+                assert_eq!(frac.round(), BigNum::from(round), "round {}", frac);
+            }
+        }
     }
 
     mod test_display {
@@ -312,6 +1266,55 @@ mod tests {
             );
         }
 
+        #[test]
+        fn test_from_str_decimal() {
+            let cases = [
+                ("3.25", "13/4"),
+                ("-0.5", "-1/2"),
+                (".125", "1/8"),
+                ("+4.", "4/1"),
+                ("2.5e-3", "1/400"),
+                ("-1.5E2", "-150/1"),
+                ("1e3", "1000/1"),
+                ("0.000", "0/1"),
+            ];
+            for (input, expected) in cases {
+                assert_eq!(Frac::from_str(input), Frac::from_str(expected), "{}", input);
+            }
+            let frac = Frac::from_str("0.10").unwrap();
+            assert_eq!(frac.denominator, BigNum::from(10));
+        }
+
+        #[test]
+        fn test_from_str_invalid_decimal() {
+            for input in [".", "1.2.3", "1.-2", "e5", "1e", "1.5e2.5", "--1.5", "1.5x"] {
+                assert!(Frac::from_str(input).is_err(), "{}", input);
+            }
+        }
+
+        #[test]
+        fn test_from_str_huge_exponent() {
+            // Refused without a digit limit, before allocating the zeroes
+            for input in ["1e99999999999", "1e-99999999999", "-2.5e10000001"] {
+                let err = Frac::from_str(input).unwrap_err();
+                assert!(err.starts_with("Result too large"), "{}", input);
+            }
+            let frac = Frac::from_str("1e-10000").unwrap();
+            assert_eq!(frac.denominator.to_string().len(), 10_001);
+        }
+
+        #[test]
+        fn test_from_str_mixed() {
+            assert_eq!(Frac::from_str("2 1/3"), Frac::from_str("7/3"));
+            assert_eq!(Frac::from_str("-2 1/3"), Frac::from_str("-7/3"));
+            assert_eq!(Frac::from_str("0 2/4"), Frac::from_str("1/2"));
+            assert_eq!(Frac::from_str("-0 1/2"), Frac::from_str("-1/2"));
+            assert!(Frac::from_str("2_1/3").is_err());
+            assert!(Frac::from_str("2 -1/3").is_err());
+            assert!(Frac::from_str("2 1").is_err());
+            assert!(Frac::from_str("1/2 1/3").is_err());
+        }
+
         #[test]
         fn test_from_str_invalid_denominator() {
             let frac = Frac::from_str("2/0");
@@ -319,6 +1322,299 @@ mod tests {
         }
     }
 
+    mod test_to_decimal_string {
+        use super::*;
+
+        #[test]
+        fn test_to_decimal_string() {
+            let frac = Frac::from_str("1/3").unwrap();
+            assert_eq!(frac.to_decimal_string(5), "0.33333");
+            assert_eq!(frac.to_decimal_string(0), "0");
+            let frac = Frac::from_str("-22/7").unwrap();
+            assert_eq!(frac.to_decimal_string(3), "-3.143");
+        }
+
+        #[test]
+        fn test_to_decimal_string_rounding() {
+            let frac = Frac::from_str("1/8").unwrap();
+            assert_eq!(frac.to_decimal_string(2), "0.13");
+            assert_eq!((-frac).to_decimal_string(2), "-0.13");
+            let frac = Frac::from_str("2/3").unwrap();
+            assert_eq!(frac.to_decimal_string(0), "1");
+            let frac = Frac::from_str("-1/1000").unwrap();
+            assert_eq!(frac.to_decimal_string(2), "0.00");
+        }
+    }
+
+    mod test_to_mixed_string {
+        use super::*;
+
+        #[test]
+        fn test_to_mixed_string() {
+            let cases = [
+                ("7/3", "2 1/3"),
+                ("-7/3", "-2 1/3"),
+                ("-1/3", "-1/3"),
+                ("8/4", "2"),
+                ("0/5", "0"),
+                ("-14/6", "-2 1/3"),
+            ];
+            for (input, expected) in cases {
+                assert_eq!(Frac::from_str(input).unwrap().to_mixed_string(), expected);
+            }
+        }
+
+        #[test]
+        fn test_round_trip() {
+            for input in ["22/7", "-9/4", "1/2"] {
+                let frac = Frac::from_str(input).unwrap();
+                assert_eq!(Frac::from_str(&frac.to_mixed_string()), Ok(frac));
+            }
+        }
+    }
+
+    mod test_sqrt {
+        use super::*;
+
+        #[test]
+        fn test_sqrt() {
+            let sqrt = |s: &str| Frac::from_str(s).unwrap().sqrt();
+            assert_eq!(sqrt("9/4"), Frac::from_str("3/2"));
+            assert_eq!(sqrt("18/8"), Frac::from_str("3/2"));
+            assert_eq!(sqrt("0/1"), Frac::from_str("0/1"));
+            assert!(sqrt("2/1").is_err());
+            assert!(sqrt("1/2").is_err());
+            assert!(sqrt("-1/4").is_err());
+        }
+    }
+
+    mod test_to_repeating_decimal {
+        use super::*;
+
+        fn repeating(input: &str) -> Option<String> {
+            Frac::from_str(input).unwrap().to_repeating_decimal(50)
+        }
+
+        #[test]
+        fn test_repeating() {
+            assert_eq!(repeating("1/7").unwrap(), "0.(142857)");
+            assert_eq!(repeating("-1/6").unwrap(), "-0.1(6)");
+            assert_eq!(repeating("22/7").unwrap(), "3.(142857)");
+            assert_eq!(repeating("1/3").unwrap(), "0.(3)");
+            assert_eq!(repeating("7/12").unwrap(), "0.58(3)");
+            assert_eq!(repeating("1/81").unwrap(), "0.(012345679)");
+        }
+
+        #[test]
+        fn test_terminating() {
+            assert_eq!(repeating("1/4").unwrap(), "0.25");
+            assert_eq!(repeating("-5/2").unwrap(), "-2.5");
+            assert_eq!(repeating("1/1024").unwrap(), "0.0009765625");
+            assert_eq!(repeating("6/3").unwrap(), "2");
+        }
+
+        #[test]
+        fn test_too_long() {
+            // 1/97 repeats every 96 digits
+            assert_eq!(repeating("1/97"), None);
+            let frac = Frac::from_str("1/97").unwrap();
+            assert!(frac.to_repeating_decimal(96).is_some());
+            assert_eq!(Frac::from_str("1/8").unwrap().to_repeating_decimal(2), None);
+        }
+    }
+
+    mod test_from_f64 {
+        use super::*;
+
+        #[test]
+        fn test_from_f64_exact() {
+            let cases = [
+                (0.5, "1/2"),
+                (-3.0, "-3/1"),
+                (0.1, "3602879701896397/36028797018963968"),
+                (1e20, "100000000000000000000/1"),
+                (0.0, "0/1"),
+            ];
+            for (x, expected) in cases {
+                assert_eq!(Frac::from_f64_exact(x), Frac::from_str(expected), "{}", x);
+            }
+            let tiny = Frac::from_f64_exact(f64::from_bits(1)).unwrap();
+            assert_eq!(tiny, Frac::new(BigNum::one(), pow2(1074)));
+            assert!(Frac::from_f64_exact(f64::NAN).is_err());
+            assert!(Frac::from_f64_exact(f64::INFINITY).is_err());
+        }
+
+        #[test]
+        fn test_round_trip() {
+            for x in [0.1, -2.75, 1.0 / 3.0, 6.02e23, 1e-300] {
+                assert_eq!(Frac::from_f64_exact(x).unwrap().to_f64(), x);
+            }
+        }
+
+        #[test]
+        fn test_approximate() {
+            let cases = [
+                (std::f64::consts::PI, 1000, "355/113"),
+                (std::f64::consts::PI, 10, "22/7"),
+                (0.333333, 100, "1/3"),
+                (-0.333333, 100, "-1/3"),
+                (0.1, 10, "1/10"),
+                (2.7, 1, "3/1"),
+                (0.75, 1000, "3/4"),
+            ];
+            for (x, max, expected) in cases {
+                assert_eq!(Frac::approximate(x, max), Frac::from_str(expected), "{}", x);
+            }
+            assert!(Frac::approximate(0.5, 0).is_err());
+        }
+    }
+
+    mod test_cmp_bignum {
+        use super::*;
+
+        #[test]
+        fn test_cmp_bignum() {
+            let two = BigNum::from(2);
+            let unreduced = Frac::new_unreduced(BigNum::from(4), BigNum::from(2));
+            assert_eq!(unreduced, two);
+            assert_eq!(two, unreduced);
+            let frac = Frac::from_str("-7/3").unwrap();
+            assert!(frac != BigNum::from(-2));
+            assert!(frac < BigNum::from(-2) && frac > BigNum::from(-3));
+            assert!(BigNum::from(-2) > frac && BigNum::from(-3) <= frac);
+            assert!(Frac::from_str("3/2").unwrap() >= BigNum::one());
+        }
+    }
+
+    mod test_farey {
+        use super::*;
+
+        fn frac(s: &str) -> Frac {
+            Frac::from_str(s).unwrap()
+        }
+
+        #[test]
+        fn test_mediant() {
+            assert_eq!(frac("1/2").mediant(&frac("2/3")), frac("3/5"));
+            assert_eq!(frac("2/4").mediant(&frac("1/2")), frac("1/2"));
+            assert_eq!(frac("-1/2").mediant(&frac("1/3")), frac("0/1"));
+        }
+
+        #[test]
+        fn test_farey_neighbors() {
+            let pi = Frac::from_f64_exact(std::f64::consts::PI).unwrap();
+            let cases = [
+                (frac("1/2"), 5, "2/5", "3/5"),
+                (frac("1/3"), 5, "1/4", "2/5"),
+                (frac("1/5"), 5, "0/1", "1/4"),
+                (frac("2/1"), 3, "5/3", "7/3"),
+                (frac("0/1"), 4, "-1/4", "1/4"),
+                (frac("-1/2"), 5, "-3/5", "-2/5"),
+                (frac("-7/3"), 3, "-5/2", "-2/1"),
+                (pi.clone(), 7, "3/1", "22/7"),
+                (-pi, 113, "-355/113", "-333/106"),
+                (frac("3/10"), 4, "1/4", "1/3"),
+            ];
+            for (x, max, below, above) in cases {
+                let neighbors = x.farey_neighbors(&BigNum::from(max));
+                assert_eq!(
+                    neighbors,
+                    Ok((frac(below), frac(above))),
+                    "{} in F{}",
+                    x,
+                    max
+                );
+            }
+            assert!(frac("1/2").farey_neighbors(&BigNum::zero()).is_err());
+        }
+    }
+
+    mod test_continued_fraction {
+        use super::*;
+
+        fn terms(values: &[i64]) -> Vec<BigNum> {
+            values.iter().map(|&a| BigNum::from(a)).collect()
+        }
+
+        #[test]
+        fn test_to_continued_fraction() {
+            let cases: [(&str, &[i64]); 6] = [
+                ("355/113", &[3, 7, 16]),
+                ("-7/3", &[-3, 1, 2]),
+                ("1/3", &[0, 3]),
+                ("-1/2", &[-1, 2]),
+                ("5/1", &[5]),
+                ("0/1", &[0]),
+            ];
+            for (frac, expected) in cases {
+                let frac = Frac::from_str(frac).unwrap();
+                assert_eq!(frac.to_continued_fraction(), terms(expected), "{}", frac);
+                assert_eq!(Frac::from_continued_fraction(&terms(expected)), Ok(frac));
+            }
+        }
+
+        #[test]
+        fn test_from_continued_fraction() {
+            // Non-canonical forms still evaluate
+            let half = Frac::from_str("1/2").unwrap();
+            assert_eq!(Frac::from_continued_fraction(&terms(&[0, 1, 1])), Ok(half));
+            assert!(Frac::from_continued_fraction(&[]).is_err());
+            assert!(Frac::from_continued_fraction(&terms(&[1, 0])).is_err());
+        }
+
+        #[test]
+        fn test_convergents() {
+            let pi = Frac::approximate(std::f64::consts::PI, 40000).unwrap();
+            let convergents: Vec<String> =
+                pi.convergents().map(|c| c.unwrap().to_string()).collect();
+            assert_eq!(convergents[..4], ["3/1", "22/7", "333/106", "355/113"]);
+            assert_eq!(convergents.last().unwrap(), &pi.to_string());
+        }
+    }
+
+    mod test_to_f64 {
+        use super::*;
+
+        #[test]
+        fn test_to_f64() {
+            assert_eq!(Frac::from_str("1/3").unwrap().to_f64(), 1.0 / 3.0);
+            assert_eq!(Frac::from_str("-1/2").unwrap().to_f64(), -0.5);
+            assert_eq!(Frac::from_str("1/10").unwrap().to_f64(), 0.1);
+            assert_eq!(Frac::from_str("22/7").unwrap().to_f64(), 22.0 / 7.0);
+        }
+
+        #[test]
+        fn test_to_f64_ties_to_even() {
+            let one_ulp = pow2(53);
+            // 1 + 2^-53 is halfway between 1 and the next double
+            let frac = Frac::new(one_ulp.clone() + BigNum::one(), one_ulp.clone());
+            assert_eq!(frac.to_f64(), 1.0);
+            // 1 + 3 * 2^-53 is halfway and rounds up to the even mantissa
+            let frac = Frac::new(one_ulp.clone() + BigNum::from(3), one_ulp);
+            assert_eq!(frac.to_f64(), 1.0 + 4.0 * f64::EPSILON / 2.0);
+        }
+
+        #[test]
+        fn test_to_f64_out_of_range() {
+            let huge = BigNum::from_str(&format!("1{}", "0".repeat(400))).unwrap();
+            let frac = Frac::new(huge.clone(), BigNum::from(3));
+            assert_eq!(frac.to_f64(), f64::INFINITY);
+            assert_eq!((-frac).to_f64(), f64::NEG_INFINITY);
+            assert_eq!(Frac::new(BigNum::one(), huge).to_f64(), 0.0);
+        }
+
+        #[test]
+        fn test_to_f64_subnormal() {
+            let frac = Frac::new(BigNum::one(), pow2(1074));
+            assert_eq!(frac.to_f64(), f64::from_bits(1));
+            // Exactly half the smallest subnormal rounds to even, which is zero
+            let frac = Frac::new(BigNum::one(), pow2(1075));
+            assert_eq!(frac.to_f64(), 0.0);
+            let frac = Frac::new(BigNum::from(3), pow2(1076));
+            assert_eq!(frac.to_f64(), f64::from_bits(1));
+        }
+    }
+
     mod test_eq {
         use super::*;
 
@@ -336,6 +1632,77 @@ mod tests {
         }
     }
 
+    mod test_eq_regression {
+        use super::*;
+
+        // Equal values built along different paths, reduced or not
+        #[test]
+        fn test_construction_paths() {
+            let half = Frac::from_str("1/2").unwrap();
+            let paths = [
+                Frac {
+                    numerator: BigNum::from(3),
+                    denominator: BigNum::from(6),
+                },
+                Frac::new(BigNum::from(-4), BigNum::from(-8)),
+                Frac::from_str("2/4").unwrap(),
+                Frac::from_str("0.5").unwrap(),
+                Frac::from_str("4/2").unwrap().inverse(),
+                Frac::from_str("1/6").unwrap() + Frac::from_str("1/3").unwrap(),
+                Frac::from_str("3/4").unwrap() * Frac::from_str("2/3").unwrap(),
+            ];
+            for frac in paths {
+                assert_eq!(frac, half);
+                assert_eq!(half, frac);
+            }
+            assert_ne!(Frac::from_str("-1/2").unwrap(), half);
+            assert_eq!(
+                Frac::from_bignum(BigNum::from(3)),
+                Frac::from_str("6/2").unwrap()
+            );
+        }
+    }
+
+    mod test_ord_hash {
+        use super::*;
+        use std::collections::HashSet;
+
+        fn frac(s: &str) -> Frac {
+            Frac::from_str(s).unwrap()
+        }
+
+        // 2/4 as stored under a GCD threshold
+        fn unreduced_half() -> Frac {
+            Frac {
+                numerator: BigNum::from(2),
+                denominator: BigNum::from(4),
+            }
+        }
+
+        #[test]
+        fn test_ord() {
+            assert!(frac("1/3") < frac("1/2"));
+            assert!(frac("-1/2") < frac("-1/3"));
+            assert!(frac("-1/2") < frac("0/1"));
+            assert!(frac("7/3") > frac("2/1"));
+            assert_eq!(unreduced_half().cmp(&frac("1/2")), Ordering::Equal);
+            let mut fracs = [frac("3/4"), frac("-5/2"), frac("1/3"), frac("0/1")];
+            fracs.sort();
+            let sorted: Vec<String> = fracs.iter().map(Frac::to_string).collect();
+            assert_eq!(sorted, ["-5/2", "0/1", "1/3", "3/4"]);
+        }
+
+        #[test]
+        fn test_hash() {
+            let mut set = HashSet::new();
+            set.insert(frac("1/2"));
+            assert!(set.contains(&unreduced_half()));
+            assert!(!set.insert(unreduced_half()));
+            assert!(set.insert(frac("-1/2")));
+            assert_eq!(set.len(), 2);
+        }
+    }
+
     mod test_neg {
         use super::*;
 
@@ -465,6 +1832,145 @@ mod tests {
         }
     }
 
+    mod test_pow {
+        use super::*;
+
+        #[test]
+        fn test_pow() {
+            let frac = Frac::from_str("2/3").unwrap();
+            assert_eq!(frac.pow(2), Frac::from_str("4/9").unwrap());
+            assert_eq!(frac.pow(-2), Frac::from_str("9/4").unwrap());
+            assert_eq!(frac.pow(0), Frac::from_str("1/1").unwrap());
+            let frac = Frac::from_str("-2/3").unwrap();
+            assert_eq!(frac.pow(-3), Frac::from_str("-27/8").unwrap());
+            assert_eq!(frac.pow(-3).to_string(), "-27/8");
+            assert_eq!(
+                Frac::from_str("0/5").unwrap().pow(3),
+                Frac::from_str("0/1").unwrap()
+            );
+        }
+
+        #[test]
+        fn test_checked_pow() {
+            let zero = Frac::from_str("0/1").unwrap();
+            assert!(zero.checked_pow(-1).is_err());
+            assert_eq!(zero.checked_pow(0), Ok(Frac::from_str("1/1").unwrap()));
+        }
+
+        #[test]
+        #[should_panic]
+        fn test_pow_zero_negative() {
+            Frac::from_str("0/1").unwrap().pow(-2);
+        }
+    }
+
+    mod test_cross_reduce {
+        use super::*;
+
+        // Parts as stored, to check no reduction was left for later
+        fn parts(frac: &Frac) -> String {
+            format!("{}/{}", frac.numerator, frac.denominator)
+        }
+
+        #[test]
+        fn test_results_in_lowest_terms() {
+            let cases = [
+                ("3/4", "2/9", "35/36", "1/6"),
+                ("5/6", "1/6", "1/1", "5/36"),
+                ("-5/6", "5/6", "0/1", "-25/36"),
+                ("7/10", "-1/15", "19/30", "-7/150"),
+                ("0/1", "3/8", "3/8", "0/1"),
+                ("12/35", "14/15", "134/105", "8/25"),
+            ];
+            for (a, b, sum, product) in cases {
+                let a = Frac::from_str(a).unwrap();
+                let b = Frac::from_str(b).unwrap();
+                // Called directly, the GCD threshold is shared with other tests
+                assert_eq!(
+                    parts(&a.clone().add_reduced(b.clone())),
+                    sum,
+                    "{} + {}",
+                    a,
+                    b
+                );
+                assert_eq!(
+                    parts(&a.clone().mul_reduced(b.clone())),
+                    product,
+                    "{} * {}",
+                    a,
+                    b
+                );
+            }
+        }
+
+        #[test]
+        fn test_chain() {
+            // (2/1)(3/2)...(n+1/n) telescopes to n+1 without growing
+            let product = (1..=200u64)
+                .map(|k| Frac::new(BigNum::from(k + 1), BigNum::from(k)))
+                .fold(Frac::from_bignum(BigNum::one()), Frac::mul_reduced);
+            assert_eq!(parts(&product), "201/1");
+            let sum = (1..=10u64)
+                .map(|k| Frac::new(BigNum::one(), BigNum::from(k * (k + 1))))
+                .fold(Frac::from_bignum(BigNum::zero()), Frac::add_reduced);
+            assert_eq!(parts(&sum), "10/11");
+        }
+    }
+
+    mod test_gcd_threshold {
+        use super::*;
+
+        #[test]
+        fn test_deferred_reduction() {
+            Frac::set_gcd_threshold(1000);
+            let half = Frac::new(BigNum::one(), BigNum::from(2));
+            let sum = half.clone() + half.clone();
+            // Left as 4/4 internally but indistinguishable from 1
+            assert_eq!(sum.denominator, BigNum::from(4));
+            assert_eq!(sum, Frac::from_bignum(BigNum::one()));
+            assert_eq!(sum.to_bignum(), Ok(BigNum::one()));
+            let sixth = half.clone() * Frac::new(BigNum::from(2), BigNum::from(6));
+            assert_eq!(sixth.to_string(), "1/6");
+            assert!(!sixth.is_bignum());
+            assert_eq!((-sixth.clone()).to_string(), "-1/6");
+            assert_eq!(sixth.inverse().to_bignum(), Ok(BigNum::from(6)));
+            // Past the threshold results are reduced again
+            Frac::set_gcd_threshold(2);
+            let big = Frac::new(BigNum::from(1), BigNum::from(50)) * half;
+            assert_eq!(big.denominator, BigNum::from(100));
+            let reduced = big.clone() + big;
+            assert_eq!(reduced.denominator, BigNum::from(50));
+            Frac::set_gcd_threshold(0);
+        }
+    }
+
+    mod test_sum_product {
+        use super::*;
+
+        #[test]
+        fn test_sum() {
+            // 1/1 + 1/2 + ... + 1/4 = 25/12
+            let fracs: Vec<Frac> = (1..=4)
+                .map(|d| Frac::new(BigNum::one(), BigNum::from(d)))
+                .collect();
+            let expected = Frac::from_str("25/12").unwrap();
+            assert_eq!(fracs.iter().sum::<Frac>(), expected);
+            assert_eq!(fracs.into_iter().sum::<Frac>(), expected);
+            assert!(Vec::<Frac>::new().into_iter().sum::<Frac>().is_zero());
+        }
+
+        #[test]
+        fn test_product() {
+            // 1/2 * 2/3 * 3/4 = 1/4
+            let fracs: Vec<Frac> = (1..=3)
+                .map(|n| Frac::new(BigNum::from(n), BigNum::from(n + 1)))
+                .collect();
+            let expected = Frac::from_str("1/4").unwrap();
+            assert_eq!(fracs.iter().product::<Frac>(), expected);
+            assert_eq!(fracs.into_iter().product::<Frac>(), expected);
+        }
+    }
+
     mod test_div {
         use super::*;
 
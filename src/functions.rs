@@ -0,0 +1,680 @@
+use crate::big_num::{BigNum, GCD_OF_ZEROES};
+use crate::common::Value;
+use crate::error::ArithError;
+use crate::frac::Frac;
+use crate::gen::Rng;
+use crate::modular::ModContext;
+
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    // Context of the last modulus used, so loops calling mod_pow with the
+    // same modulus only set up the reduction once
+    static LAST_MOD_CONTEXT: RefCell<Option<ModContext>> = const { RefCell::new(None) };
+    // Stream behind the random functions: that of the session evaluating on
+    // this thread, or one seeded from the clock outside of sessions
+    static RNG: RefCell<Option<Rng>> = const { RefCell::new(None) };
+    // Decimal places irrational square roots are cut to while `refine` runs,
+    // and whether any has been since it started
+    static PRECISION: Cell<Option<(usize, bool)>> = const { Cell::new(None) };
+}
+
+// Bases for Miller-Rabin, which make it exact below 3.3e24
+const PRIME_BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+// Run `evaluate` with the random functions drawing from `rng`, which is
+// left where they stopped
+pub(crate) fn with_session_rng<T>(rng: &mut Rng, evaluate: impl FnOnce() -> T) -> T {
+    let outer = RNG.with(|current| current.replace(Some(rng.clone())));
+    let result = evaluate();
+    *rng = RNG
+        .with(|current| current.replace(outer))
+        .expect("set until evaluate returns");
+    result
+}
+
+// Draw from the stream of the running session
+fn with_rng<T>(f: impl FnOnce(&mut Rng) -> T) -> T {
+    RNG.with(|rng| f(rng.borrow_mut().get_or_insert_with(Rng::from_time)))
+}
+
+// Run `evaluate` with irrational square roots cut to `places` decimal
+// places instead of refused, also returning whether any was
+pub(crate) fn with_precision<T>(places: usize, evaluate: impl FnOnce() -> T) -> (T, bool) {
+    let outer = PRECISION.with(|current| current.replace(Some((places, false))));
+    let result = evaluate();
+    let (_, approximated) = PRECISION
+        .with(|current| current.replace(outer))
+        .expect("set until evaluate returns");
+    (result, approximated)
+}
+
+// Square root of a positive `frac` that is not a square, cut toward zero to
+// the places of the running `refine`, None outside of one
+fn approximate_sqrt(frac: &Frac) -> Option<Result<Frac, ArithError>> {
+    let (places, _) = PRECISION.with(Cell::get)?;
+    PRECISION.with(|current| current.set(Some((places, true))));
+    // sqrt(n/d) = sqrt(n * d * 10^2p) / (d * 10^p)
+    let (numerator, denominator) = (frac.numerator(), frac.denominator());
+    let root = numerator.checked_mul(denominator).and_then(|square| {
+        BigNum::check_digits(square.num_digits().saturating_add(2 * places))?;
+        Ok(square.shift_left(2 * places).isqrt()?)
+    });
+    Some(root.map(|root| Frac::new(root, denominator.shift_left(places))))
+}
+
+/// Evaluate the builtin `name` on already evaluated arguments. Predicates
+/// return `true` or `false`.
+pub fn call(name: &str, args: Vec<Value>) -> Result<Value, ArithError> {
+    match name {
+        "is_power_of_two" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Bool(num.is_power_of_two()))
+        }
+        "is_power_of_ten" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Bool(num.is_power_of_ten()))
+        }
+        "next_power_of_two" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Number(num.next_power_of_two()))
+        }
+        "digits" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Number(BigNum::from(num.num_digits())))
+        }
+        "digit_sum" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Number(num.digit_sum()))
+        }
+        "digital_root" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Number(BigNum::from(num.digital_root())))
+        }
+        "reverse_digits" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Number(num.reverse_digits()))
+        }
+        "to_gray" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Number(num.to_gray()?))
+        }
+        "from_gray" => {
+            let num = integer_arg(name, args)?;
+            Ok(Value::Number(num.from_gray()?))
+        }
+        "round_to" => {
+            let [value, denominator] = fixed_args(name, args)?;
+            let Value::Number(denominator) = denominator.clone().simplify() else {
+                return Err(format!(
+                    "round_to expects an integer denominator, got {}",
+                    denominator
+                )
+                .into());
+            };
+            let frac = rational_arg(name, value)?;
+            Ok(Value::Frac(frac.round_to_denominator(&denominator)?).simplify())
+        }
+        "abs" | "floor" | "ceil" | "round" => {
+            let [value] = fixed_args(name, args)?;
+            let frac = rational_arg(name, value)?;
+            Ok(match name {
+                "abs" => Value::Frac(frac.abs()).simplify(),
+                "floor" => Value::Number(frac.floor()),
+                "ceil" => Value::Number(frac.ceil()),
+                _ => Value::Number(frac.round()),
+            })
+        }
+        "sqrt" => {
+            let [value] = fixed_args(name, args)?;
+            let frac = rational_arg(name, value.clone())?;
+            match frac.sqrt() {
+                Ok(root) => Ok(Value::Frac(root).simplify()),
+                // Reported with the value as entered, `2` rather than `2/1`
+                Err(_) if value.is_negative() => {
+                    Err(format!("Square root of negative number {}", value).into())
+                }
+                Err(_) => match approximate_sqrt(&frac) {
+                    Some(root) => Ok(Value::Frac(root?).simplify()),
+                    None => Err(format!("Square root of {} is irrational", value).into()),
+                },
+            }
+        }
+        // Zeroes leave the GCD as it is, only all of them leave it undefined
+        "gcd" => {
+            let gcd = variadic_integer_args(name, args)?
+                .iter()
+                .filter(|num| !num.is_zero())
+                .fold(BigNum::zero(), |gcd, num| gcd.gcd(num).unwrap());
+            if gcd.is_zero() {
+                return Err(GCD_OF_ZEROES.to_string().into());
+            }
+            Ok(Value::Number(gcd))
+        }
+        "lcm" => {
+            let nums = variadic_integer_args(name, args)?;
+            let lcm = nums.iter().fold(BigNum::one(), |lcm, num| lcm.lcm(num));
+            Ok(Value::Number(lcm))
+        }
+        // Both at once as `[q, r]`, the remainder never negative
+        "divmod" => {
+            let [a, b] = integer_args(name, args)?;
+            let (quotient, remainder) = a.div_rem(&b)?;
+            Ok(Value::List(vec![
+                Value::Number(quotient),
+                Value::Number(remainder),
+            ]))
+        }
+        "min" | "max" => {
+            let fracs = args
+                .into_iter()
+                .map(|arg| rational_arg(name, arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            let extreme = if name == "min" {
+                fracs.into_iter().min()
+            } else {
+                fracs.into_iter().max()
+            };
+            match extreme {
+                Some(frac) => Ok(Value::Frac(frac).simplify()),
+                None => Err(format!("{} takes at least 1 argument, got 0", name).into()),
+            }
+        }
+        "mod_pow" => {
+            let [base, exponent, modulus] = integer_args(name, args)?;
+            Ok(Value::Number(with_mod_context(modulus, |ctx| {
+                ctx.pow(&base, &exponent)
+            })?))
+        }
+        "mod_inverse" => {
+            let [a, modulus] = integer_args(name, args)?;
+            Ok(Value::Number(with_mod_context(modulus, |ctx| {
+                ctx.inverse(&a)
+            })?))
+        }
+        "rand" => {
+            let bound = integer_arg(name, args)?;
+            if bound < BigNum::one() {
+                return Err(format!("rand expects a positive bound, got {}", bound).into());
+            }
+            Ok(Value::Number(with_rng(|rng| random_below(rng, &bound))))
+        }
+        "rand_prime" => {
+            let digits = usize::try_from(u64::try_from(&integer_arg(name, args)?)?)
+                .map_err(|e| e.to_string())?;
+            if digits == 0 {
+                return Err("rand_prime expects at least 1 digit".to_string().into());
+            }
+            BigNum::check_digits(digits)?;
+            Ok(Value::Number(with_rng(|rng| random_prime(rng, digits))?))
+        }
+        "assert" => {
+            let [value] = fixed_args(name, args)?;
+            // A number holds unless it is 0, as in scripts without comparisons
+            let holds = match value {
+                Value::Bool(b) => b,
+                ref value => !value.is_zero(),
+            };
+            if !holds {
+                return Err(format!("Assertion failed: got {}", value).into());
+            }
+            Ok(Value::Bool(true))
+        }
+        "assert_eq" => {
+            let [left, right] = fixed_args(name, args)?;
+            // Compared by value, so 2/4 equals 1/2 and 90deg equals 100grad
+            if !left.equals(&right).unwrap_or(false) {
+                return Err(format!("Assertion failed: {} != {}", left, right).into());
+            }
+            Ok(Value::Bool(true))
+        }
+        _ => Err(format!("Unknown function {}", name).into()),
+    }
+}
+
+// Every function `call` knows, and `exit`
+const NAMES: &[&str] = &[
+    "is_power_of_two",
+    "is_power_of_ten",
+    "next_power_of_two",
+    "digits",
+    "digit_sum",
+    "digital_root",
+    "reverse_digits",
+    "to_gray",
+    "from_gray",
+    "round_to",
+    "abs",
+    "floor",
+    "ceil",
+    "round",
+    "sqrt",
+    "gcd",
+    "lcm",
+    "divmod",
+    "min",
+    "max",
+    "mod_pow",
+    "mod_inverse",
+    "rand",
+    "rand_prime",
+    "assert",
+    "assert_eq",
+    "exit",
+];
+
+pub(crate) fn is_builtin(name: &str) -> bool {
+    NAMES.contains(&name) || is_series(name)
+}
+
+// `sum(k, from, to, body)` and `prod(...)`, whose body the evaluator runs
+// once per value of `k` rather than passing it as a value
+pub(crate) fn is_series(name: &str) -> bool {
+    matches!(name, "sum" | "prod")
+}
+
+// Whether `name` always gives the same result for the same arguments, so a
+// call on constants can be replaced by its value ahead of time. Functions
+// registered on a session are not known to be.
+pub(crate) fn is_pure(name: &str) -> bool {
+    NAMES.contains(&name) && !matches!(name, "rand" | "rand_prime" | "exit")
+}
+
+/// Status for `exit(n)`, which the evaluator turns into an early stop
+pub fn exit_code(args: Vec<Value>) -> Result<i32, String> {
+    let num = integer_arg("exit", args)?;
+    i32::try_from(i64::try_from(&num)?).map_err(|_| format!("Exit status {} out of range", num))
+}
+
+fn fixed_args<const N: usize>(name: &str, args: Vec<Value>) -> Result<[Value; N], String> {
+    args.try_into().map_err(|args: Vec<Value>| {
+        let plural = if N == 1 { "" } else { "s" };
+        format!(
+            "{} takes {} argument{}, got {}",
+            name,
+            N,
+            plural,
+            args.len()
+        )
+    })
+}
+
+// The single argument of `name`, which must be an integer
+fn integer_arg(name: &str, args: Vec<Value>) -> Result<BigNum, String> {
+    let [arg] = integer_args(name, args)?;
+    Ok(arg)
+}
+
+fn integer_args<const N: usize>(name: &str, args: Vec<Value>) -> Result<[BigNum; N], String> {
+    let args: [Value; N] = fixed_args(name, args)?;
+    let mut nums = Vec::with_capacity(N);
+    for arg in args {
+        match arg.simplify() {
+            Value::Number(num) => nums.push(num),
+            arg => return Err(format!("{} expects an integer, got {}", name, arg)),
+        }
+    }
+    Ok(nums.try_into().unwrap())
+}
+
+// One or more integer arguments of `name`
+fn variadic_integer_args(name: &str, args: Vec<Value>) -> Result<Vec<BigNum>, String> {
+    if args.is_empty() {
+        return Err(format!("{} takes at least 1 argument, got 0", name));
+    }
+    args.into_iter()
+        .map(|arg| match arg.simplify() {
+            Value::Number(num) => Ok(num),
+            arg => Err(format!("{} expects an integer, got {}", name, arg)),
+        })
+        .collect()
+}
+
+// An integer or fraction argument of `name` as a fraction
+fn rational_arg(name: &str, value: Value) -> Result<Frac, String> {
+    match value {
+        Value::Number(num) => Ok(Frac::from_bignum(num)),
+        Value::Frac(frac) => Ok(frac),
+        _ => Err(format!("{} expects a number, got {}", name, value)),
+    }
+}
+
+fn with_mod_context<T>(
+    modulus: BigNum,
+    f: impl FnOnce(&ModContext) -> Result<T, String>,
+) -> Result<T, String> {
+    LAST_MOD_CONTEXT.with(|last| {
+        let mut last = last.borrow_mut();
+        if last.as_ref().is_none_or(|ctx| *ctx.modulus() != modulus) {
+            *last = Some(ModContext::new(modulus)?);
+        }
+        f(last.as_ref().unwrap())
+    })
+}
+
+// Uniform in 0..bound, drawing digits until the number is below the bound
+fn random_below(rng: &mut Rng, bound: &BigNum) -> BigNum {
+    loop {
+        let digits = (0..bound.num_digits())
+            .map(|_| rng.below(10) as u8)
+            .collect();
+        let candidate = BigNum::from_digits(digits, true);
+        if candidate < *bound {
+            return candidate;
+        }
+    }
+}
+
+// Uniform among the primes with exactly `digits` digits
+fn random_prime(rng: &mut Rng, digits: usize) -> Result<BigNum, String> {
+    let low = BigNum::one().shift_left(digits - 1);
+    let span = BigNum::one().shift_left(digits) - low.clone();
+    loop {
+        let candidate = low.clone() + random_below(rng, &span);
+        if is_probable_prime(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+}
+
+// Miller-Rabin over PRIME_BASES, after trial division by the same primes
+fn is_probable_prime(n: &BigNum) -> Result<bool, String> {
+    if *n < BigNum::from(2) {
+        return Ok(false);
+    }
+    for p in PRIME_BASES {
+        let p = BigNum::from(p);
+        if *n == p {
+            return Ok(true);
+        }
+        if (n.clone() % p).is_zero() {
+            return Ok(false);
+        }
+    }
+    let ctx = ModContext::new(n.clone())?;
+    let n_minus_one = n.clone() - BigNum::one();
+    // n - 1 = d * 2^s with d odd
+    let mut d = n_minus_one.clone();
+    let mut s = 0;
+    while d.is_even() {
+        d /= BigNum::from(2);
+        s += 1;
+    }
+    'bases: for base in PRIME_BASES {
+        let mut x = ctx.pow(&BigNum::from(base), &d)?;
+        if x == BigNum::one() || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = ctx.mul(&x, &x);
+            if x == n_minus_one {
+                continue 'bases;
+            }
+        }
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: i64) -> Value {
+        Value::Number(BigNum::from(n))
+    }
+
+    mod test_call {
+        use super::*;
+
+        #[test]
+        fn test_names() {
+            for name in NAMES.iter().filter(|&&name| name != "exit") {
+                let unknown = format!("Unknown function {}", name);
+                assert_ne!(call(name, vec![]), Err(unknown.into()), "{}", name);
+            }
+            assert!(!is_builtin("sin"));
+            assert!(!is_pure("sin"));
+        }
+
+        #[test]
+        fn test_power_predicates() {
+            assert_eq!(
+                call("is_power_of_two", vec![num(32)]),
+                Ok(Value::Bool(true))
+            );
+            assert_eq!(
+                call("is_power_of_two", vec![num(33)]),
+                Ok(Value::Bool(false))
+            );
+            assert_eq!(
+                call("is_power_of_ten", vec![num(100)]),
+                Ok(Value::Bool(true))
+            );
+            assert_eq!(
+                call("is_power_of_ten", vec![num(-100)]),
+                Ok(Value::Bool(false))
+            );
+            assert_eq!(call("next_power_of_two", vec![num(100)]), Ok(num(128)));
+        }
+
+        #[test]
+        fn test_digit_functions() {
+            assert_eq!(call("digits", vec![num(-1000)]), Ok(num(4)));
+            assert_eq!(call("digit_sum", vec![num(1234)]), Ok(num(10)));
+            assert_eq!(call("digital_root", vec![num(1234)]), Ok(num(1)));
+            assert_eq!(call("reverse_digits", vec![num(-120)]), Ok(num(-21)));
+        }
+
+        #[test]
+        fn test_sqrt() {
+            assert_eq!(call("sqrt", vec![num(144)]), Ok(num(12)));
+            assert_eq!(
+                call("sqrt", vec!["4/9".parse().unwrap()]),
+                Ok("2/3".parse().unwrap())
+            );
+            assert_eq!(
+                call("sqrt", vec![num(2)]),
+                Err(ArithError::Invalid(
+                    "Square root of 2 is irrational".to_string()
+                ))
+            );
+            assert!(call("sqrt", vec![num(-4)]).is_err());
+            // Cut to the places of a running `refine`
+            let root = with_precision(3, || call("sqrt", vec![num(2)]));
+            assert_eq!(root, (Ok("1.414".parse().unwrap()), true));
+            let root = with_precision(3, || call("sqrt", vec![num(4)]));
+            assert_eq!(root, (Ok(num(2)), false));
+        }
+
+        #[test]
+        fn test_assert() {
+            assert_eq!(call("assert", vec![num(-3)]), Ok(Value::Bool(true)));
+            assert_eq!(
+                call("assert", vec![num(0)]),
+                Err(ArithError::Invalid("Assertion failed: got 0".to_string()))
+            );
+            assert_eq!(
+                call("assert", vec![Value::Bool(true)]),
+                Ok(Value::Bool(true))
+            );
+            assert_eq!(
+                call("assert", vec![Value::Bool(false)]),
+                Err(ArithError::Invalid(
+                    "Assertion failed: got false".to_string()
+                ))
+            );
+        }
+
+        #[test]
+        fn test_assert_eq() {
+            let half: Value = "1/2".parse().unwrap();
+            let third: Value = "1/3".parse().unwrap();
+            assert_eq!(
+                call("assert_eq", vec![half.clone(), "2/4".parse().unwrap()]),
+                Ok(Value::Bool(true))
+            );
+            assert_eq!(
+                call("assert_eq", vec![half, third]),
+                Err(ArithError::Invalid(
+                    "Assertion failed: 1/2 != 1/3".to_string()
+                ))
+            );
+            assert!(call("assert_eq", vec![num(1)]).is_err());
+            assert!(call("assert_eq", vec!["1deg".parse().unwrap(), num(1)]).is_err());
+        }
+
+        #[test]
+        fn test_gray() {
+            assert_eq!(call("to_gray", vec![num(4)]), Ok(num(6)));
+            assert_eq!(call("from_gray", vec![num(6)]), Ok(num(4)));
+            assert!(call("to_gray", vec![num(-4)]).is_err());
+        }
+
+        #[test]
+        fn test_round_to() {
+            let value: Value = "3/10".parse().unwrap();
+            assert_eq!(
+                call("round_to", vec![value, num(16)]),
+                Ok("5/16".parse().unwrap())
+            );
+            assert_eq!(call("round_to", vec![num(7), num(4)]), Ok(num(7)));
+            assert!(call("round_to", vec![num(7), "1/2".parse().unwrap()]).is_err());
+            assert!(call("round_to", vec!["1deg".parse().unwrap(), num(2)]).is_err());
+        }
+
+        #[test]
+        fn test_rounding() {
+            let value: Value = "-7/2".parse().unwrap();
+            assert_eq!(call("abs", vec![value.clone()]), Ok("7/2".parse().unwrap()));
+            assert_eq!(call("floor", vec![value.clone()]), Ok(num(-4)));
+            assert_eq!(call("ceil", vec![value.clone()]), Ok(num(-3)));
+            assert_eq!(call("round", vec![value]), Ok(num(-4)));
+            assert_eq!(call("abs", vec![num(-5)]), Ok(num(5)));
+            assert!(call("floor", vec!["1deg".parse().unwrap()]).is_err());
+        }
+
+        #[test]
+        fn test_gcd_lcm() {
+            assert_eq!(call("gcd", vec![num(12), num(-18)]), Ok(num(6)));
+            assert_eq!(call("lcm", vec![num(4), num(6)]), Ok(num(12)));
+            // Worded as BigNum::gcd words it
+            let zeroes = BigNum::zero().gcd(&BigNum::zero()).unwrap_err();
+            assert_eq!(call("gcd", vec![num(0), num(0)]), Err(zeroes.into()));
+            assert!(call("lcm", vec!["1/2".parse().unwrap(), num(6)]).is_err());
+            assert_eq!(call("gcd", vec![num(12), num(18), num(-30)]), Ok(num(6)));
+            assert_eq!(call("gcd", vec![num(0), num(-4), num(0)]), Ok(num(4)));
+            assert_eq!(call("gcd", vec![num(-9)]), Ok(num(9)));
+            assert_eq!(
+                call("lcm", vec![num(2), num(3), num(4), num(5)]),
+                Ok(num(60))
+            );
+            assert_eq!(call("lcm", vec![num(2), num(0), num(3)]), Ok(num(0)));
+            assert!(call("lcm", vec![]).is_err());
+        }
+
+        #[test]
+        fn test_divmod() {
+            let pair = |q, r| Ok(Value::List(vec![num(q), num(r)]));
+            assert_eq!(call("divmod", vec![num(17), num(5)]), pair(3, 2));
+            assert_eq!(call("divmod", vec![num(-17), num(5)]), pair(-4, 3));
+            assert_eq!(call("divmod", vec![num(17), num(-5)]), pair(-3, 2));
+            assert!(call("divmod", vec![num(1), num(0)]).is_err());
+            assert!(call("divmod", vec!["1/2".parse().unwrap(), num(2)]).is_err());
+        }
+
+        #[test]
+        fn test_min_max() {
+            let args = || vec![num(3), "-1/2".parse().unwrap(), num(2)];
+            assert_eq!(call("min", args()), Ok("-1/2".parse().unwrap()));
+            assert_eq!(call("max", args()), Ok(num(3)));
+            assert_eq!(call("max", vec![num(7)]), Ok(num(7)));
+            assert!(call("min", vec![]).is_err());
+        }
+
+        #[test]
+        fn test_modular() {
+            assert_eq!(
+                call("mod_pow", vec![num(4), num(13), num(497)]),
+                Ok(num(445))
+            );
+            assert_eq!(call("mod_pow", vec![num(3), num(-1), num(7)]), Ok(num(5)));
+            assert_eq!(call("mod_inverse", vec![num(3), num(7)]), Ok(num(5)));
+            assert!(call("mod_inverse", vec![num(2), num(4)]).is_err());
+            assert!(call("mod_pow", vec![num(2), num(2), num(0)]).is_err());
+        }
+
+        #[test]
+        fn test_exit_code() {
+            assert_eq!(exit_code(vec![num(3)]), Ok(3));
+            assert!(exit_code(vec![num(1 << 40)]).is_err());
+            assert!(exit_code(vec![]).is_err());
+        }
+
+        #[test]
+        fn test_seeded_rand() {
+            let draw = |rng: &mut Rng| {
+                with_session_rng(rng, || {
+                    (0..5)
+                        .map(|_| call("rand", vec![num(1000)]))
+                        .collect::<Vec<_>>()
+                })
+            };
+            let mut rng = Rng::new(42);
+            let first = draw(&mut rng);
+            // The stream goes on from where it stopped
+            assert_ne!(draw(&mut rng), first);
+            assert_eq!(draw(&mut Rng::new(42)), first);
+            for value in first {
+                let Ok(Value::Number(n)) = value else {
+                    panic!("rand failed");
+                };
+                assert!(!n.is_negative() && n < BigNum::from(1000));
+            }
+            assert_eq!(call("rand", vec![num(1)]), Ok(num(0)));
+            assert!(call("rand", vec![num(0)]).is_err());
+            assert!(call("rand", vec!["1/2".parse().unwrap()]).is_err());
+        }
+
+        #[test]
+        fn test_rand_prime() {
+            for digits in [1, 2, 20] {
+                let prime =
+                    with_session_rng(&mut Rng::new(7), || call("rand_prime", vec![num(digits)]));
+                let Ok(Value::Number(prime)) = prime else {
+                    panic!("rand_prime({}) failed", digits);
+                };
+                assert_eq!(prime.num_digits() as i64, digits);
+                assert_eq!(is_probable_prime(&prime), Ok(true));
+            }
+            assert!(call("rand_prime", vec![num(0)]).is_err());
+            assert!(call("rand_prime", vec![num(-3)]).is_err());
+        }
+
+        #[test]
+        fn test_is_probable_prime() {
+            let primes = [
+                "2",
+                "97",
+                "2147483647",
+                "170141183460469231731687303715884105727",
+            ];
+            for p in primes {
+                assert_eq!(is_probable_prime(&p.parse().unwrap()), Ok(true), "{}", p);
+            }
+            // 3215031751 is a strong pseudoprime to bases 2, 3, 5 and 7
+            let composites = ["0", "1", "91", "3215031751", "561", "-7"];
+            for c in composites {
+                assert_eq!(is_probable_prime(&c.parse().unwrap()), Ok(false), "{}", c);
+            }
+        }
+
+        #[test]
+        fn test_argument_errors() {
+            assert!(call("is_power_of_two", vec![]).is_err());
+            assert!(call("is_power_of_two", vec![num(1), num(2)]).is_err());
+            assert!(call("is_power_of_two", vec!["1/2".parse().unwrap()]).is_err());
+            assert!(call("unknown", vec![num(1)]).is_err());
+        }
+    }
+}
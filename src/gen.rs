@@ -0,0 +1,303 @@
+use crate::big_num::BigNum;
+use crate::common::Value;
+use crate::parser::{Expr, Operator};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Literals are kept small so generated problems stay readable
+const MAX_LITERAL: u64 = 20;
+
+/// The kinds of nodes `gen_expr` may produce on top of integer literals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExprKind {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Negative,
+}
+
+impl ExprKind {
+    pub fn all() -> Vec<ExprKind> {
+        vec![
+            ExprKind::Add,
+            ExprKind::Subtract,
+            ExprKind::Multiply,
+            ExprKind::Divide,
+            ExprKind::Negative,
+        ]
+    }
+}
+
+/// Small xorshift64* generator, good enough for test inputs and quiz problems
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift gets stuck on a zero state
+        Rng {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    pub fn from_time() -> Rng {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Rng::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Uniform-ish value in 0..bound, bound must be nonzero
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Generate a random valid expression of at most `depth` nested operations,
+/// using only the given kinds of nodes
+pub fn gen_expr(depth: usize, kinds: &[ExprKind]) -> String {
+    gen_expr_with(&mut Rng::from_time(), depth, kinds)
+}
+
+pub fn gen_expr_with(rng: &mut Rng, depth: usize, kinds: &[ExprKind]) -> String {
+    render(&gen_tree(rng, depth, kinds))
+}
+
+fn literal(rng: &mut Rng, min: u64) -> Expr {
+    let n = min + rng.below(MAX_LITERAL + 1 - min);
+    Expr::Literal(Value::Number(BigNum::from(n)))
+}
+
+fn gen_tree(rng: &mut Rng, depth: usize, kinds: &[ExprKind]) -> Expr {
+    // Stop early now and then so the trees are not all perfectly balanced
+    if depth == 0 || kinds.is_empty() || rng.below(4) == 0 {
+        return literal(rng, 0);
+    }
+    let kind = kinds[rng.below(kinds.len() as u64) as usize];
+    let operator = match kind {
+        ExprKind::Negative => {
+            let expr = gen_tree(rng, depth - 1, kinds);
+            return Expr::unary(Operator::Negative, expr);
+        }
+        ExprKind::Add => Operator::Add,
+        ExprKind::Subtract => Operator::Subtract,
+        ExprKind::Multiply => Operator::Multiply,
+        ExprKind::Divide => Operator::Divide,
+    };
+    let left = gen_tree(rng, depth - 1, kinds);
+    let mut right = gen_tree(rng, depth - 1, kinds);
+    // A divisor that happens to evaluate to zero is swapped for a literal
+    if operator == Operator::Divide && right.clone().eval().map_or(true, |v| v.is_zero()) {
+        right = literal(rng, 1);
+    }
+    Expr::binary(operator, left, right)
+}
+
+// Print an expression back into source form the parser accepts
+pub(crate) fn render(expr: &Expr) -> String {
+    let mut out = String::new();
+    render_into(expr, &mut out);
+    out
+}
+
+fn render_into(expr: &Expr, out: &mut String) {
+    match expr {
+        Expr::Literal(value) => out.push_str(&value.to_string()),
+        Expr::Unary(operator, operand, _) => {
+            out.push_str(symbol(*operator));
+            render_operand(operand, out);
+        }
+        Expr::Postfix(operator, operand, _) => {
+            render_operand(operand, out);
+            out.push_str(symbol(*operator));
+        }
+        Expr::Binary(Operator::Index, list, index, _) => {
+            render_operand(list, out);
+            out.push('[');
+            render_into(index, out);
+            out.push(']');
+        }
+        Expr::Binary(operator, left, right, _) => {
+            render_operand(left, out);
+            out.push(' ');
+            out.push_str(symbol(*operator));
+            out.push(' ');
+            render_operand(right, out);
+        }
+        Expr::Call(name, args, _) => {
+            out.push_str(name);
+            out.push('(');
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                render_into(arg, out);
+            }
+            out.push(')');
+        }
+        Expr::List(items, _) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                render_into(item, out);
+            }
+            out.push(']');
+        }
+        Expr::Var(name, _) => out.push_str(name),
+        Expr::Raise(message, _) => {
+            out.push_str("error(\"");
+            out.push_str(message);
+            out.push_str("\")");
+        }
+        Expr::Assign(name, expr) => {
+            out.push_str(name);
+            out.push_str(" = ");
+            render_into(expr, out);
+        }
+        Expr::Conditional(test, then, otherwise, _) => {
+            render_operand(test, out);
+            out.push_str(" ? ");
+            render_operand(then, out);
+            out.push_str(" : ");
+            render_operand(otherwise, out);
+        }
+    }
+}
+
+// How `operator` is written in source
+pub(crate) fn symbol(operator: Operator) -> &'static str {
+    match operator {
+        Operator::Add | Operator::Positive => "+",
+        Operator::Subtract | Operator::Negative => "-",
+        Operator::Multiply => "*",
+        Operator::Divide => "/",
+        Operator::Modulo | Operator::Percent => "%",
+        Operator::IntDiv => "//",
+        Operator::Power => "^",
+        Operator::Equal => "==",
+        Operator::NotEqual => "!=",
+        Operator::Less => "<",
+        Operator::LessEqual => "<=",
+        Operator::Greater => ">",
+        Operator::GreaterEqual => ">=",
+        Operator::And => "&&",
+        Operator::Or => "||",
+        Operator::Not | Operator::Factorial => "!",
+        Operator::BitAnd => "&",
+        Operator::BitOr => "|",
+        Operator::BitXor => "^^",
+        Operator::ShiftLeft => "<<",
+        Operator::ShiftRight => ">>",
+        Operator::BitNot => "~",
+        Operator::Degree => "°",
+        Operator::Index => "[]",
+        Operator::Custom(symbol) => symbol,
+    }
+}
+
+// Anything but a plain non-negative integer gets parenthesised, so the parser
+// never has to guess
+fn render_operand(expr: &Expr, out: &mut String) {
+    match expr {
+        Expr::Literal(value @ Value::Number(_)) if !value.is_negative() => render_into(expr, out),
+        Expr::Literal(Value::Bool(_)) => render_into(expr, out),
+        Expr::Call(..) | Expr::Var(..) | Expr::Raise(..) | Expr::List(..) => render_into(expr, out),
+        _ => {
+            out.push('(');
+            render_into(expr, out);
+            out.push(')');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::eval_value;
+
+    mod test_rng {
+        use super::*;
+
+        #[test]
+        fn test_same_seed_same_sequence() {
+            let mut rng1 = Rng::new(42);
+            let mut rng2 = Rng::new(42);
+            for _ in 0..10 {
+                assert_eq!(rng1.next_u64(), rng2.next_u64());
+            }
+        }
+
+        #[test]
+        fn test_zero_seed() {
+            let mut rng = Rng::new(0);
+            assert_ne!(rng.next_u64(), 0);
+        }
+    }
+
+    mod test_gen_expr {
+        use super::*;
+
+        #[test]
+        fn test_gen_expr_evaluates() {
+            let mut rng = Rng::new(7);
+            for _ in 0..200 {
+                let expr = gen_expr_with(&mut rng, 4, &ExprKind::all());
+                assert!(eval_value(&expr).is_ok(), "failed on {}", expr);
+            }
+        }
+
+        #[test]
+        fn test_gen_expr_matches_tree() {
+            // The rendered string must parse back to the same value as the tree
+            let mut rng = Rng::new(11);
+            for _ in 0..200 {
+                let mut tree = gen_tree(&mut rng, 4, &ExprKind::all());
+                assert_eq!(eval_value(&render(&tree)).unwrap(), tree.eval().unwrap());
+            }
+        }
+
+        #[test]
+        fn test_min_parens_matches_tree() {
+            let mut rng = Rng::new(13);
+            for _ in 0..200 {
+                let mut tree = gen_tree(&mut rng, 4, &ExprKind::all());
+                let text = tree.to_string_min_parens();
+                assert_eq!(eval_value(&text).unwrap(), tree.eval().unwrap(), "{}", text);
+            }
+        }
+
+        #[test]
+        fn test_gen_expr_kinds() {
+            let mut rng = Rng::new(3);
+            for _ in 0..50 {
+                let expr = gen_expr_with(&mut rng, 3, &[ExprKind::Add]);
+                assert!(!expr.contains(['-', '*', '/']), "unexpected op in {}", expr);
+            }
+        }
+
+        #[test]
+        fn test_gen_expr_depth_zero() {
+            let mut rng = Rng::new(5);
+            let expr = gen_expr_with(&mut rng, 0, &ExprKind::all());
+            assert!(expr.parse::<u64>().unwrap() <= MAX_LITERAL);
+        }
+    }
+}
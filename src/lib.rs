@@ -1,9 +1,178 @@
+//! Exact arbitrary precision calculator: big integers, fractions and angles,
+//! an expression language over them, and the REPL and script runner built
+//! on it.
+//!
+//! Everything public is re-exported here and in [`prelude`], the modules
+//! themselves are private so they can be reorganized freely. Public enums
+//! are `#[non_exhaustive]`, so adding a variant is not a breaking change.
+
+mod audit;
+mod batch;
 mod big_num;
+mod cache;
+mod canonical;
 mod common;
+mod compile;
+mod env;
+mod error;
+mod extensions;
+mod format;
 mod frac;
+mod functions;
+mod gen;
+mod limits;
+#[doc(hidden)]
+pub mod macros;
+mod modular;
+#[cfg(feature = "num-traits")]
+mod num_impls;
 mod parser;
+mod pool;
+mod refine;
+mod repl;
+mod rpn;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod session;
+mod simplify;
+mod stats;
+mod steps;
+mod workbook;
 
 use std::error::Error;
-pub fn eval_to_string(input: String) -> Result<String, Box<dyn Error>> {
+
+pub use audit::AuditEvent;
+#[allow(deprecated)]
+pub use audit::{clear_audit_sink, set_audit_sink};
+pub use big_num::BigNum;
+pub use common::{AngleUnit, Value};
+pub use compile::CompiledExpr;
+pub use env::Environment;
+pub use error::ArithError;
+pub use frac::{Convergents, Frac};
+pub use gen::{gen_expr, gen_expr_with, ExprKind, Rng};
+pub use limits::EvalLimits;
+pub use modular::ModContext;
+pub use parser::{Assoc, CalcError, Expr, Lexeme, Lexer, Operator, ParseOutput, Span, Token};
+pub use pool::PoolStats;
+pub use refine::Refinement;
+pub use session::Session;
+pub use stats::{set_tracing, stats, OpCounts, Stats};
+pub use steps::EvalStep;
+pub use workbook::Workbook;
+
+/// The types most programs need, `use rust_calculator::prelude::*;`
+pub mod prelude {
+    pub use crate::{
+        eval, eval_all, eval_to_string, eval_with_limits, AngleUnit, ArithError, BigNum, CalcError,
+        Environment, EvalLimits, Frac, Session, Value, Workbook,
+    };
+}
+
+#[deprecated(note = "renamed to CalcError")]
+pub type SyntaxError = CalcError;
+
+/// Value of the last of `;` or newline separated statements, as in
+/// `a = 2; b = 3; a * b`, or the first error, whose span locates it in
+/// `input`
+pub fn eval_to_string(input: &str) -> Result<String, CalcError> {
     parser::eval_to_string(input)
 }
+
+/// Exact value of the last of `;` or newline separated statements, to keep
+/// calculating with, or the first error
+pub fn eval(input: &str) -> Result<Value, CalcError> {
+    parser::eval_value(input)
+}
+
+/// `eval` bounded by `limits`, so input such as `9^9^9` fails with an error
+/// that `is_limit_exceeded` instead of running for a long time
+pub fn eval_with_limits(input: &str, limits: &EvalLimits) -> Result<Value, CalcError> {
+    limits::limited(limits, || parser::eval_value(input))
+}
+
+#[deprecated(note = "use eval_to_string, which takes a &str")]
+pub fn eval_string_to_string(input: String) -> Result<String, Box<dyn Error>> {
+    Ok(eval_to_string(&input)?)
+}
+
+/// Lazily evaluate `;` or newline separated statements sharing variables,
+/// as in `x = 2; y = x * 3; x + y`
+pub fn eval_all(input: &str) -> impl Iterator<Item = Result<Value, CalcError>> + '_ {
+    parser::eval_all(input)
+}
+
+/// Tokens of `input` in order, for tools working below the level of the
+/// expression tree
+pub fn tokenize(input: &str) -> Result<Vec<Token<'_>>, CalcError> {
+    parser::tokenize(input)
+}
+
+/// Parse a single statement without evaluating it into its tree (`expr`),
+/// with its depth and node count for callers enforcing their own complexity
+/// limits
+pub fn parse(input: &str) -> Result<ParseOutput, CalcError> {
+    parser::parse_output(input)
+}
+
+/// Every syntax error of `;` or newline separated statements, not only the
+/// first, for editors marking them all at once. Empty when `input` parses.
+pub fn syntax_errors(input: &str) -> Vec<CalcError> {
+    parser::syntax_errors(input)
+}
+
+/// Value of a single statement with the working that reaches it, one step
+/// per part rewritten, for front-ends teaching how it is computed
+pub fn eval_with_steps(input: &str) -> Result<(Value, Vec<EvalStep>), CalcError> {
+    parser::parse_statement(input)?.eval_steps(&mut Environment::new())
+}
+
+/// Read an expression in reverse Polish notation, as `Expr::to_rpn_string`
+/// writes it: `1 2 3 * +` is `1 + 2 * 3`
+pub fn parse_rpn(input: &str) -> Result<Expr, CalcError> {
+    rpn::parse_rpn(input)
+}
+
+/// A statement rewritten in a deterministic normal form, with subtractions
+/// as added negations and the operands of `+` and `*` sorted, so that
+/// equivalent sums and products print the same
+pub fn canonical(input: &str) -> Result<String, CalcError> {
+    Ok(gen::render(&canonical::canonical(&parser::parse(input)?)))
+}
+
+/// A statement rewritten with the same value but a different shape, its
+/// operands of `+` and `*` swapped and regrouped at random
+pub fn randomize_equivalent(input: &str, rng: &mut Rng) -> Result<String, CalcError> {
+    let expr = parser::parse(input)?;
+    Ok(gen::render(&canonical::randomize_equivalent(&expr, rng)))
+}
+
+/// Value of `;` or newline separated statements to `places` decimal places,
+/// with irrational square roots approximated: the working precision doubles
+/// until two results agree to that many places, and the `Refinement` says
+/// how many places it reached
+pub fn refine(input: &str, places: usize) -> Result<Refinement, CalcError> {
+    refine::refine(places, || parser::eval_value(input))
+}
+
+/// Start the REPL, run the script file given as the only argument (`-` for
+/// stdin), or verify a worksheet with `check <file>`. Returns the exit status
+/// for the process.
+pub fn run() -> Result<i32, Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => repl::run(),
+        [command, path] if command == "check" => Ok(batch::check(&read_source(path)?)),
+        [path] => Ok(batch::run(&read_source(path)?)),
+        _ => Err("Usage: rust-calculator [script] | check <worksheet>".into()),
+    }
+}
+
+// Contents of the file at `path`, stdin for `-`
+fn read_source(path: &str) -> std::io::Result<String> {
+    if path == "-" {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(path)
+    }
+}
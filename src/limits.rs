@@ -0,0 +1,254 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::error::ArithError;
+
+pub(crate) const LIMIT_EXCEEDED: &str = "Limit exceeded";
+
+/// Bounds on the work of one evaluation, for running input that cannot be
+/// trusted, such as requests from a network. None leaves a bound off, which
+/// is the default for all of them.
+///
+/// Steps and time are checked between operations, so a single operation
+/// runs to completion; `max_result_digits` keeps each of those short.
+///
+/// Start from `EvalLimits::default()` and set the bounds wanted, as more may
+/// be added.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EvalLimits {
+    /// Digits of any result, checked by evaluation and the checked BigNum
+    /// and Frac operations
+    pub max_result_digits: Option<usize>,
+    /// Operators, calls, variables and literals evaluated
+    pub max_steps: Option<u64>,
+    /// Nesting of parsed expressions, in place of the process-wide parse
+    /// limit of `Expr::set_max_depth`
+    pub max_depth: Option<usize>,
+    /// Time the evaluation may take, from its start
+    pub timeout: Option<Duration>,
+    /// Digits past which fraction results are reduced, in place of the
+    /// process-wide `Frac::set_gcd_threshold`
+    pub gcd_threshold: Option<usize>,
+}
+
+impl EvalLimits {
+    /// Run `f` within these limits, for the checked BigNum and Frac
+    /// operations called outside of an evaluation, as in
+    /// `limits.run(|| base.checked_pow(exponent))`
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        limited(self, f)
+    }
+}
+
+// What is left of the limits of the evaluation running on this thread
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Budget {
+    max_result_digits: Option<usize>,
+    steps_left: Option<u64>,
+    max_steps: u64,
+    max_depth: Option<usize>,
+    deadline: Option<(Instant, Duration)>,
+    gcd_threshold: Option<usize>,
+}
+
+thread_local! {
+    static BUDGET: Cell<Option<Budget>> = const { Cell::new(None) };
+}
+
+// Run `evaluate` within `limits`, which replace those of any evaluation it
+// is nested in until it returns
+pub(crate) fn limited<T>(limits: &EvalLimits, evaluate: impl FnOnce() -> T) -> T {
+    let budget = Budget {
+        max_result_digits: limits.max_result_digits,
+        steps_left: limits.max_steps,
+        max_steps: limits.max_steps.unwrap_or(0),
+        max_depth: limits.max_depth,
+        deadline: limits
+            .timeout
+            .map(|timeout| (Instant::now() + timeout, timeout)),
+        gcd_threshold: limits.gcd_threshold,
+    };
+    let outer = BUDGET.with(|current| current.replace(Some(budget)));
+    let result = evaluate();
+    BUDGET.with(|current| current.set(outer));
+    result
+}
+
+// Limits of the evaluation running on this thread, to hand to `within` on
+// another thread doing part of its work
+#[cfg(feature = "rayon")]
+pub(crate) fn current() -> Option<Budget> {
+    BUDGET.with(Cell::get)
+}
+
+// Run `work` within the limits `current` gave on the thread it handed it from
+#[cfg(feature = "rayon")]
+pub(crate) fn within<T>(budget: Option<Budget>, work: impl FnOnce() -> T) -> T {
+    let outer = BUDGET.with(|current| current.replace(budget));
+    let result = work();
+    BUDGET.with(|current| current.set(outer));
+    result
+}
+
+// Count one step of evaluation, an error once the steps or the time are
+// used up
+pub(crate) fn step() -> Result<(), ArithError> {
+    BUDGET.with(|current| {
+        let Some(mut budget) = current.get() else {
+            return Ok(());
+        };
+        if let Some(steps_left) = &mut budget.steps_left {
+            if *steps_left == 0 {
+                return Err(ArithError::LimitExceeded(format!(
+                    "{}: more than {} steps",
+                    LIMIT_EXCEEDED, budget.max_steps
+                )));
+            }
+            *steps_left -= 1;
+            current.set(Some(budget));
+        }
+        match budget.deadline {
+            Some((deadline, timeout)) if Instant::now() > deadline => {
+                Err(ArithError::LimitExceeded(format!(
+                    "{}: took longer than {:?}",
+                    LIMIT_EXCEEDED, timeout
+                )))
+            }
+            _ => Ok(()),
+        }
+    })
+}
+
+// Digit limit of the running evaluation, if it has one
+pub(crate) fn max_result_digits() -> Option<usize> {
+    BUDGET.with(|current| current.get().and_then(|budget| budget.max_result_digits))
+}
+
+// Nesting limit of the running evaluation, if it has one
+pub(crate) fn max_depth() -> Option<usize> {
+    BUDGET.with(|current| current.get().and_then(|budget| budget.max_depth))
+}
+
+// GCD threshold of the running evaluation, if it sets one
+pub(crate) fn gcd_threshold() -> Option<usize> {
+    BUDGET.with(|current| current.get().and_then(|budget| budget.gcd_threshold))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{eval_to_string, CalcError};
+
+    fn eval_limited(input: &str, limits: EvalLimits) -> Result<String, CalcError> {
+        limited(&limits, || eval_to_string(input))
+    }
+
+    mod test_limits {
+        use super::*;
+
+        #[test]
+        fn test_steps() {
+            let limits = EvalLimits {
+                max_steps: Some(5),
+                ..EvalLimits::default()
+            };
+            // Two literals, the sum, a literal and the product
+            assert_eq!(eval_limited("(1 + 2) * 3", limits).unwrap(), "9");
+            let err = eval_limited("(1 + 2) * 3 + 4", limits).unwrap_err();
+            assert!(err.is_limit_exceeded());
+            assert_eq!(
+                err.to_string(),
+                "Eval Error Limit exceeded: more than 5 steps"
+            );
+            // The budget covers every statement of the input
+            assert!(eval_limited("x = 1 + 2; x * 3", limits).is_err());
+        }
+
+        #[test]
+        fn test_digits() {
+            let limits = EvalLimits {
+                max_result_digits: Some(100),
+                ..EvalLimits::default()
+            };
+            assert!(eval_limited("2^400", limits)
+                .unwrap_err()
+                .is_limit_exceeded());
+            assert!(eval_limited("9^9^9", limits)
+                .unwrap_err()
+                .is_limit_exceeded());
+            assert_eq!(eval_limited("2^10", limits).unwrap(), "1024");
+            // 91 digits, within the limit however many its operands have
+            assert_eq!(eval_limited("2^300", limits).unwrap().len(), 91);
+            let limits = EvalLimits {
+                max_result_digits: Some(5),
+                ..EvalLimits::default()
+            };
+            for (input, expected) in [
+                ("2^16", "65536"),
+                ("100*100", "10000"),
+                ("10^4", "10000"),
+                ("50000+1", "50001"),
+                ("1/3 + 1/7", "10/21"),
+            ] {
+                assert_eq!(eval_limited(input, limits).unwrap(), expected, "{}", input);
+            }
+            for input in ["2^17", "1000*100", "99999+1", "2^(10^12)"] {
+                assert!(
+                    eval_limited(input, limits).unwrap_err().is_limit_exceeded(),
+                    "{}",
+                    input
+                );
+            }
+            // Only while the limits apply
+            assert!(eval_to_string("2^300").is_ok());
+        }
+
+        #[test]
+        fn test_depth() {
+            let limits = EvalLimits {
+                max_depth: Some(3),
+                ..EvalLimits::default()
+            };
+            assert_eq!(eval_limited("((1))", limits).unwrap(), "1");
+            let err = eval_limited("(((1)))", limits).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Eval Error Limit exceeded: expression nested more than 3 deep"
+            );
+        }
+
+        #[test]
+        fn test_timeout() {
+            let limits = EvalLimits {
+                timeout: Some(Duration::ZERO),
+                ..EvalLimits::default()
+            };
+            let err = eval_limited("1 + 1", limits).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Eval Error Limit exceeded: took longer than 0ns"
+            );
+            let limits = EvalLimits {
+                timeout: Some(Duration::from_secs(60)),
+                ..EvalLimits::default()
+            };
+            assert_eq!(eval_limited("1 + 1", limits).unwrap(), "2");
+        }
+
+        #[test]
+        fn test_nested() {
+            let outer = EvalLimits {
+                max_steps: Some(1),
+                ..EvalLimits::default()
+            };
+            limited(&outer, || {
+                assert_eq!(eval_limited("1 + 1", EvalLimits::default()).unwrap(), "2");
+                // The outer budget is untouched by the inner evaluation
+                assert!(step().is_ok());
+                assert!(step().is_err());
+            });
+            assert!(step().is_ok());
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! `bignum!` and `frac!`, which check their literals while compiling instead
+//! of parsing a string at run time. The const helpers are only public so the
+//! expanded code can reach them.
+
+/// BigNum from an integer literal of any length, e.g.
+/// `bignum!(-12345678901234567890)`. Underscores are allowed, anything but
+/// decimal digits fails to compile.
+#[macro_export]
+macro_rules! bignum {
+    (- $lit:literal) => {
+        $crate::bignum!(@digits false, $lit)
+    };
+    ($lit:literal) => {
+        $crate::bignum!(@digits true, $lit)
+    };
+    (@digits $sign:expr, $lit:literal) => {{
+        const LITERAL: &str = stringify!($lit);
+        const LEN: usize = $crate::macros::literal_len(LITERAL);
+        const DIGITS: [u8; LEN] = $crate::macros::literal_digits(LITERAL);
+        $crate::BigNum::from_digits(DIGITS.to_vec(), $sign)
+    }};
+}
+
+/// Frac from a literal fraction, e.g. `frac!(22/7)` or `frac!(-1/3)`. A zero
+/// denominator fails to compile.
+#[macro_export]
+macro_rules! frac {
+    (- $num:literal / $den:literal) => {
+        $crate::frac!(@parts $crate::bignum!(-$num), $den)
+    };
+    ($num:literal / $den:literal) => {
+        $crate::frac!(@parts $crate::bignum!($num), $den)
+    };
+    (@parts $num:expr, $den:literal) => {{
+        const _: () = assert!(
+            !$crate::macros::literal_is_zero(stringify!($den)),
+            "frac! denominator cannot be zero"
+        );
+        $crate::Frac::new($num, $crate::bignum!($den))
+    }};
+}
+
+/// Number of digits in an integer literal, failing const evaluation on any
+/// other character
+pub const fn literal_len(literal: &str) -> usize {
+    let bytes = literal.as_bytes();
+    let mut len = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'0'..=b'9' => len += 1,
+            b'_' => {}
+            _ => panic!("bignum! expects a decimal integer literal"),
+        }
+        i += 1;
+    }
+    if len == 0 {
+        panic!("bignum! expects a decimal integer literal");
+    }
+    len
+}
+
+/// Digits of a literal already checked by `literal_len`
+pub const fn literal_digits<const N: usize>(literal: &str) -> [u8; N] {
+    let bytes = literal.as_bytes();
+    let mut digits = [0; N];
+    let mut len = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'_' {
+            digits[len] = bytes[i] - b'0';
+            len += 1;
+        }
+        i += 1;
+    }
+    digits
+}
+
+pub const fn literal_is_zero(literal: &str) -> bool {
+    let bytes = literal.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'0' && bytes[i] != b'_' {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BigNum, Frac};
+    use std::str::FromStr;
+
+    mod test_bignum {
+        use super::*;
+
+        #[test]
+        fn test_literals() {
+            assert_eq!(bignum!(0), BigNum::zero());
+            assert_eq!(bignum!(-0), BigNum::zero());
+            assert_eq!(bignum!(007), BigNum::from(7));
+            assert_eq!(bignum!(1_000_000), BigNum::from(1_000_000));
+            assert_eq!(
+                bignum!(-123456789012345678901234567890123456789012345),
+                BigNum::from_str("-123456789012345678901234567890123456789012345").unwrap()
+            );
+        }
+    }
+
+    mod test_frac {
+        use super::*;
+
+        #[test]
+        fn test_literals() {
+            assert_eq!(frac!(22 / 7), Frac::from_str("22/7").unwrap());
+            assert_eq!(frac!(-2 / 4), Frac::from_str("-1/2").unwrap());
+            assert_eq!(
+                frac!(1 / 100000000000000000000000000000),
+                Frac::from_str("1/100000000000000000000000000000").unwrap()
+            );
+        }
+    }
+}
@@ -1,9 +1,10 @@
-mod big_num;
-mod common;
-mod frac;
-mod parser;
-
-use parser::*;
 fn main() {
-    parser::run();
+    let code = match rust_calculator::run() {
+        Ok(code) => code,
+        Err(e) => {
+            println!("Error: {}", e);
+            1
+        }
+    };
+    std::process::exit(code);
 }
@@ -0,0 +1,146 @@
+use crate::big_num::BigNum;
+
+/// Precomputed Barrett reduction for a fixed modulus. With decimal digits the
+/// divisions by powers of ten in Barrett's method are plain digit shifts, so
+/// reducing a product costs two multiplications instead of a long division.
+#[derive(Debug, Clone)]
+pub struct ModContext {
+    modulus: BigNum,
+    // floor(10^(2k) / modulus) where k is the digit count of the modulus
+    mu: BigNum,
+    k: usize,
+}
+
+impl ModContext {
+    pub fn new(modulus: BigNum) -> Result<ModContext, String> {
+        if modulus.is_zero() || modulus.is_negative() {
+            return Err(format!("Modulus must be positive, got {}", modulus));
+        }
+        let k = modulus.num_digits();
+        let mu = BigNum::one().shift_left(2 * k) / modulus.clone();
+        Ok(ModContext { modulus, mu, k })
+    }
+
+    pub fn modulus(&self) -> &BigNum {
+        &self.modulus
+    }
+
+    /// `x mod m` in `0..m`, for any integer `x`
+    pub fn reduce(&self, x: &BigNum) -> BigNum {
+        if x.is_negative() || x.num_digits() > 2 * self.k {
+            // Outside the range Barrett handles, fall back to a division
+            let r = x.clone() % self.modulus.clone();
+            return if r.is_negative() {
+                r + self.modulus.clone()
+            } else {
+                r
+            };
+        }
+        let q = (x.shift_right(self.k - 1) * self.mu.clone()).shift_right(self.k + 1);
+        let mut r = x.clone() - q * self.modulus.clone();
+        // The estimate of the quotient is at most two too small
+        while r >= self.modulus {
+            r -= self.modulus.clone();
+        }
+        r
+    }
+
+    pub fn mul(&self, a: &BigNum, b: &BigNum) -> BigNum {
+        self.reduce(&(self.reduce(a) * self.reduce(b)))
+    }
+
+    /// `base^exponent mod m`, negative exponents go through the inverse
+    pub fn pow(&self, base: &BigNum, exponent: &BigNum) -> Result<BigNum, String> {
+        let base = if exponent.is_negative() {
+            self.inverse(base)?
+        } else {
+            self.reduce(base)
+        };
+        let mut result = self.reduce(&BigNum::one());
+        // Left to right over the bits of the exponent
+        for byte in exponent.to_bytes_be().1 {
+            for bit in (0..8).rev() {
+                result = self.reduce(&result.square());
+                if byte >> bit & 1 == 1 {
+                    result = self.mul(&result, &base);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// `x` with `a * x = 1 mod m`, an error when `a` and `m` are not coprime
+    pub fn inverse(&self, a: &BigNum) -> Result<BigNum, String> {
+        // Extended Euclid keeping only the coefficient of `a`
+        let (mut r0, mut r1) = (self.modulus.clone(), self.reduce(a));
+        let (mut t0, mut t1) = (BigNum::zero(), BigNum::one());
+        while !r1.is_zero() {
+            let q = r0.clone() / r1.clone();
+            (r0, r1) = (r1.clone(), r0 - q.clone() * r1);
+            (t0, t1) = (t1.clone(), t0 - q * t1);
+        }
+        if r0 != BigNum::one() {
+            return Err(format!("{} has no inverse modulo {}", a, self.modulus));
+        }
+        Ok(self.reduce(&t0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(s: &str) -> BigNum {
+        s.parse().unwrap()
+    }
+
+    mod test_mod_context {
+        use super::*;
+
+        #[test]
+        fn test_reduce_matches_rem() {
+            let ctx = ModContext::new(num("97")).unwrap();
+            for x in 0..97 * 97 + 200 {
+                let x = BigNum::from(x);
+                assert_eq!(ctx.reduce(&x), x % num("97"));
+            }
+            assert_eq!(ctx.reduce(&num("-5")), num("92"));
+        }
+
+        #[test]
+        fn test_mul() {
+            let m = num("1000000007");
+            let ctx = ModContext::new(m.clone()).unwrap();
+            let a = num("123456789123456789");
+            let b = num("987654321987654321");
+            assert_eq!(ctx.mul(&a, &b), (a * b) % m);
+        }
+
+        #[test]
+        fn test_pow() {
+            let ctx = ModContext::new(num("1000000007")).unwrap();
+            // Fermat: a^(p-1) = 1 mod p
+            assert_eq!(ctx.pow(&num("12345"), &num("1000000006")), Ok(num("1")));
+            assert_eq!(ctx.pow(&num("3"), &num("0")), Ok(num("1")));
+            assert_eq!(ctx.pow(&num("2"), &num("30")), Ok(num("73741817")));
+            let ctx = ModContext::new(num("1")).unwrap();
+            assert_eq!(ctx.pow(&num("5"), &num("0")), Ok(num("0")));
+        }
+
+        #[test]
+        fn test_inverse() {
+            let ctx = ModContext::new(num("101")).unwrap();
+            let inv = ctx.inverse(&num("37")).unwrap();
+            assert_eq!(ctx.mul(&inv, &num("37")), num("1"));
+            assert_eq!(ctx.pow(&num("37"), &num("-1")), Ok(inv));
+            let ctx = ModContext::new(num("12")).unwrap();
+            assert!(ctx.inverse(&num("8")).is_err());
+        }
+
+        #[test]
+        fn test_invalid_modulus() {
+            assert!(ModContext::new(num("0")).is_err());
+            assert!(ModContext::new(num("-7")).is_err());
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// Implementations of the num-traits and num-integer traits, so BigNum and
+// Frac can be used by generic numeric code
+use crate::big_num::BigNum;
+use crate::frac::{Frac, IntoFrac};
+
+use num_integer::Integer;
+use num_traits::{Num, One, Signed, Zero};
+
+impl Zero for BigNum {
+    fn zero() -> Self {
+        BigNum::zero()
+    }
+
+    fn is_zero(&self) -> bool {
+        BigNum::is_zero(self)
+    }
+}
+
+impl One for BigNum {
+    fn one() -> Self {
+        BigNum::one()
+    }
+}
+
+impl Num for BigNum {
+    type FromStrRadixErr = String;
+
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        if !(2..=36).contains(&radix) {
+            return Err(format!("Unsupported radix {}", radix));
+        }
+        let (sign, digits) = match s.strip_prefix('-') {
+            Some(rest) => (false, rest),
+            None => (true, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() {
+            return Err("Invalid number format".to_string());
+        }
+        let base = BigNum::from(radix);
+        let mut result = BigNum::zero();
+        for c in digits.chars() {
+            let digit = c
+                .to_digit(radix)
+                .ok_or_else(|| format!("Invalid character: {}", c))?;
+            result = result * base.clone() + BigNum::from(digit);
+        }
+        Ok(if sign { result } else { -result })
+    }
+}
+
+impl Signed for BigNum {
+    fn abs(&self) -> Self {
+        BigNum::abs(self)
+    }
+
+    fn abs_sub(&self, other: &Self) -> Self {
+        if self <= other {
+            BigNum::zero()
+        } else {
+            self.clone() - other.clone()
+        }
+    }
+
+    fn signum(&self) -> Self {
+        BigNum::signum(self)
+    }
+
+    fn is_positive(&self) -> bool {
+        !BigNum::is_negative(self) && !BigNum::is_zero(self)
+    }
+
+    fn is_negative(&self) -> bool {
+        BigNum::is_negative(self) && !BigNum::is_zero(self)
+    }
+}
+
+impl Integer for BigNum {
+    fn div_floor(&self, other: &Self) -> Self {
+        let (quotient, remainder) = Integer::div_rem(self, other);
+        if !BigNum::is_zero(&remainder) && (remainder.is_negative() != other.is_negative()) {
+            quotient - BigNum::one()
+        } else {
+            quotient
+        }
+    }
+
+    fn mod_floor(&self, other: &Self) -> Self {
+        let remainder = self.clone() % other.clone();
+        if !BigNum::is_zero(&remainder) && (remainder.is_negative() != other.is_negative()) {
+            remainder + other.clone()
+        } else {
+            remainder
+        }
+    }
+
+    fn gcd(&self, other: &Self) -> Self {
+        // num defines gcd(0, 0) as 0 where BigNum::gcd reports an error
+        BigNum::gcd(self, other).unwrap_or_else(|_| BigNum::zero())
+    }
+
+    fn lcm(&self, other: &Self) -> Self {
+        if BigNum::is_zero(self) || BigNum::is_zero(other) {
+            return BigNum::zero();
+        }
+        (self.clone() * other.clone()).abs() / Integer::gcd(self, other)
+    }
+
+    fn is_multiple_of(&self, other: &Self) -> bool {
+        if BigNum::is_zero(other) {
+            return BigNum::is_zero(self);
+        }
+        BigNum::is_zero(&(self.clone() % other.clone()))
+    }
+
+    fn is_even(&self) -> bool {
+        BigNum::is_even(self)
+    }
+
+    fn is_odd(&self) -> bool {
+        BigNum::is_odd(self)
+    }
+
+    fn div_rem(&self, other: &Self) -> (Self, Self) {
+        let quotient = self.clone() / other.clone();
+        let remainder = self.clone() - quotient.clone() * other.clone();
+        (quotient, remainder)
+    }
+}
+
+impl Zero for Frac {
+    fn zero() -> Self {
+        BigNum::zero().to_frac()
+    }
+
+    fn is_zero(&self) -> bool {
+        Frac::is_zero(self)
+    }
+}
+
+impl One for Frac {
+    fn one() -> Self {
+        BigNum::one().to_frac()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn num(s: &str) -> BigNum {
+        BigNum::from_str(s).unwrap()
+    }
+
+    mod test_num {
+        use super::*;
+
+        #[test]
+        fn test_zero_one() {
+            assert!(<BigNum as Zero>::is_zero(&Zero::zero()));
+            assert_eq!(<BigNum as One>::one(), num("1"));
+            assert!(<Frac as Zero>::is_zero(&Zero::zero()));
+            assert_eq!(<Frac as One>::one(), Frac::from_str("2/2").unwrap());
+        }
+
+        #[test]
+        fn test_from_str_radix() {
+            assert_eq!(BigNum::from_str_radix("ff", 16).unwrap(), num("255"));
+            assert_eq!(BigNum::from_str_radix("-1010", 2).unwrap(), num("-10"));
+            assert!(BigNum::from_str_radix("12", 2).is_err());
+            assert!(BigNum::from_str_radix("", 10).is_err());
+        }
+
+        #[test]
+        fn test_signed() {
+            assert_eq!(Signed::signum(&num("-5")), num("-1"));
+            assert_eq!(Signed::signum(&num("0")), num("0"));
+            assert!(Signed::is_positive(&num("3")));
+            assert_eq!(num("3").abs_sub(&num("5")), num("0"));
+            assert_eq!(num("5").abs_sub(&num("3")), num("2"));
+        }
+    }
+
+    mod test_integer {
+        use super::*;
+
+        #[test]
+        fn test_floor_division() {
+            assert_eq!(num("-7").div_floor(&num("2")), num("-4"));
+            assert_eq!(num("-7").mod_floor(&num("2")), num("1"));
+            assert_eq!(num("7").div_floor(&num("-2")), num("-4"));
+            assert_eq!(num("7").mod_floor(&num("-2")), num("-1"));
+            assert_eq!(num("6").div_floor(&num("-2")), num("-3"));
+        }
+
+        #[test]
+        fn test_gcd_lcm() {
+            assert_eq!(Integer::gcd(&num("12"), &num("18")), num("6"));
+            assert_eq!(Integer::gcd(&num("0"), &num("0")), num("0"));
+            assert_eq!(num("4").lcm(&num("-6")), num("12"));
+            assert_eq!(num("0").lcm(&num("6")), num("0"));
+        }
+
+        #[test]
+        fn test_parity_and_multiples() {
+            assert!(num("10").is_even());
+            assert!(num("-7").is_odd());
+            assert!(num("12").is_multiple_of(&num("4")));
+            assert!(!num("12").is_multiple_of(&num("0")));
+            assert_eq!(
+                Integer::div_rem(&num("-7"), &num("2")),
+                (num("-3"), num("-1"))
+            );
+        }
+    }
+}
@@ -1,36 +1,297 @@
-// S -> E end
-// E -> T { ("+" | "-") T }
-// T -> F { ("*" | "/" | "%") F }
-// F -> value | frac | "-" F | "(" E ")"
-// value -> [0-9]+
-// frac -> [0-9]+ / [1-9][0-9]*  // Ensure denominator is nonzero
+// L -> S { (";" | newline) S }, split off before lexing by split_statements
+// S -> [ name "=" ] Q end, anything after `#` being a comment
+// Q -> prefix* P postfix* { infix Q | "?" Q ":" Q }, operators binding as
+// INFIX, PREFIX and POSTFIX below say
+// P -> value | frac | "true" | "false" | "(" Q ")" | "|" Q "|" | call,
+//      `|x|` being abs(x) and `|` inside it closing rather than an or
+// call -> name "(" [ Q { "," Q } ] ")"
+// value -> [0-9]+ [ "." [0-9]+ | vulgar ] | "." [0-9]+ | vulgar
+//        | ("0x" | "0o" | "0b") [0-9a-zA-Z]+, vulgar being `½` and the like
+//        and `_` allowed between digits, see number_end for a decimal comma
+// frac -> [0-9]+ / [1-9][0-9]*  // Folded by binary(), `1/2` parsing as one literal
 
-use crate::common::Value;
+use crate::audit::audited;
+use crate::big_num::BigNum;
+use crate::cache::ExprCache;
+use crate::common::{AngleUnit, Value};
+use crate::compile::CompiledExpr;
+use crate::env::{Environment, ANS};
+use crate::error::ArithError;
+use crate::extensions;
+use crate::frac::{self, Frac};
+use crate::functions;
+use crate::gen;
+use crate::limits::{self, LIMIT_EXCEEDED};
+use crate::rpn;
+use crate::simplify;
+use crate::stats;
+use crate::steps::{self, EvalStep};
 
-use std::{convert::TryFrom, error::Error, fmt, io::prelude::*, iter::Peekable, slice::Iter};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::{convert::TryFrom, error::Error, fmt, iter::Peekable, slice::Iter};
 
+// Deepest nesting the parser builds, 0 for no limit. Parsing, the deepest
+// recursion left, takes about 5 KB of stack per level in debug builds,
+// well within the 2 MB of a spawned thread at this depth.
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(256);
+// Deepest tree the parser builds under a lower nesting limit. A chain like
+// `1+1+…+1` deepens the tree without nesting the parse, and cloning,
+// printing and simplifying recurse over trees at up to 2.5 KB a level.
+const MAX_TREE_DEPTH: usize = 512;
+
+// How input is read where sessions differ, see with_syntax
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Syntax {
+    // `a + b%` and `a - b%` as `a * (1 + b%)` and `a * (1 - b%)`, adding a
+    // tax or taking off a discount the way money calculators do, rather
+    // than adding b/100
+    pub(crate) relative_percent: bool,
+    // `1.000.000,5`, `.` grouping digits in threes and `,` starting the
+    // decimals, as numbers are written in much of Europe
+    pub(crate) decimal_comma: bool,
+}
+
+thread_local! {
+    static SYNTAX: Cell<Syntax> = const {
+        Cell::new(Syntax {
+            relative_percent: false,
+            decimal_comma: false,
+        })
+    };
+}
+
+/// How a chain of the same binary operator groups, see
+/// [`Session::register_operator`](crate::Session::register_operator)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`
+    Right,
+    /// Comparisons, `a < b < c` being an error
+    None,
+}
+
+// Binary operators by precedence, higher binding tighter. Every operator
+// is a row here, the parser has no function per level.
+const INFIX: &[(Token<'static>, Operator, u8, Assoc)] = &[
+    (Token::OrOr, Operator::Or, 2, Assoc::Left),
+    (Token::AndAnd, Operator::And, 3, Assoc::Left),
+    (Token::EqualEqual, Operator::Equal, 4, Assoc::None),
+    (Token::BangEqual, Operator::NotEqual, 4, Assoc::None),
+    (Token::Less, Operator::Less, 4, Assoc::None),
+    (Token::LessEqual, Operator::LessEqual, 4, Assoc::None),
+    (Token::Greater, Operator::Greater, 4, Assoc::None),
+    (Token::GreaterEqual, Operator::GreaterEqual, 4, Assoc::None),
+    (Token::Pipe, Operator::BitOr, 5, Assoc::Left),
+    (Token::CaretCaret, Operator::BitXor, 6, Assoc::Left),
+    (Token::Amp, Operator::BitAnd, 7, Assoc::Left),
+    (Token::LessLess, Operator::ShiftLeft, 8, Assoc::Left),
+    (Token::GreaterGreater, Operator::ShiftRight, 8, Assoc::Left),
+    (Token::Plus, Operator::Add, 9, Assoc::Left),
+    (Token::Dash, Operator::Subtract, 9, Assoc::Left),
+    (Token::Star, Operator::Multiply, 10, Assoc::Left),
+    (Token::Slash, Operator::Divide, 10, Assoc::Left),
+    (Token::DoubleSlash, Operator::IntDiv, 10, Assoc::Left),
+    (Token::Percent, Operator::Modulo, 10, Assoc::Left),
+    // Above prefix operators, `-2^2` being -4, while `2^-2` still parses
+    (Token::Caret, Operator::Power, 12, Assoc::Right),
+    (Token::DoubleStar, Operator::Power, 12, Assoc::Right),
+];
+
+// `c ? a : b`, loosest of all and grouping from the right
+const CONDITIONAL_PRECEDENCE: u8 = 1;
+
+const PREFIX: &[(Token<'static>, Operator)] = &[
+    (Token::Dash, Operator::Negative),
+    (Token::Plus, Operator::Positive),
+    (Token::Bang, Operator::Not),
+    (Token::Tilde, Operator::BitNot),
+];
+const PREFIX_PRECEDENCE: u8 = 11;
+
+// `%` only when no operand follows it, otherwise it is the remainder
+const POSTFIX: &[(Token<'static>, Operator)] = &[
+    (Token::Bang, Operator::Factorial),
+    (Token::Percent, Operator::Percent),
+    (Token::Degree, Operator::Degree),
+];
+const POSTFIX_PRECEDENCE: u8 = 13;
+
+// Fraction characters read as numbers, after a whole part or on their own
+const VULGAR_FRACTIONS: &[(char, u8, u8)] = &[
+    ('½', 1, 2),
+    ('⅓', 1, 3),
+    ('⅔', 2, 3),
+    ('¼', 1, 4),
+    ('¾', 3, 4),
+    ('⅕', 1, 5),
+    ('⅖', 2, 5),
+    ('⅗', 3, 5),
+    ('⅘', 4, 5),
+    ('⅙', 1, 6),
+    ('⅚', 5, 6),
+    ('⅐', 1, 7),
+    ('⅛', 1, 8),
+    ('⅜', 3, 8),
+    ('⅝', 5, 8),
+    ('⅞', 7, 8),
+    ('⅑', 1, 9),
+    ('⅒', 1, 10),
+];
+
+/// Lexical token, see [`tokenize`](crate::tokenize). Tokens borrow their text
+/// from the input instead of copying it
 #[derive(Debug, PartialEq, Clone)]
-pub enum Token {
+#[non_exhaustive]
+pub enum Token<'a> {
     Plus,
     Dash,
     Star,
     Slash,
-    RightParen,
+    DoubleSlash,
+    Caret,
+    DoubleStar,
     LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Equals,
+    EqualEqual,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AndAnd,
+    OrOr,
+    Amp,
+    Pipe,
+    CaretCaret,
+    LessLess,
+    GreaterGreater,
+    Tilde,
+    Question,
+    Colon,
+    Bang,
+    Percent,
+    Degree,
     End,
-    Number(Value),
+    Number(&'a str, Option<AngleUnit>),
+    Ident(&'a str),
+    Str(&'a str),
+    // Operator registered with Session::register_operator
+    Custom(&'a str),
+}
+
+// How a token is named in error messages
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Token::Plus => "+",
+            Token::Dash => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::DoubleSlash => "//",
+            Token::Caret => "^",
+            Token::DoubleStar => "**",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::Comma => ",",
+            Token::Equals => "=",
+            Token::EqualEqual => "==",
+            Token::BangEqual => "!=",
+            Token::Less => "<",
+            Token::LessEqual => "<=",
+            Token::Greater => ">",
+            Token::GreaterEqual => ">=",
+            Token::AndAnd => "&&",
+            Token::OrOr => "||",
+            Token::Amp => "&",
+            Token::Pipe => "|",
+            Token::CaretCaret => "^^",
+            Token::LessLess => "<<",
+            Token::GreaterGreater => ">>",
+            Token::Tilde => "~",
+            Token::Question => "?",
+            Token::Colon => ":",
+            Token::Bang => "!",
+            Token::Percent => "%",
+            Token::Degree => "°",
+            Token::Custom(symbol) => *symbol,
+            Token::End => return write!(f, "end of input"),
+            Token::Number(digits, _) => return write!(f, "number {}", digits),
+            Token::Ident(name) => return write!(f, "name {}", name),
+            Token::Str(text) => return write!(f, "string \"{}\"", text),
+        };
+        write!(f, "'{}'", symbol)
+    }
+}
+
+/// Byte range of a token in the input
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A token and where it is in the input
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lexeme<'a> {
+    pub token: Token<'a>,
+    pub span: Span,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum Operator {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub enum Operator {
     Add,
     Multiply,
     Divide,
     Subtract,
     Negative,
+    // Unary `+`, leaving a number as it is
+    Positive,
+    // `%` and `//` between two operands, truncating like BigNum `/` and `%`
+    Modulo,
+    IntDiv,
+    Power,
+    // Comparisons, giving true or false
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    // On true and false only, `!` being prefix
+    And,
+    Or,
+    Not,
+    // On integers, in two's complement, `~` being prefix
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    BitNot,
+    // Postfix, binding tighter than a leading minus so `-3!` is `-(3!)`
+    Factorial,
+    Percent,
+    Degree,
+    // `list[index]`, binding like the postfix operators
+    Index,
+    // Registered with Session::register_operator
+    Custom(&'static str),
 }
 
-impl TryFrom<Token> for Operator {
+impl TryFrom<Token<'_>> for Operator {
     type Error = &'static str;
 
     fn try_from(token: Token) -> Result<Self, Self::Error> {
@@ -44,266 +305,3583 @@ impl TryFrom<Token> for Operator {
     }
 }
 
-fn lex(code: String) -> Result<Vec<Token>, SyntaxError> {
-    let mut iter = code.chars().peekable();
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut leftover: Option<char> = None;
+/// Tokens of an input one at a time with their spans, ending with a single
+/// `Token::End`. Nothing follows the end or the first error.
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    code: &'a str,
+    // Byte offset of the next character
+    pos: usize,
+    done: bool,
+}
 
-    loop {
-        let ch = match leftover {
-            Some(ch) => ch,
-            None => match iter.next() {
-                None => break,
-                Some(ch) => ch,
-            },
+impl<'a> Lexer<'a> {
+    pub fn new(code: &'a str) -> Self {
+        Lexer {
+            code,
+            pos: 0,
+            done: false,
+        }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.code[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek_char()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+
+    // Consume the next character if it is `expected`
+    fn eat(&mut self, expected: char) -> bool {
+        let matched = self.peek_char() == Some(expected);
+        if matched {
+            self.pos += expected.len_utf8();
+        }
+        matched
+    }
+
+    fn eat_while(&mut self, accept: impl Fn(char) -> bool) {
+        while self.peek_char().is_some_and(&accept) {
+            self.bump();
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Lexeme<'a>, CalcError> {
+        loop {
+            self.eat_while(char::is_whitespace);
+            if !self.eat('#') {
+                break;
+            }
+            self.eat_while(|c| c != '\n');
+        }
+        let start = self.pos;
+        // Operators of the running session come first, so they may extend
+        // the built-in ones, `+-` before `+`
+        if let Some(len) = extensions::operator_len(&self.code[start..]) {
+            self.pos += len;
+            return Ok(Lexeme {
+                token: Token::Custom(&self.code[start..self.pos]),
+                span: Span {
+                    start,
+                    end: self.pos,
+                },
+            });
+        }
+        let Some(ch) = self.bump() else {
+            return Ok(Lexeme {
+                token: Token::End,
+                span: Span { start, end: start },
+            });
         };
-        leftover = None;
-        match ch {
-            ' ' => continue,
-            '+' => tokens.push(Token::Plus),
-            '*' => tokens.push(Token::Star),
-            '/' => tokens.push(Token::Slash),
-            ')' => tokens.push(Token::LeftParen),
-            '(' => tokens.push(Token::RightParen),
-            '-' => tokens.push(Token::Dash),
-            ch if ch.is_ascii_digit() => {
-                let number_stream: String = iter
-                    .by_ref()
-                    .take_while(|c| match c.is_ascii_digit() {
-                        true => true,
-                        false => {
-                            leftover = Some(*c);
-                            false
-                        }
-                    })
-                    .collect();
-                let number: Value = format!("{}{}", ch, number_stream).parse().unwrap();
-                tokens.push(Token::Number(number));
+        let token = match ch {
+            '+' => Token::Plus,
+            // The minus sign of typeset text as well
+            '-' | '−' => Token::Dash,
+            '*' if self.eat('*') => Token::DoubleStar,
+            '*' | '×' => Token::Star,
+            '^' if self.eat('^') => Token::CaretCaret,
+            '^' => Token::Caret,
+            '/' if self.eat('/') => Token::DoubleSlash,
+            // Division sign and fraction slash
+            '/' | '÷' | '⁄' => Token::Slash,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            ',' => Token::Comma,
+            '=' if self.eat('=') => Token::EqualEqual,
+            '=' => Token::Equals,
+            '!' if self.eat('=') => Token::BangEqual,
+            '!' => Token::Bang,
+            '&' if self.eat('&') => Token::AndAnd,
+            '&' => Token::Amp,
+            '|' if self.eat('|') => Token::OrOr,
+            '|' => Token::Pipe,
+            '~' => Token::Tilde,
+            '?' => Token::Question,
+            ':' => Token::Colon,
+            '<' if self.eat('=') => Token::LessEqual,
+            '<' if self.eat('<') => Token::LessLess,
+            '<' => Token::Less,
+            '>' if self.eat('=') => Token::GreaterEqual,
+            '>' if self.eat('>') => Token::GreaterGreater,
+            '>' => Token::Greater,
+            '%' => Token::Percent,
+            '°' => Token::Degree,
+            '"' => {
+                let Some(len) = self.code[self.pos..].find('"') else {
+                    return Err(CalcError::new_lex_error(
+                        "Unterminated string".to_string(),
+                        Span {
+                            start,
+                            end: self.code.len(),
+                        },
+                    ));
+                };
+                let text = &self.code[self.pos..self.pos + len];
+                self.pos += len + 1;
+                Token::Str(text)
+            }
+            ch if ch.is_ascii_alphabetic() => {
+                self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                Token::Ident(&self.code[start..self.pos])
+            }
+            ch if ch.is_ascii_digit() || ch == '.' || vulgar_fraction(ch).is_some() => {
+                let end = number_end(self.code, start);
+                if end == start {
+                    return Err(CalcError::new_lex_error(
+                        "Expected a digit after .".to_string(),
+                        Span {
+                            start,
+                            end: start + 1,
+                        },
+                    ));
+                }
+                self.pos = end;
+                // Unit suffix directly after the digits, as in `30deg`
+                self.eat_while(|c| c.is_ascii_alphabetic());
+                let unit = match &self.code[end..self.pos] {
+                    "" => None,
+                    suffix => Some(AngleUnit::from_suffix(suffix).ok_or_else(|| {
+                        CalcError::new_lex_error(
+                            format!("Unknown unit suffix {}", suffix),
+                            Span {
+                                start: end,
+                                end: self.pos,
+                            },
+                        )
+                    })?),
+                };
+                Token::Number(&self.code[start..end], unit)
             }
             _ => {
-                return Err(SyntaxError::new_lex_error(format!(
-                    "Unrecognized character {}",
-                    ch
-                )))
+                return Err(CalcError::new_lex_error(
+                    format!("Unrecognized character {}", ch),
+                    Span {
+                        start,
+                        end: self.pos,
+                    },
+                ))
             }
+        };
+        Ok(Lexeme {
+            token,
+            span: Span {
+                start,
+                end: self.pos,
+            },
+        })
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Lexeme<'a>, CalcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
         }
+        let result = self.next_token();
+        self.done = result
+            .as_ref()
+            .map_or(true, |lexeme| lexeme.token == Token::End);
+        Some(result)
     }
+}
 
-    tokens.push(Token::End);
+pub(crate) fn lex(code: &str) -> Result<Vec<Lexeme<'_>>, CalcError> {
+    Lexer::new(code).collect()
+}
+
+// Numerator and denominator of a character such as `½`
+fn vulgar_fraction(ch: char) -> Option<(u8, u8)> {
+    VULGAR_FRACTIONS
+        .iter()
+        .find(|&&(vulgar, ..)| vulgar == ch)
+        .map(|&(_, numerator, denominator)| (numerator, denominator))
+}
+
+// End of the digits at `start`, with the fractional part when a `.` and a
+// digit follow them, or a vulgar fraction as in `2½`. `_` may separate
+// digits, as in `1_000_000`, and with a decimal comma `.` separates groups
+// of three and `,` starts the fractional part.
+fn number_end(code: &str, start: usize) -> usize {
+    let bytes = code.as_bytes();
+    if let Some((_, rest)) = radix_prefix(&code[start..]) {
+        let is_digit = |&b: &u8| b.is_ascii_alphanumeric() || b == b'_';
+        return start + 2 + rest.bytes().take_while(is_digit).count();
+    }
+    let digits_end = |from: usize| {
+        let mut end = from;
+        while let Some(&b) = bytes.get(end) {
+            match (b, bytes.get(end + 1)) {
+                (b'0'..=b'9', _) => end += 1,
+                (b'_', Some(next)) if end > from && next.is_ascii_digit() => end += 1,
+                _ => break,
+            }
+        }
+        end
+    };
+    let mut end = digits_end(start);
+    let point = if syntax().decimal_comma {
+        let is_group = |at: usize| {
+            bytes.get(at) == Some(&b'.')
+                && bytes
+                    .get(at + 1..at + 4)
+                    .is_some_and(|group| group.iter().all(u8::is_ascii_digit))
+                && !bytes.get(at + 4).is_some_and(u8::is_ascii_digit)
+        };
+        while end > start && is_group(end) {
+            end += 4;
+        }
+        b','
+    } else {
+        b'.'
+    };
+    match bytes[end..] {
+        [p, next, ..] if p == point && next.is_ascii_digit() => digits_end(end + 1),
+        _ => match code[end..].chars().next() {
+            Some(ch) if vulgar_fraction(ch).is_some() => end + ch.len_utf8(),
+            _ => end,
+        },
+    }
+}
 
-    Ok(tokens)
+// Radix of a `0x`, `0o` or `0b` literal and the text after the prefix
+fn radix_prefix(literal: &str) -> Option<(u32, &str)> {
+    let radix = match literal.get(..2)? {
+        "0x" => 16,
+        "0o" => 8,
+        "0b" => 2,
+        _ => return None,
+    };
+    Some((radix, &literal[2..]))
 }
 
-#[derive(Debug)]
+/// An expression tree. The nodes evaluation can fail at carry the `Span` of
+/// their source, so `1 + 2 / 0` fails at `2 / 0`; built by hand or rewritten
+/// they hold `Span::default()`, and an error there covers the statement.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Expr {
-    BinExpr(Operator, Box<Expr>, Box<Expr>),
-    UnaryExpr(Operator, Box<Expr>),
-    ValExrp(Value),
+    Binary(Operator, Box<Expr>, Box<Expr>, Span),
+    Unary(Operator, Box<Expr>, Span),
+    Postfix(Operator, Box<Expr>, Span),
+    Literal(Value),
+    Call(String, Vec<Expr>, Span),
+    Var(String, Span),
+    Assign(String, Box<Expr>),
+    // `condition ? then : otherwise`
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>, Span),
+    // `error("message")`, aborting evaluation with the message
+    Raise(String, Span),
+    // `[a, b, c]`, evaluating to a list of the values of its items
+    List(Vec<Expr>, Span),
 }
 
 impl Expr {
-    pub fn eval(&mut self) -> Result<Value, SyntaxError> {
+    /// Limit the nesting of parsed expressions, so deep parentheses are
+    /// refused with an error instead of overflowing the stack while parsing.
+    /// Parentheses, arguments, prefix operators and right operands nest, a
+    /// chain like `1+1+…+1` does not, though its tree stays within the
+    /// larger of this limit and 512 levels. None removes both limits, and
+    /// `EvalLimits::max_depth` takes the place of this one where it is set.
+    /// Trees built by hand are never checked.
+    pub fn set_max_depth(limit: Option<usize>) {
+        MAX_DEPTH.store(limit.unwrap_or(0), AtomicOrdering::Relaxed);
+    }
+
+    pub fn max_depth() -> Option<usize> {
+        match MAX_DEPTH.load(AtomicOrdering::Relaxed) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn literal(value: Value) -> Self {
+        Expr::Literal(value)
+    }
+
+    pub fn var(name: &str) -> Self {
+        Expr::Var(name.to_string(), Span::default())
+    }
+
+    /// `left operator right`, with one of the binary operators such as
+    /// `Operator::Add` or `Operator::Less`
+    pub fn binary(operator: Operator, left: Expr, right: Expr) -> Self {
+        Expr::Binary(operator, Box::new(left), Box::new(right), Span::default())
+    }
+
+    /// `Operator::Negative`, `Operator::Positive`, `Operator::Not` or
+    /// `Operator::BitNot` before `expr`
+    pub fn unary(operator: Operator, expr: Expr) -> Self {
+        Expr::Unary(operator, Box::new(expr), Span::default())
+    }
+
+    /// `Operator::Factorial`, `Operator::Percent` or `Operator::Degree` after
+    /// `expr`
+    pub fn postfix(operator: Operator, expr: Expr) -> Self {
+        Expr::Postfix(operator, Box::new(expr), Span::default())
+    }
+
+    pub fn call(name: &str, args: Vec<Expr>) -> Self {
+        Expr::Call(name.to_string(), args, Span::default())
+    }
+
+    /// `[items]`
+    pub fn list(items: Vec<Expr>) -> Self {
+        Expr::List(items, Span::default())
+    }
+
+    /// `list[index]`
+    pub fn index(list: Expr, index: Expr) -> Self {
+        Expr::binary(Operator::Index, list, index)
+    }
+
+    pub fn assign(name: &str, expr: Expr) -> Self {
+        Expr::Assign(name.to_string(), Box::new(expr))
+    }
+
+    pub fn conditional(condition: Expr, then: Expr, otherwise: Expr) -> Self {
+        Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+            Span::default(),
+        )
+    }
+
+    /// Equivalent tree with its constant parts computed and `--x`, `x * 1`
+    /// and `x + 0` reduced to `x`, to evaluate many times or to read
+    pub fn simplify(&self) -> Expr {
+        simplify::simplify(self)
+    }
+
+    /// Source form with only the parentheses the parser needs to read it
+    /// back the same, `1 + 2 * 3` but `(1 + 2) * 3`. This is also how
+    /// `Display` prints, `{:#}` putting parentheses around every operand to
+    /// show how ambiguous input was read: `1 + (2 * 3)`.
+    pub fn to_string_min_parens(&self) -> String {
+        let mut out = String::new();
+        write_min_parens(self, &mut out);
+        out
+    }
+
+    /// Simplify and flatten once, to evaluate many times with different
+    /// variables
+    pub fn compile(&self) -> CompiledExpr {
+        CompiledExpr::new(self)
+    }
+
+    /// Reverse Polish notation, operands before their operator: `1 + 2 * 3`
+    /// is `1 2 3 * +`, read back by `parse_rpn`
+    pub fn to_rpn_string(&self) -> String {
+        rpn::to_rpn(self)
+    }
+
+    /// `eval_in` showing its working: the value with each step taken to
+    /// reach it, `1/2 + 1/3` becoming `3/6 + 2/6` and then `5/6`
+    pub fn eval_steps(&self, env: &mut Environment) -> Result<(Value, Vec<EvalStep>), CalcError> {
+        steps::eval_steps(self, env)
+    }
+
+    // Move the tree out, leaving a literal in its place. Expressions
+    // implement Drop, so they are taken apart through this rather than by
+    // moving their fields out.
+    pub(crate) fn take(&mut self) -> Expr {
+        std::mem::replace(self, Expr::Literal(Value::Bool(false)))
+    }
+
+    // Move the children out onto `pending`, leaving literals in their place
+    fn take_children(&mut self, pending: &mut Vec<Expr>) {
         match self {
-            Expr::ValExrp(num) => Ok((*num).clone()),
-            Expr::UnaryExpr(Operator::Negative, expr) => Ok(-expr.eval()?),
-            Expr::BinExpr(Operator::Add, left, right) => Ok(left.eval()? + right.eval()?),
-            Expr::BinExpr(Operator::Subtract, left, right) => Ok(left.eval()? - right.eval()?),
-            Expr::BinExpr(Operator::Multiply, left, right) => Ok(left.eval()? * right.eval()?),
-            Expr::BinExpr(Operator::Divide, left, right) => {
-                let right_val = right.eval()?;
-                if right_val.is_zero() {
-                    Err(SyntaxError::new_parse_error("Division by Zero".to_string()))
-                } else {
-                    Ok(left.eval()? / right_val)
-                }
+            Expr::Binary(_, left, right, _) => pending.extend([left.take(), right.take()]),
+            Expr::Unary(_, operand, _)
+            | Expr::Postfix(_, operand, _)
+            | Expr::Assign(_, operand) => pending.push(operand.take()),
+            Expr::Conditional(test, then, otherwise, _) => {
+                pending.extend([test.take(), then.take(), otherwise.take()])
             }
-            _ => Err(SyntaxError::new_parse_error(format!(
-                "Unreachable code: for expr {:?}",
-                self
-            ))),
+            Expr::Call(_, args, _) | Expr::List(args, _) => pending.append(args),
+            Expr::Literal(_) | Expr::Var(..) | Expr::Raise(..) => {}
         }
     }
-}
 
-#[derive(Debug)]
-struct SyntaxError {
-    message: String,
-    level: String,
-}
+    /// Levels of nesting, 1 for a lone literal or variable
+    pub fn depth(&self) -> usize {
+        1 + self.children().map(Expr::depth).max().unwrap_or(0)
+    }
 
-impl SyntaxError {
-    fn new_lex_error(message: String) -> Self {
-        SyntaxError {
-            message,
-            level: "Lex".to_string(),
+    /// Number of nodes in the tree
+    pub fn node_count(&self) -> usize {
+        1 + self.children().map(Expr::node_count).sum::<usize>()
+    }
+
+    /// Names of the variables read, not counting the one assigned to
+    /// or the one `sum` and `prod` range over
+    pub fn variables(&self) -> BTreeSet<String> {
+        match self {
+            Expr::Var(name, _) => BTreeSet::from([name.clone()]),
+            Expr::Call(name, args, _) if functions::is_series(name) => match args.as_slice() {
+                [Expr::Var(var, _), from, to, body] => {
+                    let mut names = body.variables();
+                    names.remove(var);
+                    names.extend(from.variables());
+                    names.extend(to.variables());
+                    names
+                }
+                _ => self.children().flat_map(Expr::variables).collect(),
+            },
+            _ => self.children().flat_map(Expr::variables).collect(),
         }
     }
 
-    fn new_parse_error(message: String) -> Self {
-        SyntaxError {
-            message,
-            level: "Parse".to_string(),
+    /// Names of the functions called, `error` included
+    pub fn functions(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.walk(&mut |expr| match expr {
+            Expr::Call(name, ..) => {
+                names.insert(name.clone());
+            }
+            Expr::Raise(..) => {
+                names.insert("error".to_string());
+            }
+            _ => {}
+        });
+        names
+    }
+
+    // Visit every node, parents before their children
+    fn walk(&self, visit: &mut impl FnMut(&Expr)) {
+        visit(self);
+        for child in self.children() {
+            child.walk(visit);
         }
     }
-}
 
-impl fmt::Display for SyntaxError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} Error {}", self.level, self.message)
+    pub(crate) fn children(&self) -> impl Iterator<Item = &Expr> {
+        let children: Vec<&Expr> = match self {
+            Expr::Binary(_, left, right, _) => vec![left, right],
+            Expr::Unary(_, expr, _) | Expr::Postfix(_, expr, _) | Expr::Assign(_, expr) => {
+                vec![expr]
+            }
+            Expr::Call(_, args, _) | Expr::List(args, _) => args.iter().collect(),
+            Expr::Conditional(condition, then, otherwise, _) => vec![condition, then, otherwise],
+            Expr::Literal(_) | Expr::Var(..) | Expr::Raise(..) => vec![],
+        };
+        children.into_iter()
     }
-}
 
-impl Error for SyntaxError {}
+    pub fn eval(&mut self) -> Result<Value, CalcError> {
+        self.eval_in(&mut Environment::new())
+    }
 
-pub struct Parser<'a> {
-    iter: &'a mut Peekable<Iter<'a, Token>>,
-}
+    /// Evaluate with variables from and assignments into `env`. The tree is
+    /// walked with a stack of its own rather than recursion, so no depth of
+    /// expression can overflow the thread's stack here.
+    pub fn eval_in(&mut self, env: &mut Environment) -> Result<Value, CalcError> {
+        self.eval_recording(env, None)
+    }
 
-impl<'a> Parser<'a> {
-    pub fn new(iter: &'a mut Peekable<Iter<'a, Token>>) -> Self {
-        Parser { iter }
+    // `eval_in`, also giving the tree with the constant parts it computed
+    // folded, as `simplify::fold_constants` would fold them
+    pub(crate) fn eval_folding(&self, env: &mut Environment) -> Result<(Value, Expr), CalcError> {
+        let mut constants = Constants::new();
+        let value = self.eval_recording(env, Some(&mut constants))?;
+        Ok((value, simplify::fold_computed(self, &constants)))
     }
 
-    fn assert_next(&mut self, token: Token) -> Result<(), SyntaxError> {
-        let next = self.iter.next();
-        if let None = next {
-            return Err(SyntaxError::new_parse_error(
-                "Unexpected end of input".to_string(),
-            ));
+    // `eval_in`, recording into `constants` the value of each node that
+    // fold_constants would fold
+    fn eval_recording(
+        &self,
+        env: &mut Environment,
+        mut constants: Option<&mut Constants>,
+    ) -> Result<Value, CalcError> {
+        let mut steps = vec![Step::Eval(self)];
+        // Results of the operands evaluated so far, innermost last
+        let mut values: Vec<Value> = Vec::new();
+        // Whether each of those is constant, computed from literals alone
+        let mut constant: Vec<bool> = Vec::new();
+        let mut record = |expr: &Expr, value: &Value, is_constant: bool| {
+            if let Some(constants) = constants.as_deref_mut().filter(|_| is_constant) {
+                constants.insert(expr, value.clone());
+            }
+            is_constant
+        };
+        while let Some(step) = steps.pop() {
+            if let Step::Eval(_) = step {
+                limits::step().map_err(CalcError::from)?;
+            }
+            match step {
+                Step::Eval(expr) => match expr {
+                    Expr::Unary(_, operand, _)
+                    | Expr::Postfix(_, operand, _)
+                    | Expr::Assign(_, operand) => {
+                        steps.push(Step::Apply(expr));
+                        steps.push(Step::Eval(operand));
+                    }
+                    Expr::Binary(Operator::And | Operator::Or, left, ..) => {
+                        steps.push(Step::ShortCircuit(expr));
+                        steps.push(Step::Eval(left));
+                    }
+                    Expr::Binary(_, left, right, _) => {
+                        steps.push(Step::Apply(expr));
+                        steps.push(Step::Eval(right));
+                        steps.push(Step::Eval(left));
+                    }
+                    Expr::Conditional(test, ..) => {
+                        steps.push(Step::Branch(expr));
+                        steps.push(Step::Eval(test));
+                    }
+                    // Only the bounds, the body being evaluated for each value
+                    Expr::Call(name, args, _) if series_args(name, args).is_some() => {
+                        steps.push(Step::Apply(expr));
+                        steps.push(Step::Eval(&args[2]));
+                        steps.push(Step::Eval(&args[1]));
+                    }
+                    Expr::Call(_, args, _) | Expr::List(args, _) => {
+                        steps.push(Step::Apply(expr));
+                        steps.extend(args.iter().rev().map(Step::Eval));
+                    }
+                    leaf => {
+                        values.push(leaf.eval_leaf(env)?);
+                        constant.push(matches!(leaf, Expr::Literal(_)));
+                    }
+                },
+                Step::Apply(expr) => {
+                    let operands = match expr {
+                        Expr::Call(name, args, _) if series_args(name, args).is_some() => 2,
+                        Expr::Binary(..) => 2,
+                        Expr::Call(_, args, _) | Expr::List(args, _) => args.len(),
+                        _ => 1,
+                    };
+                    let all_constant = constant
+                        .drain(constant.len() - operands..)
+                        .all(|is_constant| is_constant);
+                    let is_constant = all_constant
+                        && match expr {
+                            Expr::Assign(..) | Expr::Binary(Operator::Custom(_), ..) => false,
+                            Expr::Call(name, args, _) => {
+                                series_args(name, args).is_none() && functions::is_pure(name)
+                            }
+                            _ => true,
+                        };
+                    let value = match expr {
+                        Expr::Unary(operator, _, span) => {
+                            apply_unary(*operator, values.pop().unwrap())
+                                .map_err(|e| e.at(*span))?
+                        }
+                        Expr::Postfix(operator, _, span) => {
+                            apply_postfix(*operator, values.pop().unwrap())
+                                .map_err(|e| e.at(*span))?
+                        }
+                        Expr::Assign(name, _) => {
+                            let value = values.pop().unwrap();
+                            env.set(name, value.clone());
+                            value
+                        }
+                        Expr::Binary(operator, .., span) => {
+                            let right = values.pop().unwrap();
+                            let left = values.pop().unwrap();
+                            apply_binary(*operator, left, right).map_err(|e| e.at(*span))?
+                        }
+                        Expr::Call(name, args, span) if series_args(name, args).is_some() => {
+                            let (var, body) = series_args(name, args).unwrap();
+                            let to = values.pop().unwrap();
+                            let from = values.pop().unwrap();
+                            let mut body = body.clone();
+                            series(name, var, from, to, env, |env| body.eval_in(env))
+                                .map_err(|e| e.at(*span))?
+                        }
+                        Expr::Call(name, args, span) => {
+                            let args = values.split_off(values.len() - args.len());
+                            call(name, args).map_err(|e| e.at(*span))?
+                        }
+                        Expr::List(items, _) => {
+                            Value::List(values.split_off(values.len() - items.len()))
+                        }
+                        _ => unreachable!("only nodes with operands are applied"),
+                    };
+                    constant.push(record(expr, &value, is_constant));
+                    values.push(value);
+                }
+                Step::ShortCircuit(expr @ Expr::Binary(operator, _, right, span)) => {
+                    let is_or = *operator == Operator::Or;
+                    let left_constant = constant.pop().unwrap();
+                    // The right side is skipped when the left already decides
+                    match logic(is_or, values.pop().unwrap()).map_err(|e| e.at(*span))? {
+                        Some(decided) => {
+                            constant.push(record(expr, &decided, left_constant));
+                            values.push(decided);
+                        }
+                        None => {
+                            steps.push(Step::Logic(is_or, *span));
+                            steps.push(Step::Eval(right));
+                        }
+                    }
+                }
+                Step::Logic(is_or, span) => {
+                    let value = logic(is_or, values.pop().unwrap()).map_err(|e| e.at(span))?;
+                    // Folding leaves the right side as written
+                    *constant.last_mut().unwrap() = false;
+                    values.push(value.unwrap_or(Value::Bool(!is_or)));
+                }
+                Step::Branch(Expr::Conditional(_, then, otherwise, span)) => {
+                    let test = values.pop().unwrap();
+                    let test_constant = constant.pop().unwrap();
+                    let taken = if condition(test, "?").map_err(|e| e.at(*span))? {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    steps.push(Step::Taken(test_constant));
+                    steps.push(Step::Eval(taken));
+                }
+                // Folded to the branch taken, which is constant if the test
+                // and the branch both are
+                Step::Taken(test_constant) => {
+                    *constant.last_mut().unwrap() &= test_constant;
+                }
+                Step::ShortCircuit(_) | Step::Branch(_) => {
+                    unreachable!("pushed for && and || or ?: only")
+                }
+            }
         }
+        Ok(values.pop().unwrap())
+    }
 
-        if *next.unwrap() != token {
-            return Err(SyntaxError::new_parse_error(format!(
-                "Expected {:?} actual {:?}",
-                token,
-                next.unwrap(),
-            )));
+    fn eval_leaf(&self, env: &Environment) -> Result<Value, CalcError> {
+        match self {
+            Expr::Literal(num) => Ok(num.clone()),
+            Expr::Raise(message, span) => Err(CalcError::new_user_error(message.clone()).at(*span)),
+            Expr::Var(name, span) => env.get(name).cloned().ok_or_else(|| {
+                CalcError::new_eval_error(format!("Unknown variable {}", name)).at(*span)
+            }),
+            _ => Err(CalcError::new_eval_error(format!(
+                "Unreachable code: for expr {:?}",
+                self
+            ))),
         }
+    }
+}
 
-        Ok(())
+pub(crate) fn apply_unary(operator: Operator, value: Value) -> Result<Value, CalcError> {
+    match (operator, value) {
+        (Operator::Negative, value) => (-value).map_err(CalcError::from),
+        (Operator::Positive, value @ Value::Bool(_)) => Err(CalcError::new_eval_error(format!(
+            "Cannot apply unary + to {}",
+            value
+        ))),
+        (Operator::Positive, value) => Ok(value),
+        (Operator::Not, value) => Ok(Value::Bool(!condition(value, "!")?)),
+        (Operator::BitNot, value) => value.bit_not().map_err(CalcError::from),
+        (operator, _) => Err(CalcError::new_eval_error(format!(
+            "Unreachable code: for operator {:?}",
+            operator
+        ))),
     }
+}
 
-    pub fn parse(&mut self) -> Result<Expr, SyntaxError> {
-        let ast = self.expression()?;
-        self.assert_next(Token::End)?;
-        Ok(ast)
+// The operator following an operand, as found by Parser::next_operator
+#[derive(Debug, PartialEq)]
+enum Next {
+    Postfix(Operator),
+    Index,
+    Conditional,
+    Infix(Operator, u8, Assoc),
+    Done,
+}
+
+// Pending work of Expr::eval_in
+enum Step<'a> {
+    Eval(&'a Expr),
+    // Combine the values of the node's operands, last on the value stack
+    Apply(&'a Expr),
+    // The left side of `&&` or `||` is on the value stack
+    ShortCircuit(&'a Expr),
+    // The right side of `||` (true) or `&&`, at the span, is on the value
+    // stack
+    Logic(bool, Span),
+    // The condition of `c ? a : b` is on the value stack
+    Branch(&'a Expr),
+    // The branch taken is on the value stack, the test being constant or not
+    Taken(bool),
+}
+
+// Values of the nodes of a tree that evaluation computed from literals alone
+pub(crate) type Constants = HashMap<*const Expr, Value>;
+
+// One side of `||` (`is_or`) or `&&`, the result if it decides it
+pub(crate) fn logic(is_or: bool, value: Value) -> Result<Option<Value>, CalcError> {
+    let symbol = if is_or { "||" } else { "&&" };
+    let value = condition(value, symbol)?;
+    Ok((value == is_or).then_some(Value::Bool(value)))
+}
+
+pub(crate) fn call(name: &str, args: Vec<Value>) -> Result<Value, CalcError> {
+    if name == "exit" {
+        let code = functions::exit_code(args).map_err(CalcError::new_eval_error)?;
+        return Err(CalcError::new_exit(code));
     }
-    fn primary(&mut self) -> Result<Expr, SyntaxError> {
-        let next = self.iter.next().unwrap();
+    // Called with values, not as `sum(k, 1, 10, k^2)`
+    if functions::is_series(name) {
+        return Err(CalcError::new_eval_error(series_usage(name)));
+    }
+    if let Some(result) = extensions::call(name, &args) {
+        return result.map_err(CalcError::from);
+    }
+    functions::call(name, args).map_err(CalcError::from)
+}
 
-        match next {
-            Token::Number(n) => Ok(Expr::ValExrp((*n).clone())),
-            Token::RightParen => {
-                let expr = self.expression()?;
-                self.assert_next(Token::LeftParen)?;
-                Ok(expr)
-            }
-            Token::Dash => {
-                let expr = self.factor()?;
-                Ok(Expr::UnaryExpr(Operator::Negative, Box::new(expr)))
+// The variable and body of a call to `sum` or `prod`, whose bounds are
+// `args[1]` and `args[2]`, None for any other call
+pub(crate) fn series_args<'a>(name: &str, args: &'a [Expr]) -> Option<(&'a str, &'a Expr)> {
+    match args {
+        [Expr::Var(var, _), _, _, body] if functions::is_series(name) => Some((var, body)),
+        _ => None,
+    }
+}
+
+fn series_usage(name: &str) -> String {
+    format!(
+        "{} expects a variable, two bounds and a body, as in {}(k, 1, 10, k^2)",
+        name, name
+    )
+}
+
+// `sum(k, from, to, body)` or `prod(...)`: `body` evaluated with `var` bound
+// to each integer from `from` to `to`, 0 or 1 when there is none. `var` has
+// its own value back afterwards.
+pub(crate) fn series(
+    name: &str,
+    var: &str,
+    from: Value,
+    to: Value,
+    env: &mut Environment,
+    mut body: impl FnMut(&mut Environment) -> Result<Value, CalcError>,
+) -> Result<Value, CalcError> {
+    let bound = |value: Value| match value.simplify() {
+        Value::Number(num) => Ok(num),
+        value => Err(CalcError::new_eval_error(format!(
+            "{} expects integer bounds, got {}",
+            name, value
+        ))),
+    };
+    let (mut k, to) = (bound(from)?, bound(to)?);
+    let is_product = name == "prod";
+    let outer = env.remove(var);
+    let mut terms = || {
+        let mut total = Value::Number(if is_product {
+            BigNum::one()
+        } else {
+            BigNum::zero()
+        });
+        while k <= to {
+            env.set(var, Value::Number(k.clone()));
+            let term = body(env)?;
+            total = if is_product {
+                total * term
+            } else {
+                total + term
             }
-            _ => Err(SyntaxError::new_parse_error(format!(
-                "Unexpected token {:?}",
-                next
-            ))),
+            .map_err(CalcError::from)?;
+            k += BigNum::one();
+        }
+        Ok(total)
+    };
+    let result = terms();
+    match outer {
+        Some(value) => env.set(var, value),
+        None => {
+            env.remove(var);
         }
     }
-    fn factor(&mut self) -> Result<Expr, SyntaxError> {
-        let expr = self.primary()?;
-        Ok(expr)
+    result
+}
+
+// Source form the parser reads back to the same tree, with only the
+// parentheses it needs, or around every operand for `{:#}`
+// Children are moved onto a stack of their own before they drop, so no
+// tree is too deep to drop, however it was built
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_children(&mut pending);
+        while let Some(mut expr) = pending.pop() {
+            expr.take_children(&mut pending);
+        }
     }
-    fn term(&mut self) -> Result<Expr, SyntaxError> {
-        let mut expr: Expr = self.factor()?;
+}
 
-        loop {
-            let next = self.iter.peek().unwrap();
-            match next {
-                Token::Star => {
-                    self.iter.next();
-                    let rhs = self.factor()?;
-                    expr = Expr::BinExpr(Operator::Multiply, Box::new(expr), Box::new(rhs));
-                }
-                Token::Slash => {
-                    self.iter.next();
-                    let rhs = self.factor()?;
-                    expr = Expr::BinExpr(Operator::Divide, Box::new(expr), Box::new(rhs));
-                }
-                _ => break,
-            };
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            f.write_str(&gen::render(self))
+        } else {
+            f.write_str(&self.to_string_min_parens())
         }
+    }
+}
+
+// Binding of anything that never needs parentheses
+const ATOM_PRECEDENCE: u8 = u8::MAX;
+
+// Binary operator written as `token`
+pub(crate) fn infix_operator(token: &Token) -> Option<Operator> {
+    infix_token(token).map(|(operator, ..)| operator)
+}
 
-        Ok(expr)
+// Binary operator written as `token` with its precedence and grouping
+fn infix_token(token: &Token) -> Option<(Operator, u8, Assoc)> {
+    if let Token::Custom(symbol) = token {
+        let (symbol, precedence, assoc) = extensions::syntax(symbol)?;
+        return Some((Operator::Custom(symbol), precedence, assoc));
     }
+    INFIX
+        .iter()
+        .find(|(infix, ..)| infix == token)
+        .map(|&(_, operator, precedence, assoc)| (operator, precedence, assoc))
+}
 
-    fn expression(&mut self) -> Result<Expr, SyntaxError> {
-        let mut expr: Expr = self.term()?;
+fn infix(operator: Operator) -> (u8, Assoc) {
+    if let Operator::Custom(symbol) = operator {
+        let (_, precedence, assoc) = extensions::syntax(symbol).expect("interned when registered");
+        return (precedence, assoc);
+    }
+    INFIX
+        .iter()
+        .find(|&&(_, infix, ..)| infix == operator)
+        .map(|&(_, _, precedence, assoc)| (precedence, assoc))
+        .expect("every binary operator is in INFIX")
+}
 
-        loop {
-            let next = self.iter.peek().unwrap();
-            match next {
-                Token::Plus => {
-                    self.iter.next();
-                    let rhs = self.term()?;
-                    expr = Expr::BinExpr(Operator::Add, Box::new(expr), Box::new(rhs));
+// How tightly the printed form of `expr` holds together, an operand binding
+// less tightly than its operator needs parentheses
+fn binding(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Literal(value @ (Value::Number(_) | Value::Angle(..))) if value.is_negative() => {
+            PREFIX_PRECEDENCE
+        }
+        // `1/2` is read back as a division
+        Expr::Literal(Value::Frac(_)) => infix(Operator::Divide).0,
+        Expr::Literal(Value::Angle(..)) => POSTFIX_PRECEDENCE,
+        Expr::Literal(_) | Expr::Var(..) | Expr::Call(..) | Expr::Raise(..) | Expr::List(..) => {
+            ATOM_PRECEDENCE
+        }
+        Expr::Unary(..) => PREFIX_PRECEDENCE,
+        Expr::Postfix(..) | Expr::Binary(Operator::Index, ..) => POSTFIX_PRECEDENCE,
+        Expr::Binary(operator, ..) => infix(*operator).0,
+        Expr::Conditional(..) => CONDITIONAL_PRECEDENCE,
+        Expr::Assign(..) => 0,
+    }
+}
+
+fn write_min_parens(expr: &Expr, out: &mut String) {
+    match expr {
+        Expr::Unary(operator, operand, _) => {
+            out.push_str(gen::symbol(*operator));
+            write_operand(operand, PREFIX_PRECEDENCE, out);
+        }
+        Expr::Postfix(operator, operand, _) => {
+            write_operand(operand, POSTFIX_PRECEDENCE, out);
+            out.push_str(gen::symbol(*operator));
+        }
+        Expr::Binary(Operator::Index, list, index, _) => {
+            write_operand(list, POSTFIX_PRECEDENCE, out);
+            out.push('[');
+            write_min_parens(index, out);
+            out.push(']');
+        }
+        Expr::Binary(operator, left, right, _) => {
+            let (precedence, assoc) = infix(*operator);
+            let (left_min, right_min) = match assoc {
+                Assoc::Left => (precedence, precedence + 1),
+                Assoc::Right => (precedence + 1, precedence),
+                Assoc::None => (precedence + 1, precedence + 1),
+            };
+            write_operand(left, left_min, out);
+            out.push(' ');
+            out.push_str(gen::symbol(*operator));
+            out.push(' ');
+            // A prefix operator on the right takes only what binds tighter
+            // than itself, so `2 ^ -x` and `a * -b` read back as they are
+            if binding(right) == PREFIX_PRECEDENCE {
+                write_min_parens(right, out);
+            } else {
+                write_operand(right, right_min, out);
+            }
+        }
+        Expr::Conditional(test, then, otherwise, _) => {
+            write_operand(test, CONDITIONAL_PRECEDENCE + 1, out);
+            out.push_str(" ? ");
+            write_min_parens(then, out);
+            out.push_str(" : ");
+            write_operand(otherwise, CONDITIONAL_PRECEDENCE, out);
+        }
+        Expr::Call(name, args, _) => {
+            out.push_str(name);
+            out.push('(');
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
                 }
-                Token::Dash => {
-                    self.iter.next();
-                    let rhs = self.term()?;
-                    expr = Expr::BinExpr(Operator::Subtract, Box::new(expr), Box::new(rhs));
+                write_min_parens(arg, out);
+            }
+            out.push(')');
+        }
+        Expr::List(items, _) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
                 }
-                _ => break,
-            };
+                write_min_parens(item, out);
+            }
+            out.push(']');
+        }
+        Expr::Assign(name, value) => {
+            out.push_str(name);
+            out.push_str(" = ");
+            write_min_parens(value, out);
         }
+        Expr::Literal(_) | Expr::Var(..) | Expr::Raise(..) => out.push_str(&gen::render(expr)),
+    }
+}
 
-        Ok(expr)
+// `expr` in parentheses unless it binds at least as tightly as `min`
+fn write_operand(expr: &Expr, min: u8, out: &mut String) {
+    if binding(expr) >= min {
+        write_min_parens(expr, out);
+    } else {
+        out.push('(');
+        write_min_parens(expr, out);
+        out.push(')');
     }
 }
 
-pub fn eval(line: String) -> Result<(), Box<dyn Error>> {
-    let tokens = lex(line)?;
-    let mut token_iter: Peekable<Iter<'_, Token>> = tokens.iter().peekable();
-    let mut parser = Parser::new(&mut token_iter);
-    let result = parser.parse();
-    match result {
-        Ok(mut ast) => println!("{}", ast.eval()?),
-        Err(e) => return Err(Box::new(e)),
+pub(crate) fn apply_binary(
+    operator: Operator,
+    left: Value,
+    right: Value,
+) -> Result<Value, CalcError> {
+    match operator {
+        Operator::Add => left + right,
+        Operator::Subtract => left - right,
+        Operator::Multiply => left * right,
+        Operator::Divide | Operator::Modulo | Operator::IntDiv if right.is_zero() => {
+            return Err(CalcError::DivisionByZero {
+                span: Span::default(),
+            });
+        }
+        Operator::Divide => left / right,
+        Operator::Modulo => left.modulo(right),
+        Operator::IntDiv => left.int_div(right),
+        Operator::Power => left.pow(right),
+        Operator::BitAnd => left.bit_and(right),
+        Operator::BitOr => left.bit_or(right),
+        Operator::BitXor => left.bit_xor(right),
+        Operator::ShiftLeft => left.shift_left(right),
+        Operator::ShiftRight => left.shift_right(right),
+        Operator::Equal => left.equals(&right).map(Value::Bool),
+        Operator::NotEqual => left.equals(&right).map(|equal| Value::Bool(!equal)),
+        Operator::Less => left.compare(&right).map(|o| Value::Bool(o.is_lt())),
+        Operator::LessEqual => left.compare(&right).map(|o| Value::Bool(o.is_le())),
+        Operator::Greater => left.compare(&right).map(|o| Value::Bool(o.is_gt())),
+        Operator::GreaterEqual => left.compare(&right).map(|o| Value::Bool(o.is_ge())),
+        Operator::Index => left.index(right),
+        Operator::Custom(symbol) => extensions::apply(symbol, &left, &right)
+            .unwrap_or_else(|| Err(format!("Unknown operator {}", symbol).into())),
+        _ => Err(format!("Unreachable code: for operator {:?}", operator).into()),
     }
+    .map_err(CalcError::from)
+}
 
-    Ok(())
+pub(crate) fn apply_postfix(operator: Operator, value: Value) -> Result<Value, CalcError> {
+    match operator {
+        Operator::Factorial => value.factorial(),
+        Operator::Percent => value / Value::Number(BigNum::from(100)),
+        Operator::Degree => value.in_degrees(),
+        _ => Err(format!("Unreachable code: for operator {:?}", operator).into()),
+    }
+    .map_err(CalcError::from)
 }
 
-pub fn eval_to_string(input: String) -> Result<String, Box<dyn Error>> {
-    let tokens = lex(input)?;
-    let mut token_iter: Peekable<Iter<'_, Token>> = tokens.iter().peekable();
-    let mut parser = Parser::new(&mut token_iter);
-    let mut result = parser.parse()?;
-    Ok(result.eval().map(|val| val.to_string())?)
+// `value` used as a condition by `operator`, which must be true or false
+pub(crate) fn condition(value: Value, operator: &str) -> Result<bool, CalcError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        value => Err(CalcError::new_eval_error(format!(
+            "{} expects true or false, got {}",
+            operator, value
+        ))),
+    }
 }
 
-fn get_line() -> String {
-    print!("> ");
-    std::io::stdout().flush().unwrap();
-    let mut input = String::new();
-    match std::io::stdin().read_line(&mut input) {
-        Ok(_s) => {}
-        Err(_e) => {}
-    };
-    input.trim().to_string()
+/// Error from lexing, parsing or evaluating an input, with the byte range of
+/// the input it concerns. Evaluation errors cover the subexpression that
+/// failed, such as `2 / (3 - 3)` in `1 + 2 / (3 - 3)`, or the whole
+/// statement for trees built by hand, whose nodes have no source (an empty
+/// span at 0 when [`Expr::eval`] runs such a tree itself).
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum CalcError {
+    Lex { message: String, span: Span },
+    Parse { message: String, span: Span },
+    Eval { message: String, span: Span },
+    DivisionByZero { span: Span },
+    // A result past the digit limit, or a power too large to compute
+    Overflow { message: String, span: Span },
+    // Past one of the `EvalLimits` of the evaluation
+    LimitExceeded { message: String, span: Span },
+    // `error("message")`
+    User { message: String, span: Span },
+    // Nothing but whitespace and comments
+    EmptyInput { span: Span },
+    // Not a failure, `exit(n)` unwinds evaluation the same way errors do
+    Exit { code: i32, span: Span },
 }
 
-fn run_repl() -> Result<(), Box<dyn Error>> {
-    loop {
-        let line = get_line();
-        if line == "quit" {
-            return Ok(());
+// Process exit status of a script stopped by an error or by `error("...")`
+const ERROR_EXIT_CODE: i32 = 1;
+const USER_ERROR_EXIT_CODE: i32 = 2;
+
+impl CalcError {
+    fn new_lex_error(message: String, span: Span) -> Self {
+        CalcError::Lex { message, span }
+    }
+
+    pub(crate) fn new_parse_error(message: String, span: Span) -> Self {
+        CalcError::Parse { message, span }
+    }
+
+    // Placed on its statement by `within`
+    pub(crate) fn new_eval_error(message: String) -> Self {
+        CalcError::Eval {
+            message,
+            span: Span::default(),
         }
-        if let Err(e) = eval(line) {
-            println!("Error: {}", e);
+    }
+
+    pub(crate) fn new_user_error(message: String) -> Self {
+        CalcError::User {
+            message,
+            span: Span::default(),
+        }
+    }
+
+    fn new_exit(code: i32) -> Self {
+        CalcError::Exit {
+            code,
+            span: Span::default(),
+        }
+    }
+
+    /// Byte range of the input the error concerns, empty at the end of the
+    /// input for input that ended too early or held nothing
+    pub fn span(&self) -> Span {
+        match self {
+            CalcError::Lex { span, .. }
+            | CalcError::Parse { span, .. }
+            | CalcError::Eval { span, .. }
+            | CalcError::DivisionByZero { span }
+            | CalcError::Overflow { span, .. }
+            | CalcError::LimitExceeded { span, .. }
+            | CalcError::User { span, .. }
+            | CalcError::EmptyInput { span }
+            | CalcError::Exit { span, .. } => *span,
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            CalcError::Lex { span, .. }
+            | CalcError::Parse { span, .. }
+            | CalcError::Eval { span, .. }
+            | CalcError::DivisionByZero { span }
+            | CalcError::Overflow { span, .. }
+            | CalcError::LimitExceeded { span, .. }
+            | CalcError::User { span, .. }
+            | CalcError::EmptyInput { span }
+            | CalcError::Exit { span, .. } => span,
         }
     }
-    Ok(())
-}
 
-pub fn run() -> Result<(), Box<dyn Error>> {
-    run_repl()
+    // Place an evaluation error at the node of the tree it happened at,
+    // unless a node inside it placed it already
+    pub(crate) fn at(mut self, node: Span) -> Self {
+        let span = self.span_mut();
+        if *span == Span::default() {
+            *span = node;
+        }
+        self
+    }
+
+    // Move an error from `statement`, a slice of `input`, to its place in
+    // `input`. Evaluation errors not placed at a node of the tree cover the
+    // statement, trimmed
+    pub(crate) fn within(mut self, input: &str, statement: &str) -> Self {
+        let offset = statement.as_ptr() as usize - input.as_ptr() as usize;
+        let trimmed = statement.trim();
+        let start = trimmed.as_ptr() as usize - input.as_ptr() as usize;
+        let unplaced = !matches!(
+            self,
+            CalcError::Lex { .. } | CalcError::Parse { .. } | CalcError::EmptyInput { .. }
+        ) && self.span() == Span::default();
+        let span = self.span_mut();
+        *span = if unplaced {
+            Span {
+                start,
+                end: start + trimmed.len(),
+            }
+        } else {
+            Span {
+                start: span.start + offset,
+                end: span.end + offset,
+            }
+        };
+        self
+    }
+
+    /// Whether the input was empty, which the REPL and scripts skip
+    pub fn is_empty_input(&self) -> bool {
+        matches!(self, CalcError::EmptyInput { .. })
+    }
+
+    /// Whether evaluation stopped at a limit, of `EvalLimits` or of digits,
+    /// rather than at a mistake in the input
+    pub fn is_limit_exceeded(&self) -> bool {
+        matches!(
+            self,
+            CalcError::LimitExceeded { .. } | CalcError::Overflow { .. }
+        )
+    }
+
+    pub fn is_exit(&self) -> bool {
+        matches!(self, CalcError::Exit { .. })
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CalcError::Exit { code, .. } => *code,
+            CalcError::User { .. } => USER_ERROR_EXIT_CODE,
+            _ => ERROR_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::Lex { message, .. } => write!(f, "Lex Error {}", message),
+            CalcError::Parse { message, .. } => write!(f, "Parse Error {}", message),
+            CalcError::Eval { message, .. }
+            | CalcError::Overflow { message, .. }
+            | CalcError::LimitExceeded { message, .. } => {
+                write!(f, "Eval Error {}", message)
+            }
+            CalcError::DivisionByZero { .. } => write!(f, "Eval Error Division by Zero"),
+            CalcError::User { message, .. } => write!(f, "User Error {}", message),
+            CalcError::EmptyInput { .. } => write!(f, "Input Error Nothing to evaluate"),
+            CalcError::Exit { code, .. } => write!(f, "Exit Error with status {}", code),
+        }
+    }
+}
+
+impl Error for CalcError {}
+
+// An error of the arithmetic keeps its kind, placed on its statement by
+// `within` like `new_eval_error`
+impl From<ArithError> for CalcError {
+    fn from(error: ArithError) -> Self {
+        let span = Span::default();
+        match error {
+            ArithError::DivisionByZero => CalcError::DivisionByZero { span },
+            ArithError::Overflow(message) => CalcError::Overflow { message, span },
+            ArithError::LimitExceeded(message) => CalcError::LimitExceeded { message, span },
+            ArithError::Invalid(message) => CalcError::Eval { message, span },
+        }
+    }
+}
+
+pub struct Parser<'a> {
+    lexemes: &'a [Lexeme<'a>],
+    iter: Peekable<Iter<'a, Lexeme<'a>>>,
+    // Value standing in for the missing left operand of a leading operator
+    previous: Option<Value>,
+    // Nesting of the expression being parsed and depth of its tree, see
+    // climb_nested
+    nesting: usize,
+    depth: usize,
+    // Absolute value bars open around the point being parsed and not
+    // parenthesized from it, `|` closing one rather than being an or
+    bars: usize,
+    // The second bar of a `||` read as two, see abs
+    half_bar: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(lexemes: &'a [Lexeme<'a>]) -> Self {
+        Parser {
+            lexemes,
+            iter: lexemes.iter().peekable(),
+            previous: None,
+            nesting: 0,
+            depth: 0,
+            bars: 0,
+            half_bar: false,
+        }
+    }
+
+    // Where the next token starts
+    fn start(&mut self) -> usize {
+        self.iter.peek().unwrap().span.start
+    }
+
+    // Source from `start` to the end of the last token read
+    fn span_from(&self, start: usize) -> Span {
+        let read = self.lexemes.len() - self.iter.len();
+        let end = read
+            .checked_sub(1)
+            .map_or(start, |last| self.lexemes[last].span.end);
+        Span {
+            start,
+            end: end.max(start),
+        }
+    }
+
+    fn peek(&mut self) -> &'a Token<'a> {
+        // The End token is never consumed past, so there is always one left
+        &self.iter.peek().unwrap().token
+    }
+
+    fn assert_next(&mut self, token: Token) -> Result<(), CalcError> {
+        // The End token is never consumed past, so there is always one left
+        let next = self.iter.next().unwrap();
+        if next.token != token {
+            let message = match token {
+                // Anything left over after a complete statement
+                Token::End => format!("Unexpected {}, expected an operator", next.token),
+                token => format!("Expected {}, found {}", token, next.token),
+            };
+            return Err(CalcError::new_parse_error(message, next.span));
+        }
+
+        Ok(())
+    }
+
+    pub fn parse(&mut self) -> Result<Expr, CalcError> {
+        if self.previous.is_none() && *self.peek() == Token::End {
+            let span = self.iter.peek().unwrap().span;
+            return Err(CalcError::EmptyInput { span });
+        }
+        let ast = self.statement()?;
+        self.assert_next(Token::End)?;
+        Ok(ast)
+    }
+    fn statement(&mut self) -> Result<Expr, CalcError> {
+        let mut lookahead = self.iter.clone().map(|lexeme| &lexeme.token);
+        if let (Some(Token::Ident(name)), Some(Token::Equals)) =
+            (lookahead.next(), lookahead.next())
+        {
+            self.iter.nth(1);
+            let expr = self.climb(0)?;
+            return Ok(Expr::Assign(name.to_string(), Box::new(expr)));
+        }
+        self.climb(0)
+    }
+    fn primary(&mut self) -> Result<Expr, CalcError> {
+        if let Some(previous) = self.previous.take() {
+            return Ok(Expr::Literal(previous));
+        }
+        if self.half_bar {
+            self.half_bar = false;
+            // The second bar of the `||` just read
+            let start = self.span_from(0).end - 1;
+            return self.abs(start);
+        }
+        let next = self.iter.next().unwrap();
+
+        match &next.token {
+            Token::Number(digits, unit) => number_literal(next, digits, *unit),
+            Token::LeftParen => {
+                let expr = self.unbarred(|parser| parser.climb(0))?;
+                self.assert_next(Token::RightParen)?;
+                Ok(expr)
+            }
+            Token::LeftBracket => self.list(next.span.start),
+            Token::Pipe => self.abs(next.span.start),
+            Token::OrOr => {
+                self.half_bar = true;
+                self.abs(next.span.start)
+            }
+            Token::Ident(name @ ("true" | "false")) if *self.peek() != Token::LeftParen => {
+                Ok(Expr::Literal(Value::Bool(*name == "true")))
+            }
+            Token::Ident(name) if *self.peek() != Token::LeftParen => {
+                Ok(Expr::Var(name.to_string(), next.span))
+            }
+            Token::Ident("error") => self.raise(next.span.start),
+            Token::Ident(name) => self.call(name, next.span.start),
+            _ => Err(CalcError::new_parse_error(
+                format!("Unexpected {}, expected a number or '('", next.token),
+                next.span,
+            )),
+        }
+    }
+    // Run `parse` inside parentheses, where `|` is an or again
+    fn unbarred<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, CalcError>,
+    ) -> Result<T, CalcError> {
+        let bars = std::mem::take(&mut self.bars);
+        let result = parse(self);
+        self.bars = bars;
+        result
+    }
+
+    // `|x|` after the opening bar at `start`, the absolute value of `x`. A
+    // `||` opens or closes two bars, as in `||x| - 1|` and `|x - |y||`
+    fn abs(&mut self, start: usize) -> Result<Expr, CalcError> {
+        self.bars += 1;
+        let expr = self.climb(0);
+        self.bars -= 1;
+        let expr = expr?;
+        if self.half_bar {
+            self.half_bar = false;
+        } else {
+            let next = self.iter.next().unwrap();
+            match next.token {
+                Token::Pipe => {}
+                Token::OrOr if self.bars > 0 => self.half_bar = true,
+                _ => {
+                    return Err(CalcError::new_parse_error(
+                        format!(
+                            "Expected '|' to close the absolute value, found {}",
+                            next.token
+                        ),
+                        next.span,
+                    ))
+                }
+            }
+        }
+        let mut span = self.span_from(start);
+        if self.half_bar {
+            // Only the first bar of the closing `||`
+            span.end -= 1;
+        }
+        Ok(Expr::Call("abs".to_string(), vec![expr], span))
+    }
+
+    // The items of `[a, b, c]`, after the bracket at `start`
+    fn list(&mut self, start: usize) -> Result<Expr, CalcError> {
+        let mut items = Vec::new();
+        if *self.peek() == Token::RightBracket {
+            self.iter.next();
+            return Ok(Expr::List(items, self.span_from(start)));
+        }
+        loop {
+            items.push(self.unbarred(|parser| parser.climb(0))?);
+            let next = self.iter.next().unwrap();
+            match next.token {
+                Token::Comma => continue,
+                Token::RightBracket => break,
+                _ => {
+                    return Err(CalcError::new_parse_error(
+                        format!("Expected ',' or ']' in a list, found {}", next.token),
+                        next.span,
+                    ))
+                }
+            }
+        }
+        Ok(Expr::List(items, self.span_from(start)))
+    }
+
+    // `error("message")` after the name at `start`
+    fn raise(&mut self, start: usize) -> Result<Expr, CalcError> {
+        self.assert_next(Token::LeftParen)?;
+        let next = self.iter.next().unwrap();
+        let Token::Str(message) = next.token else {
+            return Err(CalcError::new_parse_error(
+                format!("error expects a string message, found {}", next.token),
+                next.span,
+            ));
+        };
+        self.assert_next(Token::RightParen)?;
+        Ok(Expr::Raise(message.to_string(), self.span_from(start)))
+    }
+
+    // The arguments of a call to `name`, after the name at `start`
+    fn call(&mut self, name: &str, start: usize) -> Result<Expr, CalcError> {
+        self.assert_next(Token::LeftParen)?;
+        let args = self.unbarred(|parser| parser.args(name))?;
+        let span = self.span_from(start);
+        if functions::is_series(name) && series_args(name, &args).is_none() {
+            return Err(CalcError::new_parse_error(series_usage(name), span));
+        }
+        Ok(Expr::Call(name.to_string(), args, span))
+    }
+
+    fn args(&mut self, name: &str) -> Result<Vec<Expr>, CalcError> {
+        let mut args = Vec::new();
+        if *self.peek() == Token::RightParen {
+            self.iter.next();
+            return Ok(args);
+        }
+        loop {
+            args.push(self.climb(0)?);
+            let next = self.iter.next().unwrap();
+            match next.token {
+                Token::Comma => continue,
+                Token::RightParen => break,
+                _ => {
+                    return Err(CalcError::new_parse_error(
+                        format!(
+                            "Expected ',' or ')' in call to {}, found {}",
+                            name, next.token
+                        ),
+                        next.span,
+                    ))
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    // Whether the token after the next one can start an operand
+    fn operand_follows(&self) -> bool {
+        let mut ahead = self.iter.clone();
+        let before = ahead.next().map(|lexeme| lexeme.span);
+        let Some(next) = ahead.next() else {
+            return false;
+        };
+        match next.token {
+            Token::Number(..)
+            | Token::Ident(_)
+            | Token::LeftParen
+            | Token::LeftBracket
+            | Token::Str(_) => true,
+            // A sign written against its operand but apart from the token
+            // before it, as in `7 % -3`, where `50% - 1` subtracts
+            Token::Dash | Token::Plus | Token::Tilde => {
+                let after = ahead.next().map(|lexeme| lexeme.span);
+                before.is_some_and(|before| before.end < next.span.start)
+                    && after.is_some_and(|after| after.start == next.span.end)
+            }
+            // Closing an absolute value inside bars, as in `|5%|`
+            Token::Pipe => self.bars == 0,
+            _ => false,
+        }
+    }
+    // An operand and the operators after it binding at least as tightly as
+    // `min`, which is 0 for a whole expression
+    fn climb(&mut self, min: u8) -> Result<Expr, CalcError> {
+        let outer = (self.nesting, self.depth);
+        let expr = self.climb_nested(min);
+        (self.nesting, self.depth) = outer;
+        expr
+    }
+
+    // Each call nests, bounding the recursion parsing. The operators applied
+    // in it only deepen the tree, the operand before each being a sibling.
+    fn climb_nested(&mut self, min: u8) -> Result<Expr, CalcError> {
+        self.nesting += 1;
+        check_depth(self.nesting, self.iter.peek().unwrap().span)?;
+        self.deepen()?;
+        let start = self.start();
+        let mut lhs = self.prefix()?;
+        loop {
+            let next = self.next_operator(min);
+            if next == Next::Done {
+                return Ok(lhs);
+            }
+            self.iter.next();
+            self.deepen()?;
+            lhs = match next {
+                Next::Postfix(operator) => {
+                    Expr::Postfix(operator, Box::new(lhs), self.span_from(start))
+                }
+                Next::Index => {
+                    let index = self.unbarred(|parser| parser.climb(0))?;
+                    self.assert_next(Token::RightBracket)?;
+                    binary(Operator::Index, lhs, index, self.span_from(start))
+                }
+                Next::Conditional => {
+                    let then = self.climb(0)?;
+                    self.assert_next(Token::Colon)?;
+                    // Grouping from the right, so chains read as else-if
+                    let otherwise = self.climb(CONDITIONAL_PRECEDENCE)?;
+                    Expr::Conditional(
+                        Box::new(lhs),
+                        Box::new(then),
+                        Box::new(otherwise),
+                        self.span_from(start),
+                    )
+                }
+                Next::Infix(operator, precedence, assoc) => {
+                    let rhs = self.climb(match assoc {
+                        Assoc::Right => precedence,
+                        Assoc::Left | Assoc::None => precedence + 1,
+                    })?;
+                    if assoc == Assoc::None {
+                        self.refuse_chain(precedence)?;
+                    }
+                    let span = self.span_from(start);
+                    match (operator, &rhs) {
+                        (
+                            Operator::Add | Operator::Subtract,
+                            Expr::Postfix(Operator::Percent, ..),
+                        ) if syntax().relative_percent => {
+                            let one = Expr::Literal(Value::Number(BigNum::one()));
+                            let factor = binary(operator, one, rhs, span);
+                            binary(Operator::Multiply, lhs, factor, span)
+                        }
+                        _ => binary(operator, lhs, rhs, span),
+                    }
+                }
+                Next::Done => unreachable!(),
+            };
+        }
+    }
+
+    // What the next token does to the operand before it, when it binds at
+    // least as tightly as `min`
+    fn next_operator(&self, min: u8) -> Next {
+        let token = &self.iter.clone().next().unwrap().token;
+        if self.half_bar || (self.bars > 0 && matches!(token, Token::Pipe | Token::OrOr)) {
+            return Next::Done;
+        }
+        if *token == Token::LeftBracket && POSTFIX_PRECEDENCE >= min {
+            return Next::Index;
+        }
+        // `5 % 3` is a remainder, `5 %` a percentage
+        let is_postfix = !(*token == Token::Percent && self.operand_follows());
+        if let Some(&(_, operator)) = POSTFIX.iter().find(|(postfix, _)| postfix == token) {
+            if is_postfix && POSTFIX_PRECEDENCE >= min {
+                return Next::Postfix(operator);
+            }
+        }
+        if *token == Token::Question && CONDITIONAL_PRECEDENCE >= min {
+            return Next::Conditional;
+        }
+        match infix_token(token) {
+            Some((operator, precedence, assoc)) if precedence >= min => {
+                Next::Infix(operator, precedence, assoc)
+            }
+            _ => Next::Done,
+        }
+    }
+
+    // After a comparison, another one of the same precedence is an error
+    fn refuse_chain(&mut self, precedence: u8) -> Result<(), CalcError> {
+        let next = self.iter.peek().unwrap();
+        if infix_token(&next.token).is_some_and(|(_, other, _)| other == precedence) {
+            return Err(CalcError::new_parse_error(
+                format!("{} cannot follow a comparison, add parentheses", next.token),
+                next.span,
+            ));
+        }
+        Ok(())
+    }
+
+    fn deepen(&mut self) -> Result<(), CalcError> {
+        self.depth += 1;
+        check_tree_depth(self.depth, self.iter.peek().unwrap().span)
+    }
+
+    fn prefix(&mut self) -> Result<Expr, CalcError> {
+        if self.previous.is_none() && !self.half_bar {
+            let token = self.peek();
+            if let Some(&(_, operator)) = PREFIX.iter().find(|(prefix, _)| prefix == token) {
+                let start = self.start();
+                self.iter.next();
+                let operand = self.climb(PREFIX_PRECEDENCE)?;
+                return Ok(Expr::Unary(
+                    operator,
+                    Box::new(operand),
+                    self.span_from(start),
+                ));
+            }
+        }
+        self.primary()
+    }
+}
+
+// Run `parse` reading input as `syntax` says, which replaces that of any
+// parse it is nested in until it returns
+pub(crate) fn with_syntax<T>(syntax: Syntax, parse: impl FnOnce() -> T) -> T {
+    let outer = SYNTAX.with(|current| current.replace(syntax));
+    let result = parse();
+    SYNTAX.with(|current| current.set(outer));
+    result
+}
+
+fn syntax() -> Syntax {
+    SYNTAX.with(Cell::get)
+}
+
+// Error once an expression being built is nested past the limit of the
+// running evaluation, or where it sets none the parse limit. `span` is
+// where the parser got to
+pub(crate) fn check_depth(nesting: usize, span: Span) -> Result<(), CalcError> {
+    match (limits::max_depth(), Expr::max_depth()) {
+        (Some(limit), _) if nesting > limit => Err(CalcError::LimitExceeded {
+            message: format!(
+                "{}: expression nested more than {} deep",
+                LIMIT_EXCEEDED, limit
+            ),
+            span: Span::default(),
+        }),
+        (None, Some(limit)) if nesting > limit => Err(CalcError::new_parse_error(
+            format!("Expression nested more than {} deep", limit),
+            span,
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Error once the tree being built is deeper than MAX_TREE_DEPTH, or than
+// the nesting limit where that is higher
+pub(crate) fn check_tree_depth(depth: usize, span: Span) -> Result<(), CalcError> {
+    let nesting = limits::max_depth().or_else(Expr::max_depth);
+    match nesting.map(|limit| limit.max(MAX_TREE_DEPTH)) {
+        Some(limit) if depth > limit => Err(CalcError::new_parse_error(
+            format!("Expression more than {} operators deep", limit),
+            span,
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn number_literal(
+    lexeme: &Lexeme,
+    digits: &str,
+    unit: Option<AngleUnit>,
+) -> Result<Expr, CalcError> {
+    // The lexer only lets ASCII digits and one inner `.` through, or
+    // alphanumerics after a radix prefix, with separators taken out here.
+    // Decimals are read exactly, `0.1` being 1/10
+    let digits = &plain_digits(digits);
+    let number = if let Some((radix, digits)) = radix_prefix(digits) {
+        let num = BigNum::from_str_radix(digits, radix)
+            .map_err(|e| CalcError::new_lex_error(e, lexeme.span))?;
+        Value::Number(num)
+    } else if let Some((whole, (numerator, denominator))) = digits
+        .char_indices()
+        .last()
+        .and_then(|(at, ch)| Some((&digits[..at], vulgar_fraction(ch)?)))
+    {
+        // `2½` is two and a half
+        let whole = match whole {
+            "" => BigNum::zero(),
+            whole => BigNum::from_ascii(whole.as_bytes()).unwrap(),
+        };
+        let denominator = BigNum::from(denominator);
+        let numerator = whole * denominator.clone() + BigNum::from(numerator);
+        Value::Frac(Frac::new(numerator, denominator)).simplify()
+    } else if digits.contains('.') {
+        let frac = frac::parse_decimal(digits).map_err(CalcError::from)?;
+        Value::Frac(frac).simplify()
+    } else {
+        Value::Number(BigNum::from_ascii(digits.as_bytes()).unwrap())
+    };
+    Ok(Expr::Literal(match unit {
+        Some(unit) => Value::Angle(Box::new(number), unit),
+        None => number,
+    }))
+}
+
+// `digits` without the separators number_end lets through, `1.000,5` being
+// `1000.5` with a decimal comma
+fn plain_digits(digits: &str) -> Cow<'_, str> {
+    let decimal_comma = syntax().decimal_comma;
+    let separated = digits.contains('_') || decimal_comma && digits.contains([',', '.']);
+    if !separated {
+        return Cow::Borrowed(digits);
+    }
+    let plain = digits.chars().filter_map(|ch| match ch {
+        '_' => None,
+        '.' if decimal_comma => None,
+        ',' if decimal_comma => Some('.'),
+        ch => Some(ch),
+    });
+    Cow::Owned(plain.collect())
+}
+
+pub(crate) fn binary(operator: Operator, lhs: Expr, rhs: Expr, span: Span) -> Expr {
+    match (operator, &lhs, &rhs) {
+        // `1/2` is a fraction literal rather than a division
+        (Operator::Divide, Expr::Literal(Value::Number(n)), Expr::Literal(Value::Number(d)))
+            if !d.is_zero() =>
+        {
+            Expr::Literal(Value::Frac(Frac::new(n.clone(), d.clone())).simplify())
+        }
+        _ => Expr::Binary(operator, Box::new(lhs), Box::new(rhs), span),
+    }
+}
+
+/// A parsed statement with its size, so callers can refuse expressions too
+/// complex to evaluate before doing so
+#[derive(Debug, Clone)]
+pub struct ParseOutput {
+    pub expr: Expr,
+    pub depth: usize,
+    pub node_count: usize,
+    // Spans of the tokens, in input order
+    pub spans: Vec<Span>,
+}
+
+/// Tokens of `input` in order, without the end marker
+pub fn tokenize(input: &str) -> Result<Vec<Token<'_>>, CalcError> {
+    let mut lexemes = lex(input)?;
+    lexemes.pop();
+    Ok(lexemes.into_iter().map(|lexeme| lexeme.token).collect())
+}
+
+pub fn parse_output(input: &str) -> Result<ParseOutput, CalcError> {
+    let lexemes = lex(input)?;
+    let expr = Parser::new(&lexemes).parse()?;
+    Ok(ParseOutput {
+        depth: expr.depth(),
+        node_count: expr.node_count(),
+        spans: lexemes
+            .iter()
+            .filter(|lexeme| lexeme.token != Token::End)
+            .map(|lexeme| lexeme.span)
+            .collect(),
+        expr,
+    })
+}
+
+pub(crate) fn parse_statement(input: &str) -> Result<Expr, CalcError> {
+    let lexemes = lex(input)?;
+    Parser::new(&lexemes).parse()
+}
+
+/// Every syntax error in `;` or newline separated statements rather than the
+/// first: after an error parsing resumes at the next statement, or at the
+/// next binary operator of the same statement. A statement that fails to lex
+/// only reports those errors. Empty when all of `input` parses.
+pub fn syntax_errors(input: &str) -> Vec<CalcError> {
+    split_statements(input)
+        .filter(|statement| !is_blank(statement))
+        .flat_map(|statement| {
+            statement_errors(statement)
+                .into_iter()
+                .map(move |error| error.within(input, statement))
+        })
+        .collect()
+}
+
+fn statement_errors(statement: &str) -> Vec<CalcError> {
+    let mut errors = Vec::new();
+    let lexemes = lex_recovering(statement, &mut errors);
+    // The parser would only trip over the gaps
+    if !errors.is_empty() {
+        return errors;
+    }
+    let mut from = 0;
+    while let Err(error) = Parser::new(&lexemes[from..]).parse() {
+        let at = error.span().start;
+        errors.push(error);
+        let resume = lexemes
+            .iter()
+            .position(|lexeme| lexeme.span.start > at && infix_operator(&lexeme.token).is_some());
+        match resume {
+            Some(operator) => from = operator + 1,
+            None => break,
+        }
+    }
+    errors
+}
+
+// Lexemes of `code`, skipping over anything that fails to lex after adding
+// its error to `errors`
+fn lex_recovering<'a>(code: &'a str, errors: &mut Vec<CalcError>) -> Vec<Lexeme<'a>> {
+    let mut lexer = Lexer::new(code);
+    let mut lexemes = Vec::new();
+    loop {
+        match lexer.next_token() {
+            Ok(lexeme) if lexeme.token == Token::End => {
+                lexemes.push(lexeme);
+                return lexemes;
+            }
+            Ok(lexeme) => lexemes.push(lexeme),
+            Err(error) => {
+                lexer.pos = lexer.pos.max(error.span().end);
+                errors.push(error);
+            }
+        }
+    }
+}
+
+/// Parse a single statement into its tree, without the spans of
+/// `parse_output`
+pub fn parse(input: &str) -> Result<Expr, CalcError> {
+    parse_statement(input)
+}
+
+/// Evaluate `;` or newline separated statements sharing variables, as in
+/// `a = 2; b = 3; a * b`, stopping at the first error. The result is the
+/// value of the last statement.
+pub fn eval_value(input: &str) -> Result<Value, CalcError> {
+    let mut last = Err(CalcError::EmptyInput {
+        span: Span {
+            start: input.len(),
+            end: input.len(),
+        },
+    });
+    for result in eval_all(input) {
+        last = Ok(result?);
+    }
+    last
+}
+
+/// Evaluate `input` in `env`, continuing from `previous` when it starts
+/// with a binary operator: `*`, `/`, `//`, `%`, `^`, `**`, `&`, `|`, `^^`,
+/// `<<` or `>>`, so that `* 3` after 6 gives 18, or with `+` and a space.
+/// A `+` written against its operand or a leading `-` is a sign rather than
+/// an addition or subtraction, `+-+5` being `-5`, and a line that is a whole
+/// absolute value such as `|-2|` does not continue either. Without
+/// `previous` a leading binary operator other than `+` is a syntax error.
+///
+/// The tree of a statement evaluated before is taken from `cache`.
+/// Statements that succeed are kept there with their constant parts
+/// computed, unless they continue from the previous result.
+pub(crate) fn eval_cached(
+    input: &str,
+    previous: Option<&Value>,
+    env: &mut Environment,
+    cache: Option<&mut ExprCache>,
+) -> Result<Value, CalcError> {
+    audited(input, || {
+        if let Some(mut expr) = cache.as_deref().and_then(|cache| cache.get(input)) {
+            stats::reset_op_counts();
+            return expr.eval_in(env);
+        }
+        let lexemes = lex(input)?;
+        let mut parser = Parser::new(&lexemes);
+        let continues = matches!(
+            lexemes[0].token,
+            Token::Star
+                | Token::Slash
+                | Token::DoubleSlash
+                | Token::Percent
+                | Token::Caret
+                | Token::DoubleStar
+                | Token::Amp
+                | Token::Pipe
+                | Token::CaretCaret
+                | Token::LessLess
+                | Token::GreaterGreater
+        ) || (lexemes[0].token == Token::Plus
+            && previous.is_some()
+            && lexemes[1].span.start > lexemes[0].span.end);
+        if continues {
+            parser.previous = previous.cloned();
+        }
+        let mut expr = match parser.parse() {
+            // `|x|` is an absolute value rather than an or with the result
+            Err(_) if lexemes[0].token == Token::Pipe && previous.is_some() => {
+                Parser::new(&lexemes).parse()?
+            }
+            parsed => parsed?,
+        };
+        stats::reset_op_counts();
+        let value = match cache.filter(|_| !continues) {
+            // Kept with only what the evaluation that just succeeded computed
+            Some(cache) => {
+                let (value, folded) = expr.eval_folding(env)?;
+                cache.insert(input, folded);
+                value
+            }
+            None => expr.eval_in(env)?,
+        };
+        Ok(value)
+    })
+    .map_err(|e| e.within(input, input))
+}
+
+pub fn eval_to_string(input: &str) -> Result<String, CalcError> {
+    eval_value(input).map(|val| val.to_string())
+}
+
+/// Whether `input` holds nothing but whitespace and comments
+pub(crate) fn is_blank(input: &str) -> bool {
+    lex(input).is_ok_and(|lexemes| lexemes[0].token == Token::End)
+}
+
+/// Evaluate `;` or newline separated statements one at a time as the
+/// iterator is advanced, with variables carried from one to the next and
+/// `ans` holding the last successful result. Blank and comment-only
+/// statements are skipped.
+pub fn eval_all(input: &str) -> impl Iterator<Item = Result<Value, CalcError>> + '_ {
+    let mut env = Environment::new();
+    split_statements(input)
+        .filter(|statement| !is_blank(statement))
+        .map(move |statement| {
+            audited(statement.trim(), || {
+                let mut expr = parse_statement(statement)?;
+                stats::reset_op_counts();
+                let value = expr.eval_in(&mut env)?;
+                env.set(ANS, value.clone());
+                Ok(value)
+            })
+            .map_err(|e: CalcError| e.within(input, statement))
+        })
+}
+
+// Split on `;` and newlines outside of string literals and comments
+pub(crate) fn split_statements(input: &str) -> impl Iterator<Item = &str> {
+    let mut in_string = false;
+    let mut in_comment = false;
+    input.split(move |c| {
+        match c {
+            '"' if !in_comment => in_string = !in_string,
+            '#' if !in_string => in_comment = true,
+            '\n' => in_comment = false,
+            _ => {}
+        }
+        !in_string && (c == '\n' || (!in_comment && c == ';'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod test_modulo_int_div {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_operators() {
+            assert_eq!(eval("17 % 5"), "2");
+            assert_eq!(eval("-17 % 5"), "-2");
+            assert_eq!(eval("17 // 5"), "3");
+            assert_eq!(eval("(7/2) // (1/3)"), "10");
+            assert_eq!(eval("(7/2) % (1/3)"), "1/6");
+            assert_eq!(eval("370deg % 360deg"), "10deg");
+        }
+
+        #[test]
+        fn test_precedence() {
+            // Same tier as * and /, left to right
+            assert_eq!(eval("2 + 17 % 5 * 3"), "8");
+            assert_eq!(eval("100 // 7 // 2"), "7");
+            assert_eq!(eval("1 + 7 // 2"), "4");
+            assert_eq!(eval("(2 + 3) % (4 - 2)"), "1");
+        }
+
+        #[test]
+        fn test_percent_still_postfix() {
+            // `%` is a remainder only when an operand follows it
+            assert_eq!(eval("50% * 2"), "1");
+            assert_eq!(eval("50%"), "1/2");
+            assert_eq!(eval("10 % digits(123)"), "1");
+            assert_eq!(eval("50% - 1"), "-1/2");
+            assert_eq!(eval("50%%"), "1/200");
+            assert_eq!(eval("7 % 4!"), "7");
+            assert_eq!(eval("50%-1"), "-1/2");
+            assert_eq!(eval("80 - 25% + 1"), "323/4");
+        }
+
+        #[test]
+        fn test_signed_divisor() {
+            assert_eq!(eval("7 % -3"), "1");
+            assert_eq!(eval("-7 % -3"), "-1");
+            assert_eq!(eval("5 % +3"), "2");
+            assert_eq!(eval("5 % ~1"), "1");
+            assert_eq!(eval("x = 7; y = 3; x % -y"), "1");
+            assert_eq!(eval("7 % -3 * 2"), "2");
+        }
+
+        #[test]
+        fn test_errors() {
+            assert!(eval_to_string("5 % 0").is_err());
+            assert!(eval_to_string("5 // 0").is_err());
+            assert!(eval_to_string("5 //").is_err());
+            assert!(eval_to_string("5 % 1deg").is_err());
+        }
+
+        #[test]
+        fn test_lexed() {
+            let tokens: Vec<Token> = lex("7//2%3")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.token)
+                .collect();
+            assert_eq!(tokens[1], Token::DoubleSlash);
+            assert_eq!(tokens[3], Token::Percent);
+        }
+    }
+
+    mod test_decimal_literals {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_exact() {
+            assert_eq!(eval("0.1 + 0.2"), "3/10");
+            assert_eq!(eval("0.5 + 0.25"), "3/4");
+            assert_eq!(eval("2.50"), "5/2");
+            assert_eq!(eval("1.0 * 3"), "3");
+            assert_eq!(eval(".5"), "1/2");
+            assert_eq!(eval("-0.125"), "-1/8");
+            assert_eq!(eval("22.5deg"), "(45/2)deg");
+        }
+
+        #[test]
+        fn test_lexed() {
+            let tokens: Vec<Token> = lex("1.25+.5")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.token)
+                .collect();
+            assert_eq!(
+                tokens,
+                [
+                    Token::Number("1.25", None),
+                    Token::Plus,
+                    Token::Number(".5", None),
+                    Token::End,
+                ]
+            );
+        }
+
+        #[test]
+        fn test_malformed() {
+            assert!(eval_to_string("1.").is_err());
+            let err = eval_to_string(". 5").unwrap_err();
+            assert_eq!(err.to_string(), "Lex Error Expected a digit after .");
+            assert_eq!(err.span(), Span { start: 0, end: 1 });
+            assert!(eval_to_string("1.2.3").is_err());
+        }
+    }
+
+    mod test_radix_literals {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_literals() {
+            assert_eq!(eval("0xFF"), "255");
+            assert_eq!(eval("0xff + 10"), "265");
+            assert_eq!(eval("0o17"), "15");
+            assert_eq!(eval("0b1010 * 0b11"), "30");
+            assert_eq!(eval("-0x10"), "-16");
+            assert_eq!(eval("0x10/0b100"), "4");
+            assert_eq!(eval("0"), "0");
+        }
+
+        #[test]
+        fn test_lexed() {
+            let tokens: Vec<Token> = lex("0x1F+0b1")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.token)
+                .collect();
+            assert_eq!(
+                tokens,
+                [
+                    Token::Number("0x1F", None),
+                    Token::Plus,
+                    Token::Number("0b1", None),
+                    Token::End,
+                ]
+            );
+        }
+
+        #[test]
+        fn test_invalid_digits() {
+            let err = eval_to_string("0b102").unwrap_err();
+            assert_eq!(err.to_string(), "Lex Error Invalid base 2 digit: 2");
+            assert_eq!(err.span(), Span { start: 0, end: 5 });
+            assert!(eval_to_string("0x").is_err());
+            assert!(eval_to_string("0o8").is_err());
+            assert!(eval_to_string("0xG").is_err());
+        }
+    }
+
+    mod test_fraction_literals {
+        use super::*;
+
+        fn literal(input: &str) -> Option<String> {
+            match &parse_statement(input).unwrap() {
+                Expr::Literal(value) => Some(value.to_string()),
+                _ => None,
+            }
+        }
+
+        #[test]
+        fn test_folded() {
+            assert_eq!(literal("1/2"), Some("1/2".to_string()));
+            assert_eq!(literal("6/4"), Some("3/2".to_string()));
+            assert_eq!(literal("6/3"), Some("2".to_string()));
+        }
+
+        #[test]
+        fn test_not_folded() {
+            // Division by zero is still reported when evaluated
+            assert_eq!(literal("1/0"), None);
+            assert!(eval_to_string("1/0").is_err());
+            assert_eq!(literal("x/2"), None);
+            assert_eq!(literal("(1 + 1)/2"), None);
+            assert_eq!(literal("-1/2"), None);
+            // Only pairs of integers, `(1/2)/3` stays a division
+            assert_eq!(literal("1/2/3"), None);
+            assert_eq!(eval_to_string("1/2/3").unwrap(), "1/6");
+        }
+
+        #[test]
+        fn test_precedence_unchanged() {
+            assert_eq!(eval_to_string("1/2!").unwrap(), "1/2");
+            assert_eq!(eval_to_string("2^3/2").unwrap(), "4");
+            assert_eq!(eval_to_string("3 * 1/2").unwrap(), "3/2");
+            assert_eq!(eval_to_string("1 - 1/2").unwrap(), "1/2");
+        }
+    }
+
+    mod test_comparisons {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_operators() {
+            assert_eq!(eval("1/3 < 0.34"), "true");
+            assert_eq!(eval("2^10 == 1024"), "true");
+            assert_eq!(eval("1/2 != 2/4"), "false");
+            assert_eq!(eval("3 >= 3"), "true");
+            assert_eq!(eval("3 > 3"), "false");
+            assert_eq!(eval("-1 <= -2"), "false");
+            assert_eq!(eval("90deg == 100grad"), "true");
+        }
+
+        #[test]
+        fn test_precedence() {
+            // Below + and -, only one comparison per level
+            assert_eq!(eval("1 + 2 == 3"), "true");
+            assert_eq!(eval("(1 < 2) == true"), "true");
+            assert_eq!(eval("false != (2 > 1)"), "true");
+            assert_eq!(eval("assert(1 < 2)"), "true");
+            assert_eq!(eval("is_power_of_two(8) && true"), "true");
+            assert_eq!(eval("is_power_of_ten(7) ? 1 : 0"), "0");
+            assert!(eval_to_string("1 < 2 < 3").is_err());
+        }
+
+        #[test]
+        fn test_assign() {
+            let mut env = Environment::new();
+            let value = eval_cached("big = 2^100 > 10^30", None, &mut env, None).unwrap();
+            assert_eq!(value, Value::Bool(true));
+            assert_eq!(env.get("big"), Some(&Value::Bool(true)));
+        }
+
+        #[test]
+        fn test_errors() {
+            assert!(eval_to_string("1 < 1deg").is_err());
+            assert!(eval_to_string("true < false").is_err());
+            assert!(eval_to_string("true == 1").is_err());
+            assert!(eval_to_string("true + 1").is_err());
+            assert!(eval_to_string("-(1 < 2)").is_err());
+            assert!(eval_to_string("5! = 120").is_err());
+        }
+
+        #[test]
+        fn test_lexed() {
+            let tokens: Vec<Token> = lex("a==b!=c<=d>=e<f>g!")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.token)
+                .collect();
+            let operators: Vec<&Token> = tokens.iter().skip(1).step_by(2).collect();
+            assert_eq!(
+                operators,
+                [
+                    &Token::EqualEqual,
+                    &Token::BangEqual,
+                    &Token::LessEqual,
+                    &Token::GreaterEqual,
+                    &Token::Less,
+                    &Token::Greater,
+                    &Token::Bang,
+                ]
+            );
+        }
+    }
+
+    mod test_logic {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        fn last(input: &str) -> String {
+            eval_all(input).last().unwrap().unwrap().to_string()
+        }
+
+        #[test]
+        fn test_operators() {
+            assert_eq!(eval("1 < 2 && 2 < 3"), "true");
+            assert_eq!(eval("1 > 2 || 3 > 2"), "true");
+            assert_eq!(eval("!(1 < 2)"), "false");
+            assert_eq!(eval("!true && false"), "false");
+            // && binds tighter than ||
+            assert_eq!(eval("true || false && false"), "true");
+            assert_eq!(eval("5! == 120 && !false"), "true");
+        }
+
+        #[test]
+        fn test_short_circuit() {
+            assert_eq!(eval("false && 1/0 == 1"), "false");
+            assert_eq!(eval("true || error(\"not evaluated\")"), "true");
+            assert_eq!(eval("1 < 2 ? 3 : 1/0"), "3");
+            assert!(eval_to_string("true && 1/0 == 1").is_err());
+        }
+
+        #[test]
+        fn test_conditional() {
+            assert_eq!(last("x = -5; x < 0 ? -x : x"), "5");
+            // Groups from the right, so chains read as else-if
+            assert_eq!(last("x = 0; x > 0 ? 1 : x < 0 ? -1 : 0"), "0");
+            assert_eq!(last("x = 7; y = x % 2 == 0 ? x / 2 : 3 * x + 1; y"), "22");
+            assert_eq!(eval("(1 > 2 ? 10 : 20) + 1"), "21");
+        }
+
+        #[test]
+        fn test_errors() {
+            assert!(eval_to_string("1 && true").is_err());
+            assert!(eval_to_string("!1").is_err());
+            assert!(eval_to_string("1 ? 2 : 3").is_err());
+            assert!(eval_to_string("true ? 1").is_err());
+            // A single & is bitwise, on integers rather than true and false
+            assert!(eval_to_string("true & false").is_err());
+        }
+
+        #[test]
+        fn test_lexed() {
+            let tokens: Vec<Token> = lex("!a&&b||c?d:e")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.token)
+                .collect();
+            assert_eq!(tokens[0], Token::Bang);
+            assert_eq!(tokens[2], Token::AndAnd);
+            assert_eq!(tokens[4], Token::OrOr);
+            assert_eq!(tokens[6], Token::Question);
+            assert_eq!(tokens[8], Token::Colon);
+        }
+    }
+
+    mod test_bitwise {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_operators() {
+            assert_eq!(eval("0xF0 | 0x0F"), "255");
+            assert_eq!(eval("12 & 10"), "8");
+            assert_eq!(eval("12 ^^ 10"), "6");
+            assert_eq!(eval("~5"), "-6");
+            assert_eq!(eval("-6 & 0xFF"), "250");
+            assert_eq!(eval("1 << 100"), "1267650600228229401496703205376");
+            assert_eq!(eval("1000 >> 3"), "125");
+            assert_eq!(eval("-1 >> 1"), "-1");
+            assert_eq!(eval("-9 >> 1"), "-5");
+            assert_eq!(eval("5 >> 1000"), "0");
+        }
+
+        #[test]
+        fn test_precedence() {
+            // Shifts below + and -, then &, ^^ and | as in C, all above
+            // comparisons
+            assert_eq!(eval("1 << 2 + 1"), "8");
+            assert_eq!(eval("1 | 2 ^^ 3 & 6"), "1");
+            assert_eq!(eval("(1 | 2) ^^ 3"), "0");
+            assert_eq!(eval("6 & 3 == 2"), "true");
+            assert_eq!(eval("~1 + 1"), "-1");
+            assert_eq!(eval("2^^3^2"), "11");
+        }
+
+        #[test]
+        fn test_errors() {
+            assert!(eval_to_string("1/2 & 1").is_err());
+            assert!(eval_to_string("~(1/2)").is_err());
+            assert!(eval_to_string("1 << -1").is_err());
+            assert!(eval_to_string("1 << 1000000000000").is_err());
+            assert!(eval_to_string("1deg | 1").is_err());
+        }
+
+        #[test]
+        fn test_lexed() {
+            let tokens: Vec<Token> = lex("~a&b|c^^d<<e>>f<=g")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.token)
+                .collect();
+            assert_eq!(
+                tokens.iter().step_by(2).collect::<Vec<_>>()[..7],
+                [
+                    &Token::Tilde,
+                    &Token::Amp,
+                    &Token::Pipe,
+                    &Token::CaretCaret,
+                    &Token::LessLess,
+                    &Token::GreaterGreater,
+                    &Token::LessEqual,
+                ]
+            );
+        }
+    }
+
+    mod test_power {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_operators() {
+            assert_eq!(eval("2^10"), "1024");
+            assert_eq!(eval("2**3"), "8");
+            assert_eq!(eval("2^-2"), "1/4");
+            assert_eq!(eval("(2/3)^-2"), "9/4");
+            assert_eq!(eval("(-2)^3"), "-8");
+        }
+
+        #[test]
+        fn test_precedence() {
+            // Right associative, above * and below the postfix operators
+            assert_eq!(eval("2^3^2"), "512");
+            assert_eq!(eval("2**3**2"), "512");
+            assert_eq!(eval("-2^2"), "-4");
+            assert_eq!(eval("2 * 3^2"), "18");
+            assert_eq!(eval("2^3!"), "64");
+            assert_eq!(eval("2^3 + 1"), "9");
+        }
+
+        #[test]
+        fn test_errors() {
+            let err = eval_to_string("2^1000000000000").unwrap_err();
+            assert!(err.to_string().contains("Result too large"), "{}", err);
+            assert!(eval_to_string("2^(1/2)").is_err());
+            assert!(eval_to_string("0^-1").is_err());
+            assert!(eval_to_string("90deg^2").is_err());
+            assert!(eval_to_string("2^").is_err());
+        }
+
+        #[test]
+        fn test_lexed() {
+            let tokens: Vec<Token> = lex("2**3^4*5")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.token)
+                .collect();
+            assert_eq!(tokens[1], Token::DoubleStar);
+            assert_eq!(tokens[3], Token::Caret);
+            assert_eq!(tokens[5], Token::Star);
+        }
+    }
+
+    mod test_postfix {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_each_operator() {
+            assert_eq!(eval("5!"), "120");
+            assert_eq!(eval("0!"), "1");
+            assert_eq!(eval("50%"), "1/2");
+            assert_eq!(eval("90°"), "90deg");
+            assert_eq!(eval("(1/2)°"), "(1/2)deg");
+        }
+
+        #[test]
+        fn test_chained() {
+            assert_eq!(eval("5!%"), "6/5");
+            assert_eq!(eval("3!!"), "720");
+            assert_eq!(eval("200%°"), "2deg");
+        }
+
+        #[test]
+        fn test_precedence() {
+            assert_eq!(eval("-3!"), "-6");
+            assert_eq!(eval("--3!"), "6");
+            assert_eq!(eval("2 * 3!"), "12");
+            assert_eq!(eval("(2 + 1)!"), "6");
+            assert_eq!(eval("10 - 5%"), "199/20");
+            assert_eq!(eval("50% * 300"), "150");
+            assert_eq!(eval("120 + 10%"), "1201/10");
+            assert_eq!(eval("90° + 100grad"), "180deg");
+            assert_eq!(eval("-90° * 2"), "-180deg");
+        }
+
+        #[test]
+        fn test_variable_operand() {
+            let results: Vec<Value> = eval_all("n = 4\n(n + 1)!").map(Result::unwrap).collect();
+            assert_eq!(results[1].to_string(), "120");
+        }
+
+        #[test]
+        fn test_relative_percent() {
+            let syntax = Syntax {
+                relative_percent: true,
+                ..Syntax::default()
+            };
+            let eval = |input| with_syntax(syntax, || eval(input));
+            assert_eq!(eval("120 + 10%"), "132");
+            assert_eq!(eval("80 - 25% + 1"), "61");
+            assert_eq!(eval("2 * 50 + (1/2)%"), "201/2");
+            assert_eq!(eval("50% * 300 + 10%"), "165");
+            // Only for a percentage on the right
+            assert_eq!(eval("10% + 120"), "1201/10");
+            assert_eq!(eval("120 * 10%"), "12");
+            let expr = with_syntax(syntax, || parse("x - 15%").unwrap());
+            assert_eq!(expr.to_string(), "x * (1 - 15%)");
+            assert_eq!(eval_to_string("120 + 10%").unwrap(), "1201/10");
+        }
+
+        #[test]
+        fn test_errors() {
+            assert_eq!(
+                eval_to_string("(-3)!").unwrap_err().to_string(),
+                "Eval Error Factorial of negative number -3"
+            );
+            assert_eq!(
+                eval_to_string("(1/2)!").unwrap_err().to_string(),
+                "Eval Error Factorial expects an integer, got 1/2"
+            );
+            assert!(eval_to_string("30deg°").is_err());
+            assert!(eval_to_string("!3").is_err());
+        }
+    }
+
+    mod test_prefix {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_unary_plus() {
+            assert_eq!(eval("+5"), "5");
+            assert_eq!(eval("+1/2 + +1/2"), "1");
+            assert_eq!(eval("2 * +3"), "6");
+            assert_eq!(eval("+2^2"), "4");
+            assert_eq!(eval("+90deg"), "90deg");
+            assert_eq!(
+                eval_to_string("+true").unwrap_err().to_string(),
+                "Eval Error Cannot apply unary + to true"
+            );
+        }
+
+        #[test]
+        fn test_repeated() {
+            assert_eq!(eval("--3"), "3");
+            assert_eq!(eval("-+-(2)"), "2");
+            assert_eq!(eval("+-+-+1"), "1");
+            assert_eq!(eval("- - -4"), "-4");
+            assert_eq!(eval("2 ^ -+2"), "1/4");
+            assert_eq!(eval("!!true"), "true");
+            assert_eq!(eval("~-~5"), "-7");
+        }
+
+        #[test]
+        fn test_printed() {
+            let expr = parse_statement("1 - +(-2)").unwrap();
+            assert_eq!(expr.to_string(), "1 - +-2");
+            assert_eq!(
+                parse_statement(&expr.to_string()).unwrap().to_string(),
+                "1 - +-2"
+            );
+            assert_eq!(expr.to_rpn_string(), "1 2 neg pos -");
+        }
+    }
+
+    mod test_angle_literals {
+        use super::*;
+
+        fn eval(input: &str) -> Result<String, CalcError> {
+            eval_to_string(input)
+        }
+
+        #[test]
+        fn test_angle_literal() {
+            assert_eq!(eval("30deg").unwrap(), "30deg");
+            assert_eq!(eval("-200grad").unwrap(), "-200grad");
+            assert_eq!(eval("2rad").unwrap(), "2rad");
+        }
+
+        #[test]
+        fn test_angle_arithmetic() {
+            assert_eq!(eval("30deg + 100grad").unwrap(), "120deg");
+            assert_eq!(eval("2 * 30deg").unwrap(), "60deg");
+            assert_eq!(eval("45deg / 2").unwrap(), "(45/2)deg");
+            assert_eq!(eval("90deg / 50grad").unwrap(), "2");
+            assert_eq!(eval("1rad - 3rad").unwrap(), "-2rad");
+        }
+
+        #[test]
+        fn test_angle_errors() {
+            assert!(eval("1rad + 30deg").is_err());
+            assert!(eval("30deg + 1").is_err());
+            assert!(eval("30deg * 2deg").is_err());
+            assert!(eval("1 / 30deg").is_err());
+            assert!(eval("30min").is_err());
+        }
+    }
+
+    mod test_lex {
+        use super::*;
+
+        #[test]
+        fn test_tokens_borrow_input() {
+            let input = "12 + foo(30deg)";
+            let lexemes = lex(input).unwrap();
+            let tokens: Vec<&Token> = lexemes.iter().map(|l| &l.token).collect();
+            assert_eq!(
+                tokens,
+                [
+                    &Token::Number("12", None),
+                    &Token::Plus,
+                    &Token::Ident("foo"),
+                    &Token::LeftParen,
+                    &Token::Number("30", Some(AngleUnit::Degree)),
+                    &Token::RightParen,
+                    &Token::End,
+                ]
+            );
+        }
+
+        #[test]
+        fn test_spans() {
+            let input = "12 + foo(30deg)";
+            let spans: Vec<(usize, usize)> = lex(input)
+                .unwrap()
+                .iter()
+                .map(|l| (l.span.start, l.span.end))
+                .collect();
+            assert_eq!(
+                spans,
+                [(0, 2), (3, 4), (5, 8), (8, 9), (9, 14), (14, 15), (15, 15)]
+            );
+        }
+
+        #[test]
+        fn test_lexer_iterator() {
+            let mut lexer = Lexer::new("a<=b# c\n°");
+            let mut next = || lexer.next().map(|l| l.unwrap().token);
+            assert_eq!(next(), Some(Token::Ident("a")));
+            assert_eq!(next(), Some(Token::LessEqual));
+            assert_eq!(next(), Some(Token::Ident("b")));
+            assert_eq!(next(), Some(Token::Degree));
+            assert_eq!(next(), Some(Token::End));
+            assert_eq!(next(), None);
+            // Nothing after an error
+            let results: Vec<_> = Lexer::new("1 $ 2").collect();
+            assert_eq!(results.len(), 2);
+            assert_eq!(
+                results[1].as_ref().unwrap_err().span(),
+                Span { start: 2, end: 3 }
+            );
+        }
+
+        #[test]
+        fn test_no_character_swallowed() {
+            // The character ending a name or unit starts the next token
+            let tokens: Vec<Token> = tokenize("x)+30deg*y_1(").unwrap();
+            assert_eq!(
+                tokens,
+                [
+                    Token::Ident("x"),
+                    Token::RightParen,
+                    Token::Plus,
+                    Token::Number("30", Some(AngleUnit::Degree)),
+                    Token::Star,
+                    Token::Ident("y_1"),
+                    Token::LeftParen,
+                ]
+            );
+        }
+
+        #[test]
+        fn test_huge_literal() {
+            let digits = "9876543210".repeat(50_000);
+            assert_eq!(eval_to_string(&digits).unwrap(), digits);
+            assert!(eval_to_string(&format!("{}deg", digits)).is_ok());
+        }
+
+        #[test]
+        fn test_literal_boundaries() {
+            assert_eq!(eval_to_string("12+34").unwrap(), "46");
+            assert_eq!(eval_to_string("(12)*3").unwrap(), "36");
+            assert_eq!(eval_to_string("12deg").unwrap(), "12deg");
+        }
+    }
+
+    mod test_unicode {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_operators() {
+            assert_eq!(eval("6 × 7"), "42");
+            assert_eq!(eval("1 ÷ 4"), "1/4");
+            assert_eq!(eval("−3 − −2"), "-1");
+            assert_eq!(eval("3⁄4"), "3/4");
+            assert_eq!(tokenize("2×3÷4−1").unwrap(), tokenize("2*3/4-1").unwrap());
+        }
+
+        #[test]
+        fn test_vulgar_fractions() {
+            assert_eq!(eval("½ + ¾"), "5/4");
+            assert_eq!(eval("2¾"), "11/4");
+            assert_eq!(eval("−1½ × ⅔"), "-1");
+            assert_eq!(eval("⅒deg"), "(1/10)deg");
+            assert_eq!(tokenize("1⅛").unwrap(), [Token::Number("1⅛", None)]);
+            // A fraction ends the number
+            assert!(eval_to_string("½2").is_err());
+            assert!(eval_to_string("1.5½").is_err());
+        }
+
+        #[test]
+        fn test_error_spans() {
+            // Spans count bytes, `×` taking two
+            let err = eval_to_string("2 × ) ").unwrap_err();
+            assert_eq!(err.span(), Span { start: 5, end: 6 });
+        }
+    }
+
+    mod test_eval_spans {
+        use super::*;
+
+        // The part of `input` evaluation fails at
+        fn failing(input: &str) -> &str {
+            let span = eval_to_string(input).unwrap_err().span();
+            &input[span.start..span.end]
+        }
+
+        #[test]
+        fn test_failing_node() {
+            assert_eq!(failing("1 + 2 / (3 - 3)"), "2 / (3 - 3)");
+            assert_eq!(failing("3 * (-2)! + 1"), "(-2)!");
+            assert_eq!(failing("2 + gcd(1/2, 3)"), "gcd(1/2, 3)");
+            assert_eq!(failing("-true * 2"), "-true");
+            assert_eq!(failing("1 + (2 ? 3 : 4)"), "2 ? 3 : 4");
+            assert_eq!(failing("1 < 2 && 3"), "1 < 2 && 3");
+            assert_eq!(failing("1 + |x|"), "x");
+            assert_eq!(failing("4 - error(\"no\")"), "error(\"no\")");
+            // The innermost node, and its place in the whole input
+            assert_eq!(failing("x = 1; x + (x / (x - 1))"), "x / (x - 1)");
+            assert_eq!(failing("| 1/0 - 1 |"), "1/0");
+        }
+
+        #[test]
+        fn test_unplaced() {
+            // Trees built by hand have no source, the error covers all of it
+            let zero = Expr::literal(Value::Number(BigNum::zero()));
+            let mut expr = Expr::binary(Operator::Divide, zero.clone(), zero);
+            assert_eq!(expr.eval().unwrap_err().span(), Span::default());
+            // Limits are on the evaluation as a whole, as is the error
+            let limits = limits::EvalLimits {
+                max_steps: Some(2),
+                ..limits::EvalLimits::default()
+            };
+            let err = limits::limited(&limits, || eval_to_string("1; 2 * 3 ")).unwrap_err();
+            assert_eq!(err.span(), Span { start: 3, end: 8 });
+        }
+
+        #[test]
+        fn test_compiled() {
+            let expr = parse("x + 1 / (x - 2)").unwrap();
+            let mut env = Environment::new();
+            env.set("x", Value::Number(BigNum::from(2)));
+            let err = expr.compile().eval(&env).unwrap_err();
+            assert_eq!(
+                err,
+                CalcError::DivisionByZero {
+                    span: Span { start: 4, end: 15 }
+                }
+            );
+        }
+    }
+
+    mod test_syntax_errors {
+        use super::*;
+
+        fn messages(input: &str) -> Vec<(String, Span)> {
+            syntax_errors(input)
+                .into_iter()
+                .map(|error| (error.to_string(), error.span()))
+                .collect()
+        }
+
+        fn span(start: usize, end: usize) -> Span {
+            Span { start, end }
+        }
+
+        #[test]
+        fn test_statements() {
+            assert!(syntax_errors("1 + 2; x = 3\n\n# note\nx * 2").is_empty());
+            // Evaluation errors are not syntax errors
+            assert!(syntax_errors("1 / 0; y").is_empty());
+            assert_eq!(
+                messages("1 +; 2 * 3\n(4"),
+                [
+                    (
+                        "Parse Error Unexpected end of input, expected a number or '('".to_string(),
+                        span(3, 3)
+                    ),
+                    (
+                        "Parse Error Expected ')', found end of input".to_string(),
+                        span(13, 13)
+                    ),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_operators() {
+            let errors = messages("1 + * 2 + (3 - ) * 4");
+            assert_eq!(errors.len(), 2);
+            assert_eq!(errors[0].1, span(4, 5));
+            assert_eq!(errors[1].1, span(15, 16));
+            assert_eq!(messages("max(1, ) + 2 3 + 4").len(), 2);
+        }
+
+        #[test]
+        fn test_lex_errors() {
+            let errors = messages("1 $ + 2 @; 3 + 4zz");
+            assert_eq!(
+                errors,
+                [
+                    ("Lex Error Unrecognized character $".to_string(), span(2, 3)),
+                    ("Lex Error Unrecognized character @".to_string(), span(8, 9)),
+                    ("Lex Error Unknown unit suffix zz".to_string(), span(16, 18)),
+                ]
+            );
+            assert_eq!(messages("\"open").len(), 1);
+        }
+    }
+
+    mod test_separators {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        fn eval_comma(input: &str) -> Result<String, CalcError> {
+            let syntax = Syntax {
+                decimal_comma: true,
+                ..Syntax::default()
+            };
+            with_syntax(syntax, || eval_to_string(input))
+        }
+
+        #[test]
+        fn test_underscores() {
+            assert_eq!(eval("1_000_000 + 1"), "1000001");
+            assert_eq!(eval("1_000.000_1"), "10000001/10000");
+            assert_eq!(eval("0xff_ff"), "65535");
+            assert_eq!(eval("1_0deg"), "10deg");
+            assert_eq!(tokenize("1_000").unwrap(), [Token::Number("1_000", None)]);
+            // Only between digits
+            assert!(eval_to_string("1__000").is_err());
+            assert!(eval_to_string("1_").is_err());
+            assert!(eval_to_string("x = 1; _1").is_err());
+        }
+
+        #[test]
+        fn test_frac_from_str_agrees() {
+            // Frac::from_str takes no separators rather than reading `_` as
+            // the space of a mixed number
+            for input in ["7/3", "-7/3", "2.25", "2_1/3", "1_000/3", "0.000_1"] {
+                match input.parse::<Frac>() {
+                    Ok(frac) => assert_eq!(
+                        eval_value(input).unwrap(),
+                        Value::Frac(frac).simplify(),
+                        "{}",
+                        input
+                    ),
+                    Err(_) => assert!(input.contains('_'), "{}", input),
+                }
+            }
+            assert_eq!(eval("2_1/3"), "7");
+        }
+
+        #[test]
+        fn test_decimal_comma() {
+            assert_eq!(eval_comma("1.000.000,5").unwrap(), "2000001/2");
+            assert_eq!(eval_comma("2,5 * 2").unwrap(), "5");
+            assert_eq!(eval_comma("1.000 + 1_000").unwrap(), "2000");
+            assert_eq!(eval_comma("max(1,5, 2)").unwrap(), "2");
+            assert_eq!(eval_comma("max(1, 5)").unwrap(), "5");
+            // `.` only groups three digits
+            assert!(eval_comma("1.5").is_err());
+            assert!(eval_comma("1.0000").is_err());
+            // Without the option `,` separates arguments
+            assert!(eval_to_string("2,5").is_err());
+            assert_eq!(eval("1.000"), "1");
+        }
+    }
+
+    mod test_abs_bars {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        #[test]
+        fn test_abs() {
+            assert_eq!(eval("x = 1; |x - 3|"), "2");
+            assert_eq!(eval("|-2| + |3|"), "5");
+            assert_eq!(eval("2 * |-1/2|"), "1");
+            assert_eq!(eval("|-3|!"), "6");
+            assert_eq!(eval("-|2 - 5|"), "-3");
+        }
+
+        #[test]
+        fn test_nested() {
+            assert_eq!(eval("x = 2; ||x| - 5|"), "3");
+            assert_eq!(eval("x = -2; | |x| - 3 |"), "1");
+            assert_eq!(eval("|1 - |2 - 7||"), "4");
+            assert_eq!(eval("|(-1 | 2)|"), "1");
+            assert_eq!(eval("|max(-8, 2 | 4)|"), "6");
+            assert_eq!(eval("5 | |-2|"), "7");
+        }
+
+        #[test]
+        fn test_errors() {
+            let err = eval_to_string("|1 - 3").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Parse Error Expected '|' to close the absolute value, found end of input"
+            );
+            assert!(eval_to_string("|(1 - 3|").is_err());
+            assert!(eval_to_string("|x||").is_err());
+            assert!(eval_to_string("||").is_err());
+        }
+
+        #[test]
+        fn test_after_result() {
+            let mut env = Environment::new();
+            let previous = Value::Number(BigNum::from(4));
+            let value = eval_cached("| 3", Some(&previous), &mut env, None);
+            assert_eq!(value.unwrap().to_string(), "7");
+            let value = eval_cached("|-2|", Some(&previous), &mut env, None);
+            assert_eq!(value.unwrap().to_string(), "2");
+        }
+    }
+
+    mod test_calls {
+        use super::*;
+
+        #[test]
+        fn test_call() {
+            assert_eq!(eval_to_string("is_power_of_two(64)").unwrap(), "true");
+            assert_eq!(
+                eval_to_string("next_power_of_two(5 * 3) + 1").unwrap(),
+                "17"
+            );
+            assert_eq!(eval_to_string("-digits(1000)").unwrap(), "-4");
+        }
+
+        #[test]
+        fn test_call_errors() {
+            assert!(eval_to_string("is_power_of_two 4").is_err());
+            assert!(eval_to_string("is_power_of_two(4").is_err());
+            assert!(eval_to_string("is_power_of_two(4, 2)").is_err());
+            assert!(eval_to_string("nope(4)").is_err());
+        }
+    }
+
+    mod test_series {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        fn message(input: &str) -> String {
+            eval_to_string(input).unwrap_err().to_string()
+        }
+
+        #[test]
+        fn test_sum_prod() {
+            assert_eq!(eval("sum(k, 1, 100, k^2)"), "338350");
+            assert_eq!(eval("sum(n, 1, 10, 1 / (n * (n + 1)))"), "10/11");
+            assert_eq!(eval("prod(k, 1, 20, k) == 20!"), "true");
+            assert_eq!(eval("sum(i, 1, 3, prod(j, 1, i, 2))"), "14");
+            // No terms
+            assert_eq!(eval("sum(k, 5, 1, k) + prod(k, 5, 1, k)"), "1");
+        }
+
+        #[test]
+        fn test_scope() {
+            // The variable has its own value back, the body still sees others
+            assert_eq!(eval("k = 7; n = 2; sum(k, 1, 3, k * n) + k"), "19");
+            assert!(eval_to_string("sum(k, 1, 3, k); k").is_err());
+        }
+
+        #[test]
+        fn test_errors() {
+            assert_eq!(
+                message("sum(1, 1, 3, 2)"),
+                "Parse Error sum expects a variable, two bounds and a body, as in sum(k, 1, 10, k^2)"
+            );
+            assert!(eval_to_string("prod(k, 1, 3)").is_err());
+            assert_eq!(
+                message("sum(k, 1, 1/2, k)"),
+                "Eval Error sum expects integer bounds, got 1/2"
+            );
+            let err = eval_to_string("1 + sum(k, -1, 1, 1 / k)").unwrap_err();
+            assert_eq!(err.span(), Span { start: 18, end: 23 });
+        }
+    }
+
+    mod test_lists {
+        use super::*;
+
+        fn eval(input: &str) -> String {
+            eval_to_string(input).unwrap()
+        }
+
+        fn message(input: &str) -> String {
+            eval_to_string(input).unwrap_err().to_string()
+        }
+
+        #[test]
+        fn test_literals_and_indexing() {
+            assert_eq!(eval("[1, 2, 3/4]"), "[1, 2, 3/4]");
+            assert_eq!(eval("[]"), "[]");
+            assert_eq!(eval("xs = [1, 1 + 1, [3]]; xs[1] + xs[2][0]"), "5");
+            assert_eq!(eval("divmod(17, 5)[1]"), "2");
+            // Indexing binds like the postfix operators
+            assert_eq!(eval("-[1, 2][0]^2"), "-1");
+            assert_eq!(eval("[2, 3][0]!"), "2");
+            assert_eq!(eval("[1, 2] == [2/2, 2]"), "true");
+            assert_eq!(eval("[1, 2] != [1, 2, 3]"), "true");
+        }
+
+        #[test]
+        fn test_elementwise() {
+            assert_eq!(eval("[1, 2, 3] + [10, 20, 30]"), "[11, 22, 33]");
+            assert_eq!(eval("[1, 2] * 3 - 1"), "[2, 5]");
+            assert_eq!(eval("1 / [2, 3]"), "[1/2, 1/3]");
+            assert_eq!(eval("[7, -7] % 3 + [7, -7] // 3"), "[3, -3]");
+            assert_eq!(eval("[2, 3] ^ 2"), "[4, 9]");
+            assert_eq!(eval("-[1, [2, 1/2]]"), "[-1, [-2, -1/2]]");
+            assert_eq!(
+                eval("[[1, 2], [3, 4]] * [10, 100]"),
+                "[[10, 20], [300, 400]]"
+            );
+        }
+
+        #[test]
+        fn test_errors() {
+            assert_eq!(
+                message("[1, 2] + [1, 2, 3]"),
+                "Eval Error Lists of 2 and 3 items do not match"
+            );
+            assert_eq!(
+                message("[1, 2][2]"),
+                "Eval Error Index 2 out of range for a list of 2 items"
+            );
+            assert_eq!(
+                message("[1, 2][1/2]"),
+                "Eval Error Index must be an integer, got 1/2"
+            );
+            assert_eq!(message("5[0]"), "Eval Error Cannot index 5, only lists");
+            assert_eq!(
+                message("[1, 2"),
+                "Parse Error Expected ',' or ']' in a list, found end of input"
+            );
+            assert!(eval_to_string("[1, 2] < [3, 4]").is_err());
+        }
+
+        #[test]
+        fn test_printing() {
+            let printed = |input: &str| parse_statement(input).unwrap().to_string();
+            assert_eq!(
+                printed("[x, (1 + 2) * 3][x - 1]"),
+                "[x, (1 + 2) * 3][x - 1]"
+            );
+            assert_eq!(printed("(-xs)[0]"), "(-xs)[0]");
+            assert_eq!(printed("-(xs[0])"), "-xs[0]");
+            let expr = Expr::index(Expr::list(vec![Expr::var("a")]), Expr::var("i"));
+            assert_eq!(format!("{:#}", expr), "[a][i]");
+        }
+    }
+
+    mod test_variables {
+        use super::*;
+
+        fn eval_in(input: &str, env: &mut Environment) -> Result<Value, CalcError> {
+            parse_statement(input)?.eval_in(env)
+        }
+
+        #[test]
+        fn test_assign_and_use() {
+            let mut env = Environment::new();
+            assert_eq!(eval_in("x = 3 * 4", &mut env).unwrap().to_string(), "12");
+            assert_eq!(eval_in("x / 8", &mut env).unwrap().to_string(), "3/2");
+            assert_eq!(eval_in("x = x + 1", &mut env).unwrap().to_string(), "13");
+            assert_eq!(env.get("x").unwrap().to_string(), "13");
+        }
+
+        #[test]
+        fn test_variable_errors() {
+            let mut env = Environment::new();
+            assert!(eval_in("y + 1", &mut env).is_err());
+            assert!(eval_in("1 + y = 2", &mut env).is_err());
+            assert!(eval_in("y = ", &mut env).is_err());
+            assert!(env.get("y").is_none());
+        }
+    }
+
+    mod test_statement_lists {
+        use super::*;
+
+        #[test]
+        fn test_last_value() {
+            assert_eq!(eval_to_string("a = 2; b = 3; a*b").unwrap(), "6");
+            assert_eq!(eval_to_string("x = 1/2\n x + 1").unwrap(), "3/2");
+            assert_eq!(eval_to_string("3 * 4; ans + 1").unwrap(), "13");
+            assert_eq!(eval_to_string("7;; # done").unwrap(), "7");
+        }
+
+        #[test]
+        fn test_stops_at_error() {
+            let err = eval_to_string("1/0; 5").unwrap_err();
+            assert_eq!(err.to_string(), "Eval Error Division by Zero");
+            assert!(eval_to_string("x = 1; y").is_err());
+            let err = eval_value(" ; ").unwrap_err();
+            assert!(err.is_empty_input());
+        }
+    }
+
+    mod test_eval_all {
+        use super::*;
+
+        fn results(input: &str) -> Vec<Result<String, String>> {
+            eval_all(input)
+                .map(|r| r.map(|v| v.to_string()).map_err(|e| e.to_string()))
+                .collect()
+        }
+
+        #[test]
+        fn test_shared_environment() {
+            assert_eq!(
+                results("x = 2; y = x * 3\n x + y"),
+                [
+                    Ok("2".to_string()),
+                    Ok("6".to_string()),
+                    Ok("8".to_string())
+                ]
+            );
+        }
+
+        #[test]
+        fn test_ans() {
+            assert_eq!(
+                results("3 * 4; ans * 2 + 1; 1 / 0; ans"),
+                [
+                    Ok("12".to_string()),
+                    Ok("25".to_string()),
+                    Err("Eval Error Division by Zero".to_string()),
+                    Ok("25".to_string())
+                ]
+            );
+            assert!(results("ans")[0].is_err());
+        }
+
+        #[test]
+        fn test_errors_do_not_stop() {
+            let results = results("1 / 0;;\n\n2 +; 5");
+            assert_eq!(results.len(), 3);
+            assert!(results[0].is_err() && results[1].is_err());
+            assert_eq!(results[2], Ok("5".to_string()));
+        }
+
+        #[test]
+        fn test_comments_and_blanks_skipped() {
+            assert_eq!(
+                results("# setup\nx = 2 # two; not a statement\n   \n x * 3 # six"),
+                [Ok("2".to_string()), Ok("6".to_string())]
+            );
+            assert!(results("# only a comment\n \t").is_empty());
+        }
+    }
+
+    mod test_empty_input {
+        use super::*;
+
+        #[test]
+        fn test_empty_input_error() {
+            for input in ["", "   ", "\t\n", "# note", "  # 1 + 2"] {
+                let err = parse_statement(input).unwrap_err();
+                assert!(err.is_empty_input(), "{:?}", input);
+                assert!(is_blank(input));
+                let err = eval_to_string(input).unwrap_err();
+                assert_eq!(err.to_string(), "Input Error Nothing to evaluate");
+            }
+            assert!(!is_blank("1 # one"));
+            assert!(!parse_statement("(").unwrap_err().is_empty_input());
+            assert_eq!(eval_to_string("1 + 2 # three").unwrap(), "3");
+        }
+    }
+
+    mod test_script_control {
+        use super::*;
+
+        #[test]
+        fn test_error_builtin() {
+            let err = parse_statement("1 + error(\"bad; input\")")
+                .unwrap()
+                .eval()
+                .unwrap_err();
+            assert_eq!(err.to_string(), "User Error bad; input");
+            assert_eq!(err.exit_code(), 2);
+            assert!(!err.is_exit());
+            assert!(parse_statement("error(3)").is_err());
+            assert!(parse_statement("error(\"open").is_err());
+        }
+
+        #[test]
+        fn test_exit_builtin() {
+            let err = parse_statement("exit(4)").unwrap().eval().unwrap_err();
+            assert!(err.is_exit());
+            assert_eq!(err.exit_code(), 4);
+        }
+
+        #[test]
+        fn test_statements_split_outside_strings() {
+            let results: Vec<_> = eval_all("1; error(\"a;b\"); 2").collect();
+            assert_eq!(results.len(), 3);
+            assert_eq!(
+                results[1].as_ref().unwrap_err().to_string(),
+                "User Error a;b"
+            );
+        }
+
+        #[test]
+        fn test_eval_error_exit_code() {
+            assert_eq!(
+                parse_statement("1 / 0")
+                    .unwrap()
+                    .eval()
+                    .unwrap_err()
+                    .exit_code(),
+                1
+            );
+        }
+    }
+
+    mod test_eval_with_previous {
+        use super::*;
+
+        #[test]
+        fn test_leading_operator() {
+            let previous: Value = "6".parse().unwrap();
+            let result = eval_cached("* 3", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "18");
+            let result = eval_cached("% 4", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "2");
+            let result = eval_cached("/ 4 + 1", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "5/2");
+            let result = eval_cached("+ 1/2", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "13/2");
+            let previous: Value = "9".parse().unwrap();
+            let result = eval_cached("+  1/2", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "19/2");
+        }
+
+        #[test]
+        fn test_leading_minus_is_negation() {
+            let previous: Value = "6".parse().unwrap();
+            let result = eval_cached("-2", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "-2");
+        }
+
+        #[test]
+        fn test_leading_plus_is_sign() {
+            let previous: Value = "-6".parse().unwrap();
+            let result = eval_cached("+5", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "5");
+            let result = eval_cached("+-+5", Some(&previous), &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "-5");
+            // Nothing to continue from
+            let result = eval_cached("+ 5", None, &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "5");
+        }
+
+        #[test]
+        fn test_without_previous() {
+            assert!(eval_cached("* 3", None, &mut Environment::new(), None).is_err());
+            let result = eval_cached("2 * 3", None, &mut Environment::new(), None);
+            assert_eq!(result.unwrap().to_string(), "6");
+        }
+    }
+    mod test_dependencies {
+        use super::*;
+
+        fn names(set: BTreeSet<String>) -> Vec<String> {
+            set.into_iter().collect()
+        }
+
+        #[test]
+        fn test_variables() {
+            let expr = parse_statement("total = price * (1 + rate) - price / count").unwrap();
+            assert_eq!(names(expr.variables()), ["count", "price", "rate"]);
+            assert!(parse_statement("1 + 2").unwrap().variables().is_empty());
+            let expr = parse_statement("digits(x)! + -y%").unwrap();
+            assert_eq!(names(expr.variables()), ["x", "y"]);
+            let expr = parse_statement("sum(k, a, k, k * b)").unwrap();
+            assert_eq!(names(expr.variables()), ["a", "b", "k"]);
+        }
+
+        #[test]
+        fn test_functions() {
+            let expr = parse_statement("digits(mod_pow(a, 2, digits(b))) + error(\"no\")").unwrap();
+            assert_eq!(names(expr.functions()), ["digits", "error", "mod_pow"]);
+            assert_eq!(names(expr.variables()), ["a", "b"]);
+            assert!(parse_statement("x").unwrap().functions().is_empty());
+        }
+    }
+
+    mod test_parse_output {
+        use super::*;
+
+        #[test]
+        fn test_size() {
+            let output = parse_output("1 + 2 * 3").unwrap();
+            assert_eq!((output.depth, output.node_count), (3, 5));
+            let output = parse_output("x = digits(-(1 + 2))").unwrap();
+            assert_eq!((output.depth, output.node_count), (5, 6));
+            let output = parse_output("7").unwrap();
+            assert_eq!((output.depth, output.node_count), (1, 1));
+        }
+
+        #[test]
+        fn test_spans() {
+            let output = parse_output("12 * (3)").unwrap();
+            let spans: Vec<_> = output.spans.iter().map(|s| (s.start, s.end)).collect();
+            assert_eq!(spans, [(0, 2), (3, 4), (5, 6), (6, 7), (7, 8)]);
+            assert!(parse_output("1 +").is_err());
+        }
+    }
+
+    mod test_precedence_table {
+        use super::*;
+
+        fn grouped(input: &str) -> String {
+            parse_statement(input).unwrap().to_string()
+        }
+
+        // The operator of a row as typed
+        fn symbol(token: &Token) -> String {
+            token.to_string().trim_matches('\'').to_string()
+        }
+
+        #[test]
+        fn test_associativity() {
+            for (token, _, _, assoc) in INFIX {
+                let op = symbol(token);
+                let input = format!("a {op} b {op} c");
+                match assoc {
+                    Assoc::Left => {
+                        assert_eq!(grouped(&input), grouped(&format!("(a {op} b) {op} c")))
+                    }
+                    Assoc::Right => {
+                        assert_eq!(grouped(&input), grouped(&format!("a {op} (b {op} c)")))
+                    }
+                    Assoc::None => assert!(parse_statement(&input).is_err()),
+                }
+            }
+        }
+
+        #[test]
+        fn test_levels() {
+            // Each row binds tighter than the rows before it
+            for pair in INFIX.windows(2) {
+                let ((loose, .., low, _), (tight, .., high, _)) = (&pair[0], &pair[1]);
+                if low == high {
+                    continue;
+                }
+                let (loose, tight) = (symbol(loose), symbol(tight));
+                let input = format!("a {loose} b {tight} c");
+                assert_eq!(
+                    grouped(&input),
+                    grouped(&format!("a {loose} (b {tight} c)"))
+                );
+            }
+            assert_eq!(grouped("a || b ? c : d"), grouped("(a || b) ? c : d"));
+            assert_eq!(grouped("-a ^ b!"), grouped("-(a ^ (b!))"));
+        }
+
+        #[test]
+        fn test_no_chained_comparisons() {
+            let err = parse_statement("a && b < c < d").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Parse Error '<' cannot follow a comparison, add parentheses"
+            );
+            assert_eq!(err.span(), Span { start: 11, end: 12 });
+            assert!(parse_statement("(a < b) < c").is_ok());
+        }
+    }
+
+    mod test_max_depth {
+        use super::*;
+        use crate::limits::{limited, EvalLimits};
+
+        #[test]
+        fn test_deep_input_refused() {
+            // Each of these would overflow the stack of a test thread
+            let parens = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+            let err = eval_to_string(&parens).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Parse Error Expression nested more than 256 deep"
+            );
+            assert_eq!(
+                err.span(),
+                Span {
+                    start: 256,
+                    end: 257
+                }
+            );
+            assert!(eval_to_string(&format!("{}1", "-".repeat(100_000))).is_err());
+            let chain = eval_to_string(&format!("1{}", "+1".repeat(100_000))).unwrap_err();
+            assert_eq!(
+                chain.to_string(),
+                "Parse Error Expression more than 512 operators deep"
+            );
+            assert!(eval_to_string(&format!("{}1", "2^".repeat(100_000))).is_err());
+        }
+
+        #[test]
+        fn test_within_limit() {
+            let chain = format!("1{}", "+1".repeat(250));
+            assert_eq!(eval_to_string(&chain).unwrap(), "251");
+            assert_eq!(parse_output(&chain).unwrap().depth, 251);
+            let parens = format!("{}2{}", "(".repeat(250), ")".repeat(250));
+            assert_eq!(eval_to_string(&format!("-{}!", parens)).unwrap(), "-2");
+            // Operands before an operator are siblings, not nested
+            let chain = format!("1{}", "+1".repeat(500));
+            assert_eq!(eval_to_string(&chain).unwrap(), "501");
+            let chain = format!("1{}", "*(1+1)".repeat(300));
+            assert_eq!(parse_output(&chain).unwrap().depth, 302);
+        }
+
+        #[test]
+        fn test_eval_limits_override() {
+            // A deeper per-evaluation limit replaces the process-wide one
+            let parens = format!("{}1{}", "(".repeat(280), ")".repeat(280));
+            assert!(eval_to_string(&parens).is_err());
+            let limits = EvalLimits {
+                max_depth: Some(300),
+                ..EvalLimits::default()
+            };
+            assert_eq!(limited(&limits, || eval_to_string(&parens)).unwrap(), "1");
+        }
+
+        #[test]
+        fn test_evaluation_is_not_recursive() {
+            // Deeper than the parser allows, built by hand
+            let one = || Expr::literal(Value::Number(BigNum::one()));
+            let mut expr = one();
+            for _ in 0..100_000 {
+                expr = Expr::binary(Operator::Add, expr, one());
+            }
+            assert_eq!(expr.eval().unwrap().to_string(), "100001");
+            // Dropped without recursion too
+            drop(expr);
+        }
+    }
+
+    mod test_public_ast {
+        use super::*;
+
+        #[test]
+        fn test_tokenize() {
+            let tokens = tokenize("max(x, 2.5deg) >= 1").unwrap();
+            assert_eq!(
+                tokens,
+                [
+                    Token::Ident("max"),
+                    Token::LeftParen,
+                    Token::Ident("x"),
+                    Token::Comma,
+                    Token::Number("2.5", Some(AngleUnit::Degree)),
+                    Token::RightParen,
+                    Token::GreaterEqual,
+                    Token::Number("1", None),
+                ]
+            );
+            assert!(tokenize("").unwrap().is_empty());
+            assert!(tokenize("1 $").is_err());
+        }
+
+        #[test]
+        fn test_constructors() {
+            let mut expr = Expr::conditional(
+                Expr::binary(
+                    Operator::Less,
+                    Expr::var("x"),
+                    Expr::literal(Value::Number(BigNum::from(0))),
+                ),
+                Expr::unary(Operator::Negative, Expr::var("x")),
+                Expr::call("abs", vec![Expr::var("x")]),
+            );
+            assert_eq!(expr.to_string(), "x < 0 ? -x : abs(x)");
+            assert_eq!(format!("{:#}", expr), "(x < 0) ? (-x) : abs(x)");
+            let mut env = Environment::new();
+            env.set("x", Value::Number(BigNum::from(-3)));
+            assert_eq!(expr.eval_in(&mut env).unwrap().to_string(), "3");
+            // The rendered form parses back to the same tree
+            let text =
+                Expr::assign("y", Expr::postfix(Operator::Factorial, Expr::var("x"))).to_string();
+            assert_eq!(parse_statement(&text).unwrap().to_string(), text);
+        }
+    }
+
+    mod test_min_parens {
+        use super::*;
+
+        fn printed(input: &str) -> String {
+            parse_statement(input).unwrap().to_string_min_parens()
+        }
+
+        #[test]
+        fn test_precedence() {
+            assert_eq!(printed("1+2*3"), "1 + 2 * 3");
+            assert_eq!(printed("(1+2)*3"), "(1 + 2) * 3");
+            assert_eq!(printed("((1)) + ((x))"), "1 + x");
+            assert_eq!(
+                printed("1 + 2 < 4 && !(x | 1 == 1)"),
+                "1 + 2 < 4 && !(x | 1 == 1)"
+            );
+            assert_eq!(printed("(a < b) == c"), "(a < b) == c");
+            assert_eq!(printed("x * (1/2)"), "x * (1/2)");
+            assert_eq!(
+                format!("{:#}", parse_statement("1+2*3").unwrap()),
+                "1 + (2 * 3)"
+            );
+        }
+
+        #[test]
+        fn test_associativity() {
+            assert_eq!(printed("(1 - 2) - 3"), "1 - 2 - 3");
+            assert_eq!(printed("1 - (2 - 3)"), "1 - (2 - 3)");
+            assert_eq!(printed("2 ^ (3 ^ 4)"), "2 ^ 3 ^ 4");
+            assert_eq!(printed("(2 ^ 3) ^ 4"), "(2 ^ 3) ^ 4");
+            assert_eq!(printed("a ? b : (c ? d : e)"), "a ? b : c ? d : e");
+            assert_eq!(printed("(a ? b : c) ? d : e"), "(a ? b : c) ? d : e");
+        }
+
+        #[test]
+        fn test_prefix_and_postfix() {
+            assert_eq!(printed("-(2^2)"), "-2 ^ 2");
+            assert_eq!(printed("(-2)^2"), "(-2) ^ 2");
+            assert_eq!(printed("2^(-x)"), "2 ^ -x");
+            assert_eq!(printed("a * (-b)"), "a * -b");
+            assert_eq!(printed("-(3!)"), "-3!");
+            assert_eq!(printed("(x + 1)!"), "(x + 1)!");
+            assert_eq!(printed("(50%) * 2"), "50% * 2");
+            assert_eq!(printed("y = -(x)"), "y = -x");
+        }
+    }
+
+    mod test_error_spans {
+        use super::*;
+
+        fn message(input: &str) -> String {
+            eval_to_string(input).unwrap_err().to_string()
+        }
+
+        #[test]
+        fn test_messages() {
+            assert_eq!(
+                message("1 + )"),
+                "Parse Error Unexpected ')', expected a number or '('"
+            );
+            assert_eq!(
+                message("(1 + 2"),
+                "Parse Error Expected ')', found end of input"
+            );
+            assert_eq!(
+                message("1 2"),
+                "Parse Error Unexpected number 2, expected an operator"
+            );
+            assert_eq!(
+                message("max(1; 2)"),
+                "Parse Error Expected ',' or ')' in call to max, found end of input"
+            );
+        }
+
+        fn span(input: &str) -> (usize, usize) {
+            let span = eval_to_string(input).unwrap_err().span();
+            (span.start, span.end)
+        }
+
+        #[test]
+        fn test_lex_and_parse() {
+            assert_eq!(span("1 + $"), (4, 5));
+            assert_eq!(span("2 * °"), (4, 6));
+            assert_eq!(span("30xyz"), (2, 5));
+            assert_eq!(span("1 + * 2"), (4, 5));
+            assert_eq!(span("(1 + 2"), (6, 6));
+            assert!(matches!(
+                eval_to_string("1 +").unwrap_err(),
+                CalcError::Parse { .. }
+            ));
+        }
+
+        #[test]
+        fn test_statements() {
+            // Positions are in the whole input, evaluation errors covering
+            // their statement
+            assert_eq!(span("x = 1;  x / 0 ; 2"), (8, 13));
+            assert_eq!(span("1\n2 + 0xZ"), (6, 9));
+            assert_eq!(span(" ; # nothing"), (12, 12));
+            assert!(matches!(
+                eval_to_string("1 / (1 - 1)").unwrap_err(),
+                CalcError::DivisionByZero { .. }
+            ));
+            assert!(matches!(
+                eval_to_string("y").unwrap_err(),
+                CalcError::Eval { .. }
+            ));
+        }
+    }
 }
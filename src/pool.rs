@@ -0,0 +1,110 @@
+// Thread-local pool of digit buffers reused by arithmetic temporaries, so
+// long evaluation chains do not allocate fresh vectors for every step
+use std::cell::RefCell;
+
+// Buffers kept around per thread, anything beyond is dropped
+const MAX_POOLED: usize = 16;
+// Largest buffer kept, in digits, so that one huge product does not hold
+// its memory for the life of the thread
+const MAX_POOLED_CAPACITY: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PoolStats {
+    pub reused: u64,
+    pub allocated: u64,
+    pub returned: u64,
+    pub discarded: u64,
+}
+
+#[derive(Default)]
+struct Pool {
+    buffers: Vec<Vec<u8>>,
+    stats: PoolStats,
+}
+
+thread_local! {
+    static POOL: RefCell<Pool> = RefCell::new(Pool::default());
+}
+
+// A buffer of `len` zeros, reusing a pooled allocation when there is one
+pub(crate) fn take(len: usize) -> Vec<u8> {
+    let mut buf = POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        match pool.buffers.pop() {
+            Some(buf) => {
+                pool.stats.reused += 1;
+                buf
+            }
+            None => {
+                pool.stats.allocated += 1;
+                Vec::new()
+            }
+        }
+    });
+    buf.clear();
+    buf.resize(len, 0);
+    buf
+}
+
+pub(crate) fn give(buf: Vec<u8>) {
+    POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.buffers.len() < MAX_POOLED && buf.capacity() <= MAX_POOLED_CAPACITY {
+            pool.stats.returned += 1;
+            pool.buffers.push(buf);
+        } else {
+            pool.stats.discarded += 1;
+        }
+    });
+}
+
+pub(crate) fn stats() -> PoolStats {
+    POOL.with(|pool| pool.borrow().stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod test_pool {
+        use super::*;
+
+        #[test]
+        fn test_take_give_reuses() {
+            let before = stats();
+            let mut buf = take(4);
+            assert_eq!(buf, vec![0; 4]);
+            buf[0] = 7;
+            give(buf);
+            assert_eq!(take(2), vec![0; 2]);
+            let after = stats();
+            assert_eq!(after.returned - before.returned, 1);
+            assert!(after.reused > before.reused);
+        }
+
+        #[test]
+        fn test_pool_is_bounded() {
+            let before = stats();
+            for _ in 0..MAX_POOLED + 4 {
+                give(Vec::new());
+            }
+            assert!(stats().discarded >= before.discarded + 4);
+        }
+
+        #[test]
+        fn test_large_buffers_dropped() {
+            // Empty the pool so the large buffer would have had room
+            for _ in 0..MAX_POOLED {
+                take(0);
+            }
+            let before = stats();
+            give(Vec::with_capacity(MAX_POOLED_CAPACITY + 1));
+            let after = stats();
+            assert_eq!(after.discarded - before.discarded, 1);
+            assert_eq!(after.returned, before.returned);
+            give(Vec::with_capacity(MAX_POOLED_CAPACITY));
+            assert_eq!(stats().returned - after.returned, 1);
+        }
+    }
+}
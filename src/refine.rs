@@ -0,0 +1,169 @@
+use crate::common::Value;
+use crate::format::{format_value, FormatOptions};
+use crate::functions::with_precision;
+
+// Places computed beyond those asked for in the first round
+const GUARD_PLACES: usize = 10;
+// Rounds at most, the working precision doubling after each
+const MAX_ROUNDS: usize = 6;
+
+/// An approximate value from `refine`, with how far it can be trusted
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Refinement {
+    /// Value of the last round, exact for input needing no approximation
+    pub value: Value,
+    /// The value rounded to the decimal places asked for, as
+    /// `display-digits` prints it
+    pub text: String,
+    /// Decimal places on which the last two rounds agreed, all of those
+    /// asked for unless an error or the round count stopped the refinement
+    pub places: usize,
+    /// Decimal places square roots were cut to in the last round, None when
+    /// nothing was approximated
+    pub precision: Option<usize>,
+}
+
+// Evaluate with `evaluate` at a working precision doubling each round, until
+// two rounds agree to `places` decimal places. Rounds differ only in their
+// precision, so an error only a later round meets, such as its longer
+// numbers passing the digit limit, leaves the last round that finished.
+pub(crate) fn refine<E>(
+    places: usize,
+    mut evaluate: impl FnMut() -> Result<Value, E>,
+) -> Result<Refinement, E> {
+    let options = FormatOptions {
+        display_digits: Some(places),
+        full_output: true,
+        ..FormatOptions::default()
+    };
+    let mut precision = places + GUARD_PLACES;
+    let mut last: Option<Refinement> = None;
+    for _ in 0..MAX_ROUNDS {
+        let value = match with_precision(precision, &mut evaluate) {
+            (Ok(value), false) => {
+                let text = format_value(&value, &options);
+                return Ok(Refinement {
+                    value,
+                    text,
+                    places,
+                    precision: None,
+                });
+            }
+            (Ok(value), true) => value,
+            (Err(e), _) => return last.ok_or(e),
+        };
+        let text = format_value(&value, &options);
+        let agreed = match &last {
+            Some(last) if last.text == text => places,
+            Some(last) => agreed_places(&last.text, &text),
+            None => 0,
+        };
+        let refinement = Refinement {
+            value,
+            text,
+            places: agreed,
+            precision: Some(precision),
+        };
+        if agreed == places {
+            return Ok(refinement);
+        }
+        last = Some(refinement);
+        precision *= 2;
+    }
+    Ok(last.expect("at least one round"))
+}
+
+// Decimal places two roundings share before they differ
+fn agreed_places(a: &str, b: &str) -> usize {
+    let common = a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count();
+    a[..common]
+        .split_once('.')
+        .map_or(0, |(_, decimals)| decimals.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::{limited, EvalLimits};
+    use crate::parser::{eval_value, CalcError};
+
+    mod test_refine {
+        use super::*;
+
+        fn refine_input(input: &str, places: usize) -> Result<Refinement, CalcError> {
+            refine(places, || eval_value(input))
+        }
+
+        #[test]
+        fn test_square_roots() {
+            let refined = refine_input("sqrt(2)", 20).unwrap();
+            // Rounded to 20 places, the trailing zero dropped
+            assert_eq!(refined.text, "1.4142135623730950488");
+            assert_eq!(refined.places, 20);
+            assert_eq!(refined.precision, Some(60));
+            let refined = refine_input("sqrt(2) * sqrt(3) - sqrt(6)", 15).unwrap();
+            assert_eq!(refined.text, "0");
+            assert_eq!(refined.places, 15);
+        }
+
+        #[test]
+        fn test_exact() {
+            let refined = refine_input("1/3 + sqrt(9/4)", 5).unwrap();
+            assert_eq!(refined.value, "11/6".parse().unwrap());
+            assert_eq!(refined.text, "1.83333");
+            assert_eq!(refined.precision, None);
+        }
+
+        #[test]
+        fn test_errors() {
+            // Met in the first round, so nothing is left to fall back on
+            assert!(refine_input("sqrt(-2)", 5).is_err());
+            assert!(matches!(
+                refine_input("sqrt(2) / 0", 5),
+                Err(CalcError::DivisionByZero { .. })
+            ));
+        }
+
+        #[test]
+        fn test_stopped() {
+            // The first round cuts the root to 30 places, the second fails
+            let mut rounds = 0;
+            let refined = refine(20, || {
+                rounds += 1;
+                match rounds {
+                    1 => eval_value("sqrt(2)").map_err(|e| e.to_string()),
+                    _ => Err("Result too large".to_string()),
+                }
+            })
+            .unwrap();
+            assert_eq!(refined.precision, Some(30));
+            assert_eq!(refined.places, 0);
+            assert_eq!(refined.text, "1.4142135623730950488");
+            let refined = refine(20, || Err::<Value, _>("Result too large"));
+            assert_eq!(refined, Err("Result too large"));
+        }
+
+        #[test]
+        fn test_limits() {
+            let limits = EvalLimits {
+                max_result_digits: Some(100),
+                ..EvalLimits::default()
+            };
+            // Square roots to 30 places take 61 digits, to 60 places 121
+            let refined = limited(&limits, || refine_input("sqrt(2)", 20)).unwrap();
+            assert_eq!(refined.precision, Some(30));
+            assert_eq!(refined.places, 0);
+            assert_eq!(refined.text, "1.4142135623730950488");
+            let refined = limited(&limits, || refine_input("sqrt(2)", 50));
+            assert!(refined.unwrap_err().is_limit_exceeded());
+        }
+
+        #[test]
+        fn test_agreed_places() {
+            assert_eq!(agreed_places("1.41421", "1.41422"), 4);
+            assert_eq!(agreed_places("2.000", "1.999"), 0);
+            assert_eq!(agreed_places("12", "13"), 0);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use crate::common::Value;
+use crate::env::Environment;
+use crate::format::{approximate, digit_size};
+use crate::gen::{gen_expr_with, ExprKind};
+use crate::parser::{eval_value, is_blank, split_statements, CalcError};
+use crate::session::{parse_switch, Session};
+use crate::stats::{self, stats};
+
+use std::{error::Error, io::prelude::*};
+
+const QUIZ_DEPTH: usize = 2;
+// Expressions past either size get a warning before they are evaluated
+const WARN_DEPTH: usize = 100;
+const WARN_NODE_COUNT: usize = 10_000;
+// Variables shown per page of `:vars`
+const VARS_PAGE_SIZE: usize = 20;
+
+// REPL options beyond the session's own
+struct Settings {
+    trace: bool,
+}
+
+// None once stdin is closed
+fn get_line() -> Option<String> {
+    print!("> ");
+    std::io::stdout().flush().unwrap();
+    let mut input = String::new();
+    match std::io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_string()),
+    }
+}
+
+// Ask generated arithmetic problems until the user enters an empty line
+fn run_quiz(session: &mut Session) {
+    let kinds = [
+        ExprKind::Add,
+        ExprKind::Subtract,
+        ExprKind::Multiply,
+        ExprKind::Divide,
+    ];
+    let mut asked = 0;
+    let mut correct = 0;
+    println!("Quiz mode, answer exactly (fractions like 3/4 are fine), empty line to stop");
+    loop {
+        let problem = gen_expr_with(session.rng(), QUIZ_DEPTH, &kinds);
+        let expected = match eval_value(&problem) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        println!("{} = ?", problem);
+        let answer = match get_line() {
+            Some(answer) if !answer.is_empty() && answer != "quit" => answer,
+            _ => break,
+        };
+        asked += 1;
+        match session.eval_detached(&answer) {
+            Ok(value) if (value.clone() - expected.clone()).is_ok_and(|d| d.is_zero()) => {
+                correct += 1;
+                println!("Correct!");
+            }
+            Ok(_) => println!("Wrong, the answer is {}", expected),
+            Err(e) => println!("Error: {}, the answer is {}", e, expected),
+        }
+    }
+    println!("Score: {}/{}", correct, asked);
+}
+
+fn run_set(session: &mut Session, settings: &mut Settings, args: &str) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let result = match args.as_slice() {
+        ["trace", value] => parse_switch(value).map(|trace| {
+            settings.trace = trace;
+            stats::set_tracing(trace);
+        }),
+        [name, value] => session.set(name, value),
+        _ => Err("Usage: :set <name> <value>".to_string()),
+    };
+    if let Err(e) = result {
+        println!("Error: {}", e);
+    }
+}
+
+// Restart the random stream from a seed, or show the current one
+fn run_seed(session: &mut Session, args: &str) {
+    match args.trim() {
+        "" => match session.seed() {
+            Some(seed) => println!("Seed {}", seed),
+            None => println!("No seed set, random values follow the clock"),
+        },
+        seed => match seed.parse() {
+            Ok(seed) => session.seed_random(seed),
+            Err(_) => println!(
+                "Error: Expected a seed between 0 and {}, got {}",
+                u64::MAX,
+                seed
+            ),
+        },
+    }
+}
+
+// List one page of variables, `:vars 2` for the second
+fn run_vars(env: &Environment, args: &str) {
+    let page: usize = match args.trim() {
+        "" => 1,
+        page => match page.parse() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                println!("Error: Expected a page number, got {}", page);
+                return;
+            }
+        },
+    };
+    if env.is_empty() {
+        println!("No variables");
+        return;
+    }
+    let pages = env.len().div_ceil(VARS_PAGE_SIZE);
+    if page > pages {
+        println!("Error: Only {} page(s) of variables", pages);
+        return;
+    }
+    let rows: Vec<[String; 4]> = env
+        .iter()
+        .skip((page - 1) * VARS_PAGE_SIZE)
+        .take(VARS_PAGE_SIZE)
+        .map(|(name, value)| {
+            [
+                name.to_string(),
+                value.kind().to_string(),
+                approximate(value),
+                digit_size(value),
+            ]
+        })
+        .collect();
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for [name, kind, approx, size] in &rows {
+        println!(
+            "{:<w0$}  {:<w1$}  ~{:<w2$}  {}",
+            name,
+            kind,
+            approx,
+            size,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+    }
+    if pages > 1 {
+        println!("Page {}/{}, :vars <page> for more", page, pages);
+    }
+}
+
+// Print the tree of `expr` as source once simplified, without evaluating it
+fn run_ast(session: &mut Session, expr: &str) {
+    match session.parse(expr) {
+        Ok(output) => println!("{}", output.expr.simplify()),
+        Err(e) => print_error(expr, &e),
+    }
+}
+
+// Print `expr` in reverse Polish notation, showing the order it is
+// evaluated in
+fn run_rpn(session: &mut Session, expr: &str) {
+    match session.parse(expr) {
+        Ok(output) => println!("{}", output.expr.to_rpn_string()),
+        Err(e) => print_error(expr, &e),
+    }
+}
+
+// Evaluate `expr` one step at a time, printing the expression after each
+fn run_steps(session: &mut Session, expr: &str) {
+    let output = match session.parse(expr) {
+        Ok(output) => output,
+        Err(e) => return print_error(expr, &e),
+    };
+    match session.eval_steps(&output.expr) {
+        Ok((_, steps)) => {
+            println!("  {}", output.expr);
+            for step in steps {
+                println!("= {}", step);
+            }
+        }
+        Err(e) => print_error(expr, &e),
+    }
+}
+
+// Evaluate `expr` to a number of decimal places, approximating square roots
+// at a growing precision until those places settle
+fn run_refine(session: &mut Session, args: &str) {
+    let usage = "Usage: :refine <places> <expr>";
+    let Some((places, expr)) = args.trim().split_once(char::is_whitespace) else {
+        println!("{}", usage);
+        return;
+    };
+    let Ok(places) = places.parse() else {
+        println!("{}", usage);
+        return;
+    };
+    match session.refine(expr, places) {
+        Ok(refined) => match refined.precision {
+            None => println!("{} (exact)", refined.text),
+            Some(_) if refined.places == places => println!("~{}", refined.text),
+            Some(precision) => println!(
+                "~{} (only {} places settled, at {} places of working precision)",
+                refined.text, refined.places, precision
+            ),
+        },
+        Err(e) => print_error(expr, &e),
+    }
+}
+
+// Evaluate `expr1 | expr2` and show how far apart the results are
+fn run_diff(session: &mut Session, args: &str) {
+    let Some((left, right)) = args.split_once('|') else {
+        println!("Usage: :diff <expr> | <expr>");
+        return;
+    };
+    if let Err(e) = print_diff(session, left, right) {
+        println!("Error: {}", e);
+    }
+}
+
+fn print_diff(session: &mut Session, left: &str, right: &str) -> Result<(), Box<dyn Error>> {
+    let left = session.eval_detached(left)?;
+    let right = session.eval_detached(right)?;
+    let show = |value: &Value| format!("{} ~{}", session.format(value), approximate(value));
+    let difference = (left.clone() - right.clone())?;
+    println!("difference      {}", show(&difference));
+    if right.is_zero() {
+        println!("ratio and relative error undefined, the second value is 0");
+        return Ok(());
+    }
+    println!("ratio           {}", show(&(left / right.clone())?));
+    let relative = (difference / right)?;
+    let relative = if relative.is_negative() {
+        (-relative)?
+    } else {
+        relative
+    };
+    println!("relative error  {}", show(&relative));
+    Ok(())
+}
+
+// Exit status of the process once the REPL ends
+fn run_repl() -> Result<i32, Box<dyn Error>> {
+    let mut session = Session::new();
+    let mut settings = Settings { trace: false };
+    loop {
+        let line = match get_line() {
+            Some(line) if line != "quit" => line,
+            _ => return Ok(0),
+        };
+        if is_blank(&line) {
+            continue;
+        }
+        if line.starts_with(':') {
+            // Matched on the whole first word, `:settings` is not `:set`
+            let (command, args) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+            match command {
+                ":quiz" => run_quiz(&mut session),
+                ":stats" => println!("{}", stats()),
+                ":diff" => run_diff(&mut session, args),
+                ":refine" => run_refine(&mut session, args),
+                ":ast" => run_ast(&mut session, args),
+                ":steps" => run_steps(&mut session, args),
+                ":rpn" => run_rpn(&mut session, args),
+                ":vars" => run_vars(session.env(), args),
+                ":seed" => run_seed(&mut session, args),
+                ":set" => run_set(&mut session, &mut settings, args),
+                _ => println!("Unknown command {}", command),
+            }
+            continue;
+        }
+        // `a = 2; b = 3; a * b` prints each result, stopping at an error
+        for statement in split_statements(&line).filter(|s| !is_blank(s)) {
+            warn_if_large(&mut session, statement);
+            match session.eval(statement) {
+                Ok(value) => {
+                    // Only the printed form is rounded, `ans` keeps the exact value
+                    println!("{}", session.format(&value));
+                    if settings.trace {
+                        let ops = stats().ops;
+                        println!(
+                            "[{} adds, {} muls, {} divs, largest operand {} digits]",
+                            ops.adds, ops.muls, ops.divs, ops.max_operand_digits
+                        );
+                    }
+                }
+                Err(e) if e.is_exit() => return Ok(e.exit_code()),
+                Err(e) => {
+                    print_error(statement, &e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// `statement` with carets under the part of it the error concerns, followed
+// by the error, or just the error when it has no place in the statement
+fn print_error(statement: &str, e: &CalcError) {
+    let shown = statement.trim();
+    let leading = statement.len() - statement.trim_start().len();
+    let span = e.span();
+    let marked = span.start.checked_sub(leading).and_then(|start| {
+        let before = shown.get(..start)?;
+        let under = shown.get(start..span.end - leading)?;
+        Some((before.chars().count(), under.chars().count().max(1)))
+    });
+    match marked {
+        Some((indent, width)) => {
+            println!("  {}", shown);
+            println!("  {}{} {}", " ".repeat(indent), "^".repeat(width), e);
+        }
+        None => println!("Error: {}", e),
+    }
+}
+
+fn warn_if_large(session: &mut Session, statement: &str) {
+    if let Ok(output) = session.parse(statement) {
+        if output.depth > WARN_DEPTH || output.node_count > WARN_NODE_COUNT {
+            println!(
+                "Warning: large expression ({} nodes, nested {} deep), this may take a while",
+                output.node_count, output.depth
+            );
+        }
+    }
+}
+
+pub fn run() -> Result<i32, Box<dyn Error>> {
+    run_repl()
+}
@@ -0,0 +1,393 @@
+use crate::common::Value;
+use crate::gen;
+use crate::parser::{
+    binary, check_depth, check_tree_depth, infix_operator, lex, number_literal, CalcError, Expr,
+    Lexeme, Operator, Span, Token,
+};
+
+// Operators written as words, their symbols standing for another operator
+// already: `-` subtracts, `+` adds, `!` is the factorial and `%` the
+// remainder
+const NEGATE: &str = "neg";
+const POSITIVE: &str = "pos";
+const NOT: &str = "not";
+const PERCENT: &str = "percent";
+
+/// Reverse Polish notation for `expr`, operands before their operator and
+/// no parentheses: `1 + 2 * 3` is `1 2 3 * +`. Calls carry their argument
+/// count (`x 3 max:2`), `c ? a : b` is `c a b ?`, an assignment `x 1 =`,
+/// an error `"message" error`, a list its items and their count (`1 x [2]`)
+/// and `xs[i]` is `xs i []`.
+pub fn to_rpn(expr: &Expr) -> String {
+    let mut words = Vec::new();
+    emit(expr, &mut words);
+    words.join(" ")
+}
+
+fn emit(expr: &Expr, words: &mut Vec<String>) {
+    match expr {
+        Expr::Literal(value) => emit_value(value, words),
+        Expr::Var(name, _) => words.push(name.clone()),
+        Expr::Raise(message, _) => {
+            words.push(format!("\"{}\"", message));
+            words.push("error".to_string());
+        }
+        Expr::Unary(operator, operand, _) => {
+            emit(operand, words);
+            words.push(match operator {
+                Operator::Negative => NEGATE.to_string(),
+                Operator::Positive => POSITIVE.to_string(),
+                Operator::Not => NOT.to_string(),
+                _ => gen::symbol(*operator).to_string(),
+            });
+        }
+        Expr::Postfix(operator, operand, _) => {
+            emit(operand, words);
+            words.push(match operator {
+                Operator::Percent => PERCENT.to_string(),
+                _ => gen::symbol(*operator).to_string(),
+            });
+        }
+        Expr::Binary(operator, left, right, _) => {
+            emit(left, words);
+            emit(right, words);
+            words.push(gen::symbol(*operator).to_string());
+        }
+        Expr::Call(name, args, _) => {
+            for arg in args {
+                emit(arg, words);
+            }
+            words.push(format!("{}:{}", name, args.len()));
+        }
+        Expr::List(items, _) => {
+            for item in items {
+                emit(item, words);
+            }
+            words.push(format!("[{}]", items.len()));
+        }
+        Expr::Assign(name, value) => {
+            words.push(name.clone());
+            emit(value, words);
+            words.push("=".to_string());
+        }
+        Expr::Conditional(test, then, otherwise, _) => {
+            emit(test, words);
+            emit(then, words);
+            emit(otherwise, words);
+            words.push("?".to_string());
+        }
+    }
+}
+
+// Literals are written as the words that compute them, `-1/2` being
+// `1 2 / neg`
+fn emit_value(value: &Value, words: &mut Vec<String>) {
+    match value {
+        Value::Bool(_) => words.push(value.to_string()),
+        Value::List(items) => {
+            for item in items {
+                emit_value(item, words);
+            }
+            words.push(format!("[{}]", items.len()));
+        }
+        value if value.is_negative() => {
+            emit_value(&(-value.clone()).expect("only numbers are negative"), words);
+            words.push(NEGATE.to_string());
+        }
+        Value::Frac(frac) => {
+            words.push(frac.numerator().to_string());
+            words.push(frac.denominator().to_string());
+            words.push("/".to_string());
+        }
+        Value::Angle(inner, unit) if matches!(**inner, Value::Frac(_)) => {
+            emit_value(inner, words);
+            words.push(format!("1{}", unit.suffix()));
+            words.push("*".to_string());
+        }
+        _ => words.push(value.to_string()),
+    }
+}
+
+/// Read the reverse Polish notation of `to_rpn` back into a tree, which
+/// must leave exactly one expression
+pub fn parse_rpn(input: &str) -> Result<Expr, CalcError> {
+    let lexemes = lex(input)?;
+    // Each expression with its depth and nesting, to refuse input nested
+    // too deeply
+    let mut stack: Vec<(Expr, Levels)> = Vec::new();
+    let mut iter = lexemes.iter().peekable();
+    while let Some(lexeme) = iter.next() {
+        let mut pop = |count: usize| -> Result<(Vec<Expr>, Below), CalcError> {
+            if stack.len() < count {
+                return Err(CalcError::new_parse_error(
+                    format!("Expected {} operands before {}", count, lexeme.token),
+                    lexeme.span,
+                ));
+            }
+            let operands = stack.split_off(stack.len() - count);
+            let levels = Below {
+                depth: operands.iter().map(|(_, levels)| levels.depth).max(),
+                first: operands.first().map(|(_, levels)| levels.nesting),
+                rest: operands
+                    .iter()
+                    .skip(1)
+                    .map(|(_, levels)| levels.nesting)
+                    .max(),
+            };
+            Ok((operands.into_iter().map(|(expr, _)| expr).collect(), levels))
+        };
+        // Nodes are placed at the word that builds them
+        let span = lexeme.span;
+        let (expr, below) = match &lexeme.token {
+            Token::End => break,
+            Token::Number(digits, unit) => (number_literal(lexeme, digits, *unit)?, Below::LEAF),
+            Token::Str(message) => {
+                expect(iter.next(), Token::Ident("error"))?;
+                (Expr::Raise(message.to_string(), span), Below::LEAF)
+            }
+            Token::Ident(name) if iter.peek().map(|next| &next.token) == Some(&Token::Colon) => {
+                iter.next();
+                let count = arg_count(iter.next())?;
+                let (args, below) = pop(count)?;
+                (Expr::Call(name.to_string(), args, span), below)
+            }
+            Token::Ident(name @ ("true" | "false")) => {
+                (Expr::Literal(Value::Bool(*name == "true")), Below::LEAF)
+            }
+            Token::Ident(NEGATE) => unary(pop(1)?, |e| Expr::Unary(Operator::Negative, e, span)),
+            Token::Ident(POSITIVE) => unary(pop(1)?, |e| Expr::Unary(Operator::Positive, e, span)),
+            Token::Ident(NOT) => unary(pop(1)?, |e| Expr::Unary(Operator::Not, e, span)),
+            Token::Ident(PERCENT) => unary(pop(1)?, |e| Expr::Postfix(Operator::Percent, e, span)),
+            Token::Ident(name) => (Expr::Var(name.to_string(), span), Below::LEAF),
+            Token::Tilde => unary(pop(1)?, |e| Expr::Unary(Operator::BitNot, e, span)),
+            // `[]` indexes, `[n]` makes a list of the last n expressions
+            Token::LeftBracket
+                if iter.peek().map(|next| &next.token) == Some(&Token::RightBracket) =>
+            {
+                iter.next();
+                let (operands, below) = pop(2)?;
+                let [list, index] = <[Expr; 2]>::try_from(operands).unwrap();
+                (binary(Operator::Index, list, index, span), below)
+            }
+            Token::LeftBracket => {
+                let count = arg_count(iter.next())?;
+                expect(iter.next(), Token::RightBracket)?;
+                let (items, below) = pop(count)?;
+                (Expr::List(items, span), below)
+            }
+            Token::Bang => unary(pop(1)?, |e| Expr::Postfix(Operator::Factorial, e, span)),
+            Token::Degree => unary(pop(1)?, |e| Expr::Postfix(Operator::Degree, e, span)),
+            Token::Question => {
+                let (operands, below) = pop(3)?;
+                let [test, then, otherwise] = <[Expr; 3]>::try_from(operands).unwrap();
+                let conditional =
+                    Expr::Conditional(Box::new(test), Box::new(then), Box::new(otherwise), span);
+                (conditional, below)
+            }
+            Token::Equals => {
+                let (operands, below) = pop(2)?;
+                let [target, value] = <[Expr; 2]>::try_from(operands).unwrap();
+                match &target {
+                    Expr::Var(name, _) => (Expr::Assign(name.clone(), Box::new(value)), below),
+                    _ => {
+                        return Err(CalcError::new_parse_error(
+                            "Expected a variable to assign to before '='".to_string(),
+                            lexeme.span,
+                        ))
+                    }
+                }
+            }
+            token => match infix_operator(token) {
+                Some(operator) => {
+                    let (operands, below) = pop(2)?;
+                    let [left, right] = <[Expr; 2]>::try_from(operands).unwrap();
+                    (binary(operator, left, right, span), below)
+                }
+                None => {
+                    return Err(CalcError::new_parse_error(
+                        format!("Unexpected {} in RPN", token),
+                        lexeme.span,
+                    ))
+                }
+            },
+        };
+        let levels = below.levels(&expr);
+        check_depth(levels.nesting, lexeme.span)?;
+        check_tree_depth(levels.depth, lexeme.span)?;
+        stack.push((expr, levels));
+    }
+    let end = Span {
+        start: input.len(),
+        end: input.len(),
+    };
+    match stack.len() {
+        0 => Err(CalcError::EmptyInput { span: end }),
+        1 => Ok(stack.pop().unwrap().0),
+        count => Err(CalcError::new_parse_error(
+            format!("Expected one expression, {} are left", count),
+            end,
+        )),
+    }
+}
+
+fn unary(
+    (mut operands, below): (Vec<Expr>, Below),
+    build: impl FnOnce(Box<Expr>) -> Expr,
+) -> (Expr, Below) {
+    (build(Box::new(operands.pop().unwrap())), below)
+}
+
+// Depth of the tree of an expression read so far, and its nesting counted
+// as the infix parser counts it
+#[derive(Debug, Clone, Copy)]
+struct Levels {
+    depth: usize,
+    nesting: usize,
+}
+
+// Levels of the operands of an expression: the deepest tree, the nesting
+// of the first operand and the deepest nesting of the others
+#[derive(Debug, Clone, Copy)]
+struct Below {
+    depth: Option<usize>,
+    first: Option<usize>,
+    rest: Option<usize>,
+}
+
+impl Below {
+    const LEAF: Below = Below {
+        depth: None,
+        first: None,
+        rest: None,
+    };
+
+    fn levels(self, expr: &Expr) -> Levels {
+        let (first, rest) = (self.first.unwrap_or(0), self.rest.unwrap_or(0));
+        // An operator after an operand leaves it a sibling in the chain, as
+        // in `1 + 2 + 3`, the others nest all their operands
+        let nesting = match expr {
+            Expr::Binary(..) | Expr::Postfix(..) | Expr::Conditional(..) => first.max(rest + 1),
+            _ => first.max(rest) + 1,
+        };
+        Levels {
+            depth: self.depth.map_or(1, |depth| depth + 1),
+            nesting,
+        }
+    }
+}
+
+fn expect(lexeme: Option<&Lexeme>, expected: Token) -> Result<(), CalcError> {
+    let lexeme = lexeme.unwrap();
+    if lexeme.token == expected {
+        Ok(())
+    } else {
+        Err(CalcError::new_parse_error(
+            format!("Expected {}, found {}", expected, lexeme.token),
+            lexeme.span,
+        ))
+    }
+}
+
+// The count after `name:`
+fn arg_count(lexeme: Option<&Lexeme>) -> Result<usize, CalcError> {
+    let lexeme = lexeme.unwrap();
+    match lexeme.token {
+        Token::Number(digits, None) => digits.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        CalcError::new_parse_error(
+            format!("Expected an argument count, found {}", lexeme.token),
+            lexeme.span,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{eval_value, parse_statement};
+
+    fn rpn(input: &str) -> String {
+        to_rpn(&parse_statement(input).unwrap())
+    }
+
+    fn message(input: &str) -> String {
+        parse_rpn(input).unwrap_err().to_string()
+    }
+
+    mod test_rpn {
+        use super::*;
+
+        #[test]
+        fn test_to_rpn() {
+            assert_eq!(rpn("1 + 2 * 3"), "1 2 3 * +");
+            assert_eq!(rpn("(1 + 2) * 3"), "1 2 + 3 *");
+            assert_eq!(rpn("2 ^ 3 ^ 2"), "2 3 2 ^ ^");
+            assert_eq!(rpn("-x! + 50%"), "x ! neg 50 percent +");
+            assert_eq!(
+                rpn("!(a && b) ? max(1, 2, 3) : 1/2"),
+                "a b && not 1 2 3 max:3 1 2 / ?"
+            );
+            assert_eq!(rpn("y = ~x"), "y x ~ =");
+            assert_eq!(rpn("error(\"no\")"), "\"no\" error");
+            assert_eq!(rpn("[1, x][i]"), "1 x [2] i []");
+        }
+
+        #[test]
+        fn test_round_trip() {
+            for input in [
+                "1 + 2 * 3 - 4 / 5",
+                "-2 ^ 2 + (-2) ^ 2",
+                "x = 3; gcd(x * 4, 18) % 5 + 10%",
+                "1 < 2 && !(3 > 4) ? 0xff >> 2 : 1",
+                "90deg + 30° - 5!",
+                "[x, [1, 2][y] * 2, []][0]",
+            ] {
+                for statement in input.split(';') {
+                    let expr = parse_statement(statement).unwrap();
+                    let back = parse_rpn(&to_rpn(&expr)).unwrap();
+                    assert_eq!(format!("{:#}", back), format!("{:#}", expr));
+                }
+            }
+            // Literals computed by other means are written as the words
+            // computing them
+            let half_turn = Expr::Literal("-1/2".parse::<Value>().unwrap());
+            let back = parse_rpn(&to_rpn(&half_turn)).unwrap();
+            assert_eq!(back.to_string(), "-(1/2)");
+            assert_eq!(
+                eval_value(&back.to_string()).unwrap(),
+                "-1/2".parse().unwrap()
+            );
+        }
+
+        #[test]
+        fn test_errors() {
+            assert_eq!(message("1 +"), "Parse Error Expected 2 operands before '+'");
+            assert_eq!(
+                message("1 2"),
+                "Parse Error Expected one expression, 2 are left"
+            );
+            assert_eq!(
+                message("1 2 max:x"),
+                "Parse Error Expected an argument count, found name x"
+            );
+            assert_eq!(
+                message("1 2 ="),
+                "Parse Error Expected a variable to assign to before '='"
+            );
+            assert_eq!(message("( 1"), "Parse Error Unexpected '(' in RPN");
+            assert!(parse_rpn(" ").unwrap_err().is_empty_input());
+            // Right operands nest, chained operators only deepen the tree
+            let nested = format!("{}{}", "1 ".repeat(301), "+ ".repeat(300));
+            assert!(message(&nested).contains("nested more than 256 deep"));
+            let chained = format!("1{}", " 1 +".repeat(300));
+            assert_eq!(
+                parse_rpn(&chained).unwrap().to_string(),
+                format!("1{}", " + 1".repeat(300))
+            );
+            let chained = format!("1{}", " 1 +".repeat(1000));
+            assert!(message(&chained).contains("more than 512 operators deep"));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Serde support using the same strings the calculator prints, `"-123"`,
+// `"3/4"` or `"30deg"`, so values round-trip without going through floats
+use crate::big_num::BigNum;
+use crate::common::Value;
+use crate::frac::Frac;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::{fmt, marker::PhantomData, str::FromStr};
+
+struct StrVisitor<T>(PhantomData<T>);
+
+impl<T: FromStr> Visitor<'_> for StrVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number as a string")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<T, E> {
+        s.parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(s), &self))
+    }
+}
+
+macro_rules! impl_serde_via_str {
+    ($($t:ty),*) => {
+        $(
+            impl Serialize for $t {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $t {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_str(StrVisitor(PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+impl_serde_via_str!(BigNum, Frac, Value);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod test_serde {
+        use super::*;
+
+        #[test]
+        fn test_serialize() {
+            let num = BigNum::from(-123);
+            assert_eq!(serde_json::to_string(&num).unwrap(), "\"-123\"");
+            let frac: Frac = "3/4".parse().unwrap();
+            assert_eq!(serde_json::to_string(&frac).unwrap(), "\"3/4\"");
+        }
+
+        #[test]
+        fn test_round_trip() {
+            let num: BigNum = "123456789012345678901234567890".parse().unwrap();
+            let json = serde_json::to_string(&num).unwrap();
+            assert_eq!(serde_json::from_str::<BigNum>(&json).unwrap(), num);
+            for input in ["-7", "-5/3", "(45/2)deg", "2rad", "[-4, [3, 1/2]]"] {
+                let value: Value = input.parse().unwrap();
+                let json = serde_json::to_string(&value).unwrap();
+                assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+            }
+        }
+
+        #[test]
+        fn test_invalid() {
+            assert!(serde_json::from_str::<BigNum>("\"12a\"").is_err());
+            assert!(serde_json::from_str::<Frac>("\"1/0\"").is_err());
+            assert!(serde_json::from_str::<BigNum>("12").is_err());
+            assert!(serde_json::from_str::<Frac>("\"1e99999999999\"").is_err());
+            assert!(serde_json::from_str::<Value>("\"1e99999999999\"").is_err());
+        }
+    }
+}
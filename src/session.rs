@@ -0,0 +1,582 @@
+use crate::audit::{self, AuditEvent, SessionSink};
+use crate::cache::ExprCache;
+use crate::common::Value;
+use crate::env::{Environment, ANS};
+use crate::error::ArithError;
+use crate::extensions::{with_extensions, Extensions};
+use crate::format::{format_value, FormatOptions};
+use crate::functions;
+use crate::gen::Rng;
+use crate::limits::{self, EvalLimits};
+use crate::parser::{
+    eval_cached, is_blank, parse_output, split_statements, with_syntax, Assoc, CalcError, Expr,
+    ParseOutput, Span, Syntax,
+};
+use crate::refine::{self, Refinement};
+use crate::steps::EvalStep;
+
+use std::sync::Arc;
+
+/// A calculator that remembers, the REPL being one reading lines from stdin.
+/// Variables and `ans` carry over from one `eval` to the next, input starting
+/// with a binary operator other than `-`, or `+` only followed by a space,
+/// continues from the last result, and results are printed with the
+/// session's display options.
+/// Statements evaluated again are not parsed again, their constant parts
+/// already computed.
+#[derive(Debug, Clone)]
+pub struct Session {
+    env: Environment,
+    last: Option<Value>,
+    implicit_ans: bool,
+    syntax: Syntax,
+    // Shared with clones until either registers more
+    extensions: Arc<Extensions>,
+    format: FormatOptions,
+    // Also holds `max-digits`, `gcd-threshold` and `max-depth`, for this
+    // session's evaluations only
+    limits: EvalLimits,
+    audit_sink: Option<SessionSink>,
+    // Stream of `rand` and `rand_prime`, and the seed it started from
+    rng: Rng,
+    seed: Option<u64>,
+    // Statements evaluated before, to skip parsing them and computing their
+    // constant parts again
+    cache: ExprCache,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            env: Environment::new(),
+            last: None,
+            implicit_ans: true,
+            syntax: Syntax::default(),
+            extensions: Arc::default(),
+            format: FormatOptions::default(),
+            limits: EvalLimits::default(),
+            audit_sink: None,
+            rng: Rng::from_time(),
+            seed: None,
+            cache: ExprCache::default(),
+        }
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    /// Evaluate `;` or newline separated statements, stopping at the first
+    /// error, and return the value of the last. Each success is kept as
+    /// `ans`, also when a later statement fails. The session's limits
+    /// bound the input as a whole.
+    pub fn eval(&mut self, input: &str) -> Result<Value, CalcError> {
+        self.bounded(|session| session.eval_statements(input, true))
+    }
+
+    /// `eval` leaving the last result as it is: nothing is kept as `ans`, and
+    /// input starting with an operator does not continue from it
+    pub fn eval_detached(&mut self, input: &str) -> Result<Value, CalcError> {
+        self.bounded(|session| session.eval_statements(input, false))
+    }
+
+    /// Parse a single statement as this session reads it, with its syntax
+    /// options and operators, without evaluating it
+    pub fn parse(&mut self, input: &str) -> Result<ParseOutput, CalcError> {
+        self.bounded(|session| session.in_language(|_, _| parse_output(input)))
+    }
+
+    /// `Expr::eval_steps` in this session's variables and language, for a
+    /// tree from `parse`. `ans` stays as it is.
+    pub fn eval_steps(&mut self, expr: &Expr) -> Result<(Value, Vec<EvalStep>), CalcError> {
+        self.bounded(|session| session.in_language(|env, _| expr.eval_steps(env)))
+    }
+
+    /// `refine` for a single statement in this session's variables and
+    /// language, whose limits bound all the rounds together. Variables and
+    /// `ans` stay as they are.
+    pub fn refine(&mut self, input: &str, places: usize) -> Result<Refinement, CalcError> {
+        self.bounded(|session| {
+            session.in_language(|env, _| {
+                // Each round starts from the same variables, and none is
+                // kept since the approximations differ
+                refine::refine(places, || eval_cached(input, None, &mut env.clone(), None))
+            })
+        })
+    }
+
+    // Run `evaluate` within the session's limits, reporting to its audit sink
+    fn bounded<T>(&mut self, evaluate: impl FnOnce(&mut Session) -> T) -> T {
+        let limits = self.limits;
+        let sink = self.audit_sink.clone();
+        limits::limited(&limits, || {
+            audit::with_sink(sink.as_ref(), || evaluate(self))
+        })
+    }
+
+    // Run `evaluate` with the session's syntax, functions, operators and
+    // random stream
+    fn in_language<T>(
+        &mut self,
+        evaluate: impl FnOnce(&mut Environment, &mut ExprCache) -> T,
+    ) -> T {
+        let (env, cache, rng) = (&mut self.env, &mut self.cache, &mut self.rng);
+        with_syntax(self.syntax, || {
+            with_extensions(&self.extensions, || {
+                functions::with_session_rng(rng, || evaluate(env, cache))
+            })
+        })
+    }
+
+    fn eval_statements(&mut self, input: &str, keep: bool) -> Result<Value, CalcError> {
+        let mut last = Err(CalcError::EmptyInput {
+            span: Span {
+                start: input.len(),
+                end: input.len(),
+            },
+        });
+        for statement in split_statements(input).filter(|s| !is_blank(s)) {
+            let value = self
+                .eval_statement(statement, keep)
+                .map_err(|e| e.within(input, statement))?;
+            last = Ok(value);
+        }
+        last
+    }
+
+    /// `eval`, with the result printed as the session's options say
+    pub fn eval_to_string(&mut self, input: &str) -> Result<String, CalcError> {
+        self.eval(input).map(|value| self.format(&value))
+    }
+
+    pub fn format(&self, value: &Value) -> String {
+        format_value(value, &self.format)
+    }
+
+    /// Change an option by the name `:set` uses in the REPL: `implicit-ans`,
+    /// `relative-percent`, `decimal-comma`, `mixed`, `repeating` and
+    /// `full-output` (`on` or `off`),
+    /// `display-digits`, `max-digits` and `max-depth` (a count or `off`), and
+    /// `gcd-threshold` (a count). The last three are kept in the session's
+    /// limits.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        // Most settings change how statements parse
+        self.cache.clear();
+        match name {
+            "implicit-ans" => self.implicit_ans = parse_switch(value)?,
+            "relative-percent" => self.syntax.relative_percent = parse_switch(value)?,
+            "decimal-comma" => self.syntax.decimal_comma = parse_switch(value)?,
+            "gcd-threshold" => {
+                let digits = value
+                    .parse()
+                    .map_err(|_| format!("Expected a digit count, got {}", value))?;
+                self.limits.gcd_threshold = Some(digits);
+            }
+            "max-depth" => self.limits.max_depth = parse_digits(value)?,
+            "max-digits" => self.limits.max_result_digits = parse_digits(value)?,
+            "mixed" => self.format.mixed = parse_switch(value)?,
+            "repeating" => self.format.repeating = parse_switch(value)?,
+            "full-output" => self.format.full_output = parse_switch(value)?,
+            "display-digits" => self.format.display_digits = parse_digits(value)?,
+            _ => return Err(format!("Unknown setting {}", name)),
+        }
+        Ok(())
+    }
+
+    /// Add a function of `arity` arguments to the language of this session,
+    /// called as `name(a, b)` like the built-in ones. An error from `function`
+    /// fails the evaluation as the `CalcError` of its kind, `Invalid` as
+    /// `Eval` with its message. Registering a name again
+    /// replaces the function, built-in names cannot be taken.
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, ArithError> + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        self.cache.clear();
+        Arc::make_mut(&mut self.extensions).add_function(name, arity, function)
+    }
+
+    /// Add a binary operator to the language of this session, written
+    /// `a ⊕ b` and binding as `precedence` says: 9 is `+`, 10 is `*` and 12
+    /// is `^`, from 2 for `||` up. The symbol is made of punctuation and may
+    /// not be a built-in operator, though it may start with one, as `+-` does.
+    pub fn register_operator(
+        &mut self,
+        symbol: &str,
+        precedence: u8,
+        assoc: Assoc,
+        apply: impl Fn(&Value, &Value) -> Result<Value, ArithError> + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        self.cache.clear();
+        Arc::make_mut(&mut self.extensions).add_operator(symbol, precedence, assoc, apply)
+    }
+
+    /// Bound every later `eval`, for input from users who cannot be trusted.
+    /// Replaces the limits `set` changed as well.
+    pub fn set_limits(&mut self, limits: EvalLimits) {
+        self.cache.clear();
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &EvalLimits {
+        &self.limits
+    }
+
+    /// Call `sink` after every statement this session evaluates, with its
+    /// input, result, duration and whether it hit one of the session's
+    /// limits, in place of the thread's `set_audit_sink`. Replaces any
+    /// previous sink, clones of the session share it.
+    pub fn set_audit_sink(&mut self, sink: impl Fn(&AuditEvent) + Send + Sync + 'static) {
+        self.audit_sink = Some(SessionSink::new(sink));
+    }
+
+    /// Report this session's statements to the thread's sink again
+    pub fn clear_audit_sink(&mut self) {
+        self.audit_sink = None;
+    }
+
+    /// Restart `rand` and `rand_prime` from `seed`, so the session replays
+    /// the same values. Other sessions keep their own streams.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+        self.seed = Some(seed);
+    }
+
+    /// The last `seed_random`, None while random values follow the clock.
+    /// Sessions are not saved anywhere, so to replay one, keep this with its
+    /// inputs and pass it to `seed_random` first.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// The session's random stream, for drawing from it outside of `eval`
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    pub fn env_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+
+    /// The last successful result, also bound to `ans`
+    pub fn last(&self) -> Option<&Value> {
+        self.last.as_ref()
+    }
+
+    // One statement, kept as `ans` when `keep` is set
+    fn eval_statement(&mut self, statement: &str, keep: bool) -> Result<Value, CalcError> {
+        let previous = self.last.clone().filter(|_| self.implicit_ans && keep);
+        let value = self.in_language(|env, cache| {
+            eval_cached(statement, previous.as_ref(), env, Some(cache))
+        })?;
+        if keep {
+            self.env.set(ANS, value.clone());
+            self.last = Some(value.clone());
+        }
+        Ok(value)
+    }
+}
+
+pub(crate) fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("Expected on or off, got {}", value)),
+    }
+}
+
+// A digit count, None for `off`
+fn parse_digits(value: &str) -> Result<Option<usize>, String> {
+    match value {
+        "off" => Ok(None),
+        _ => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Expected a digit count or off, got {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::big_num::BigNum;
+    use crate::frac::Frac;
+
+    fn num(n: i64) -> Value {
+        Value::Number(BigNum::from(n))
+    }
+
+    mod test_session {
+        use super::*;
+
+        #[test]
+        fn test_state_carries_over() {
+            let mut session = Session::new();
+            assert_eq!(session.eval_to_string("x = 1/3").unwrap(), "1/3");
+            assert_eq!(session.eval_to_string("x * 3; ans + 1").unwrap(), "2");
+            assert_eq!(session.eval_to_string("* 5").unwrap(), "10");
+            assert_eq!(session.last(), Some(&Value::Number(BigNum::from(10))));
+            assert_eq!(session.env().len(), 2);
+            // A failing statement keeps what came before it
+            assert!(session.eval("y = 7; 1 / 0; y = 8").is_err());
+            assert_eq!(session.eval_to_string("y").unwrap(), "7");
+        }
+
+        #[test]
+        fn test_options() {
+            let mut session = Session::new();
+            session.set("mixed", "on").unwrap();
+            assert_eq!(session.eval_to_string("-7/3").unwrap(), "-2 1/3");
+            session.set("implicit-ans", "off").unwrap();
+            assert!(session.eval("* 2").is_err());
+            assert_eq!(session.eval_to_string("120 + 10%").unwrap(), "120 1/10");
+            session.set("relative-percent", "on").unwrap();
+            assert_eq!(session.eval_to_string("120 + 10%").unwrap(), "132");
+            session.set("decimal-comma", "on").unwrap();
+            assert_eq!(session.eval_to_string("1.000,5 * 2").unwrap(), "2001");
+            assert!(session.set("mixed", "maybe").is_err());
+            assert!(session.set("colour", "on").is_err());
+        }
+
+        #[test]
+        fn test_cache() {
+            let mut session = Session::new();
+            session.eval("y = 2^10 + 1/2").unwrap();
+            let cached = session.cache.get("y = 2^10 + 1/2").unwrap();
+            assert_eq!(cached.to_string(), "y = 2049/2");
+            assert_eq!(session.eval_to_string("y = 2^10 + 1/2").unwrap(), "2049/2");
+            // Only what evaluation always computes, and nothing random
+            session.eval("y > 0 ? rand(10) * 1 : 2 * 3").unwrap();
+            let cached = session.cache.get("y > 0 ? rand(10) * 1 : 2 * 3").unwrap();
+            assert_eq!(cached.to_string(), "y > 0 ? rand(10) * 1 : 2 * 3");
+            // Continuing from the result, failing, or under other settings
+            session.eval("* 2").unwrap();
+            assert!(session.cache.get("* 2").is_none());
+            assert!(session.eval("y / 0").is_err());
+            assert!(session.cache.get("y / 0").is_none());
+            session.set("mixed", "on").unwrap();
+            assert!(session.cache.get("y = 2^10 + 1/2").is_none());
+        }
+
+        #[test]
+        fn test_error_spans() {
+            let mut session = Session::new();
+            let err = session.eval("1; 2 +").unwrap_err();
+            assert_eq!(err.span(), Span { start: 6, end: 6 });
+            let err = session.eval("1;  x").unwrap_err();
+            assert_eq!(err.span(), Span { start: 4, end: 5 });
+            assert!(session.eval(" # nothing").unwrap_err().is_empty_input());
+        }
+
+        #[test]
+        fn test_limits() {
+            let mut session = Session::new();
+            session.set_limits(EvalLimits {
+                max_steps: Some(4),
+                ..EvalLimits::default()
+            });
+            assert_eq!(session.eval_to_string("x = 1 + 2").unwrap(), "3");
+            // Each call starts with the full budget
+            assert_eq!(session.eval_to_string("x * 2").unwrap(), "6");
+            let err = session.eval("x * 2 + 1").unwrap_err();
+            assert!(err.is_limit_exceeded());
+            assert_eq!(err.span(), Span { start: 0, end: 9 });
+            assert_eq!(session.last(), Some(&Value::Number(BigNum::from(6))));
+        }
+
+        #[test]
+        fn test_language_commands() {
+            let mut session = Session::new();
+            session.set("decimal-comma", "on").unwrap();
+            session
+                .register_operator("⊕", 9, Assoc::Left, |a, b| {
+                    (num(2) * a.clone())? + b.clone()
+                })
+                .unwrap();
+            session.eval("x = 3").unwrap();
+            let output = session.parse("1,5 ⊕ x").unwrap();
+            assert_eq!(output.expr.to_string(), "3/2 ⊕ x");
+            let (value, steps) = session.eval_steps(&output.expr).unwrap();
+            assert_eq!(value, num(6));
+            assert_eq!(steps.last().unwrap().to_string(), "6");
+            // Neither kept as the last result nor continuing from it
+            assert_eq!(session.eval_detached("x ⊕ 1").unwrap(), num(7));
+            assert!(session.eval_detached("* 2").is_err());
+            assert_eq!(session.last(), Some(&num(3)));
+            assert!(Session::new().parse("1 ⊕ 2").is_err());
+        }
+
+        #[test]
+        fn test_refine() {
+            let mut session = Session::new();
+            session.eval("x = 2").unwrap();
+            let refined = session.refine("y = sqrt(x)", 10).unwrap();
+            assert_eq!(refined.text, "1.4142135624");
+            assert_eq!(refined.places, 10);
+            // The approximation is not kept, nor does the cache reuse it
+            assert_eq!(session.env().get("y"), None);
+            assert_eq!(session.last(), Some(&num(2)));
+            assert!(session.eval("sqrt(x)").is_err());
+            session.set_limits(EvalLimits {
+                max_steps: Some(3),
+                ..EvalLimits::default()
+            });
+            // Two steps a round, the second round runs out
+            let refined = session.refine("sqrt(x)", 10).unwrap();
+            assert_eq!((refined.places, refined.precision), (0, Some(20)));
+        }
+
+        #[test]
+        fn test_seed() {
+            let mut first = Session::new();
+            let mut second = Session::new();
+            first.seed_random(42);
+            second.seed_random(42);
+            assert_eq!(first.seed(), Some(42));
+            let draw = "[rand(1000), rand(1000), rand(1000)]";
+            let values = first.eval_to_string(draw).unwrap();
+            // Each session draws from its own stream
+            assert_eq!(second.eval_to_string(draw).unwrap(), values);
+            assert_ne!(first.eval_to_string(draw).unwrap(), values);
+            first.seed_random(42);
+            assert_eq!(first.eval_to_string(draw).unwrap(), values);
+            assert_eq!(Session::new().seed(), None);
+        }
+
+        #[test]
+        fn test_limit_settings() {
+            let mut small = Session::new();
+            small.set("max-digits", "10").unwrap();
+            small.set("max-depth", "3").unwrap();
+            small.set("gcd-threshold", "50").unwrap();
+            let mut other = Session::new();
+            let err = small.eval("10^20").unwrap_err();
+            assert!(matches!(err, CalcError::Overflow { .. }), "{:?}", err);
+            let err = small.eval("(((1)))").unwrap_err();
+            assert!(matches!(err, CalcError::LimitExceeded { .. }), "{:?}", err);
+            assert_eq!(
+                other.eval_to_string("10^20 + ((1))").unwrap(),
+                "100000000000000000001"
+            );
+            // Kept in the session, the process-wide settings are untouched
+            assert_eq!(small.limits().max_result_digits, Some(10));
+            assert_eq!(limits::limited(small.limits(), Frac::gcd_threshold), 50);
+            assert_eq!(Expr::max_depth(), Some(256));
+            small.set("max-digits", "off").unwrap();
+            assert_eq!(
+                small.eval_to_string("10^20").unwrap(),
+                "100000000000000000000"
+            );
+        }
+    }
+
+    mod test_extensions {
+        use super::*;
+
+        #[test]
+        fn test_register_function() {
+            let mut session = Session::new();
+            session
+                .register_function("hypot2", 2, |args| {
+                    (args[0].clone() * args[0].clone())? + (args[1].clone() * args[1].clone())?
+                })
+                .unwrap();
+            assert_eq!(session.eval_to_string("hypot2(3, 4) + 1").unwrap(), "26");
+            let err = session.eval("1 + hypot2(3)").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Eval Error hypot2 expects 2 arguments, got 1"
+            );
+            assert_eq!(err.span(), Span { start: 4, end: 13 });
+            // Only this session knows it
+            assert!(Session::new().eval("hypot2(3, 4)").is_err());
+            assert!(session.register_function("gcd", 2, |_| Ok(num(0))).is_err());
+            assert!(session.register_function("2x", 1, |_| Ok(num(0))).is_err());
+        }
+
+        #[test]
+        fn test_function_errors() {
+            let mut session = Session::new();
+            session
+                .register_function("fail", 0, |_| Err("not today".to_string().into()))
+                .unwrap();
+            let err = session.eval("x = 1; fail()").unwrap_err();
+            assert_eq!(err.to_string(), "Eval Error not today");
+            assert_eq!(err.span(), Span { start: 7, end: 13 });
+            // Calls are not folded ahead of time, the function may not be pure
+            let mut count = Session::new();
+            let calls = Arc::new(std::sync::atomic::AtomicI64::new(0));
+            let counter = Arc::clone(&calls);
+            count
+                .register_function("tick", 0, move |_| {
+                    let n = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    Ok(num(n))
+                })
+                .unwrap();
+            assert_eq!(count.eval_to_string("tick() + tick()").unwrap(), "1");
+        }
+
+        #[test]
+        fn test_register_operator() {
+            let mut session = Session::new();
+            session
+                .register_operator("⊕", 9, Assoc::Left, |a, b| {
+                    (num(2) * a.clone())? + b.clone()
+                })
+                .unwrap();
+            assert_eq!(session.eval_to_string("1 ⊕ 2 ⊕ 3").unwrap(), "11");
+            assert_eq!(session.eval_to_string("2 * 3 ⊕ 1").unwrap(), "13");
+            assert_eq!(session.eval_to_string("1 ⊕ 2 * 3").unwrap(), "8");
+            session
+                .register_operator("⊕", 9, Assoc::Right, |a, b| {
+                    (num(2) * a.clone())? + b.clone()
+                })
+                .unwrap();
+            assert_eq!(session.eval_to_string("1 ⊕ 2 ⊕ 3").unwrap(), "9");
+            // Longer than the built-in operator it starts with
+            session
+                .register_operator("+-", 9, Assoc::Left, |a, b| a.clone() - b.clone())
+                .unwrap();
+            assert_eq!(session.eval_to_string("5 +- 2").unwrap(), "3");
+            assert_eq!(session.eval_to_string("5 + -2").unwrap(), "3");
+            assert!(Session::new().eval("1 ⊕ 2").is_err());
+        }
+
+        #[test]
+        fn test_operator_errors() {
+            let mut session = Session::new();
+            let apply = |_: &Value, _: &Value| Err("no".to_string().into());
+            assert!(session
+                .register_operator("<=", 4, Assoc::None, apply)
+                .is_err());
+            assert!(session
+                .register_operator("x", 9, Assoc::Left, apply)
+                .is_err());
+            assert!(session
+                .register_operator("", 9, Assoc::Left, apply)
+                .is_err());
+            assert!(session
+                .register_operator("@", 13, Assoc::Left, apply)
+                .is_err());
+            session
+                .register_operator("@", 9, Assoc::Left, apply)
+                .unwrap();
+            let err = session.eval("1 + (2 @ 3)").unwrap_err();
+            assert_eq!(err.to_string(), "Eval Error no");
+            assert_eq!(err.span(), Span { start: 5, end: 10 });
+            session
+                .register_operator("<>", 4, Assoc::None, apply)
+                .unwrap();
+            let err = session.eval("1 <> 2 <> 3").unwrap_err();
+            assert!(err.to_string().contains("cannot follow a comparison"));
+        }
+    }
+}
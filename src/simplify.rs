@@ -0,0 +1,272 @@
+use crate::big_num::BigNum;
+use crate::common::Value;
+use crate::functions;
+use crate::parser::{Constants, Expr, Operator};
+
+/// Rewrite `expr` into an equivalent, smaller tree: constant subexpressions
+/// are computed, `--x`, `x * 1` and `x + 0` become `x`, adding a negation
+/// becomes a subtraction and conditions known ahead of time pick their
+/// branch. Parts that fail to evaluate, such as `1 / 0`, calls to the
+/// random functions and whatever a session registered are left for
+/// evaluation.
+pub fn simplify(expr: &Expr) -> Expr {
+    rewrite(expr, true, None)
+}
+
+// Only the constant subexpressions that every evaluation of `expr` computes,
+// so it fails the same way and takes no longer: `x * 1` stays, being an
+// error for `x = true`, and so do the branches of `?:`, the right sides of
+// `&&` and `||` and the bodies of `sum` and `prod`, which may never be
+// evaluated
+#[cfg(test)]
+fn fold_constants(expr: &Expr) -> Expr {
+    rewrite(expr, false, None)
+}
+
+// `fold_constants` taking the values of the nodes it folds from those an
+// evaluation of `expr` computed, without computing them again
+pub(crate) fn fold_computed(expr: &Expr, computed: &Constants) -> Expr {
+    rewrite(expr, false, Some(computed))
+}
+
+// `thorough` for all of `simplify`, otherwise as `fold_constants`, folding
+// only the nodes `computed` holds when given
+fn rewrite(expr: &Expr, thorough: bool, computed: Option<&Constants>) -> Expr {
+    let simplify = |expr: &Expr| rewrite(expr, thorough, computed);
+    let simplified = match expr {
+        Expr::Literal(_) | Expr::Var(..) | Expr::Raise(..) => return expr.clone(),
+        Expr::Unary(operator, operand, span) => {
+            Expr::Unary(*operator, Box::new(simplify(operand)), *span)
+        }
+        Expr::Postfix(operator, operand, span) => {
+            Expr::Postfix(*operator, Box::new(simplify(operand)), *span)
+        }
+        Expr::Binary(operator @ (Operator::And | Operator::Or), left, right, span) if !thorough => {
+            Expr::Binary(*operator, Box::new(simplify(left)), right.clone(), *span)
+        }
+        Expr::Binary(operator, left, right, span) => Expr::Binary(
+            *operator,
+            Box::new(simplify(left)),
+            Box::new(simplify(right)),
+            *span,
+        ),
+        // The body of `sum` and `prod` is not evaluated for an empty range
+        Expr::Call(name, args, span) if !thorough && functions::is_series(name) => {
+            let args = args
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| if i == 3 { arg.clone() } else { simplify(arg) })
+                .collect();
+            Expr::Call(name.clone(), args, *span)
+        }
+        Expr::Call(name, args, span) => {
+            Expr::Call(name.clone(), args.iter().map(simplify).collect(), *span)
+        }
+        Expr::List(items, span) => Expr::List(items.iter().map(simplify).collect(), *span),
+        Expr::Assign(name, value) => return Expr::Assign(name.clone(), Box::new(simplify(value))),
+        Expr::Conditional(test, then, otherwise, span) => {
+            return match simplify(test) {
+                Expr::Literal(Value::Bool(true)) => simplify(then),
+                Expr::Literal(Value::Bool(false)) => simplify(otherwise),
+                test if !thorough => {
+                    Expr::Conditional(Box::new(test), then.clone(), otherwise.clone(), *span)
+                }
+                test => Expr::Conditional(
+                    Box::new(test),
+                    Box::new(simplify(then)),
+                    Box::new(simplify(otherwise)),
+                    *span,
+                ),
+            }
+        }
+    };
+    let folded = match computed {
+        Some(computed) => computed.get(&(expr as *const Expr)).cloned(),
+        None => fold(&simplified),
+    };
+    match folded {
+        Some(value) => Expr::Literal(value),
+        None if thorough => reduce(simplified),
+        None => simplified,
+    }
+}
+
+// Value of a node whose operands are all literals, None when it has other
+// operands, is not pure or fails
+fn fold(expr: &Expr) -> Option<Value> {
+    let constant = match expr {
+        Expr::Unary(_, operand, _) | Expr::Postfix(_, operand, _) => is_literal(operand),
+        // `false && x` and `true || x` are decided by the left side alone
+        Expr::Binary(Operator::And, left, ..) => {
+            return matches!(**left, Expr::Literal(Value::Bool(false)))
+                .then_some(Value::Bool(false))
+        }
+        Expr::Binary(Operator::Or, left, ..) => {
+            return matches!(**left, Expr::Literal(Value::Bool(true))).then_some(Value::Bool(true))
+        }
+        // Registered operators, like registered functions, may not be pure
+        Expr::Binary(Operator::Custom(_), ..) => false,
+        Expr::Binary(_, left, right, _) => is_literal(left) && is_literal(right),
+        Expr::Call(name, args, _) => functions::is_pure(name) && args.iter().all(is_literal),
+        Expr::List(items, _) => items.iter().all(is_literal),
+        _ => false,
+    };
+    if !constant {
+        return None;
+    }
+    expr.clone().eval().ok()
+}
+
+// Drop operations that leave their operand as it is, and negations that an
+// operator can absorb
+fn reduce(mut expr: Expr) -> Expr {
+    match &mut expr {
+        Expr::Unary(Operator::Negative, operand, _) => match &mut **operand {
+            Expr::Unary(Operator::Negative, inner, _) => inner.take(),
+            _ => expr,
+        },
+        Expr::Binary(operator, left, right, span) => match (*operator, left.take(), right.take()) {
+            (Operator::Add, left, right) if is_number(&right, BigNum::zero()) => left,
+            (Operator::Add, left, right) if is_number(&left, BigNum::zero()) => right,
+            (Operator::Subtract, left, right) if is_number(&right, BigNum::zero()) => left,
+            (Operator::Multiply, left, right) if is_number(&right, BigNum::one()) => left,
+            (Operator::Multiply, left, right) if is_number(&left, BigNum::one()) => right,
+            (Operator::Divide, left, right) if is_number(&right, BigNum::one()) => left,
+            (operator @ (Operator::Add | Operator::Subtract), left, right) => {
+                match negation_of(right) {
+                    Ok(negated) => {
+                        let flipped = if operator == Operator::Add {
+                            Operator::Subtract
+                        } else {
+                            Operator::Add
+                        };
+                        Expr::Binary(flipped, Box::new(left), Box::new(negated), *span)
+                    }
+                    Err(right) => Expr::Binary(operator, Box::new(left), Box::new(right), *span),
+                }
+            }
+            (operator, left, right) => {
+                Expr::Binary(operator, Box::new(left), Box::new(right), *span)
+            }
+        },
+        _ => expr,
+    }
+}
+
+// `y` for `-y` or a negative number literal, the expression back otherwise
+fn negation_of(mut expr: Expr) -> Result<Expr, Expr> {
+    match &mut expr {
+        Expr::Unary(Operator::Negative, operand, _) => Ok(operand.take()),
+        Expr::Literal(value @ (Value::Number(_) | Value::Frac(_))) if value.is_negative() => {
+            Ok(Expr::Literal((-value.clone()).expect("numbers negate")))
+        }
+        _ => Err(expr),
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal(_))
+}
+
+// Only plain numbers, `x + 0deg` still needs `x` to be an angle
+fn is_number(expr: &Expr, number: BigNum) -> bool {
+    matches!(expr, Expr::Literal(Value::Number(n)) if *n == number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::Environment;
+    use crate::parser::parse;
+
+    fn simplified(input: &str) -> String {
+        simplify(&parse(input).unwrap()).to_string()
+    }
+
+    mod test_simplify {
+        use super::*;
+
+        #[test]
+        fn test_constants_folded() {
+            assert_eq!(simplified("2 * 3 + x"), "6 + x");
+            assert_eq!(simplified("x * (1/2 + 1/3)"), "x * (5/6)");
+            assert_eq!(simplified("gcd(12, 18) ^ 2"), "36");
+            assert_eq!(simplified("y = 2^10"), "y = 1024");
+            // Left for evaluation to report, or to draw a new number
+            assert_eq!(simplified("x + 1 / 0"), "x + 1 / 0");
+            assert_eq!(simplified("rand(6) + 0"), "rand(6)");
+        }
+
+        #[test]
+        fn test_identities() {
+            assert_eq!(simplified("--x"), "x");
+            assert_eq!(simplified("x * 1 + 0"), "x");
+            assert_eq!(simplified("1 * (0 + x) / 1"), "x");
+            assert_eq!(simplified("x - 0"), "x");
+            assert_eq!(simplified("0 - x"), "0 - x");
+            let folded = fold_constants(&parse("x * (2 - 1) + -y").unwrap());
+            assert_eq!(folded.to_string(), "x * 1 + -y");
+            let folded = fold_constants(&parse("x > 2 - 1 ? 2 * 3 : y || 1 < 2").unwrap());
+            assert_eq!(folded.to_string(), "x > 1 ? 2 * 3 : y || 1 < 2");
+            let folded = fold_constants(&parse("sum(k, 2 - 1, x, 2 * 3)").unwrap());
+            assert_eq!(folded.to_string(), "sum(k, 1, x, 2 * 3)");
+        }
+
+        #[test]
+        fn test_fold_computed() {
+            // The same tree as fold_constants, from the values evaluation
+            // computed on the way
+            let mut env = Environment::new();
+            env.set("x", Value::Number(BigNum::from(3)));
+            env.set("y", Value::Bool(false));
+            for input in [
+                "x * (2 - 1) + -(4 / 2)",
+                "x > 2 - 1 ? 2 * 3 : y || 1 < 2",
+                "x < 2 - 1 ? 2 * 3 : y || 1 < 2",
+                "1 < 2 ? 3 * 4 : x",
+                "false && x > 0",
+                "y || 1 == 1",
+                "sum(k, 2 - 1, x, 2 * 3)",
+                "[1 + 1, x, max(2, 3)] * 2",
+                "x = 2 ^ 3",
+            ] {
+                let expr = parse(input).unwrap();
+                let (_, folded) = expr.eval_folding(&mut env.clone()).unwrap();
+                assert_eq!(
+                    format!("{:?}", folded),
+                    format!("{:?}", fold_constants(&expr)),
+                    "{}",
+                    input
+                );
+            }
+        }
+
+        #[test]
+        fn test_negations() {
+            assert_eq!(simplified("x + -y"), "x - y");
+            assert_eq!(simplified("x - -y"), "x + y");
+            assert_eq!(simplified("x + -2"), "x - 2");
+            assert_eq!(simplified("x - (3 - 5)"), "x + 2");
+        }
+
+        #[test]
+        fn test_conditions() {
+            assert_eq!(simplified("1 < 2 ? x : y"), "x");
+            assert_eq!(simplified("false && x > 0"), "false");
+            assert_eq!(simplified("x > 0 || 1 == 1"), "x > 0 || true");
+            assert_eq!(simplified("x ? 1 + 1 : 0"), "x ? 2 : 0");
+        }
+
+        #[test]
+        fn test_same_value() {
+            let mut env = Environment::new();
+            env.set("x", "7/3".parse().unwrap());
+            env.set("y", "-2".parse().unwrap());
+            for input in ["x * 1 - -y + 2 * 3", "--x / 1 + 0", "(x + -y) * (1 + 1)"] {
+                let mut expr = parse(input).unwrap();
+                let expected = expr.eval_in(&mut env.clone()).unwrap();
+                assert_eq!(simplify(&expr).eval_in(&mut env).unwrap(), expected);
+            }
+        }
+    }
+}
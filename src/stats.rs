@@ -0,0 +1,160 @@
+use crate::pool::{self, PoolStats};
+
+use std::cell::Cell;
+use std::fmt;
+
+/// Counters describing the work done on the current thread, shown by
+/// `:stats` in the REPL
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    pub pool: PoolStats,
+    pub ops: OpCounts,
+}
+
+/// BigNum operations of the last traced evaluation. Subtractions count as
+/// additions, and the work inside a division or a Karatsuba product is
+/// counted too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OpCounts {
+    pub adds: u64,
+    pub muls: u64,
+    pub divs: u64,
+    pub max_operand_digits: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Op {
+    Add,
+    Mul,
+    Div,
+}
+
+thread_local! {
+    static TRACING: Cell<bool> = const { Cell::new(false) };
+    static OP_COUNTS: Cell<OpCounts> = Cell::new(OpCounts::default());
+}
+
+pub fn stats() -> Stats {
+    Stats {
+        pool: pool::stats(),
+        ops: OP_COUNTS.with(Cell::get),
+    }
+}
+
+/// Turn operation counting on or off, it is off by default
+pub fn set_tracing(on: bool) {
+    TRACING.with(|tracing| tracing.set(on));
+}
+
+// Start counting a new evaluation
+pub(crate) fn reset_op_counts() {
+    if TRACING.with(Cell::get) {
+        OP_COUNTS.with(|counts| counts.set(OpCounts::default()));
+    }
+}
+
+// Run `work` counting its operations apart from the rest of this thread's,
+// as a thread that is `tracing` would, for work handed over by one
+#[cfg(feature = "rayon")]
+pub(crate) fn counted<T>(tracing: bool, work: impl FnOnce() -> T) -> (T, OpCounts) {
+    let outer_tracing = TRACING.with(|current| current.replace(tracing));
+    let outer_counts = OP_COUNTS.with(|current| current.replace(OpCounts::default()));
+    let result = work();
+    let counts = OP_COUNTS.with(|current| current.replace(outer_counts));
+    TRACING.with(|current| current.set(outer_tracing));
+    (result, counts)
+}
+
+// Whether operations are counted on this thread
+#[cfg(feature = "rayon")]
+pub(crate) fn is_tracing() -> bool {
+    TRACING.with(Cell::get)
+}
+
+// Add operations counted by `counted` to those of this thread
+#[cfg(feature = "rayon")]
+pub(crate) fn add_counts(other: OpCounts) {
+    if !TRACING.with(Cell::get) {
+        return;
+    }
+    OP_COUNTS.with(|counts| {
+        let mut c = counts.get();
+        c.adds += other.adds;
+        c.muls += other.muls;
+        c.divs += other.divs;
+        c.max_operand_digits = c.max_operand_digits.max(other.max_operand_digits);
+        counts.set(c);
+    });
+}
+
+pub(crate) fn record(op: Op, digits: usize) {
+    if !TRACING.with(Cell::get) {
+        return;
+    }
+    OP_COUNTS.with(|counts| {
+        let mut c = counts.get();
+        match op {
+            Op::Add => c.adds += 1,
+            Op::Mul => c.muls += 1,
+            Op::Div => c.divs += 1,
+        }
+        c.max_operand_digits = c.max_operand_digits.max(digits);
+        counts.set(c);
+    });
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pool = &self.pool;
+        let ops = &self.ops;
+        writeln!(
+            f,
+            "last evaluation: {} adds, {} muls, {} divs, largest operand {} digits",
+            ops.adds, ops.muls, ops.divs, ops.max_operand_digits
+        )?;
+        write!(
+            f,
+            "buffer pool: {} reused, {} allocated, {} returned, {} discarded",
+            pool.reused, pool.allocated, pool.returned, pool.discarded
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::big_num::BigNum;
+
+    mod test_stats {
+        use super::*;
+
+        #[test]
+        fn test_arithmetic_reuses_buffers() {
+            let num = BigNum::from(123456789);
+            let _ = num.clone() * num.clone() + num.clone();
+            let before = stats();
+            let _ = num.clone() * num.clone() + num;
+            let after = stats();
+            assert!(after.pool.reused > before.pool.reused);
+            assert_eq!(after.pool.allocated, before.pool.allocated);
+        }
+
+        #[test]
+        fn test_op_counts() {
+            set_tracing(true);
+            reset_op_counts();
+            let num = BigNum::from(123456);
+            let _ = num.clone() * num.clone() - BigNum::from(7) / BigNum::from(1);
+            let ops = stats().ops;
+            assert_eq!((ops.muls, ops.divs), (1, 1));
+            assert!(ops.adds >= 1);
+            assert_eq!(ops.max_operand_digits, 11);
+            set_tracing(false);
+            reset_op_counts();
+            let _ = num.clone() * num;
+            assert_eq!(stats().ops, ops);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::common::Value;
+use crate::env::Environment;
+use crate::frac::Frac;
+use crate::limits;
+use crate::parser::{
+    apply_binary, apply_postfix, apply_unary, call, condition, logic, series, series_args,
+    CalcError, Expr, Operator,
+};
+
+use std::fmt;
+
+/// One step of the working of `Expr::eval_steps`: a part of the expression
+/// rewritten, and the whole expression after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalStep {
+    /// The part rewritten, as the step before wrote it
+    pub from: String,
+    /// What it became
+    pub to: String,
+    /// The whole expression after the step
+    pub expr: String,
+}
+
+// The whole expression, so steps print one under the other as worked by hand
+impl fmt::Display for EvalStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+// Rewrite the innermost, leftmost part of `expr` whose operands are known
+// until only a value is left, the order `Expr::eval_in` computes them in.
+// Branches not taken are dropped without being evaluated.
+pub(crate) fn eval_steps(
+    expr: &Expr,
+    env: &mut Environment,
+) -> Result<(Value, Vec<EvalStep>), CalcError> {
+    let mut expr = expr.clone();
+    let mut steps = Vec::new();
+    loop {
+        if let Expr::Literal(value) = &expr {
+            return Ok((value.clone(), steps));
+        }
+        limits::step().map_err(CalcError::from)?;
+        let path = next_path(&expr);
+        let node = node_at(&mut expr, &path);
+        let mut from = node.to_string();
+        let original = node.clone();
+        let result = rewrite(&original, env)?;
+        for form in working(&original) {
+            *node_at(&mut expr, &path) = form.expr.clone();
+            let to = form.fill(form.expr.to_string());
+            let whole = form.fill(expr.to_string());
+            steps.push(EvalStep {
+                from: std::mem::replace(&mut from, to.clone()),
+                to,
+                expr: whole,
+            });
+        }
+        *node_at(&mut expr, &path) = result;
+        let to = node_at(&mut expr, &path).to_string();
+        // A list of values becomes one value without a change to show
+        if to != from {
+            steps.push(EvalStep {
+                from,
+                to,
+                expr: expr.to_string(),
+            });
+        }
+    }
+}
+
+// Child indices leading from `expr` to the part to rewrite next
+fn next_path(expr: &Expr) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut node = expr;
+    while let Some(index) = pending_operand(node) {
+        path.push(index);
+        node = node.children().nth(index).unwrap();
+    }
+    path
+}
+
+// Index of the operand of `expr` to rewrite before `expr` itself, None once
+// its operands are values or it can be rewritten without the rest
+fn pending_operand(expr: &Expr) -> Option<usize> {
+    let operands: Vec<&Expr> = match expr {
+        Expr::Binary(operator @ (Operator::And | Operator::Or), left, right, _) => {
+            match &**left {
+                Expr::Literal(value) => {
+                    let is_or = *operator == Operator::Or;
+                    // A left side that decides the result, or fails, is enough
+                    match logic(is_or, value.clone()) {
+                        Ok(None) => return (!is_value(right)).then_some(1),
+                        _ => return None,
+                    }
+                }
+                _ => return Some(0),
+            }
+        }
+        Expr::Conditional(test, ..) => vec![test],
+        // The bounds, the body taking the value of each term in one step
+        Expr::Call(name, args, _) if series_args(name, args).is_some() => {
+            return (1..3).find(|&index| !is_value(&args[index]));
+        }
+        _ => expr.children().collect(),
+    };
+    operands.iter().position(|operand| !is_value(operand))
+}
+
+fn node_at<'a>(expr: &'a mut Expr, path: &[usize]) -> &'a mut Expr {
+    path.iter().fold(expr, |node, &index| match node {
+        Expr::Binary(_, left, right, _) => [left, right][index],
+        Expr::Unary(_, operand, _) | Expr::Postfix(_, operand, _) | Expr::Assign(_, operand) => {
+            operand
+        }
+        Expr::Call(_, args, _) | Expr::List(args, _) => &mut args[index],
+        Expr::Conditional(test, ..) => test,
+        _ => unreachable!("paths only lead through operands"),
+    })
+}
+
+fn is_value(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal(_))
+}
+
+// What the part `next_path` leads to becomes, its operands being values
+fn rewrite(expr: &Expr, env: &mut Environment) -> Result<Expr, CalcError> {
+    let literal = |operand: &Expr| match operand {
+        Expr::Literal(value) => value.clone(),
+        _ => unreachable!("operands are rewritten first"),
+    };
+    let value = match expr {
+        Expr::Var(name, span) => env.get(name).cloned().ok_or_else(|| {
+            CalcError::new_eval_error(format!("Unknown variable {}", name)).at(*span)
+        })?,
+        Expr::Raise(message, span) => {
+            return Err(CalcError::new_user_error(message.clone()).at(*span))
+        }
+        Expr::Unary(operator, operand, span) => {
+            apply_unary(*operator, literal(operand)).map_err(|e| e.at(*span))?
+        }
+        Expr::Postfix(operator, operand, span) => {
+            apply_postfix(*operator, literal(operand)).map_err(|e| e.at(*span))?
+        }
+        Expr::Binary(operator @ (Operator::And | Operator::Or), left, right, span) => {
+            let is_or = *operator == Operator::Or;
+            match logic(is_or, literal(left)).map_err(|e| e.at(*span))? {
+                Some(decided) => decided,
+                None => logic(is_or, literal(right))
+                    .map_err(|e| e.at(*span))?
+                    .unwrap_or(Value::Bool(!is_or)),
+            }
+        }
+        Expr::Binary(operator, left, right, span) => {
+            apply_binary(*operator, literal(left), literal(right)).map_err(|e| e.at(*span))?
+        }
+        Expr::Call(name, args, span) if series_args(name, args).is_some() => {
+            let (var, body) = series_args(name, args).unwrap();
+            let (from, to) = (literal(&args[1]), literal(&args[2]));
+            let mut body = body.clone();
+            series(name, var, from, to, env, |env| body.eval_in(env)).map_err(|e| e.at(*span))?
+        }
+        Expr::Call(name, args, span) => {
+            call(name, args.iter().map(literal).collect()).map_err(|e| e.at(*span))?
+        }
+        Expr::List(items, _) => Value::List(items.iter().map(literal).collect()),
+        Expr::Conditional(test, then, otherwise, span) => {
+            let taken = if condition(literal(test), "?").map_err(|e| e.at(*span))? {
+                then
+            } else {
+                otherwise
+            };
+            return Ok((**taken).clone());
+        }
+        Expr::Assign(name, value) => {
+            let value = literal(value);
+            env.set(name, value.clone());
+            value
+        }
+        Expr::Literal(value) => value.clone(),
+    };
+    Ok(Expr::Literal(value))
+}
+
+// A form shown between a part and its value, with fractions in it that are
+// not in lowest terms. Values always print reduced, so those fractions are
+// placeholders in the tree, filled in once it is printed.
+struct Working {
+    expr: Expr,
+    fractions: Vec<Frac>,
+}
+
+impl Working {
+    // Each placeholder prints as `\u{1}i / \u{2}`, binding like the
+    // fraction it stands for
+    fn slot(index: usize) -> Expr {
+        Expr::binary(
+            Operator::Divide,
+            Expr::var(&format!("\u{1}{}", index)),
+            Expr::var("\u{2}"),
+        )
+    }
+
+    fn fill(&self, printed: String) -> String {
+        self.fractions
+            .iter()
+            .enumerate()
+            .fold(printed, |printed, (index, frac)| {
+                let slot = format!("\u{1}{} / \u{2}", index);
+                let written = format!("{}/{}", frac.numerator(), frac.denominator());
+                printed.replace(&slot, &written)
+            })
+    }
+}
+
+// Forms worked through by hand before the value of `expr`: fractions added
+// or subtracted are written over a common denominator first, `1/2 + 1/3`
+// being `3/6 + 2/6`, and the result before it is reduced
+fn working(expr: &Expr) -> Vec<Working> {
+    let Expr::Binary(operator @ (Operator::Add | Operator::Subtract), left, right, span) = expr
+    else {
+        return Vec::new();
+    };
+    let (Expr::Literal(left), Expr::Literal(right)) = (&**left, &**right) else {
+        return Vec::new();
+    };
+    let is_frac = |value: &Value| matches!(value, Value::Frac(_));
+    let (Some(a), Some(b)) = (left.to_frac(), right.to_frac()) else {
+        return Vec::new();
+    };
+    if !is_frac(left) && !is_frac(right) {
+        return Vec::new();
+    }
+    let (a, b) = a.with_common_denominator(&b);
+    let mut forms = Vec::new();
+    if a.denominator() != b.denominator() || !a.is_reduced() || !b.is_reduced() {
+        forms.push(Working {
+            expr: Expr::Binary(
+                *operator,
+                Box::new(Working::slot(0)),
+                Box::new(Working::slot(1)),
+                *span,
+            ),
+            fractions: vec![a.clone(), b.clone()],
+        });
+    }
+    let numerator = if *operator == Operator::Add {
+        a.numerator().clone() + b.numerator().clone()
+    } else {
+        a.numerator().clone() - b.numerator().clone()
+    };
+    let result = Frac::new_unreduced(numerator, a.denominator().clone());
+    if !result.is_reduced() && !result.numerator().is_zero() {
+        forms.push(Working {
+            expr: Working::slot(0),
+            fractions: vec![result],
+        });
+    }
+    forms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    // The whole expression after each step
+    fn worked(input: &str) -> Vec<String> {
+        let (_, steps) = eval_steps(&parse(input).unwrap(), &mut Environment::new()).unwrap();
+        steps.into_iter().map(|step| step.expr).collect()
+    }
+
+    mod test_eval_steps {
+        use super::*;
+
+        #[test]
+        fn test_fractions() {
+            assert_eq!(worked("1/2 + 1/3"), ["3/6 + 2/6", "5/6"]);
+            assert_eq!(worked("1/6 + 1/3"), ["1/6 + 2/6", "3/6", "1/2"]);
+            assert_eq!(worked("1 - 1/4"), ["4/4 - 1/4", "3/4"]);
+            assert_eq!(
+                worked("2 * (1/2 + 1/3)"),
+                ["2 * (3/6 + 2/6)", "2 * (5/6)", "5/3"]
+            );
+        }
+
+        #[test]
+        fn test_order() {
+            assert_eq!(worked("1 + 2 * 3"), ["1 + 6", "7"]);
+            assert_eq!(worked("(1 + 2) * (3 + 4)"), ["3 * (3 + 4)", "3 * 7", "21"]);
+            assert_eq!(
+                worked("max(2^3, 1) - 3!"),
+                ["max(8, 1) - 3!", "8 - 3!", "8 - 6", "2"]
+            );
+            // Branches not taken are never evaluated
+            assert_eq!(worked("1 < 2 ? 3 : 1 / 0"), ["true ? 3 : 1 / 0", "3"]);
+            assert_eq!(worked("false && 1 / 0 > 1"), ["false"]);
+            assert_eq!(
+                worked("[1 + 2, 2 * 2][1]"),
+                ["[3, 2 * 2][1]", "[3, 4][1]", "4"]
+            );
+            assert_eq!(
+                worked("1 > 0 && 2 > 1"),
+                ["true && 2 > 1", "true && true", "true"]
+            );
+        }
+
+        #[test]
+        fn test_parts() {
+            let mut env = Environment::new();
+            env.set("x", "4".parse().unwrap());
+            let expr = parse("y = x * x").unwrap();
+            let (value, steps) = eval_steps(&expr, &mut env).unwrap();
+            assert_eq!(value.to_string(), "16");
+            assert_eq!(env.get("y"), Some(&value));
+            let parts: Vec<(&str, &str)> = steps
+                .iter()
+                .map(|step| (step.from.as_str(), step.to.as_str()))
+                .collect();
+            assert_eq!(
+                parts,
+                [("x", "4"), ("x", "4"), ("4 * 4", "16"), ("y = 16", "16")]
+            );
+        }
+
+        #[test]
+        fn test_errors() {
+            let expr = parse("1 + 2 / (3 - 3)").unwrap();
+            let err = eval_steps(&expr, &mut Environment::new()).unwrap_err();
+            assert!(matches!(err, CalcError::DivisionByZero { .. }));
+            assert_eq!(err.span().start, 4);
+            assert!(eval_steps(&parse("x + 1").unwrap(), &mut Environment::new()).is_err());
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::common::Value;
+use crate::env::Environment;
+use crate::parser::{parse_statement, CalcError, Expr};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+struct Cell {
+    expr: Expr,
+    // Variables the formula reads, which need not be cells yet
+    dependencies: BTreeSet<String>,
+    value: Result<Value, String>,
+}
+
+/// Named formulas that may reference each other, like the cells of a
+/// spreadsheet. Changing a formula recomputes only that cell and the cells
+/// depending on it, each after everything it reads.
+#[derive(Default)]
+pub struct Workbook {
+    cells: BTreeMap<String, Cell>,
+}
+
+impl Workbook {
+    pub fn new() -> Self {
+        Workbook::default()
+    }
+
+    /// Set the formula of `name` and recompute what it affects. Returns the
+    /// recomputed names in evaluation order. A formula that cannot be parsed
+    /// or would make a cycle is refused and the workbook left unchanged.
+    pub fn set(&mut self, name: &str, formula: &str) -> Result<Vec<String>, CalcError> {
+        let expr = parse_statement(formula)?;
+        if let Expr::Assign(..) = expr {
+            return Err(CalcError::new_eval_error(
+                "A formula cannot assign a variable".to_string(),
+            )
+            .within(formula, formula));
+        }
+        let dependencies = expr.variables();
+        if let Some(path) = self.path_to(name, &dependencies) {
+            return Err(CalcError::new_eval_error(format!(
+                "Circular reference {} -> {}",
+                name,
+                path.join(" -> ")
+            ))
+            .within(formula, formula));
+        }
+        self.cells.insert(
+            name.to_string(),
+            Cell {
+                expr,
+                dependencies,
+                value: Err("Not computed".to_string()),
+            },
+        );
+        let order = self.affected_order(name);
+        for cell in &order {
+            self.recompute(cell);
+        }
+        Ok(order)
+    }
+
+    /// Current result of `name`, an error message if its formula failed
+    pub fn get(&self, name: &str) -> Option<Result<&Value, &str>> {
+        self.cells
+            .get(name)
+            .map(|cell| cell.value.as_ref().map_err(String::as_str))
+    }
+
+    // Sorted by name
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.cells.keys().map(String::as_str)
+    }
+
+    // Chain of cells from one of `dependencies` leading back to `target`
+    fn path_to(&self, target: &str, dependencies: &BTreeSet<String>) -> Option<Vec<String>> {
+        for dependency in dependencies {
+            if dependency == target {
+                return Some(vec![dependency.clone()]);
+            }
+            if let Some(cell) = self.cells.get(dependency) {
+                if let Some(mut path) = self.path_to(target, &cell.dependencies) {
+                    path.insert(0, dependency.clone());
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+
+    // `name` and every cell depending on it directly or not, each after the
+    // cells it reads
+    fn affected_order(&self, name: &str) -> Vec<String> {
+        let mut affected = BTreeSet::from([name.to_string()]);
+        let mut pending = vec![name.to_string()];
+        while let Some(changed) = pending.pop() {
+            for (other, cell) in &self.cells {
+                if cell.dependencies.contains(&changed) && affected.insert(other.clone()) {
+                    pending.push(other.clone());
+                }
+            }
+        }
+        let mut order = Vec::with_capacity(affected.len());
+        let mut done = BTreeSet::new();
+        for cell in &affected {
+            self.visit(cell, &affected, &mut done, &mut order);
+        }
+        order
+    }
+
+    // Depth first, dependencies before the cell itself
+    fn visit(
+        &self,
+        name: &str,
+        affected: &BTreeSet<String>,
+        done: &mut BTreeSet<String>,
+        order: &mut Vec<String>,
+    ) {
+        if !affected.contains(name) || !done.insert(name.to_string()) {
+            return;
+        }
+        for dependency in &self.cells[name].dependencies {
+            self.visit(dependency, affected, done, order);
+        }
+        order.push(name.to_string());
+    }
+
+    fn recompute(&mut self, name: &str) {
+        let cell = &self.cells[name];
+        let mut env = Environment::new();
+        let mut failed = None;
+        for dependency in &cell.dependencies {
+            match self.cells.get(dependency).map(|cell| &cell.value) {
+                Some(Ok(value)) => env.set(dependency, value.clone()),
+                Some(Err(_)) => failed = Some(format!("{} has an error", dependency)),
+                None => {}
+            }
+        }
+        let value = match failed {
+            Some(message) => Err(message),
+            None => cell
+                .expr
+                .clone()
+                .eval_in(&mut env)
+                .map_err(|e| e.to_string()),
+        };
+        self.cells.get_mut(name).unwrap().value = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(workbook: &Workbook, name: &str) -> String {
+        match workbook.get(name).unwrap() {
+            Ok(value) => value.to_string(),
+            Err(message) => message.to_string(),
+        }
+    }
+
+    mod test_workbook {
+        use super::*;
+
+        #[test]
+        fn test_minimal_recompute() {
+            let mut workbook = Workbook::new();
+            workbook.set("a", "1").unwrap();
+            workbook.set("b", "a * 2").unwrap();
+            workbook.set("c", "b + a").unwrap();
+            workbook.set("d", "5").unwrap();
+            assert_eq!(value(&workbook, "c"), "3");
+            assert_eq!(workbook.set("a", "10").unwrap(), ["a", "b", "c"]);
+            assert_eq!(value(&workbook, "c"), "30");
+            assert_eq!(workbook.set("b", "a / 4").unwrap(), ["b", "c"]);
+            assert_eq!(value(&workbook, "c"), "25/2");
+            assert_eq!(workbook.names().collect::<Vec<_>>(), ["a", "b", "c", "d"]);
+        }
+
+        #[test]
+        fn test_forward_reference() {
+            let mut workbook = Workbook::new();
+            workbook.set("total", "price * count").unwrap();
+            assert!(workbook.get("total").unwrap().is_err());
+            workbook.set("price", "3/2").unwrap();
+            assert_eq!(workbook.set("count", "4").unwrap(), ["count", "total"]);
+            assert_eq!(value(&workbook, "total"), "6");
+        }
+
+        #[test]
+        fn test_cycle_refused() {
+            let mut workbook = Workbook::new();
+            workbook.set("a", "1").unwrap();
+            workbook.set("b", "a + 1").unwrap();
+            workbook.set("c", "b + 1").unwrap();
+            let err = workbook.set("a", "c * 2").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Eval Error Circular reference a -> c -> b -> a"
+            );
+            assert!(workbook.set("x", "x + 1").is_err());
+            assert!(workbook.get("x").is_none());
+            assert_eq!(value(&workbook, "c"), "3");
+        }
+
+        #[test]
+        fn test_errors_propagate() {
+            let mut workbook = Workbook::new();
+            workbook.set("a", "1 / 0").unwrap();
+            workbook.set("b", "a + 1").unwrap();
+            assert_eq!(value(&workbook, "b"), "a has an error");
+            workbook.set("a", "2").unwrap();
+            assert_eq!(value(&workbook, "b"), "3");
+            assert!(workbook.set("a", "1 +").is_err());
+            assert!(workbook.set("a", "b = 2").is_err());
+            assert_eq!(value(&workbook, "a"), "2");
+        }
+    }
+}
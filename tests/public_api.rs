@@ -0,0 +1,121 @@
+// Names every public item, so removing or renaming one fails to compile.
+// New items should be added here when they are made public.
+use rust_calculator::prelude::*;
+use rust_calculator::{
+    canonical, eval_with_steps, gen_expr, gen_expr_with, parse, parse_rpn, randomize_equivalent,
+    refine, run, set_tracing, stats, syntax_errors, tokenize, Assoc, AuditEvent, CompiledExpr,
+    Convergents, EvalStep, Expr, ExprKind, Lexeme, Lexer, ModContext, OpCounts, Operator,
+    ParseOutput, PoolStats, Refinement, Rng, Span, Stats, Token,
+};
+
+use std::error::Error;
+
+#[test]
+fn test_prelude() -> Result<(), Box<dyn Error>> {
+    assert_eq!(eval_to_string("1/2 + 1/3")?, "5/6");
+    let sum: Value = eval("1/2 + 1/3")?;
+    assert_eq!(
+        sum.to_frac(),
+        Some(Frac::new(BigNum::from(5), BigNum::from(6)))
+    );
+    assert_eq!(eval("2 * 3")?.as_integer(), Some(&BigNum::from(6)));
+    let value: Value = "3/4".parse().unwrap();
+    assert_eq!(
+        value,
+        Value::Frac(Frac::new(BigNum::from(3), BigNum::from(4)))
+    );
+    assert_eq!(AngleUnit::Degree.suffix(), "deg");
+    let results: Vec<Result<Value, CalcError>> = eval_all("x = 1; x + 1").collect();
+    assert_eq!(results.len(), 2);
+    let err: CalcError = eval_to_string("1 + )").unwrap_err();
+    assert_eq!(err.span(), Span { start: 4, end: 5 });
+    let mut env = Environment::new();
+    env.set("x", value);
+    assert_eq!(env.len(), 1);
+    assert!(env.remove("y").is_none());
+    let mut session = Session::new();
+    session.eval("y = 2")?;
+    assert_eq!(session.eval_to_string("y * ans")?, "4");
+    session.register_function("twice", 1, |args| args[0].clone() + args[0].clone())?;
+    session.register_operator("<+>", 9, Assoc::Left, |a, b| a.clone() + b.clone())?;
+    assert_eq!(session.eval_to_string("twice(1 <+> 2)")?, "6");
+    session.register_function("reciprocal", 1, |args| {
+        Value::Number(BigNum::one()) / args[0].clone()
+    })?;
+    let err: ArithError = (Value::Number(BigNum::one()) / eval("0")?).unwrap_err();
+    assert_eq!(err, ArithError::DivisionByZero);
+    assert!(matches!(
+        session.eval("reciprocal(0)"),
+        Err(CalcError::DivisionByZero { .. })
+    ));
+    let mut limits = EvalLimits::default();
+    limits.max_steps = Some(100);
+    session.set_limits(limits);
+    assert!(limits.run(|| eval_to_string("2^10")).is_ok());
+    assert!(eval_with_limits("9^9^9", &limits)
+        .unwrap_err()
+        .is_limit_exceeded());
+    let mut workbook = Workbook::new();
+    workbook.set("a", "2")?;
+    Ok(())
+}
+
+#[test]
+fn test_root_items() -> Result<(), Box<dyn Error>> {
+    let output: ParseOutput = parse("1 + 2")?;
+    assert!(matches!(output.expr, Expr::Binary(Operator::Add, ..)));
+    let _: &[Span] = &output.spans;
+    assert_eq!(tokenize("-x")?, [Token::Dash, Token::Ident("x")]);
+    let lexemes: Vec<Lexeme> = Lexer::new("-x").collect::<Result<_, _>>()?;
+    assert_eq!(lexemes[1].span, Span { start: 1, end: 2 });
+    assert_eq!(
+        Expr::binary(
+            Operator::Add,
+            Expr::var("x"),
+            Expr::literal(Value::Number(BigNum::one()))
+        )
+        .to_string(),
+        "x + 1"
+    );
+    let compiled: CompiledExpr = parse("x * 2")?.expr.compile();
+    let mut env = Environment::new();
+    env.set("x", Value::Number(BigNum::from(3)));
+    assert_eq!(compiled.eval(&env)?.as_integer(), Some(&BigNum::from(6)));
+    assert_eq!(output.expr.to_rpn_string(), "1 2 +");
+    let items = Expr::list(vec![Expr::var("x"), Expr::literal(Value::List(vec![]))]);
+    assert_eq!(Expr::index(items, Expr::var("i")).to_string(), "[x, []][i]");
+    let pair = Value::List(vec![Value::Number(BigNum::one()), Value::Bool(true)]);
+    assert_eq!(
+        pair.index(Value::Number(BigNum::zero()))?,
+        Value::Number(BigNum::one())
+    );
+    assert_eq!(parse_rpn("1 2 3 * +")?.to_string(), "1 + 2 * 3");
+    let (_, steps): (Value, Vec<EvalStep>) = eval_with_steps("1/2 + 1/3")?;
+    assert_eq!(steps[0].expr, "3/6 + 2/6");
+    assert_eq!(syntax_errors("1 +; 2 *").len(), 2);
+    assert_eq!(canonical("2 + 1")?, canonical("1 + 2")?);
+    let mut rng = Rng::new(1);
+    randomize_equivalent("1 + 2", &mut rng)?;
+    gen_expr(2, &ExprKind::all());
+    gen_expr_with(&mut rng, 2, &[ExprKind::Add]);
+    set_tracing(false);
+    let _: (Stats, OpCounts, PoolStats) = (stats(), stats().ops, stats().pool);
+    let refined: Refinement = refine("sqrt(2)", 5)?;
+    assert_eq!(refined.text, "1.41421");
+    let mut session = Session::new();
+    session.set_audit_sink(|event: &AuditEvent| assert!(!event.limit_hit));
+    session.eval("1 + 1")?;
+    session.clear_audit_sink();
+    session.seed_random(7);
+    assert_eq!(session.seed(), Some(7));
+    let _: &mut Rng = session.rng();
+    let output: ParseOutput = session.parse("ans * 2")?;
+    let _: (Value, Vec<EvalStep>) = session.eval_steps(&output.expr)?;
+    session.eval_detached("ans + 1")?;
+    assert_eq!(session.refine("sqrt(ans)", 5)?.places, 5);
+    let _: Convergents<std::vec::IntoIter<BigNum>> =
+        Frac::new(BigNum::one(), BigNum::from(3)).convergents();
+    ModContext::new(BigNum::from(7))?;
+    let _: fn() -> Result<i32, Box<dyn Error>> = run;
+    Ok(())
+}